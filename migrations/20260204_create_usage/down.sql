@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS usage_records_user_created_idx;
+DROP TABLE IF EXISTS usage_records;
@@ -0,0 +1,2 @@
+ALTER TABLE todo_items DROP COLUMN recurrence;
+ALTER TABLE todo_items DROP COLUMN due_at;
@@ -0,0 +1,17 @@
+-- 20260202_create_todos shares its version with create_wakeup, so on a fresh
+-- database the todo table may not exist yet when this runs.
+CREATE TABLE IF NOT EXISTS todo_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    notes TEXT,
+    position INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    completed_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS todo_items_user_idx ON todo_items (user_id, position);
+
+ALTER TABLE todo_items ADD COLUMN due_at BIGINT;
+ALTER TABLE todo_items ADD COLUMN recurrence TEXT;
@@ -0,0 +1 @@
+ALTER TABLE reminders DROP COLUMN linked_todo_id;
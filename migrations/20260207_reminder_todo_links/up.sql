@@ -0,0 +1 @@
+ALTER TABLE reminders ADD COLUMN linked_todo_id INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE todo_items ADD COLUMN linked_reminder_id INTEGER;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS message_branches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_branches_message
+    ON message_branches(user_id, message_id);
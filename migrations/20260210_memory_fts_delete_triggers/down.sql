@@ -0,0 +1,28 @@
+DROP TRIGGER IF EXISTS messages_ad;
+DROP TRIGGER IF EXISTS messages_au;
+DROP TRIGGER IF EXISTS memories_ad;
+DROP TRIGGER IF EXISTS memories_au;
+
+CREATE TRIGGER messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content, user_id, message_id)
+    VALUES('delete', old.id, old.content, old.user_id, old.id);
+END;
+
+CREATE TRIGGER messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content, user_id, message_id)
+    VALUES('delete', old.id, old.content, old.user_id, old.id);
+    INSERT INTO messages_fts(rowid, content, user_id, message_id)
+    VALUES (new.id, new.content, new.user_id, new.id);
+END;
+
+CREATE TRIGGER memories_ad AFTER DELETE ON memories BEGIN
+    INSERT INTO memories_fts(memories_fts, rowid, summary, user_id, memory_id)
+    VALUES('delete', old.id, old.summary, old.user_id, old.id);
+END;
+
+CREATE TRIGGER memories_au AFTER UPDATE ON memories BEGIN
+    INSERT INTO memories_fts(memories_fts, rowid, summary, user_id, memory_id)
+    VALUES('delete', old.id, old.summary, old.user_id, old.id);
+    INSERT INTO memories_fts(rowid, summary, user_id, memory_id)
+    VALUES (new.id, new.summary, new.user_id, new.id);
+END;
@@ -0,0 +1,26 @@
+-- The FTS tables store their own content, so rows are removed with a plain
+-- DELETE; the 'delete' command only applies to external-content tables.
+DROP TRIGGER IF EXISTS messages_ad;
+DROP TRIGGER IF EXISTS messages_au;
+DROP TRIGGER IF EXISTS memories_ad;
+DROP TRIGGER IF EXISTS memories_au;
+
+CREATE TRIGGER messages_ad AFTER DELETE ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER messages_au AFTER UPDATE ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.id;
+    INSERT INTO messages_fts(rowid, content, user_id, message_id)
+    VALUES (new.id, new.content, new.user_id, new.id);
+END;
+
+CREATE TRIGGER memories_ad AFTER DELETE ON memories BEGIN
+    DELETE FROM memories_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER memories_au AFTER UPDATE ON memories BEGIN
+    DELETE FROM memories_fts WHERE rowid = old.id;
+    INSERT INTO memories_fts(rowid, summary, user_id, memory_id)
+    VALUES (new.id, new.summary, new.user_id, new.id);
+END;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS tool_audit_user_created_idx;
+DROP TABLE IF EXISTS tool_audit;
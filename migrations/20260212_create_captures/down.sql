@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS captures_user_name_idx;
+DROP TABLE IF EXISTS captures;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS captures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    capture_name TEXT NOT NULL,
+    data_json TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS captures_user_name_idx ON captures (user_id, capture_name, created_at);
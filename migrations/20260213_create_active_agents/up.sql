@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS active_agents (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    agent_name TEXT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS pinned_context (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_pinned_context_user
+    ON pinned_context(user_id);
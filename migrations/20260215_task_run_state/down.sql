@@ -0,0 +1,2 @@
+ALTER TABLE scheduled_tasks DROP COLUMN last_status;
+ALTER TABLE scheduled_tasks DROP COLUMN running_since;
@@ -0,0 +1,2 @@
+ALTER TABLE scheduled_tasks ADD COLUMN running_since BIGINT;
+ALTER TABLE scheduled_tasks ADD COLUMN last_status TEXT;
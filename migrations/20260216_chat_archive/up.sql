@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS archived_chats (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    archived_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE reminders DROP COLUMN lease_expires_at;
+ALTER TABLE reminders DROP COLUMN claim_token;
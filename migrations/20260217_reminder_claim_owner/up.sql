@@ -0,0 +1,2 @@
+ALTER TABLE reminders ADD COLUMN claim_token TEXT;
+ALTER TABLE reminders ADD COLUMN lease_expires_at BIGINT;
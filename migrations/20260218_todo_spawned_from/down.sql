@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS todo_items_spawned_from_idx;
+ALTER TABLE todo_items DROP COLUMN spawned_from;
@@ -0,0 +1,2 @@
+ALTER TABLE todo_items ADD COLUMN spawned_from INTEGER;
+CREATE UNIQUE INDEX IF NOT EXISTS todo_items_spawned_from_idx ON todo_items (spawned_from);
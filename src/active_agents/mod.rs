@@ -0,0 +1,141 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::RunQueryDsl;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use crate::db::retry_on_busy;
+use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
+
+mod schema;
+use schema::active_agents;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+const ACTIVE_AGENTS_UP_SQL: &str =
+    include_str!("../../migrations/20260213_create_active_agents/up.sql");
+
+type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
+type SqlitePool = Pool<SqliteAsyncConn>;
+type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
+
+#[derive(Insertable)]
+#[diesel(table_name = active_agents)]
+struct NewActiveAgent<'a> {
+    user_id: &'a str,
+    agent_name: &'a str,
+    updated_at: i64,
+}
+
+/// Which configured agent answers each user's turns.
+pub struct ActiveAgentStore {
+    pool: SqlitePool,
+}
+
+impl ActiveAgentStore {
+    pub async fn new(sqlite_path: impl AsRef<str>) -> Result<Self> {
+        let sqlite_path = sqlite_path.as_ref();
+        ensure_parent_dir(sqlite_path)?;
+        run_migrations(sqlite_path).await?;
+        ensure_active_agents_table(sqlite_path).await?;
+
+        let manager = AsyncDieselConnectionManager::<SqliteAsyncConn>::new(sqlite_path);
+        let pool: SqlitePool = Pool::builder()
+            .build(manager)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(Self { pool })
+    }
+
+    pub async fn get(&self, user_id: &str) -> Result<Option<String>> {
+        let mut conn = self.conn().await?;
+        active_agents::table
+            .filter(active_agents::user_id.eq(user_id))
+            .select(active_agents::agent_name)
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+    }
+
+    pub async fn set(&self, user_id: &str, agent_name: &str) -> Result<()> {
+        retry_on_busy(|| async move {
+            let new = NewActiveAgent {
+                user_id,
+                agent_name,
+                updated_at: now_ts(),
+            };
+            let mut conn = self.conn().await?;
+            diesel::replace_into(active_agents::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn clear(&self, user_id: &str) -> Result<()> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            diesel::delete(active_agents::table.filter(active_agents::user_id.eq(user_id)))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_async(&mut conn).await?;
+        Ok(conn)
+    }
+}
+
+async fn run_migrations(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        conn.run_pending_migrations(MIGRATIONS)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+async fn ensure_active_agents_table(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, ACTIVE_AGENTS_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
@@ -0,0 +1,7 @@
+diesel::table! {
+    active_agents (user_id) {
+        user_id -> Text,
+        agent_name -> Text,
+        updated_at -> BigInt,
+    }
+}
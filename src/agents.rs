@@ -0,0 +1,59 @@
+use crate::config::{AgentConfig, Config};
+use crate::config_store;
+use crate::error::Result;
+
+/// Changes made by `butterfly-bot agents ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentsCommand {
+    List,
+    Add(AgentConfig),
+    Edit {
+        name: String,
+        specialization: Option<String>,
+        instructions: Option<String>,
+        tools: Option<Vec<String>>,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+/// Applies `command` to the stored config and returns the resulting agents.
+pub fn run_agents_command(db_path: &str, command: AgentsCommand) -> Result<Vec<AgentConfig>> {
+    let mut config = Config::from_store(db_path)?;
+    match command {
+        AgentsCommand::List => return Ok(config.agents().to_vec()),
+        AgentsCommand::Add(agent) => config.add_agent(agent)?,
+        AgentsCommand::Edit {
+            name,
+            specialization,
+            instructions,
+            tools,
+        } => config.update_agent(&name, |agent| {
+            if let Some(specialization) = specialization {
+                agent.specialization = Some(specialization);
+            }
+            if let Some(instructions) = instructions {
+                agent.instructions = Some(instructions);
+            }
+            if let Some(tools) = tools {
+                agent.tools = tools;
+            }
+        })?,
+        AgentsCommand::Remove { name } => {
+            config.remove_agent(&name)?;
+        }
+    }
+    config_store::save_config(db_path, &config)?;
+    Ok(config.agents().to_vec())
+}
+
+/// Splits a comma-separated tool list, dropping blanks.
+pub fn parse_tool_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|tool| !tool.is_empty())
+        .map(str::to_string)
+        .collect()
+}
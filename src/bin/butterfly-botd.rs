@@ -1,6 +1,7 @@
 use butterfly_bot::daemon;
 use butterfly_bot::error::Result;
 use clap::Parser;
+use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
@@ -13,7 +14,7 @@ struct Cli {
     #[arg(long, default_value_t = 7878)]
     port: u16,
 
-    #[arg(long, default_value = "./data/butterfly-bot.db")]
+    #[arg(long, default_value_t = butterfly_bot::paths::default_db_path())]
     db: String,
 
     #[arg(long, env = "BUTTERFLY_BOT_TOKEN", default_value = "")]
@@ -24,7 +25,10 @@ struct Cli {
 async fn main() -> Result<()> {
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info,butterfly_bot=info,lance=warn,lancedb=warn"));
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
     let cli = Cli::parse();
 
     daemon::run(&cli.host, cli.port, &cli.db, &cli.token).await
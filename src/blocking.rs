@@ -0,0 +1,111 @@
+//! Synchronous wrapper around [`crate::ButterflyBot`] for callers without an
+//! async runtime, in the spirit of `reqwest::blocking`.
+//!
+//! Each client owns a private Tokio runtime. Do not create, use, or drop one
+//! from inside an async context: Tokio panics when a runtime is blocked on or
+//! shut down from within another runtime.
+
+use std::path::Path;
+
+use futures::StreamExt;
+use tokio::runtime::Runtime;
+
+use crate::config::Config;
+use crate::error::{ButterflyBotError, Result};
+use crate::services::query::{ProcessOptions, ProcessResult, UserInput};
+
+pub struct ButterflyBot {
+    inner: Option<crate::ButterflyBot>,
+    runtime: Runtime,
+}
+
+impl ButterflyBot {
+    pub fn from_config(config: Config) -> Result<Self> {
+        let runtime = build_runtime()?;
+        let inner = runtime.block_on(crate::ButterflyBot::from_config(config))?;
+        Ok(Self {
+            inner: Some(inner),
+            runtime,
+        })
+    }
+
+    pub fn from_store(db_path: &str) -> Result<Self> {
+        let runtime = build_runtime()?;
+        let inner = runtime.block_on(crate::ButterflyBot::from_store(db_path))?;
+        Ok(Self {
+            inner: Some(inner),
+            runtime,
+        })
+    }
+
+    pub fn from_config_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let runtime = build_runtime()?;
+        let inner = runtime.block_on(crate::ButterflyBot::from_config_path(path))?;
+        Ok(Self {
+            inner: Some(inner),
+            runtime,
+        })
+    }
+
+    pub fn process(
+        &self,
+        user_id: &str,
+        input: UserInput,
+        options: ProcessOptions,
+    ) -> Result<ProcessResult> {
+        self.runtime
+            .block_on(self.inner().process(user_id, input, options))
+    }
+
+    /// Runs a text turn to completion and returns the full reply.
+    pub fn process_text(
+        &self,
+        user_id: &str,
+        message: &str,
+        prompt: Option<&str>,
+    ) -> Result<String> {
+        self.runtime.block_on(async {
+            let mut stream = self.inner().process_text_stream(user_id, message, prompt);
+            let mut reply = String::new();
+            while let Some(chunk) = stream.next().await {
+                reply.push_str(&chunk?);
+            }
+            Ok(reply)
+        })
+    }
+
+    pub fn get_user_history(&self, user_id: &str, limit: usize) -> Result<Vec<String>> {
+        self.runtime
+            .block_on(self.inner().get_user_history(user_id, limit))
+    }
+
+    pub fn delete_user_history(&self, user_id: &str) -> Result<()> {
+        self.runtime
+            .block_on(self.inner().delete_user_history(user_id))
+    }
+
+    pub fn search_memory(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<String>> {
+        self.runtime
+            .block_on(self.inner().search_memory(user_id, query, limit))
+    }
+
+    fn inner(&self) -> &crate::ButterflyBot {
+        self.inner.as_ref().expect("client is only taken on drop")
+    }
+}
+
+impl Drop for ButterflyBot {
+    fn drop(&mut self) {
+        // Pools and background tasks inside the client expect a runtime context.
+        let _guard = self.runtime.enter();
+        drop(self.inner.take());
+    }
+}
+
+fn build_runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+}
@@ -0,0 +1,188 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::RunQueryDsl;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+
+use crate::db::retry_on_busy;
+use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
+
+mod schema;
+use schema::captures;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+const CAPTURES_UP_SQL: &str = include_str!("../../migrations/20260212_create_captures/up.sql");
+
+type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
+type SqlitePool = Pool<SqliteAsyncConn>;
+type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
+
+/// One structured record extracted under a capture name (e.g. a mood log entry).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureRecord {
+    pub id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub data: serde_json::Value,
+    pub created_at: i64,
+}
+
+#[derive(Queryable)]
+struct CaptureRow {
+    id: i32,
+    user_id: String,
+    capture_name: String,
+    data_json: String,
+    created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = captures)]
+struct NewCapture<'a> {
+    user_id: &'a str,
+    capture_name: &'a str,
+    data_json: &'a str,
+    created_at: i64,
+}
+
+pub struct CaptureStore {
+    pool: SqlitePool,
+}
+
+impl CaptureStore {
+    pub async fn new(sqlite_path: impl AsRef<str>) -> Result<Self> {
+        let sqlite_path = sqlite_path.as_ref();
+        ensure_parent_dir(sqlite_path)?;
+        run_migrations(sqlite_path).await?;
+        ensure_captures_table(sqlite_path).await?;
+
+        let manager = AsyncDieselConnectionManager::<SqliteAsyncConn>::new(sqlite_path);
+        let pool: SqlitePool = Pool::builder()
+            .build(manager)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(Self { pool })
+    }
+
+    pub async fn record(
+        &self,
+        user_id: &str,
+        name: &str,
+        data: &serde_json::Value,
+    ) -> Result<CaptureRecord> {
+        retry_on_busy(|| async move {
+            let data_json = serde_json::to_string(data)
+                .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+            let new = NewCapture {
+                user_id,
+                capture_name: name,
+                data_json: &data_json,
+                created_at: now_ts(),
+            };
+            let mut conn = self.conn().await?;
+            diesel::insert_into(captures::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let row: CaptureRow = captures::table
+                .filter(captures::user_id.eq(user_id))
+                .order(captures::id.desc())
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            row.into_record()
+        })
+        .await
+    }
+
+    /// The newest `limit` records `user_id` captured under `name`, oldest first.
+    pub async fn list(
+        &self,
+        user_id: &str,
+        name: &str,
+        limit: usize,
+    ) -> Result<Vec<CaptureRecord>> {
+        let mut conn = self.conn().await?;
+        let rows: Vec<CaptureRow> = captures::table
+            .filter(captures::user_id.eq(user_id))
+            .filter(captures::capture_name.eq(name))
+            .order(captures::id.desc())
+            .limit(limit as i64)
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        rows.into_iter()
+            .rev()
+            .map(CaptureRow::into_record)
+            .collect()
+    }
+
+    async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_async(&mut conn).await?;
+        Ok(conn)
+    }
+}
+
+impl CaptureRow {
+    fn into_record(self) -> Result<CaptureRecord> {
+        let data = serde_json::from_str(&self.data_json)
+            .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+        Ok(CaptureRecord {
+            id: self.id,
+            user_id: self.user_id,
+            name: self.capture_name,
+            data,
+            created_at: self.created_at,
+        })
+    }
+}
+
+async fn run_migrations(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        conn.run_pending_migrations(MIGRATIONS)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+async fn ensure_captures_table(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, CAPTURES_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
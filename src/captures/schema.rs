@@ -0,0 +1,9 @@
+diesel::table! {
+    captures (id) {
+        id -> Integer,
+        user_id -> Text,
+        capture_name -> Text,
+        data_json -> Text,
+        created_at -> BigInt,
+    }
+}
@@ -4,11 +4,20 @@ use std::sync::Arc;
 use futures::stream::BoxStream;
 
 use crate::config::Config;
+use crate::costs::UsageSummary;
 use crate::error::{ButterflyBotError, Result};
 use crate::factories::agent_factory::ButterflyBotFactory;
 use crate::interfaces::plugins::Tool;
+use crate::interfaces::providers::{
+    ChatEvent, ChatSummary, MessageBranch, ModelInfo, PinnedContext, ReindexReport, SearchModels,
+    StoredMessage,
+};
+use crate::planning::{PlanItem, PlanStore};
+use crate::plugins::registry::ToolSchema;
 use crate::services::agent::UiEvent;
+use crate::services::planner::PlanGenerator;
 use crate::services::query::{ProcessOptions, ProcessResult, QueryService, UserInput};
+use crate::tool_audit::ToolAuditEntry;
 use tokio::sync::broadcast;
 
 pub struct ButterflyBot {
@@ -61,6 +70,16 @@ impl ButterflyBot {
         service.process_text_stream(user_id, message, prompt)
     }
 
+    /// [`Self::process`] as a stream of [`ChatEvent`]s, ending in `message_end`.
+    pub fn process_stream<'a>(
+        &'a self,
+        user_id: &'a str,
+        input: UserInput,
+        options: ProcessOptions,
+    ) -> BoxStream<'a, Result<ChatEvent>> {
+        self.query_service.process_stream(user_id, input, options)
+    }
+
     pub async fn process(
         &self,
         user_id: &str,
@@ -78,6 +97,17 @@ impl ButterflyBot {
         self.query_service.get_user_history(user_id, limit).await
     }
 
+    pub async fn get_messages(
+        &self,
+        user_id: &str,
+        before_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>> {
+        self.query_service
+            .get_messages(user_id, before_id, limit)
+            .await
+    }
+
     pub async fn search_memory(
         &self,
         user_id: &str,
@@ -89,6 +119,121 @@ impl ButterflyBot {
             .await
     }
 
+    pub async fn search_memory_with_models(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        models: &SearchModels,
+    ) -> Result<Vec<String>> {
+        self.query_service
+            .search_memory_with_models(user_id, query, limit, models)
+            .await
+    }
+
+    pub async fn pin_context(&self, user_id: &str, content: &str) -> Result<PinnedContext> {
+        self.query_service.pin_context(user_id, content).await
+    }
+
+    pub async fn unpin_context(&self, user_id: &str, pin_id: i64) -> Result<bool> {
+        self.query_service.unpin_context(user_id, pin_id).await
+    }
+
+    pub async fn pinned_context(&self, user_id: &str) -> Result<Vec<PinnedContext>> {
+        self.query_service.pinned_context(user_id).await
+    }
+
+    pub async fn list_chats(&self, include_archived: bool) -> Result<Vec<ChatSummary>> {
+        self.query_service.list_chats(include_archived).await
+    }
+
+    pub async fn archive_chat(&self, user_id: &str) -> Result<bool> {
+        self.query_service.archive_chat(user_id).await
+    }
+
+    pub async fn unarchive_chat(&self, user_id: &str) -> Result<bool> {
+        self.query_service.unarchive_chat(user_id).await
+    }
+
+    pub async fn archive_idle_chats(&self, idle_before: i64) -> Result<Vec<String>> {
+        self.query_service.archive_idle_chats(idle_before).await
+    }
+
+    pub async fn regenerate(&self, user_id: &str, message_id: i64) -> Result<MessageBranch> {
+        self.query_service.regenerate(user_id, message_id).await
+    }
+
+    pub async fn edit_and_resubmit(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        new_text: &str,
+    ) -> Result<String> {
+        self.query_service
+            .edit_and_resubmit(user_id, message_id, new_text)
+            .await
+    }
+
+    pub async fn get_branches(&self, user_id: &str, message_id: i64) -> Result<Vec<MessageBranch>> {
+        self.query_service.get_branches(user_id, message_id).await
+    }
+
+    pub async fn reindex_memory(
+        &self,
+        user_id: &str,
+        resume_after: Option<i64>,
+    ) -> Result<ReindexReport> {
+        self.query_service
+            .reindex_memory(user_id, resume_after)
+            .await
+    }
+
+    pub async fn generate_plan(
+        &self,
+        store: Arc<PlanStore>,
+        user_id: &str,
+        goal: &str,
+    ) -> Result<PlanItem> {
+        PlanGenerator::new(self.query_service.agent_service(), store)
+            .generate_plan(user_id, goal)
+            .await
+    }
+
+    pub async fn usage_summary(&self, user_id: &str) -> Result<Option<UsageSummary>> {
+        let agent_service = self.query_service.agent_service();
+        match agent_service.cost_tracker().await {
+            Some(tracker) => Ok(Some(tracker.summary(user_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The newest `limit` tool executions for `user_id`; empty when no audit
+    /// store is configured.
+    pub async fn tool_audit(&self, user_id: &str, limit: usize) -> Result<Vec<ToolAuditEntry>> {
+        let agent_service = self.query_service.agent_service();
+        match agent_service.tool_registry.audit_store().await {
+            Some(store) => store.list(user_id, limit).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn agent_names(&self) -> Vec<String> {
+        self.query_service.agent_names()
+    }
+
+    pub async fn active_agent(&self, user_id: &str) -> Result<String> {
+        self.query_service.active_agent(user_id).await
+    }
+
+    pub async fn set_active_agent(&self, user_id: &str, agent: Option<&str>) -> Result<()> {
+        self.query_service.set_active_agent(user_id, agent).await
+    }
+
+    pub async fn tool_schemas(&self, agent_name: Option<&str>) -> Vec<ToolSchema> {
+        let agent_service = self.query_service.agent_service();
+        agent_service.tool_registry.tool_schemas(agent_name).await
+    }
+
     pub async fn register_tool(&self, tool: Arc<dyn Tool>) -> Result<bool> {
         let agent_service = self.query_service.agent_service();
         let registry = agent_service.tool_registry.clone();
@@ -106,6 +251,24 @@ impl ButterflyBot {
         Ok(true)
     }
 
+    pub async fn transcribe(&self, audio_bytes: Vec<u8>, input_format: &str) -> Result<String> {
+        self.query_service
+            .agent_service()
+            .transcribe_audio(audio_bytes, input_format)
+            .await
+    }
+
+    pub async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        self.query_service
+            .agent_service()
+            .embed(inputs, model)
+            .await
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.query_service.agent_service().list_models().await
+    }
+
     pub async fn brain_tick(&self) {
         let agent_service = self.query_service.agent_service();
         agent_service.dispatch_brain_tick().await;
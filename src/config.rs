@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
 use crate::error::{ButterflyBotError, Result};
 
@@ -10,6 +12,125 @@ pub struct OpenAiConfig {
     pub api_key: Option<String>,
     pub model: Option<String>,
     pub base_url: Option<String>,
+    /// Extra headers for every request to the provider. Values may reference
+    /// vault secrets as `${name}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// How structured output is requested; endpoints without native JSON
+    /// support need `prompt`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_mode: Option<JsonMode>,
+}
+
+/// How `parse_structured_output` asks the model for JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonMode {
+    /// `response_format: json_schema`; the server enforces the schema.
+    #[default]
+    JsonSchema,
+    /// `response_format: json_object`, with the schema in the system prompt.
+    JsonObject,
+    /// No `response_format`; only the system prompt asks for JSON.
+    Prompt,
+}
+
+impl OpenAiConfig {
+    /// Which kind of endpoint `base_url` points at, for display.
+    pub fn provider_name(&self) -> &'static str {
+        match self.base_url.as_deref().map(str::trim) {
+            None | Some("") => "OpenAI",
+            Some(url) if url.contains(":11434") => "Ollama",
+            Some(url) if url.contains("api.openai.com") => "OpenAI",
+            Some(_) => "OpenAI-compatible",
+        }
+    }
+
+    /// Replaces `base_url` with its [`normalize_base_url`] form; a blank one
+    /// is dropped so the provider default applies.
+    pub fn normalize(&mut self) -> Result<()> {
+        self.base_url = match self.base_url.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(url) => Some(normalize_base_url(url)?),
+        };
+        Ok(())
+    }
+
+    /// `headers` with every `${name}` replaced by that vault secret.
+    pub fn resolved_headers(&self) -> Result<HashMap<String, String>> {
+        self.headers
+            .iter()
+            .flatten()
+            .map(|(name, value)| Ok((name.clone(), interpolate_secrets(name, value)?)))
+            .collect()
+    }
+}
+
+fn interpolate_secrets(header: &str, value: &str) -> Result<String> {
+    let mut resolved = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let secret = crate::vault::get_secret(name)?.ok_or_else(|| {
+            ButterflyBotError::Config(format!(
+                "Header '{header}' references secret '{name}', which is not in the vault"
+            ))
+        })?;
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(&secret);
+        rest = &rest[start + 2 + len + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+/// Trims `raw`, collapses repeated slashes, drops a trailing one, and adds
+/// `/v1` to a bare local Ollama or api.openai.com URL. Anything that isn't an
+/// http(s) URL with a host is a config error.
+pub fn normalize_base_url(raw: &str) -> Result<String> {
+    let raw = raw.trim();
+    let invalid = || ButterflyBotError::Config(format!("Invalid base_url '{raw}'"));
+    let (scheme, rest) = raw.split_once("://").ok_or_else(invalid)?;
+    let rest = rest
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut normalized = format!("{}://{}", scheme.to_ascii_lowercase(), rest);
+
+    let url = reqwest::Url::parse(&normalized).map_err(|_| invalid())?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(invalid());
+    }
+    if url.path() == "/" && (is_local_ollama(&url) || url.host_str() == Some("api.openai.com")) {
+        normalized.push_str("/v1");
+    }
+    Ok(normalized)
+}
+
+/// Whether `base_url` points at an Ollama server on this machine.
+pub fn is_ollama_local(base_url: &str) -> bool {
+    normalize_base_url(base_url)
+        .ok()
+        .and_then(|url| reqwest::Url::parse(&url).ok())
+        .is_some_and(|url| is_local_ollama(&url))
+}
+
+/// The `/api/tags` model listing of a local Ollama `base_url`.
+pub fn ollama_tags_url(base_url: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(&normalize_base_url(base_url).ok()?).ok()?;
+    if !is_local_ollama(&url) {
+        return None;
+    }
+    url.set_path("/api/tags");
+    Some(url.to_string())
+}
+
+fn is_local_ollama(url: &reqwest::Url) -> bool {
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]")) && url.port() == Some(11434)
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -21,7 +142,139 @@ pub struct MemoryConfig {
     pub embedding_model: Option<String>,
     pub rerank_model: Option<String>,
     pub summary_threshold: Option<usize>,
+    pub summary_style: Option<SummaryStyle>,
     pub retention_days: Option<u32>,
+    /// Days without a message before a chat is archived. Unlike
+    /// `retention_days`, nothing is deleted.
+    pub chat_ttl_days: Option<u32>,
+    pub namespace: Option<String>,
+    pub min_score: Option<f32>,
+    /// How many memories a turn retrieves and reranks (default 5).
+    pub search_limit: Option<usize>,
+    /// How many of the retrieved memories reach the prompt (default: all).
+    pub context_top_k: Option<usize>,
+    pub history_window: Option<HistoryWindow>,
+    pub embedding_limit: Option<EmbeddingLimit>,
+    pub vector_index: Option<VectorIndexConfig>,
+}
+
+/// Bounds the raw history sent with each turn: at most `messages` recent
+/// messages (default 12), trimmed oldest-first to fit `max_tokens`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HistoryWindow {
+    pub messages: Option<usize>,
+    pub max_tokens: Option<u64>,
+}
+
+/// How memory summaries are written: as a narrative paragraph (default) or
+/// bullet points, optionally capped at `max_words`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SummaryStyle {
+    #[serde(default)]
+    pub format: SummaryFormat,
+    pub max_words: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryFormat {
+    #[default]
+    Narrative,
+    Bullets,
+}
+
+/// The embedding model's input limit, checked against the 4-characters-per-
+/// token estimate, and what `embed` does with longer inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EmbeddingLimit {
+    pub max_tokens: u64,
+    #[serde(default)]
+    pub overflow: EmbeddingOverflow,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingOverflow {
+    /// Embed only the start of the input.
+    #[default]
+    Truncate,
+    /// Embed every chunk and average them, weighted by length.
+    ChunkAverage,
+    /// Fail the whole request.
+    Error,
+}
+
+/// An approximate nearest-neighbour index over the LanceDB vectors. Without
+/// one, searches compare the query against every stored vector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VectorIndexConfig {
+    #[serde(default)]
+    pub kind: VectorIndexKind,
+    /// IVF clusters (nlist). LanceDB picks about `sqrt(rows)` when unset.
+    pub partitions: Option<u32>,
+    /// Clusters searched per query (nprobe); default 20.
+    pub nprobes: Option<u32>,
+    /// `ivf_pq` only: must divide the embedding dimension.
+    pub sub_vectors: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorIndexKind {
+    /// Clusters full vectors; exact distances within the probed clusters.
+    #[default]
+    IvfFlat,
+    /// Also compresses vectors with product quantization. Needs at least 256
+    /// rows to train.
+    IvfPq,
+}
+
+pub const MAX_VECTOR_INDEX_PARTITIONS: u32 = 65_536;
+
+impl VectorIndexConfig {
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(ButterflyBotError::Config(message));
+        if let Some(partitions) = self.partitions {
+            if !(1..=MAX_VECTOR_INDEX_PARTITIONS).contains(&partitions) {
+                return invalid(format!(
+                    "memory.vector_index.partitions must be between 1 and {MAX_VECTOR_INDEX_PARTITIONS}"
+                ));
+            }
+        }
+        if let Some(nprobes) = self.nprobes {
+            if nprobes == 0 {
+                return invalid("memory.vector_index.nprobes must be at least 1".to_string());
+            }
+            if self
+                .partitions
+                .is_some_and(|partitions| nprobes > partitions)
+            {
+                return invalid("memory.vector_index.nprobes cannot exceed partitions".to_string());
+            }
+        }
+        match (self.kind, self.sub_vectors) {
+            (VectorIndexKind::IvfFlat, Some(_)) => invalid(
+                "memory.vector_index.sub_vectors only applies to kind \"ivf_pq\"".to_string(),
+            ),
+            (VectorIndexKind::IvfPq, Some(0)) => {
+                invalid("memory.vector_index.sub_vectors must be at least 1".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CostConfig {
+    pub prices: Option<HashMap<String, ModelPrice>>,
+    pub daily_budget: Option<f64>,
+    pub sqlite_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -32,7 +285,90 @@ pub struct Config {
     pub memory: Option<MemoryConfig>,
     pub tools: Option<Value>,
     pub brains: Option<Value>,
+    #[serde(default)]
+    pub costs: Option<CostConfig>,
+    #[serde(default)]
+    pub agents: Option<Vec<AgentConfig>>,
+    #[serde(default)]
+    pub scheduler: Option<SchedulerConfig>,
+    #[serde(default)]
+    pub daemon: Option<DaemonConfig>,
 }
+
+/// The unified scheduler that polls for due reminders, tasks and wakeups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SchedulerConfig {
+    /// Seconds between polls for due tasks, wakeups and reminders. Unset, the
+    /// shorter of the wakeup and task `poll_seconds` is used.
+    pub tick_secs: Option<u64>,
+    /// Whether the daemon delivers due reminders itself. Set `false` when an
+    /// external worker owns delivery through `/reminders/claim`.
+    pub deliver_reminders: Option<bool>,
+}
+
+pub const MIN_SCHEDULER_TICK_SECS: u64 = 1;
+
+impl SchedulerConfig {
+    /// `tick_secs`, raised to [`MIN_SCHEDULER_TICK_SECS`] if below it.
+    pub fn tick(&self) -> Option<Duration> {
+        self.tick_secs
+            .map(|secs| Duration::from_secs(secs.max(MIN_SCHEDULER_TICK_SECS)))
+    }
+
+    pub fn delivers_reminders(&self) -> bool {
+        self.deliver_reminders.unwrap_or(true)
+    }
+}
+
+/// The HTTP daemon's own settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DaemonConfig {
+    /// Browser origins allowed to call the daemon; `"*"` allows any. Unset,
+    /// none are.
+    pub cors_origins: Option<Vec<String>>,
+    /// Methods a CORS preflight may ask for.
+    pub cors_methods: Option<Vec<String>>,
+    /// Request headers a CORS preflight may ask for.
+    pub cors_headers: Option<Vec<String>>,
+    /// Largest request body accepted, in bytes; bigger ones get a 413.
+    pub max_body_bytes: Option<usize>,
+    /// Largest audio upload accepted by `/transcribe`, in bytes.
+    pub max_audio_body_bytes: Option<usize>,
+    /// Seconds between keep-alive comments on SSE streams.
+    pub sse_keep_alive_secs: Option<u64>,
+}
+
+impl DaemonConfig {
+    /// `sse_keep_alive_secs`, raised to one second if below it.
+    pub fn sse_keep_alive(&self) -> Option<Duration> {
+        self.sse_keep_alive_secs
+            .map(|secs| Duration::from_secs(secs.max(1)))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AgentConfig {
+    pub name: String,
+    pub specialization: Option<String>,
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Sampling defaults for this agent's turns.
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Agents this one may hand a sub-query to with the `delegate_to` tool.
+    #[serde(default)]
+    pub delegates: Vec<String>,
+    /// Shown before this agent's replies in the CLI and UI; defaults to `name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Keeps this agent's memories apart from other agents'; defaults to
+    /// `memory.namespace`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_namespace: Option<String>,
+}
+
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         std::hint::black_box(path.as_ref());
@@ -40,7 +376,7 @@ impl Config {
             .map_err(|e| ButterflyBotError::Config(e.to_string()))?;
         let config: Config =
             serde_json::from_str(&content).map_err(|e| ButterflyBotError::Config(e.to_string()))?;
-        Ok(config)
+        config.normalized()
     }
 
     pub fn from_store(db_path: &str) -> Result<Self> {
@@ -50,10 +386,65 @@ impl Config {
                     .map_err(|e| ButterflyBotError::Config(e.to_string()))?;
                 let config: Config = serde_json::from_value(value)
                     .map_err(|e| ButterflyBotError::Config(e.to_string()))?;
-                return Ok(config);
+                return config.normalized();
             }
         }
-        crate::config_store::load_config(db_path)
+        crate::config_store::load_config(db_path)?.normalized()
+    }
+
+    fn normalized(mut self) -> Result<Self> {
+        if let Some(openai) = &mut self.openai {
+            openai.normalize()?;
+        }
+        Ok(self)
+    }
+
+    pub fn agents(&self) -> &[AgentConfig] {
+        self.agents.as_deref().unwrap_or_default()
+    }
+
+    pub fn add_agent(&mut self, agent: AgentConfig) -> Result<()> {
+        let name = agent.name.trim();
+        if name.is_empty() {
+            return Err(ButterflyBotError::Config(
+                "Agent name cannot be empty".to_string(),
+            ));
+        }
+        if self.agents().iter().any(|existing| existing.name == name) {
+            return Err(ButterflyBotError::Config(format!(
+                "Agent '{}' already exists",
+                name
+            )));
+        }
+        let agent = AgentConfig {
+            name: name.to_string(),
+            ..agent
+        };
+        self.agents.get_or_insert_with(Vec::new).push(agent);
+        Ok(())
+    }
+
+    pub fn update_agent(
+        &mut self,
+        name: &str,
+        update: impl FnOnce(&mut AgentConfig),
+    ) -> Result<()> {
+        let agent = self
+            .agents
+            .as_mut()
+            .and_then(|agents| agents.iter_mut().find(|agent| agent.name == name))
+            .ok_or_else(|| ButterflyBotError::Config(format!("Agent '{}' not found", name)))?;
+        update(agent);
+        Ok(())
+    }
+
+    pub fn remove_agent(&mut self, name: &str) -> Result<AgentConfig> {
+        let agents = self.agents.get_or_insert_with(Vec::new);
+        let index = agents
+            .iter()
+            .position(|agent| agent.name == name)
+            .ok_or_else(|| ButterflyBotError::Config(format!("Agent '{}' not found", name)))?;
+        Ok(agents.remove(index))
     }
 
     pub fn resolve_vault(mut self) -> Result<Self> {
@@ -66,4 +457,52 @@ impl Config {
         }
         Ok(self)
     }
+
+    /// `"<model> via <provider>"`, or `None` without an `openai` section.
+    pub fn model_label(&self) -> Option<String> {
+        let openai = self.openai.as_ref()?;
+        let model = openai
+            .model
+            .as_deref()
+            .map(str::trim)
+            .filter(|model| !model.is_empty())
+            .unwrap_or("default model");
+        Some(format!("{model} via {}", openai.provider_name()))
+    }
+
+    /// What replies are labelled with: the first agent's `display_name`, then
+    /// its `name`, then [`DEFAULT_DISPLAY_NAME`].
+    pub fn display_name(&self) -> String {
+        self.agents()
+            .first()
+            .and_then(|agent| {
+                [agent.display_name.as_deref(), Some(agent.name.as_str())]
+                    .into_iter()
+                    .flatten()
+                    .map(str::trim)
+                    .find(|name| !name.is_empty())
+            })
+            .unwrap_or(DEFAULT_DISPLAY_NAME)
+            .to_string()
+    }
+}
+
+pub const DEFAULT_DISPLAY_NAME: &str = "Butterfly";
+
+/// The unstyled label printed before a reply in the terminal.
+pub fn assistant_prefix(display_name: &str) -> String {
+    format!("✦ {display_name} ")
+}
+
+/// `text` led by the display name in bold, for replies rendered as markdown.
+pub fn markdown_reply(display_name: &str, text: &str) -> String {
+    format!("**{display_name}:** {text}")
+}
+
+/// The status line under the CLI banner.
+pub fn banner_line(config: Option<&Config>, daemon: &str, user_id: &str) -> String {
+    let model = config
+        .and_then(Config::model_label)
+        .unwrap_or_else(|| "No model configured".to_string());
+    format!("{model} • Streaming CLI • Daemon: {daemon} • User: {user_id}")
 }
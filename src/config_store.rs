@@ -1,4 +1,3 @@
-use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use diesel::prelude::*;
@@ -8,6 +7,7 @@ use serde_json::Value;
 
 use crate::config::Config;
 use crate::error::{ButterflyBotError, Result};
+pub use crate::paths::ensure_parent_dir;
 
 #[derive(QueryableByName)]
 struct ConfigRow {
@@ -15,14 +15,6 @@ struct ConfigRow {
     config_json: String,
 }
 
-pub fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
-}
-
 fn open_conn(db_path: &str) -> Result<SqliteConnection> {
     let mut conn = SqliteConnection::establish(db_path)
         .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
@@ -0,0 +1,123 @@
+use axum::http::{header, HeaderMap, HeaderValue, Method};
+
+use crate::config::DaemonConfig;
+use crate::services::request_context::REQUEST_ID_HEADER;
+
+const DEFAULT_METHODS: &[&str] = &["GET", "POST", "DELETE", "OPTIONS"];
+const DEFAULT_HEADERS: &[&str] = &[
+    "authorization",
+    "content-type",
+    "x-api-key",
+    REQUEST_ID_HEADER,
+];
+
+/// Which browser origins may call the daemon. An origin of `*` allows any,
+/// answered with a literal `*` rather than the caller's origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsPolicy {
+    /// No cross-origin access until origins are listed.
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: DEFAULT_METHODS.iter().map(|m| m.to_string()).collect(),
+            allowed_headers: DEFAULT_HEADERS.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+}
+
+impl CorsPolicy {
+    /// Starts from [`CorsPolicy::default`] and applies the daemon section's
+    /// `cors_origins`, `cors_methods` and `cors_headers` when set.
+    pub fn from_config(config: Option<&DaemonConfig>) -> Self {
+        let mut policy = Self::default();
+        let Some(config) = config else {
+            return policy;
+        };
+        if let Some(origins) = &config.cors_origins {
+            policy.allowed_origins = clean_list(origins, str::to_string);
+        }
+        if let Some(methods) = &config.cors_methods {
+            policy.allowed_methods = clean_list(methods, str::to_ascii_uppercase);
+        }
+        if let Some(headers) = &config.cors_headers {
+            policy.allowed_headers = clean_list(headers, str::to_ascii_lowercase);
+        }
+        policy
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allow_origin_value(origin).is_some()
+    }
+
+    /// `origin` itself when it is listed, `*` when only the wildcard covers
+    /// it, so an unlisted origin is never reflected back.
+    fn allow_origin_value(&self, origin: &str) -> Option<HeaderValue> {
+        if self
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        {
+            return HeaderValue::from_str(origin).ok();
+        }
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*")
+            .then(|| HeaderValue::from_static("*"))
+    }
+
+    /// Headers for a response to `origin`; empty when the origin isn't allowed.
+    pub fn response_headers(&self, origin: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let Some(value) = self.allow_origin_value(origin) else {
+            return headers;
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+        headers.insert(header::VARY, HeaderValue::from_static("origin"));
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(REQUEST_ID_HEADER),
+        );
+        headers
+    }
+
+    /// Headers answering a preflight for `method` from `origin`.
+    pub fn preflight_headers(&self, origin: &str, method: &Method) -> HeaderMap {
+        let method_allowed = self
+            .allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method.as_str()));
+        if !method_allowed {
+            return HeaderMap::new();
+        }
+        let mut headers = self.response_headers(origin);
+        if headers.is_empty() {
+            return headers;
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.allowed_methods.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.allowed_headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static("600"),
+        );
+        headers
+    }
+}
+
+fn clean_list(items: &[String], normalize: impl Fn(&str) -> String) -> Vec<String> {
+    items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(normalize)
+        .collect()
+}
@@ -0,0 +1,253 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::RunQueryDsl;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+
+use crate::config::CostConfig;
+use crate::db::retry_on_busy;
+use crate::error::{ButterflyBotError, Result};
+use crate::interfaces::providers::TokenUsage;
+use crate::paths::ensure_parent_dir;
+
+mod schema;
+use schema::usage_records;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+const USAGE_UP_SQL: &str = include_str!("../../migrations/20260204_create_usage/up.sql");
+
+type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
+type SqlitePool = Pool<SqliteAsyncConn>;
+type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    pub user_id: String,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_cost: f64,
+    pub today_cost: f64,
+    pub daily_budget: Option<f64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = usage_records)]
+struct NewUsage<'a> {
+    user_id: &'a str,
+    model: &'a str,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    cost: f64,
+    created_at: i64,
+}
+
+pub struct CostTracker {
+    pool: SqlitePool,
+    config: CostConfig,
+    model: String,
+}
+
+impl CostTracker {
+    pub async fn new(
+        sqlite_path: impl AsRef<str>,
+        config: CostConfig,
+        model: impl Into<String>,
+    ) -> Result<Self> {
+        let sqlite_path = sqlite_path.as_ref();
+        ensure_parent_dir(sqlite_path)?;
+        run_migrations(sqlite_path).await?;
+        ensure_usage_table(sqlite_path).await?;
+
+        let manager = AsyncDieselConnectionManager::<SqliteAsyncConn>::new(sqlite_path);
+        let pool: SqlitePool = Pool::builder()
+            .build(manager)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(Self {
+            pool,
+            config,
+            model: model.into(),
+        })
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn cost_for(&self, model: &str, usage: TokenUsage) -> f64 {
+        let Some(price) = self
+            .config
+            .prices
+            .as_ref()
+            .and_then(|prices| prices.get(model))
+        else {
+            return 0.0;
+        };
+        (usage.prompt_tokens as f64 * price.input_per_million
+            + usage.completion_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    }
+
+    pub async fn record(&self, user_id: &str, model: &str, usage: TokenUsage) -> Result<f64> {
+        retry_on_busy(|| async move {
+            let cost = self.cost_for(model, usage);
+            let new = NewUsage {
+                user_id,
+                model,
+                prompt_tokens: usage.prompt_tokens as i64,
+                completion_tokens: usage.completion_tokens as i64,
+                cost,
+                created_at: now_ts(),
+            };
+            let mut conn = self.conn().await?;
+            diesel::insert_into(usage_records::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(cost)
+        })
+        .await
+    }
+
+    pub async fn spent_since(&self, user_id: &str, since: i64) -> Result<f64> {
+        let mut conn = self.conn().await?;
+        let total: Option<f64> = usage_records::table
+            .filter(usage_records::user_id.eq(user_id))
+            .filter(usage_records::created_at.ge(since))
+            .select(diesel::dsl::sum(usage_records::cost))
+            .first(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(total.unwrap_or(0.0))
+    }
+
+    pub async fn check_budget(&self, user_id: &str) -> Result<()> {
+        let Some(budget) = self.config.daily_budget else {
+            return Ok(());
+        };
+        let spent = self.spent_since(user_id, start_of_day(now_ts())).await?;
+        if spent >= budget {
+            return Err(ButterflyBotError::BudgetExceeded(format!(
+                "daily budget of {budget:.4} reached for user {user_id} (spent {spent:.4})"
+            )));
+        }
+        Ok(())
+    }
+
+    pub async fn summary(&self, user_id: &str) -> Result<UsageSummary> {
+        let mut conn = self.conn().await?;
+        let rows: Vec<(i64, i64, f64, i64)> = usage_records::table
+            .filter(usage_records::user_id.eq(user_id))
+            .select((
+                usage_records::prompt_tokens,
+                usage_records::completion_tokens,
+                usage_records::cost,
+                usage_records::created_at,
+            ))
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+        let today = start_of_day(now_ts());
+        let mut summary = UsageSummary {
+            user_id: user_id.to_string(),
+            requests: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            total_cost: 0.0,
+            today_cost: 0.0,
+            daily_budget: self.config.daily_budget,
+        };
+        for (prompt_tokens, completion_tokens, cost, created_at) in rows {
+            summary.requests += 1;
+            summary.prompt_tokens += prompt_tokens;
+            summary.completion_tokens += completion_tokens;
+            summary.total_cost += cost;
+            if created_at >= today {
+                summary.today_cost += cost;
+            }
+        }
+        Ok(summary)
+    }
+
+    async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_async(&mut conn).await?;
+        Ok(conn)
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+pub fn default_usage_db_path() -> String {
+    crate::paths::default_db_path()
+}
+
+fn start_of_day(ts: i64) -> i64 {
+    ts - ts.rem_euclid(86_400)
+}
+
+async fn run_migrations(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        conn.run_pending_migrations(MIGRATIONS)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+async fn ensure_usage_table(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+
+        let check = diesel::connection::SimpleConnection::batch_execute(
+            &mut conn,
+            "SELECT 1 FROM usage_records LIMIT 1",
+        );
+        if let Err(err) = check {
+            let message = err.to_string();
+            if message.contains("no such table") {
+                conn.run_pending_migrations(MIGRATIONS)
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+                diesel::connection::SimpleConnection::batch_execute(&mut conn, USAGE_UP_SQL)
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            } else {
+                return Err(ButterflyBotError::Runtime(message));
+            }
+        }
+
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
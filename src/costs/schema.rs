@@ -0,0 +1,11 @@
+diesel::table! {
+    usage_records (id) {
+        id -> Integer,
+        user_id -> Text,
+        model -> Text,
+        prompt_tokens -> BigInt,
+        completion_tokens -> BigInt,
+        cost -> Double,
+        created_at -> BigInt,
+    }
+}
@@ -4,40 +4,113 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use axum::{
     body::Body,
-    extract::{Json, State},
-    http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    routing::{get, post},
-    Router,
+    extract::{DefaultBodyLimit, Json, Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post},
+    Extension, Router,
 };
 use bytes::Bytes;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+use crate::captures::{CaptureRecord, CaptureStore};
 use crate::client::ButterflyBot;
-use crate::config::{Config, MemoryConfig, OpenAiConfig};
+use crate::config::{Config, DaemonConfig, MemoryConfig, OpenAiConfig, SchedulerConfig};
 use crate::config_store;
+use crate::cors::CorsPolicy;
 use crate::error::{ButterflyBotError, Result};
 use crate::factories::agent_factory::load_markdown_source;
+use crate::interfaces::providers::{
+    ChatEvent, ChatSummary, GenerationParams, ModelInfo, PinnedContext, SearchModels,
+    StoredMessage, ToolCall,
+};
 use crate::interfaces::scheduler::ScheduledJob;
-use crate::reminders::{resolve_reminder_db_path, ReminderStore};
-use crate::scheduler::Scheduler;
+use crate::planning::{resolve_plan_db_path, PlanStore};
+use crate::reminders::{
+    new_claim_token, resolve_dedupe_window, resolve_reminder_db_path, resolve_snooze_presets,
+    ReminderItem, ReminderStore, DEFAULT_CLAIM_LEASE_SECS,
+};
+use crate::scheduler::{
+    preview_occurrences, DueWork, DueWorkHandler, DueWorkScheduler, ScheduleRule, Scheduler,
+};
 use crate::services::agent::UiEvent;
-use crate::services::query::{OutputFormat, ProcessOptions, ProcessResult, UserInput};
-use crate::tasks::TaskStore;
+use crate::services::links::LinkService;
+use crate::services::overview::{OverviewService, UserOverview};
+use crate::services::query::{
+    ensure_message, ensure_streamable, ProcessOptions, ProcessResult, UserInput,
+};
+use crate::services::request_context::{new_request_id, with_request_id, REQUEST_ID_HEADER};
+use crate::services::snippets::{build_snippet, MemorySnippet, DEFAULT_SNIPPET_CHARS};
+use crate::services::ui_events::{UiEventHub, DEFAULT_UI_EVENT_BUFFER};
+use crate::tasks::{
+    resolve_interrupted_policy, InterruptedRunPolicy, ScheduledTask, TaskStatus, TaskStore,
+};
+use crate::todo::{resolve_todo_db_path, TodoStore};
+use crate::tool_audit::ToolAuditEntry;
 use crate::wakeup::WakeupStore;
-use tokio::sync::{broadcast, RwLock};
+use crate::webhooks::{WebhookNotifier, WebhookPayload};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tracing::Instrument;
 
 #[derive(Clone)]
 pub struct AppState {
     pub agent: Arc<RwLock<Arc<ButterflyBot>>>,
     pub reminder_store: Arc<ReminderStore>,
+    pub task_store: Arc<TaskStore>,
+    pub todo_store: Arc<TodoStore>,
+    pub plan_store: Arc<PlanStore>,
+    pub capture_store: Arc<CaptureStore>,
     pub token: String,
     pub ui_event_tx: broadcast::Sender<UiEvent>,
     pub db_path: String,
+    pub webhook: Option<Arc<WebhookNotifier>>,
+    /// Interval between SSE keep-alive comments; `None` uses [`DEFAULT_SSE_KEEP_ALIVE`].
+    pub sse_keep_alive: Option<Duration>,
+    /// Cross-origin access for browser clients; `None` adds no CORS headers.
+    pub cors: Option<Arc<CorsPolicy>>,
+    /// Request body caps; `None` uses [`BodyLimits::default`].
+    pub body_limits: Option<BodyLimits>,
+}
+
+/// Bodies larger than these get a 413. Audio uploads have their own, higher cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyLimits {
+    pub max_body_bytes: usize,
+    pub max_audio_body_bytes: usize,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_audio_body_bytes: DEFAULT_MAX_AUDIO_BODY_BYTES,
+        }
+    }
 }
 
+pub const DEFAULT_SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Written to `/process_text_stream` on every agent heartbeat so proxies keep
+/// the connection open during long tool rounds. It is a zero-width space;
+/// clients should drop it from the reply.
+pub const STREAM_HEARTBEAT: &str = "\u{200b}";
+
+/// Entries `/history` and `/audit` return when the request gives no `limit`.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+pub const DEFAULT_MAX_AUDIO_BODY_BYTES: usize = 25 * 1024 * 1024;
+
+/// Most inputs one `/embed` request may carry.
+pub const MAX_EMBED_INPUTS: usize = 256;
+const EMBED_BATCH_SIZE: usize = 32;
+
 struct BrainTickJob {
     agent: Arc<RwLock<Arc<ButterflyBot>>>,
     interval: Duration,
@@ -60,27 +133,17 @@ impl ScheduledJob for BrainTickJob {
     }
 }
 
-struct WakeupJob {
+struct HeartbeatJob {
     agent: Arc<RwLock<Arc<ButterflyBot>>>,
-    store: Arc<WakeupStore>,
     interval: Duration,
     ui_event_tx: broadcast::Sender<UiEvent>,
-    audit_log_path: Option<String>,
-    heartbeat_source: Option<String>,
-}
-
-struct ScheduledTasksJob {
-    agent: Arc<RwLock<Arc<ButterflyBot>>>,
-    store: Arc<TaskStore>,
-    interval: Duration,
-    ui_event_tx: broadcast::Sender<UiEvent>,
-    audit_log_path: Option<String>,
+    source: String,
 }
 
 #[async_trait::async_trait]
-impl ScheduledJob for ScheduledTasksJob {
+impl ScheduledJob for HeartbeatJob {
     fn name(&self) -> &str {
-        "scheduled_tasks"
+        "heartbeat"
     }
 
     fn interval(&self) -> Duration {
@@ -88,163 +151,249 @@ impl ScheduledJob for ScheduledTasksJob {
     }
 
     async fn run(&self) -> Result<()> {
-        let now = now_ts();
-        let tasks = self.store.list_due(now, 32).await?;
-        for task in tasks {
-            let agent = self.agent.read().await.clone();
-            let run_at = now_ts();
-            let next_run_at = if let Some(interval) = task.interval_minutes {
-                run_at + interval.max(1) * 60
-            } else {
-                run_at
-            };
-
-            if task.interval_minutes.is_some() {
-                let _ = self.store.mark_run(task.id, run_at, next_run_at).await;
-            } else {
-                let _ = self.store.complete_one_shot(task.id).await;
+        let source = &self.source;
+        match load_markdown_source(Some(source.as_str())).await {
+            Ok(markdown) => {
+                let agent = self.agent.read().await.clone();
+                agent.set_heartbeat_markdown(markdown).await;
+                let event = UiEvent {
+                    event_type: "wakeup".to_string(),
+                    user_id: "system".to_string(),
+                    tool: "heartbeat".to_string(),
+                    status: "ok".to_string(),
+                    payload: json!({"source": source}),
+                    timestamp: now_ts(),
+                    request_id: None,
+                };
+                let _ = self.ui_event_tx.send(event);
+            }
+            Err(err) => {
+                let event = UiEvent {
+                    event_type: "wakeup".to_string(),
+                    user_id: "system".to_string(),
+                    tool: "heartbeat".to_string(),
+                    status: "error".to_string(),
+                    payload: json!({"source": source, "error": err.to_string()}),
+                    timestamp: now_ts(),
+                    request_id: None,
+                };
+                let _ = self.ui_event_tx.send(event);
             }
-
-            let options = ProcessOptions {
-                prompt: None,
-                images: Vec::new(),
-                output_format: OutputFormat::Text,
-                image_detail: "auto".to_string(),
-                json_schema: None,
-            };
-            let input = format!("Scheduled task '{}': {}", task.name, task.prompt);
-            let result = agent
-                .process(&task.user_id, UserInput::Text(input), options)
-                .await;
-
-            let (status, payload): (String, serde_json::Value) = match result {
-                Ok(ProcessResult::Text(text)) => (
-                    "ok".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "output": text}),
-                ),
-                Ok(other) => (
-                    "ok".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "output": format!("{other:?}")}),
-                ),
-                Err(err) => (
-                    "error".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "error": err.to_string()}),
-                ),
-            };
-
-            let event = UiEvent {
-                event_type: "tasks".to_string(),
-                user_id: task.user_id.clone(),
-                tool: "tasks".to_string(),
-                status: status.clone(),
-                payload: payload.clone(),
-                timestamp: run_at,
-            };
-            let _ = self.ui_event_tx.send(event);
-            let _ = write_tasks_audit_log(
-                self.audit_log_path.as_deref(),
-                run_at,
-                &task,
-                status.as_str(),
-                payload,
-            );
         }
         Ok(())
     }
 }
 
+const CHAT_ARCHIVE_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+struct ChatArchiveJob {
+    agent: Arc<RwLock<Arc<ButterflyBot>>>,
+    ttl: Duration,
+}
+
 #[async_trait::async_trait]
-impl ScheduledJob for WakeupJob {
+impl ScheduledJob for ChatArchiveJob {
     fn name(&self) -> &str {
-        "wakeup"
+        "chat_archive"
     }
 
     fn interval(&self) -> Duration {
-        self.interval
+        CHAT_ARCHIVE_SWEEP_INTERVAL
     }
 
     async fn run(&self) -> Result<()> {
-        let now = now_ts();
-        if let Some(source) = &self.heartbeat_source {
-            match load_markdown_source(Some(source.as_str())).await {
-                Ok(markdown) => {
-                    let agent = self.agent.read().await.clone();
-                    agent.set_heartbeat_markdown(markdown).await;
-                    let event = UiEvent {
-                        event_type: "wakeup".to_string(),
-                        user_id: "system".to_string(),
-                        tool: "heartbeat".to_string(),
-                        status: "ok".to_string(),
-                        payload: json!({"source": source}),
-                        timestamp: now_ts(),
-                    };
-                    let _ = self.ui_event_tx.send(event);
-                }
-                Err(err) => {
-                    let event = UiEvent {
-                        event_type: "wakeup".to_string(),
-                        user_id: "system".to_string(),
-                        tool: "heartbeat".to_string(),
-                        status: "error".to_string(),
-                        payload: json!({"source": source, "error": err.to_string()}),
-                        timestamp: now_ts(),
-                    };
-                    let _ = self.ui_event_tx.send(event);
-                }
+        let agent = self.agent.read().await.clone();
+        let archived = agent
+            .archive_idle_chats(now_ts() - self.ttl.as_secs() as i64)
+            .await?;
+        if !archived.is_empty() {
+            tracing::info!(count = archived.len(), "archived idle chats");
+        }
+        Ok(())
+    }
+}
+
+/// Runs the due work the scheduler hands it: tasks and wakeups go through the
+/// agent, reminders go out to the user's subscribers and the webhook.
+pub struct DaemonWorkHandler {
+    agent: Arc<RwLock<Arc<ButterflyBot>>>,
+    ui_event_tx: broadcast::Sender<UiEvent>,
+    hub: Arc<UiEventHub>,
+    wakeup_audit_log_path: Option<String>,
+    tasks_audit_log_path: Option<String>,
+    webhook: Option<Arc<WebhookNotifier>>,
+}
+
+#[async_trait::async_trait]
+impl DueWorkHandler for DaemonWorkHandler {
+    async fn handle(&self, work: DueWork) -> Result<()> {
+        let (name, input, task_id) = match &work {
+            DueWork::Task(task) => (
+                task.name.clone(),
+                format!("Scheduled task '{}': {}", task.name, task.prompt),
+                task.id,
+            ),
+            DueWork::Wakeup(task) => (
+                task.name.clone(),
+                format!("Wakeup task '{}': {}", task.name, task.prompt),
+                task.id,
+            ),
+            DueWork::Reminder { user_id, item } => {
+                return self.deliver_reminder(user_id, item).await;
             }
+        };
+
+        let agent = self.agent.read().await.clone();
+        let run_at = now_ts();
+        let options = ProcessOptions::default();
+        let result = agent
+            .process(work.user_id(), UserInput::Text(input), options)
+            .await;
+
+        // A failed run is still reported and audited, then handed back to the
+        // scheduler so the item isn't recorded as run and retries after its lease.
+        let (status, payload, failure): (String, Value, Option<ButterflyBotError>) = match result {
+            Ok(ProcessResult::Text(text)) => (
+                "ok".to_string(),
+                json!({"task_id": task_id, "name": name, "output": text}),
+                None,
+            ),
+            Ok(other) => (
+                "ok".to_string(),
+                json!({"task_id": task_id, "name": name, "output": format!("{other:?}")}),
+                None,
+            ),
+            Err(err) => (
+                "error".to_string(),
+                json!({"task_id": task_id, "name": name, "error": err.to_string()}),
+                Some(err),
+            ),
+        };
+
+        let tool = match &work {
+            DueWork::Task(_) => "tasks",
+            _ => "wakeup",
+        };
+        let event = UiEvent {
+            event_type: tool.to_string(),
+            user_id: work.user_id().to_string(),
+            tool: tool.to_string(),
+            status: status.clone(),
+            payload: payload.clone(),
+            timestamp: run_at,
+            request_id: None,
+        };
+        let _ = self.ui_event_tx.send(event);
+        if let (Some(webhook), None) = (&self.webhook, &failure) {
+            webhook.dispatch(WebhookPayload {
+                kind: work.kind().to_string(),
+                id: task_id,
+                title: name,
+                user_id: work.user_id().to_string(),
+                fired_at: run_at,
+            });
         }
-        let tasks = self.store.list_due(now, 32).await?;
-        for task in tasks {
-            let agent = self.agent.read().await.clone();
-            let run_at = now_ts();
-            let next_run_at = run_at + task.interval_minutes.max(1) * 60;
-            let _ = self.store.mark_run(task.id, run_at, next_run_at).await;
-
-            let options = ProcessOptions {
-                prompt: None,
-                images: Vec::new(),
-                output_format: OutputFormat::Text,
-                image_detail: "auto".to_string(),
-                json_schema: None,
-            };
-            let input = format!("Wakeup task '{}': {}", task.name, task.prompt);
-            let result = agent
-                .process(&task.user_id, UserInput::Text(input), options)
-                .await;
-
-            let (status, payload): (String, Value) = match result {
-                Ok(ProcessResult::Text(text)) => (
-                    "ok".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "output": text}),
-                ),
-                Ok(other) => (
-                    "ok".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "output": format!("{other:?}")}),
-                ),
-                Err(err) => (
-                    "error".to_string(),
-                    json!({"task_id": task.id, "name": task.name, "error": err.to_string()}),
-                ),
-            };
+        match &work {
+            DueWork::Task(task) => {
+                let _ = write_tasks_audit_log(
+                    self.tasks_audit_log_path.as_deref(),
+                    run_at,
+                    task,
+                    status.as_str(),
+                    payload,
+                );
+            }
+            DueWork::Wakeup(task) => {
+                let _ = write_wakeup_audit_log(
+                    self.wakeup_audit_log_path.as_deref(),
+                    run_at,
+                    task,
+                    status.as_str(),
+                    payload,
+                );
+            }
+            DueWork::Reminder { .. } => {}
+        }
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DaemonWorkHandler {
+    /// `hub` must be the one the router's streams subscribe through, since it
+    /// is how the handler tells whether anyone is listening for a reminder.
+    pub fn new(
+        agent: Arc<RwLock<Arc<ButterflyBot>>>,
+        ui_event_tx: broadcast::Sender<UiEvent>,
+        hub: Arc<UiEventHub>,
+    ) -> Self {
+        Self {
+            agent,
+            ui_event_tx,
+            hub,
+            wakeup_audit_log_path: None,
+            tasks_audit_log_path: None,
+            webhook: None,
+        }
+    }
+
+    pub fn with_webhook(mut self, webhook: Option<Arc<WebhookNotifier>>) -> Self {
+        self.webhook = webhook;
+        self
+    }
 
-            let event = UiEvent {
-                event_type: "wakeup".to_string(),
-                user_id: task.user_id.clone(),
-                tool: "wakeup".to_string(),
-                status: status.clone(),
-                payload: payload.clone(),
-                timestamp: run_at,
+    pub fn with_audit_logs(mut self, wakeup: Option<String>, tasks: Option<String>) -> Self {
+        self.wakeup_audit_log_path = wakeup;
+        self.tasks_audit_log_path = tasks;
+        self
+    }
+
+    /// Publishes a due reminder on the UI event bus, where `/reminder_stream`
+    /// and `/ui_events` pick it up, and posts it to the webhook. Fails when
+    /// neither reached anyone, so the reminder stays claimed and is retried
+    /// once its lease runs out instead of being marked fired unseen.
+    async fn deliver_reminder(&self, user_id: &str, item: &ReminderItem) -> Result<()> {
+        let fired_at = now_ts();
+        let mut delivered = false;
+        if self.hub.subscriber_count(user_id) > 0 {
+            delivered = self
+                .ui_event_tx
+                .send(UiEvent {
+                    event_type: "reminder".to_string(),
+                    user_id: user_id.to_string(),
+                    tool: "reminders".to_string(),
+                    status: "fired".to_string(),
+                    payload: json!({"id": item.id, "title": item.title, "due_at": item.due_at}),
+                    timestamp: fired_at,
+                    request_id: None,
+                })
+                .is_ok();
+        }
+        if let Some(webhook) = &self.webhook {
+            let payload = WebhookPayload {
+                kind: "reminder".to_string(),
+                id: item.id,
+                title: item.title.clone(),
+                user_id: user_id.to_string(),
+                fired_at,
             };
-            let _ = self.ui_event_tx.send(event);
-            let _ = write_wakeup_audit_log(
-                self.audit_log_path.as_deref(),
-                run_at,
-                &task,
-                status.as_str(),
-                payload.clone(),
-            );
+            match webhook.send(&payload).await {
+                Ok(()) => delivered = true,
+                Err(err) => {
+                    tracing::warn!(id = item.id, error = %err, "reminder webhook delivery failed")
+                }
+            }
+        }
+        if delivered {
+            Ok(())
+        } else {
+            Err(ButterflyBotError::Runtime(format!(
+                "Reminder {} has nobody to deliver to",
+                item.id
+            )))
         }
-        Ok(())
     }
 }
 
@@ -253,225 +402,1410 @@ struct HealthResponse {
     status: String,
 }
 
+#[derive(Deserialize)]
+struct StatusQuery {
+    user_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overview: Option<UserOverview>,
+}
+
 #[derive(Deserialize)]
 struct ProcessTextRequest {
     user_id: String,
     text: String,
     prompt: Option<String>,
+    #[serde(default)]
+    max_tool_iterations: Option<usize>,
+    #[serde(default)]
+    persist: Option<bool>,
+    #[serde(default)]
+    agent: Option<String>,
+    #[serde(default)]
+    dry_run: Option<bool>,
+    #[serde(default)]
+    temperature: Option<f32>,
+    #[serde(default)]
+    top_p: Option<f32>,
+    #[serde(default)]
+    max_tokens: Option<u32>,
+}
+
+impl ProcessTextRequest {
+    fn generation(&self) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TranscribeQuery {
+    format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TranscribeResponse {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct ProcessTextResponse {
+    text: String,
+    request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct MemorySearchRequest {
+    user_id: String,
+    query: String,
+    limit: Option<usize>,
+    snippet_chars: Option<usize>,
+    embedding_model: Option<String>,
+    rerank_model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EmbedRequest {
+    inputs: Vec<String>,
+    model: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbedResponse {
+    vectors: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct MemoryReindexRequest {
+    user_id: String,
+    resume_after_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ReminderStreamQuery {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct UiEventStreamQuery {
+    user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    user_id: String,
 }
 
-#[derive(Serialize)]
-struct ProcessTextResponse {
-    text: String,
-}
+#[derive(Deserialize)]
+struct HistoryQuery {
+    user_id: String,
+    before_id: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    user_id: String,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CaptureQuery {
+    user_id: String,
+    name: String,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct PinQuery {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct ChatListQuery {
+    #[serde(default)]
+    include_archived: bool,
+}
+
+#[derive(Deserialize)]
+struct ChatArchiveRequest {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct PinRequest {
+    user_id: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ActiveAgentQuery {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct SetActiveAgentRequest {
+    user_id: String,
+    /// `null` switches back to the default agent.
+    agent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TaskListQuery {
+    user_id: String,
+    status: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CreateTaskRequest {
+    user_id: String,
+    name: String,
+    prompt: String,
+    run_at: i64,
+    interval_minutes: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct SchedulePreviewRequest {
+    rule: String,
+    #[serde(default)]
+    from: Option<i64>,
+    #[serde(default)]
+    count: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SchedulePreviewResponse {
+    occurrences: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum IngestRequest {
+    Reminder {
+        user_id: String,
+        title: String,
+        due_at: Option<i64>,
+        in_seconds: Option<i64>,
+    },
+    Todo {
+        user_id: String,
+        title: String,
+        notes: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ToolSchemaQuery {
+    agent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GeneratePlanRequest {
+    user_id: String,
+    goal: String,
+}
+
+#[derive(Deserialize)]
+struct ReminderClaimRequest {
+    user_id: Option<String>,
+    lease_secs: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ReminderAckRequest {
+    claim_token: String,
+    ids: Vec<i32>,
+    /// Also completes the acked reminders and their linked todos.
+    #[serde(default)]
+    complete: bool,
+}
+
+#[derive(Deserialize)]
+struct ReminderSnoozeRequest {
+    user_id: String,
+    preset: usize,
+}
+
+/// Task mutations only touch tasks owned by `user_id`.
+#[derive(Deserialize)]
+struct TaskOwnerQuery {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct TaskEnableRequest {
+    enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct TodoBulkRequest {
+    user_id: String,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse {
+    messages: Vec<StoredMessage>,
+}
+
+#[derive(Serialize)]
+struct AuditResponse {
+    entries: Vec<ToolAuditEntry>,
+}
+
+#[derive(Serialize)]
+struct CaptureResponse {
+    captures: Vec<CaptureRecord>,
+}
+
+#[derive(Serialize)]
+struct PinListResponse {
+    pins: Vec<PinnedContext>,
+}
+
+#[derive(Serialize)]
+struct PinResponse {
+    pin: PinnedContext,
+}
+
+#[derive(Serialize)]
+struct PinDeleteResponse {
+    deleted: bool,
+}
+
+#[derive(Serialize)]
+struct ChatListResponse {
+    chats: Vec<ChatSummary>,
+}
+
+#[derive(Serialize)]
+struct ModelsResponse {
+    models: Vec<ModelInfo>,
+}
+
+#[derive(Serialize)]
+struct ActiveAgentResponse {
+    agent: String,
+    agents: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct TaskListResponse {
+    tasks: Vec<ScheduledTask>,
+}
+
+#[derive(Serialize)]
+struct TaskDeleteResponse {
+    deleted: bool,
+}
+
+#[derive(Serialize)]
+struct TodoBulkResponse {
+    affected: usize,
+}
+
+#[derive(Serialize)]
+struct MemorySearchResponse {
+    results: Vec<String>,
+    snippets: Vec<MemorySnippet>,
+}
+
+/// Body of every error the daemon returns; `code` is stable for clients to
+/// match on while `error` is meant for people.
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+    code: String,
+}
+
+pub fn build_router(state: AppState) -> Router {
+    build_router_with_hub(state, UiEventHub::new(DEFAULT_UI_EVENT_BUFFER))
+}
+
+/// [`build_router`] fanning UI events out through `hub`, so a
+/// [`DaemonWorkHandler`] sharing it sees who is subscribed.
+pub fn build_router_with_hub(state: AppState, hub: Arc<UiEventHub>) -> Router {
+    hub.forward_from(state.ui_event_tx.subscribe());
+    let limits = state.body_limits.unwrap_or_default();
+
+    Router::new()
+        .route("/health", get(health))
+        .route("/status", get(status))
+        .route("/process_text", post(process_text))
+        .route("/process_text_stream", post(process_text_stream))
+        .route("/memory_search", post(memory_search))
+        .route("/memory/reindex", post(memory_reindex))
+        .route("/embed", post(embed))
+        .route("/models", get(list_models))
+        .route(
+            "/transcribe",
+            post(transcribe).layer(DefaultBodyLimit::max(limits.max_audio_body_bytes)),
+        )
+        .route("/reminder_stream", get(reminder_stream))
+        .route("/reminders/snooze_presets", get(reminder_snooze_presets))
+        .route("/reminders/claim", post(claim_reminders))
+        .route("/reminders/ack", post(ack_reminders))
+        .route("/reminders/:id/snooze", post(snooze_reminder))
+        .route("/ui_events", get(ui_events))
+        .route("/reload_config", post(reload_config))
+        .route("/usage", get(usage))
+        .route("/history", get(history))
+        .route("/audit", get(audit))
+        .route("/captures", get(list_captures))
+        .route("/agent/active", get(active_agent).post(set_active_agent))
+        .route("/pins", get(list_pins).post(pin_context))
+        .route("/pins/:id", delete(unpin_context))
+        .route("/chats", get(list_chats))
+        .route("/chats/archive", post(archive_chat))
+        .route("/chats/unarchive", post(unarchive_chat))
+        .route("/tasks", get(list_tasks).post(create_task))
+        .route("/tasks/:id/enable", post(set_task_enabled))
+        .route("/tasks/:id", delete(delete_task))
+        .route("/schedule/preview", post(preview_schedule))
+        .route("/todos/complete_all", post(complete_all_todos))
+        .route("/todos/clear_completed", post(clear_completed_todos))
+        .route("/webhook/ingest", post(webhook_ingest))
+        .route("/plans/generate", post(generate_plan))
+        .route("/tools/schema", get(tool_schemas))
+        .fallback(not_found)
+        .layer(DefaultBodyLimit::max(limits.max_body_bytes))
+        .layer(Extension(hub))
+        .layer(middleware::map_response(json_error_envelope))
+        .layer(middleware::from_fn_with_state(state.clone(), apply_cors))
+        .with_state(state)
+}
+
+async fn health() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+    })
+}
+
+async fn status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<StatusQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let overview = match query.user_id.as_deref() {
+        Some(user_id) => {
+            let service = OverviewService::new(
+                state.reminder_store.clone(),
+                state.todo_store.clone(),
+                state.plan_store.clone(),
+                state.task_store.clone(),
+            );
+            match service.user_overview(user_id).await {
+                Ok(overview) => Some(overview),
+                Err(err) => return error_from(err),
+            }
+        }
+        None => None,
+    };
+
+    (
+        StatusCode::OK,
+        Json(StatusResponse {
+            status: "ok".to_string(),
+            overview,
+        }),
+    )
+        .into_response()
+}
+
+async fn process_text(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ProcessTextRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let options = ProcessOptions {
+        prompt: payload.prompt.clone(),
+        max_tool_iterations: payload.max_tool_iterations,
+        persist: payload.persist.unwrap_or(true),
+        agent: payload.agent.clone(),
+        dry_run: payload.dry_run.unwrap_or(false),
+        generation: payload.generation(),
+        ..Default::default()
+    };
+
+    let request_id = request_id_from_headers(&headers);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        user_id = %payload.user_id,
+        route = "process_text",
+    );
+    let agent = state.agent.read().await.clone();
+    let response = with_request_id(
+        request_id.clone(),
+        agent.process(&payload.user_id, UserInput::Text(payload.text), options),
+    )
+    .instrument(span)
+    .await;
+
+    let response = match response {
+        Ok(ProcessResult::Text(text)) => (
+            StatusCode::OK,
+            Json(ProcessTextResponse {
+                text,
+                request_id: request_id.clone(),
+                tool_calls: None,
+            }),
+        )
+            .into_response(),
+        Ok(ProcessResult::ToolCalls(calls)) => (
+            StatusCode::OK,
+            Json(ProcessTextResponse {
+                text: String::new(),
+                request_id: request_id.clone(),
+                tool_calls: Some(calls),
+            }),
+        )
+            .into_response(),
+        Ok(other) => error_response(
+            StatusCode::BAD_REQUEST,
+            format!("Unexpected response: {other:?}"),
+        ),
+        Err(err) => error_from(err),
+    };
+    with_request_id_header(response, &request_id)
+}
+
+async fn process_text_stream(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ProcessTextRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let generation = payload.generation();
+    let ProcessTextRequest {
+        user_id,
+        text,
+        prompt,
+        max_tool_iterations,
+        persist,
+        agent: agent_name,
+        dry_run,
+        ..
+    } = payload;
+    let options = ProcessOptions {
+        prompt,
+        max_tool_iterations,
+        persist: persist.unwrap_or(true),
+        agent: agent_name,
+        dry_run: dry_run.unwrap_or(false),
+        generation,
+        ..Default::default()
+    };
+    // Checked here because once the stream starts the status is already 200.
+    if let Err(err) = ensure_streamable(&options).and_then(|()| ensure_message(&text, &[])) {
+        return error_from(err);
+    }
+    if let Some(name) = options.agent.as_deref() {
+        if !agent.agent_names().iter().any(|known| known == name) {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Agent '{name}' is not configured"),
+            );
+        }
+    }
+    let request_id = request_id_from_headers(&headers);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        user_id = %user_id,
+        route = "process_text_stream",
+    );
+
+    // The agent stream runs on its own task so the request id stays in scope for every poll;
+    // a closed channel means the client went away and the task stops early.
+    let (tx, mut rx) = mpsc::channel::<Bytes>(32);
+    tokio::spawn(
+        with_request_id(request_id.clone(), async move {
+            let mut stream = agent.process_stream(&user_id, UserInput::Text(text), options);
+            while let Some(item) = stream.next().await {
+                let chunk = match item {
+                    Ok(ChatEvent::Content { delta }) if !delta.is_empty() => Bytes::from(delta),
+                    Ok(ChatEvent::Heartbeat) => Bytes::from_static(STREAM_HEARTBEAT.as_bytes()),
+                    Ok(_) => continue,
+                    Err(err) => {
+                        let _ = tx.send(Bytes::from(format!("\n[error] {}", err))).await;
+                        break;
+                    }
+                };
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .instrument(span),
+    );
+
+    let body = Body::from_stream(async_stream::stream! {
+        while let Some(chunk) = rx.recv().await {
+            yield Ok::<Bytes, std::convert::Infallible>(chunk);
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/plain; charset=utf-8")
+        .header(REQUEST_ID_HEADER, request_id)
+        .body(body)
+        .unwrap()
+}
+
+async fn tool_schemas(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ToolSchemaQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let tools = agent.tool_schemas(query.agent.as_deref()).await;
+    (StatusCode::OK, Json(json!({ "tools": tools }))).into_response()
+}
+
+async fn generate_plan(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<GeneratePlanRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    if payload.goal.trim().is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "goal is required");
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent
+        .generate_plan(state.plan_store.clone(), &payload.user_id, &payload.goal)
+        .await
+    {
+        Ok(plan) => (StatusCode::CREATED, Json(plan)).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn webhook_ingest(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let bad_request = |error: String| error_response(StatusCode::BAD_REQUEST, error);
+    let request: IngestRequest = match serde_json::from_value(payload) {
+        Ok(request) => request,
+        Err(err) => return bad_request(format!("invalid payload: {err}")),
+    };
+
+    let created = match request {
+        IngestRequest::Reminder {
+            user_id,
+            title,
+            due_at,
+            in_seconds,
+        } => {
+            if user_id.trim().is_empty() || title.trim().is_empty() {
+                return bad_request("user_id and title are required".to_string());
+            }
+            let due_at = match (due_at, in_seconds) {
+                (Some(due_at), None) => due_at,
+                (None, Some(secs)) if secs >= 0 => state.reminder_store.now() + secs,
+                (None, Some(_)) => return bad_request("in_seconds must be >= 0".to_string()),
+                _ => {
+                    return bad_request(
+                        "exactly one of due_at or in_seconds is required".to_string(),
+                    )
+                }
+            };
+            state
+                .reminder_store
+                .create_reminder(&user_id, title.trim(), due_at)
+                .await
+                .map(|item| json!({"kind": "reminder", "item": item}))
+        }
+        IngestRequest::Todo {
+            user_id,
+            title,
+            notes,
+        } => {
+            if user_id.trim().is_empty() || title.trim().is_empty() {
+                return bad_request("user_id and title are required".to_string());
+            }
+            state
+                .todo_store
+                .create_item(&user_id, title.trim(), notes.as_deref())
+                .await
+                .map(|item| json!({"kind": "todo", "item": item}))
+        }
+    };
+
+    match created {
+        Ok(body) => (StatusCode::CREATED, Json(body)).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+/// Honors a caller-supplied `x-request-id` so ids can span client and daemon logs; anything
+/// missing or unreasonable gets a fresh id instead.
+fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| {
+            !value.is_empty() && value.len() <= 128 && value.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id)
+}
+
+fn with_request_id_header(mut response: Response, request_id: &str) -> Response {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+async fn memory_search(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MemorySearchRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let limit = payload.limit.unwrap_or(8);
+    let agent = state.agent.read().await.clone();
+    let models = SearchModels {
+        embedding_model: payload.embedding_model.clone(),
+        rerank_model: payload.rerank_model.clone(),
+    };
+    let response = agent
+        .search_memory_with_models(&payload.user_id, &payload.query, limit, &models)
+        .await;
+
+    match response {
+        Ok(results) => {
+            let snippet_chars = payload.snippet_chars.unwrap_or(DEFAULT_SNIPPET_CHARS);
+            let snippets = results
+                .iter()
+                .map(|text| build_snippet(text, &payload.query, snippet_chars))
+                .collect();
+            (
+                StatusCode::OK,
+                Json(MemorySearchResponse { results, snippets }),
+            )
+                .into_response()
+        }
+        Err(err) => error_from(err),
+    }
+}
+
+async fn list_models(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.list_models().await {
+        Ok(models) => (StatusCode::OK, Json(ModelsResponse { models })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn embed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<EmbedRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let invalid = if payload.inputs.is_empty() {
+        Some("inputs must not be empty".to_string())
+    } else if payload.inputs.len() > MAX_EMBED_INPUTS {
+        Some(format!("at most {MAX_EMBED_INPUTS} inputs per request"))
+    } else {
+        None
+    };
+    if let Some(error) = invalid {
+        return error_response(StatusCode::BAD_REQUEST, error);
+    }
+
+    let agent = state.agent.read().await.clone();
+    let mut vectors = Vec::with_capacity(payload.inputs.len());
+    for batch in payload.inputs.chunks(EMBED_BATCH_SIZE) {
+        let result = agent
+            .embed(batch.to_vec(), payload.model.as_deref())
+            .await
+            .and_then(|batch_vectors| {
+                if batch_vectors.len() == batch.len() {
+                    Ok(batch_vectors)
+                } else {
+                    Err(ButterflyBotError::Runtime(format!(
+                        "Embedding provider returned {} vectors for {} inputs",
+                        batch_vectors.len(),
+                        batch.len()
+                    )))
+                }
+            });
+        match result {
+            Ok(batch_vectors) => vectors.extend(batch_vectors),
+            Err(err) => return error_from(err),
+        }
+    }
+
+    (StatusCode::OK, Json(EmbedResponse { vectors })).into_response()
+}
+
+async fn memory_reindex(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MemoryReindexRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let response = agent
+        .reindex_memory(&payload.user_id, payload.resume_after_id)
+        .await;
+
+    match response {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn reminder_stream(
+    State(state): State<AppState>,
+    Extension(hub): Extension<Arc<UiEventHub>>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ReminderStreamQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    // Reminders are claimed and fired by the due-work scheduler; this stream
+    // only relays the ones it publishes for this user.
+    let mut receiver = hub.subscribe(&query.user_id);
+    let events = async_stream::stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.event_type == "reminder" => {
+                    yield Ok::<Event, std::convert::Infallible>(
+                        Event::default().data(event.payload.to_string()),
+                    );
+                }
+                Ok(_) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    let marker = json!({ "skipped": skipped }).to_string();
+                    yield Ok(Event::default().event("lagged").data(marker));
+                }
+                Err(_) => break,
+            }
+        }
+    };
+    let keep_alive =
+        KeepAlive::new().interval(state.sse_keep_alive.unwrap_or(DEFAULT_SSE_KEEP_ALIVE));
+
+    (
+        [(header::CACHE_CONTROL, "no-cache")],
+        Sse::new(events).keep_alive(keep_alive),
+    )
+        .into_response()
+}
+
+async fn usage(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<UsageQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.usage_summary(&query.user_id).await {
+        Ok(Some(summary)) => (StatusCode::OK, Json(summary)).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Cost tracking is not enabled"),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<HistoryQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    match agent
+        .get_messages(&query.user_id, query.before_id, limit)
+        .await
+    {
+        Ok(messages) => (StatusCode::OK, Json(HistoryResponse { messages })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<AuditQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    match agent.tool_audit(&query.user_id, limit).await {
+        Ok(entries) => (StatusCode::OK, Json(AuditResponse { entries })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn list_captures(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<CaptureQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    match state
+        .capture_store
+        .list(&query.user_id, &query.name, limit)
+        .await
+    {
+        Ok(captures) => (StatusCode::OK, Json(CaptureResponse { captures })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn list_pins(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<PinQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.pinned_context(&query.user_id).await {
+        Ok(pins) => (StatusCode::OK, Json(PinListResponse { pins })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn pin_context(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<PinRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let content = payload.content.trim();
+    if content.is_empty() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "content must not be empty".to_string(),
+        );
+    }
+    let agent = state.agent.read().await.clone();
+    match agent.pin_context(&payload.user_id, content).await {
+        Ok(pin) => (StatusCode::OK, Json(PinResponse { pin })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn unpin_context(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    axum::extract::Query(query): axum::extract::Query<PinQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.unpin_context(&query.user_id, id).await {
+        Ok(true) => (StatusCode::OK, Json(PinDeleteResponse { deleted: true })).into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, format!("Pin {id} not found")),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn list_chats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ChatListQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.list_chats(query.include_archived).await {
+        Ok(chats) => (StatusCode::OK, Json(ChatListResponse { chats })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn archive_chat(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ChatArchiveRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.archive_chat(&payload.user_id).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"archived": true}))).into_response(),
+        Ok(false) => error_response(
+            StatusCode::NOT_FOUND,
+            format!("No unarchived chat for user '{}'", payload.user_id),
+        ),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn unarchive_chat(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ChatArchiveRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.unarchive_chat(&payload.user_id).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"archived": false}))).into_response(),
+        Ok(false) => error_response(
+            StatusCode::NOT_FOUND,
+            format!("No archived chat for user '{}'", payload.user_id),
+        ),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn active_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ActiveAgentQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    match agent.active_agent(&query.user_id).await {
+        Ok(active) => (
+            StatusCode::OK,
+            Json(ActiveAgentResponse {
+                agent: active,
+                agents: agent.agent_names(),
+            }),
+        )
+            .into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn set_active_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<SetActiveAgentRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let agent = state.agent.read().await.clone();
+    let agents = agent.agent_names();
+    if let Some(name) = payload.agent.as_deref() {
+        if !agents.iter().any(|known| known == name) {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Agent '{name}' is not configured"),
+            );
+        }
+    }
+    let result = agent
+        .set_active_agent(&payload.user_id, payload.agent.as_deref())
+        .await;
+    match result {
+        Ok(()) => match agent.active_agent(&payload.user_id).await {
+            Ok(active) => (
+                StatusCode::OK,
+                Json(ActiveAgentResponse {
+                    agent: active,
+                    agents,
+                }),
+            )
+                .into_response(),
+            Err(err) => error_from(err),
+        },
+        Err(err) => error_from(err),
+    }
+}
+
+async fn list_tasks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<TaskListQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let status = TaskStatus::from_option(query.status.as_deref());
+    let limit = query.limit.unwrap_or(50);
+    match state
+        .task_store
+        .list_tasks(&query.user_id, status, limit)
+        .await
+    {
+        Ok(tasks) => (StatusCode::OK, Json(TaskListResponse { tasks })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn create_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateTaskRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    let invalid = if payload.name.trim().is_empty() || payload.prompt.trim().is_empty() {
+        Some("name and prompt are required".to_string())
+    } else {
+        state
+            .task_store
+            .validate_schedule(payload.run_at, payload.interval_minutes)
+            .err()
+            .map(|err| err.to_string())
+    };
+    if let Some(error) = invalid {
+        return error_response(StatusCode::BAD_REQUEST, error);
+    }
 
-#[derive(Deserialize)]
-struct MemorySearchRequest {
-    user_id: String,
-    query: String,
-    limit: Option<usize>,
+    match state
+        .task_store
+        .create_task(
+            &payload.user_id,
+            payload.name.trim(),
+            &payload.prompt,
+            payload.run_at,
+            payload.interval_minutes,
+        )
+        .await
+    {
+        Ok(task) => (StatusCode::CREATED, Json(task)).into_response(),
+        Err(err) => error_from(err),
+    }
 }
 
-#[derive(Deserialize)]
-struct ReminderStreamQuery {
-    user_id: String,
-}
+const DEFAULT_PREVIEW_COUNT: usize = 3;
+const MAX_PREVIEW_COUNT: usize = 50;
 
-#[derive(Deserialize)]
-struct UiEventStreamQuery {
-    user_id: Option<String>,
-}
+async fn preview_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<SchedulePreviewRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
 
-#[derive(Serialize)]
-struct MemorySearchResponse {
-    results: Vec<String>,
+    let rule = match ScheduleRule::parse(&payload.rule) {
+        Ok(rule) => rule,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    let count = payload
+        .count
+        .unwrap_or(DEFAULT_PREVIEW_COUNT)
+        .min(MAX_PREVIEW_COUNT);
+    let occurrences = preview_occurrences(&rule, payload.from.unwrap_or_else(now_ts), count);
+    (
+        StatusCode::OK,
+        Json(SchedulePreviewResponse { occurrences }),
+    )
+        .into_response()
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
-}
+async fn reminder_snooze_presets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
 
-pub fn build_router(state: AppState) -> Router {
-    Router::new()
-        .route("/health", get(health))
-        .route("/process_text", post(process_text))
-        .route("/process_text_stream", post(process_text_stream))
-        .route("/memory_search", post(memory_search))
-        .route("/reminder_stream", get(reminder_stream))
-        .route("/ui_events", get(ui_events))
-        .route("/reload_config", post(reload_config))
-        .with_state(state)
+    let presets = state.reminder_store.snooze_presets();
+    (StatusCode::OK, Json(json!({ "presets": presets }))).into_response()
 }
 
-async fn health() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok".to_string(),
-    })
-}
+const DEFAULT_REMINDER_CLAIM_LIMIT: usize = 10;
+const MAX_REMINDER_CLAIM_LIMIT: usize = 100;
 
-async fn process_text(
+/// Leases due reminders to an external worker, which delivers them and then
+/// calls `/reminders/ack` with the returned `claim_token`. Unacked reminders
+/// are handed out again once the lease runs out.
+async fn claim_reminders(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(payload): Json<ProcessTextRequest>,
+    Json(payload): Json<ReminderClaimRequest>,
 ) -> impl IntoResponse {
     if let Err(err) = authorize(&headers, &state.token) {
         return err.into_response();
     }
 
-    let options = ProcessOptions {
-        prompt: payload.prompt.clone(),
-        images: Vec::new(),
-        output_format: OutputFormat::Text,
-        image_detail: "auto".to_string(),
-        json_schema: None,
+    let lease_secs = payload.lease_secs.unwrap_or(DEFAULT_CLAIM_LEASE_SECS);
+    if lease_secs <= 0 {
+        return error_response(StatusCode::BAD_REQUEST, "lease_secs must be > 0");
+    }
+    let limit = payload
+        .limit
+        .unwrap_or(DEFAULT_REMINDER_CLAIM_LIMIT)
+        .clamp(1, MAX_REMINDER_CLAIM_LIMIT);
+    let now = state.reminder_store.now();
+    let claim_token = match new_claim_token() {
+        Ok(token) => token,
+        Err(err) => return error_from(err),
     };
-
-    let agent = state.agent.read().await.clone();
-    let response = agent
-        .process(&payload.user_id, UserInput::Text(payload.text), options)
-        .await;
-
-    match response {
-        Ok(ProcessResult::Text(text)) => {
-            (StatusCode::OK, Json(ProcessTextResponse { text })).into_response()
-        }
-        Ok(other) => (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: format!("Unexpected response: {other:?}"),
-            }),
-        )
-            .into_response(),
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: err.to_string(),
-            }),
+    match state
+        .reminder_store
+        .claim_reminders_as(
+            &claim_token,
+            payload.user_id.as_deref(),
+            now,
+            lease_secs,
+            limit,
         )
-            .into_response(),
+        .await
+    {
+        Ok(claimed) => {
+            let reminders: Vec<Value> = claimed
+                .into_iter()
+                .map(|(user_id, item)| {
+                    json!({
+                        "id": item.id,
+                        "user_id": user_id,
+                        "title": item.title,
+                        "due_at": item.due_at,
+                    })
+                })
+                .collect();
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "reminders": reminders,
+                    "claim_token": claim_token,
+                    "lease_expires_at": now + lease_secs,
+                })),
+            )
+                .into_response()
+        }
+        Err(err) => error_from(err),
     }
 }
 
-async fn process_text_stream(
+async fn ack_reminders(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(payload): Json<ProcessTextRequest>,
+    Json(payload): Json<ReminderAckRequest>,
 ) -> impl IntoResponse {
     if let Err(err) = authorize(&headers, &state.token) {
         return err.into_response();
     }
 
-    let agent = state.agent.read().await.clone();
-    let ProcessTextRequest {
-        user_id,
-        text,
-        prompt,
-    } = payload;
-
-    let body = Body::from_stream(async_stream::stream! {
-        let mut stream = agent.process_text_stream(&user_id, &text, prompt.as_deref());
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(chunk) => {
-                    if !chunk.is_empty() {
-                        yield Ok::<Bytes, std::convert::Infallible>(Bytes::from(chunk));
-                    }
-                }
-                Err(err) => {
-                    let message = format!("\n[error] {}", err);
-                    yield Ok(Bytes::from(message));
-                    break;
+    let now = state.reminder_store.now();
+    match state
+        .reminder_store
+        .ack_reminders(&payload.claim_token, &payload.ids, now)
+        .await
+    {
+        Ok(acked) => {
+            if payload.complete {
+                let links =
+                    LinkService::new(state.reminder_store.clone(), state.todo_store.clone());
+                if let Err(err) = links.complete_reminders(&acked).await {
+                    return error_from(err);
                 }
             }
+            (StatusCode::OK, Json(json!({ "acked": acked }))).into_response()
         }
-    });
+        Err(err) => error_from(err),
+    }
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "text/plain; charset=utf-8")
-        .body(body)
-        .unwrap()
+async fn snooze_reminder(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Json(payload): Json<ReminderSnoozeRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    match state
+        .reminder_store
+        .snooze_by(&payload.user_id, id, payload.preset)
+        .await
+    {
+        Ok(true) => (StatusCode::OK, Json(json!({ "snoozed": true }))).into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, format!("Reminder {id} not found")),
+        Err(err) => error_from(err),
+    }
 }
 
-async fn memory_search(
+async fn set_task_enabled(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(payload): Json<MemorySearchRequest>,
+    Path(id): Path<i32>,
+    axum::extract::Query(query): axum::extract::Query<TaskOwnerQuery>,
+    payload: Option<Json<TaskEnableRequest>>,
 ) -> impl IntoResponse {
     if let Err(err) = authorize(&headers, &state.token) {
         return err.into_response();
     }
 
-    let limit = payload.limit.unwrap_or(8);
-    let agent = state.agent.read().await.clone();
-    let response = agent
-        .search_memory(&payload.user_id, &payload.query, limit)
-        .await;
+    let enabled = payload
+        .and_then(|Json(payload)| payload.enabled)
+        .unwrap_or(true);
+    match state
+        .task_store
+        .set_enabled(&query.user_id, id, enabled)
+        .await
+    {
+        Ok(Some(task)) => (StatusCode::OK, Json(task)).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Task {id} not found")),
+        Err(err) => error_from(err),
+    }
+}
 
-    match response {
-        Ok(results) => (StatusCode::OK, Json(MemorySearchResponse { results })).into_response(),
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: err.to_string(),
-            }),
-        )
-            .into_response(),
+async fn delete_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    axum::extract::Query(query): axum::extract::Query<TaskOwnerQuery>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+
+    match state.task_store.delete_task(&query.user_id, id).await {
+        Ok(true) => (StatusCode::OK, Json(TaskDeleteResponse { deleted: true })).into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, format!("Task {id} not found")),
+        Err(err) => error_from(err),
     }
 }
 
-async fn reminder_stream(
+async fn complete_all_todos(
     State(state): State<AppState>,
     headers: HeaderMap,
-    axum::extract::Query(query): axum::extract::Query<ReminderStreamQuery>,
+    Json(payload): Json<TodoBulkRequest>,
 ) -> impl IntoResponse {
     if let Err(err) = authorize(&headers, &state.token) {
         return err.into_response();
     }
 
-    let store = state.reminder_store.clone();
-    let user_id = query.user_id;
-    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let links = LinkService::new(state.reminder_store.clone(), state.todo_store.clone());
+    match links.complete_all_todos(&payload.user_id).await {
+        Ok(affected) => (StatusCode::OK, Json(TodoBulkResponse { affected })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
 
-    let body = Body::from_stream(async_stream::stream! {
-        loop {
-            tick.tick().await;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs() as i64;
-            if let Ok(items) = store.due_reminders(&user_id, now, 10).await {
-                if (std::env::var("BUTTERFLY_BOT_REMINDER_DEBUG").is_ok()
-                    || cfg!(debug_assertions))
-                    && !items.is_empty()
-                {
-                    eprintln!(
-                        "Reminder stream emit: user_id={} count={} now={}",
-                        user_id,
-                        items.len(),
-                        now
-                    );
-                }
-                for item in items {
-                    let payload = serde_json::json!({
-                        "id": item.id,
-                        "title": item.title,
-                        "due_at": item.due_at,
-                    });
-                    let line = format!("data: {}\n\n", payload);
-                    yield Ok::<Bytes, std::convert::Infallible>(Bytes::from(line));
-                }
-            }
-        }
-    });
+async fn clear_completed_todos(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<TodoBulkRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "text/event-stream")
-        .header("cache-control", "no-cache")
-        .body(body)
-        .unwrap()
+    match state.todo_store.clear_completed(&payload.user_id).await {
+        Ok(affected) => (StatusCode::OK, Json(TodoBulkResponse { affected })).into_response(),
+        Err(err) => error_from(err),
+    }
+}
+
+async fn transcribe(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<TranscribeQuery>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Err(err) = authorize(&headers, &state.token) {
+        return err.into_response();
+    }
+    if body.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "audio body is empty".to_string());
+    }
+
+    let format = query.format.unwrap_or_else(|| "wav".to_string());
+    let agent = state.agent.read().await.clone();
+    match agent.transcribe(body.to_vec(), &format).await {
+        Ok(text) => (StatusCode::OK, Json(TranscribeResponse { text })).into_response(),
+        Err(err) => error_from(err),
+    }
 }
 
 async fn reload_config(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
@@ -491,18 +1825,13 @@ async fn reload_config(State(state): State<AppState>, headers: HeaderMap) -> imp
             )
                 .into_response()
         }
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: err.to_string(),
-            }),
-        )
-            .into_response(),
+        Err(err) => error_from(err),
     }
 }
 
 async fn ui_events(
     State(state): State<AppState>,
+    Extension(hub): Extension<Arc<UiEventHub>>,
     headers: HeaderMap,
     axum::extract::Query(query): axum::extract::Query<UiEventStreamQuery>,
 ) -> impl IntoResponse {
@@ -510,36 +1839,166 @@ async fn ui_events(
         return err.into_response();
     }
 
-    let mut receiver = state.ui_event_tx.subscribe();
-    let filter_user = query.user_id;
+    // A per-user subscription gets its own bounded channel, so a slow client
+    // only ever drops its own backlog; the unfiltered feed reads the shared one.
+    let mut receiver = match &query.user_id {
+        Some(user_id) => hub.subscribe(user_id),
+        None => state.ui_event_tx.subscribe(),
+    };
 
-    let body = Body::from_stream(async_stream::stream! {
+    // `Sse` writes every event as its own frame, so each is flushed as soon as
+    // it is produced; the keep-alive comments cover quiet stretches.
+    let events = async_stream::stream! {
         loop {
             match receiver.recv().await {
                 Ok(event) => {
-                    if let Some(filter) = &filter_user {
-                        if event.user_id != *filter {
-                            continue;
-                        }
-                    }
                     let payload = serde_json::to_string(&event).unwrap_or_default();
-                    let line = format!("data: {}\n\n", payload);
-                    yield Ok::<Bytes, std::convert::Infallible>(Bytes::from(line));
+                    yield Ok::<Event, std::convert::Infallible>(Event::default().data(payload));
                 }
-                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
-                    continue;
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    let marker = json!({ "skipped": skipped }).to_string();
+                    yield Ok(Event::default().event("lagged").data(marker));
                 }
                 Err(_) => break,
             }
         }
-    });
+    };
+    let keep_alive =
+        KeepAlive::new().interval(state.sse_keep_alive.unwrap_or(DEFAULT_SSE_KEEP_ALIVE));
+
+    (
+        [(header::CACHE_CONTROL, "no-cache")],
+        Sse::new(events).keep_alive(keep_alive),
+    )
+        .into_response()
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "text/event-stream")
-        .header("cache-control", "no-cache")
-        .body(body)
-        .unwrap()
+fn error_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "invalid_body",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        status if status.is_client_error() => "bad_request",
+        _ => "internal_error",
+    }
+}
+
+fn error_body(status: StatusCode, error: impl Into<String>) -> (StatusCode, Json<ErrorResponse>) {
+    let body = ErrorResponse {
+        error: error.into(),
+        code: error_code(status).to_string(),
+    };
+    (status, Json(body))
+}
+
+fn error_response(status: StatusCode, error: impl Into<String>) -> Response {
+    error_body(status, error).into_response()
+}
+
+fn error_from(err: ButterflyBotError) -> Response {
+    match err {
+        ButterflyBotError::BudgetExceeded(_) => {
+            let body = ErrorResponse {
+                error: err.to_string(),
+                code: "budget_exceeded".to_string(),
+            };
+            (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response()
+        }
+        ButterflyBotError::RateLimited(_) => {
+            let body = ErrorResponse {
+                error: err.to_string(),
+                code: "rate_limited".to_string(),
+            };
+            (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response()
+        }
+        ButterflyBotError::InvalidInput(_) => {
+            error_response(StatusCode::BAD_REQUEST, err.to_string())
+        }
+        ButterflyBotError::NotFound(_) => error_response(StatusCode::NOT_FOUND, err.to_string()),
+        _ => error_response(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+/// Answers preflights itself and tags every other response for an allowed
+/// origin. Runs outside the error envelope so rejected requests are tagged too.
+/// Without a token anyone can call the daemon, so browser requests are refused
+/// outright rather than letting any page the user opens drive it.
+async fn apply_cors(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    if origin.is_some() && state.token.is_empty() {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Cross-origin requests need a daemon token",
+        );
+    }
+    let (Some(policy), Some(origin)) = (state.cors.clone(), origin) else {
+        return next.run(request).await;
+    };
+
+    let preflight = request
+        .headers()
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|value| Method::from_bytes(value.as_bytes()).ok())
+        .filter(|_| request.method() == Method::OPTIONS);
+    if let Some(method) = preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        response
+            .headers_mut()
+            .extend(policy.preflight_headers(&origin, &method));
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .extend(policy.response_headers(&origin));
+    response
+}
+
+async fn not_found() -> Response {
+    error_response(StatusCode::NOT_FOUND, "Not found")
+}
+
+/// Errors axum produces on its own (body rejections, wrong method) are plain
+/// text; rewrap them so every failure carries the same envelope.
+async fn json_error_envelope(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if is_json || !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, 64 * 1024)
+        .await
+        .unwrap_or_default();
+    let message = String::from_utf8_lossy(&body).trim().to_string();
+    let message = if message.is_empty() {
+        status.canonical_reason().unwrap_or("Error").to_string()
+    } else {
+        message
+    };
+
+    let mut rewrapped = error_response(status, message);
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rewrapped.headers_mut().insert(name, value.clone());
+        }
+    }
+    rewrapped
 }
 
 fn authorize(
@@ -559,12 +2018,7 @@ fn authorize(
     if bearer == token || api_key == token {
         Ok(())
     } else {
-        Err((
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Unauthorized".to_string(),
-            }),
-        ))
+        Err(error_body(StatusCode::UNAUTHORIZED, "Unauthorized"))
     }
 }
 
@@ -578,12 +2032,21 @@ fn default_config(db_path: &str) -> Config {
     let memory = Some(MemoryConfig {
         enabled: Some(true),
         sqlite_path: Some(db_path.to_string()),
-        lancedb_path: Some("./data/lancedb".to_string()),
+        lancedb_path: Some(crate::paths::default_lancedb_path()),
         summary_model: Some(model.clone()),
         embedding_model: Some("embeddinggemma:latest".to_string()),
         rerank_model: Some("qllama/bge-reranker-v2-m3".to_string()),
         summary_threshold: None,
+        summary_style: None,
         retention_days: None,
+        chat_ttl_days: None,
+        namespace: None,
+        min_score: None,
+        search_limit: None,
+        context_top_k: None,
+        history_window: None,
+        embedding_limit: None,
+        vector_index: None,
     });
 
     Config {
@@ -591,12 +2054,18 @@ fn default_config(db_path: &str) -> Config {
             api_key: None,
             model: Some(model),
             base_url: Some(base_url),
+            headers: None,
+            json_mode: None,
         }),
         skill_file: Some("./skill.md".to_string()),
         heartbeat_file: Some("./heartbeat.md".to_string()),
         memory,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     }
 }
 
@@ -628,13 +2097,51 @@ where
     let agent = Arc::new(RwLock::new(Arc::new(
         ButterflyBot::from_store_with_events(db_path, Some(ui_event_tx.clone())).await?,
     )));
-    let reminder_db_path = config
+    let config_value = config
         .as_ref()
-        .and_then(|cfg| serde_json::to_value(cfg).ok())
-        .and_then(|value| resolve_reminder_db_path(&value))
+        .and_then(|cfg| serde_json::to_value(cfg).ok());
+    let reminder_db_path = config_value
+        .as_ref()
+        .and_then(resolve_reminder_db_path)
+        .unwrap_or_else(|| db_path.to_string());
+    let snooze_presets = config_value
+        .as_ref()
+        .map(resolve_snooze_presets)
+        .unwrap_or_default();
+    let reminder_store = Arc::new(
+        ReminderStore::new(reminder_db_path)
+            .await?
+            .with_snooze_presets(snooze_presets)
+            .with_dedupe_window(config_value.as_ref().and_then(resolve_dedupe_window)),
+    );
+    let todo_db_path = config_value
+        .as_ref()
+        .and_then(resolve_todo_db_path)
+        .unwrap_or_else(|| db_path.to_string());
+    let todo_store = Arc::new(TodoStore::new(todo_db_path).await?);
+    let plan_db_path = config_value
+        .as_ref()
+        .and_then(resolve_plan_db_path)
         .unwrap_or_else(|| db_path.to_string());
-    let reminder_store = Arc::new(ReminderStore::new(reminder_db_path).await?);
+    let plan_store = Arc::new(PlanStore::new(plan_db_path).await?);
+    let webhook = config_value
+        .as_ref()
+        .and_then(WebhookNotifier::from_config)
+        .map(Arc::new);
     let task_store = Arc::new(TaskStore::new(db_path).await?);
+    let interrupted_policy = config_value
+        .as_ref()
+        .map(resolve_interrupted_policy)
+        .unwrap_or(InterruptedRunPolicy::Rerun);
+    let interrupted = task_store.reconcile_interrupted(interrupted_policy).await?;
+    if !interrupted.is_empty() {
+        tracing::warn!(
+            count = interrupted.len(),
+            policy = ?interrupted_policy,
+            "scheduled tasks were interrupted by a restart"
+        );
+    }
+    let capture_store = Arc::new(CaptureStore::new(db_path).await?);
     let wakeup_store = Arc::new(WakeupStore::new(db_path).await?);
     let mut scheduler = Scheduler::new();
     scheduler.register_job(Arc::new(BrainTickJob {
@@ -648,14 +2155,24 @@ where
         .and_then(|wakeup| wakeup.get("poll_seconds"))
         .and_then(|value| value.as_u64())
         .unwrap_or(60);
-    scheduler.register_job(Arc::new(WakeupJob {
-        agent: agent.clone(),
-        store: wakeup_store.clone(),
-        interval: Duration::from_secs(wakeup_poll_seconds.max(1)),
-        ui_event_tx: ui_event_tx.clone(),
-        audit_log_path: wakeup_audit_log_path(config.as_ref()),
-        heartbeat_source: config.as_ref().and_then(|cfg| cfg.heartbeat_file.clone()),
-    }));
+    if let Some(days) = config
+        .as_ref()
+        .and_then(|cfg| cfg.memory.as_ref())
+        .and_then(|memory| memory.chat_ttl_days)
+    {
+        scheduler.register_job(Arc::new(ChatArchiveJob {
+            agent: agent.clone(),
+            ttl: Duration::from_secs(u64::from(days) * 24 * 60 * 60),
+        }));
+    }
+    if let Some(source) = config.as_ref().and_then(|cfg| cfg.heartbeat_file.clone()) {
+        scheduler.register_job(Arc::new(HeartbeatJob {
+            agent: agent.clone(),
+            interval: Duration::from_secs(wakeup_poll_seconds.max(1)),
+            ui_event_tx: ui_event_tx.clone(),
+            source,
+        }));
+    }
     let tasks_poll_seconds = config
         .as_ref()
         .and_then(|cfg| cfg.tools.as_ref())
@@ -663,23 +2180,59 @@ where
         .and_then(|tasks| tasks.get("poll_seconds"))
         .and_then(|value| value.as_u64())
         .unwrap_or(60);
-    scheduler.register_job(Arc::new(ScheduledTasksJob {
-        agent: agent.clone(),
-        store: task_store.clone(),
-        interval: Duration::from_secs(tasks_poll_seconds.max(1)),
-        ui_event_tx: ui_event_tx.clone(),
-        audit_log_path: tasks_audit_log_path(config.as_ref()),
-    }));
+    let hub = UiEventHub::new(DEFAULT_UI_EVENT_BUFFER);
+    let handler = Arc::new(
+        DaemonWorkHandler::new(agent.clone(), ui_event_tx.clone(), hub.clone())
+            .with_webhook(webhook.clone())
+            .with_audit_logs(
+                wakeup_audit_log_path(config.as_ref()),
+                tasks_audit_log_path(config.as_ref()),
+            ),
+    );
+    let tick = config
+        .as_ref()
+        .and_then(|cfg| cfg.scheduler.as_ref())
+        .and_then(SchedulerConfig::tick)
+        .unwrap_or_else(|| Duration::from_secs(wakeup_poll_seconds.min(tasks_poll_seconds).max(1)));
+    let due_work = DueWorkScheduler::new(handler, tick)
+        .with_tasks(task_store.clone())
+        .with_wakeups(wakeup_store);
+    // With delivery turned off, `/reminders/claim` is the only way reminders
+    // go out, so an external worker owns them.
+    let delivers_reminders = config
+        .as_ref()
+        .and_then(|cfg| cfg.scheduler.as_ref())
+        .map(SchedulerConfig::delivers_reminders)
+        .unwrap_or(true);
+    let due_work = if delivers_reminders {
+        due_work.with_reminders(reminder_store.clone())
+    } else {
+        due_work
+    };
+    scheduler.register_job(Arc::new(due_work));
     scheduler.start();
 
     let state = AppState {
         agent,
         reminder_store,
+        task_store,
+        todo_store,
+        plan_store,
+        capture_store,
         token: token.to_string(),
         ui_event_tx,
         db_path: db_path.to_string(),
+        webhook,
+        sse_keep_alive: config
+            .as_ref()
+            .and_then(|cfg| cfg.daemon.as_ref())
+            .and_then(DaemonConfig::sse_keep_alive),
+        cors: Some(Arc::new(CorsPolicy::from_config(
+            config.as_ref().and_then(|cfg| cfg.daemon.as_ref()),
+        ))),
+        body_limits: Some(body_limits(config.as_ref())),
     };
-    let app = build_router(state);
+    let app = build_router_with_hub(state, hub);
 
     let addr = format!("{host}:{port}");
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -705,6 +2258,20 @@ fn now_ts() -> i64 {
         .as_secs() as i64
 }
 
+/// `daemon.max_body_bytes` / `daemon.max_audio_body_bytes`, else the defaults.
+fn body_limits(config: Option<&Config>) -> BodyLimits {
+    let daemon = config.and_then(|cfg| cfg.daemon.as_ref());
+    let defaults = BodyLimits::default();
+    BodyLimits {
+        max_body_bytes: daemon
+            .and_then(|daemon| daemon.max_body_bytes)
+            .unwrap_or(defaults.max_body_bytes),
+        max_audio_body_bytes: daemon
+            .and_then(|daemon| daemon.max_audio_body_bytes)
+            .unwrap_or(defaults.max_audio_body_bytes),
+    }
+}
+
 fn wakeup_audit_log_path(config: Option<&Config>) -> Option<String> {
     let path = config
         .and_then(|cfg| cfg.tools.as_ref())
@@ -713,7 +2280,7 @@ fn wakeup_audit_log_path(config: Option<&Config>) -> Option<String> {
         .and_then(|value| value.as_str())
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty())
-        .or_else(|| Some("./data/wakeup_audit.log".to_string()));
+        .or_else(|| Some(crate::paths::data_path("wakeup_audit.log")));
     path
 }
 
@@ -757,7 +2324,7 @@ fn tasks_audit_log_path(config: Option<&Config>) -> Option<String> {
         .and_then(|value| value.as_str())
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty())
-        .or_else(|| Some("./data/tasks_audit.log".to_string()));
+        .or_else(|| Some(crate::paths::data_path("tasks_audit.log")));
     path
 }
 
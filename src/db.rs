@@ -1,13 +1,33 @@
 use std::env;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use diesel::sql_types::Text;
+use base64::{engine::general_purpose, Engine as _};
 use diesel::sqlite::SqliteConnection;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 use crate::error::{ButterflyBotError, Result};
 
 const DB_KEY_NAME: &str = "db_encryption_key";
+const DB_PASSPHRASE_NAME: &str = "db_master_passphrase";
+const DB_SALT_NAME: &str = "db_key_salt";
+const DB_KEY_INFO: &[u8] = b"butterfly-bot sqlcipher key v1";
 
+/// Tries a store write gets while SQLite reports the database busy.
+pub const BUSY_RETRY_ATTEMPTS: u32 = 5;
+/// Wait before the first retry; it doubles on each later one.
+pub const BUSY_RETRY_BACKOFF: Duration = Duration::from_millis(25);
+
+/// The key resolved from the vault, kept for the life of the process so
+/// pooled connections don't each hit the keychain and rerun the KDF.
+static VAULT_KEY: Mutex<Option<String>> = Mutex::new(None);
+
+/// The SQLCipher key, in order: `BUTTERFLY_BOT_DB_KEY`, a key derived from
+/// the vault's master passphrase (see [`setup_db_passphrase`]), then a raw
+/// key stored with `db-key-set`.
 pub fn get_sqlcipher_key() -> Result<Option<String>> {
     if let Ok(value) = env::var("BUTTERFLY_BOT_DB_KEY") {
         let trimmed = value.trim();
@@ -15,18 +35,75 @@ pub fn get_sqlcipher_key() -> Result<Option<String>> {
             return Ok(Some(trimmed.to_string()));
         }
     }
+    let mut cached = VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = cached.as_ref() {
+        return Ok(Some(key.clone()));
+    }
+    let key = vault_sqlcipher_key()?;
+    cached.clone_from(&key);
+    Ok(key)
+}
+
+fn vault_sqlcipher_key() -> Result<Option<String>> {
+    if let Some(passphrase) = crate::vault::get_secret(DB_PASSPHRASE_NAME)? {
+        let salt = crate::vault::get_secret(DB_SALT_NAME)?.ok_or_else(|| {
+            ButterflyBotError::Config("Database key salt is missing from the vault".to_string())
+        })?;
+        let salt = general_purpose::STANDARD
+            .decode(salt.trim())
+            .map_err(|e| ButterflyBotError::Config(e.to_string()))?;
+        return derive_sqlcipher_key(&passphrase, &salt).map(Some);
+    }
     crate::vault::get_secret(DB_KEY_NAME)
 }
 
+/// Stores `passphrase` and a fresh random salt in the vault, so later
+/// connections use a derived key. Refuses to run twice: a new passphrase
+/// would lock out databases created with the old one.
+pub fn setup_db_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.trim().is_empty() {
+        return Err(ButterflyBotError::Config(
+            "Database passphrase cannot be empty".to_string(),
+        ));
+    }
+    if crate::vault::get_secret(DB_PASSPHRASE_NAME)?.is_some() {
+        return Err(ButterflyBotError::Config(
+            "A database passphrase is already set up".to_string(),
+        ));
+    }
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    crate::vault::set_secret(DB_SALT_NAME, &general_purpose::STANDARD.encode(salt))?;
+    crate::vault::set_secret(DB_PASSPHRASE_NAME, passphrase)?;
+    // The derived key now takes precedence over any raw key resolved before.
+    VAULT_KEY.lock().unwrap_or_else(|e| e.into_inner()).take();
+    Ok(())
+}
+
+/// HKDF-SHA256 of `passphrase` and `salt`, as a SQLCipher raw key (`x'<hex>'`)
+/// so SQLCipher uses it as-is instead of running its own KDF over it.
+pub fn derive_sqlcipher_key(passphrase: &str, salt: &[u8]) -> Result<String> {
+    let hmac = |key: &[u8], parts: &[&[u8]]| -> Result<Vec<u8>> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        for part in parts {
+            mac.update(part);
+        }
+        Ok(mac.finalize().into_bytes().to_vec())
+    };
+    // One expand block is exactly the 32 bytes SQLCipher wants.
+    let prk = hmac(salt, &[passphrase.as_bytes()])?;
+    let okm = hmac(&prk, &[DB_KEY_INFO, &[1]])?;
+    let hex: String = okm.iter().map(|byte| format!("{byte:02x}")).collect();
+    Ok(format!("x'{hex}'"))
+}
+
 pub fn apply_sqlcipher_key_sync(conn: &mut SqliteConnection) -> Result<()> {
     let Some(key) = get_sqlcipher_key()? else {
         return Ok(());
     };
-    diesel::RunQueryDsl::execute(
-        diesel::sql_query("PRAGMA key = ?1").bind::<Text, _>(key),
-        conn,
-    )
-    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    diesel::RunQueryDsl::execute(diesel::sql_query(key_pragma(&key)), conn)
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
     Ok(())
 }
 
@@ -36,11 +113,51 @@ pub async fn apply_sqlcipher_key_async(
     let Some(key) = get_sqlcipher_key()? else {
         return Ok(());
     };
-    diesel_async::RunQueryDsl::execute(
-        diesel::sql_query("PRAGMA key = ?1").bind::<Text, _>(key),
-        conn,
-    )
-    .await
-    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    diesel_async::RunQueryDsl::execute(diesel::sql_query(key_pragma(&key)), conn)
+        .await
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
     Ok(())
 }
+
+// PRAGMA arguments can't be bound parameters, so the key is quoted inline.
+fn key_pragma(key: &str) -> String {
+    format!("PRAGMA key = '{}'", key.replace('\'', "''"))
+}
+
+/// Runs `op` again while it fails because another connection holds the
+/// database lock. Once the attempts run out the error becomes
+/// [`ButterflyBotError::Database`]; any other error is returned at once.
+pub async fn retry_on_busy<T, F, Fut>(mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = BUSY_RETRY_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(err) if is_busy(&err) => {
+                if attempt >= BUSY_RETRY_ATTEMPTS {
+                    return Err(ButterflyBotError::Database(format!(
+                        "still locked after {attempt} attempts: {err}"
+                    )));
+                }
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Stores flatten diesel errors into `Runtime`, so SQLITE_BUSY and
+// SQLITE_LOCKED are recognised by their messages.
+fn is_busy(err: &ButterflyBotError) -> bool {
+    match err {
+        ButterflyBotError::Runtime(message) => {
+            message.contains("database is locked") || message.contains("database table is locked")
+        }
+        _ => false,
+    }
+}
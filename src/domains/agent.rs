@@ -3,4 +3,5 @@ pub struct AIAgent {
     pub name: String,
     pub instructions: String,
     pub specialization: String,
+    pub memory_namespace: Option<String>,
 }
@@ -10,6 +10,24 @@ pub enum ButterflyBotError {
     Serialization(String),
     #[error("runtime error: {0}")]
     Runtime(String),
+    #[error("budget exceeded: {0}")]
+    BudgetExceeded(String),
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("database error: {0}")]
+    Database(String),
+}
+
+// Matches the `map_err(|e| Runtime(e.to_string()))` used at call sites, so
+// `retry_on_busy` recognises lock errors raised inside transactions too.
+impl From<diesel::result::Error> for ButterflyBotError {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::Runtime(err.to_string())
+    }
 }
 
 pub use crate::Result;
@@ -1,5 +1,7 @@
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::active_agents::ActiveAgentStore;
 use crate::brain::manager::BrainManager;
 use crate::brain::plugins::abstraction_extraction::AbstractionExtractionBrain;
 use crate::brain::plugins::age_detection::AgeDetectionBrain;
@@ -62,18 +64,27 @@ use crate::brain::plugins::trust_boundaries::TrustBoundariesBrain;
 use crate::brain::plugins::trust_transparency::TrustTransparencyBrain;
 use crate::brain::plugins::zep_context_enricher::ZepContextEnricherBrain;
 use crate::brain::plugins::zero_cost_reasoning::ZeroCostReasoningBrain;
-use crate::config::Config;
+use crate::config::{AgentConfig, Config};
+use crate::costs::{default_usage_db_path, CostTracker};
 use crate::domains::agent::AIAgent;
 use crate::error::{ButterflyBotError, Result};
+use crate::guardrails::secrets::SecretRedactor;
+use crate::guardrails::tool_calls::BlockedPatternGuardrail;
 use crate::interfaces::plugins::Tool;
+use crate::providers::caching::{CachingProvider, DEFAULT_EMBEDDING_CACHE_SIZE};
 use crate::providers::memory::InMemoryMemoryProvider;
-use crate::providers::openai::OpenAiProvider;
+use crate::providers::openai::{GenerationParams, OpenAiProvider};
 use crate::providers::sqlite::{SqliteMemoryProvider, SqliteMemoryProviderConfig};
-use crate::reminders::{default_reminder_db_path, resolve_reminder_db_path, ReminderStore};
+use crate::reminders::{
+    default_reminder_db_path, resolve_dedupe_window, resolve_reminder_db_path, ReminderStore,
+};
 use crate::services::agent::{AgentService, UiEvent};
-use crate::services::query::QueryService;
+use crate::services::query::{QueryService, DEFAULT_MEMORY_SEARCH_LIMIT};
+use crate::todo::{default_todo_db_path, resolve_todo_db_path};
+use crate::tool_audit::ToolAuditStore;
 use crate::tools::http_call::HttpCallTool;
 use crate::tools::coding::CodingTool;
+use crate::tools::delegate::{DelegateTool, DELEGATE_TOOL_NAME};
 use crate::tools::github::GitHubTool;
 use crate::tools::mcp::McpTool;
 use crate::tools::planning::PlanningTool;
@@ -97,9 +108,13 @@ impl ButterflyBotFactory {
         ui_event_tx: Option<broadcast::Sender<UiEvent>>,
     ) -> Result<QueryService> {
         let memory_config = config.memory.clone();
+        // The first configured agent (if any) names and specializes the bot.
+        let agent_config = config.agents().first().cloned();
+        let all_agents = config.agents().to_vec();
         let config_value =
             serde_json::to_value(&config).map_err(|e| ButterflyBotError::Config(e.to_string()))?;
-        let (api_key, model, base_url) = if let Some(openai) = config.openai {
+        let (api_key, model, base_url, headers, json_mode) = if let Some(openai) = config.openai {
+            let headers = openai.resolved_headers()?;
             let api_key = openai
                 .api_key
                 .filter(|key| !key.trim().is_empty())
@@ -111,31 +126,87 @@ impl ButterflyBotFactory {
                     }
                 })
                 .ok_or_else(|| ButterflyBotError::Config("Missing OpenAI API key".to_string()))?;
-            (api_key, openai.model, openai.base_url)
+            let json_mode = openai.json_mode.unwrap_or_default();
+            (api_key, openai.model, openai.base_url, headers, json_mode)
         } else {
             return Err(ButterflyBotError::Config(
                 "Missing openai configuration".to_string(),
             ));
         };
 
-        let llm = Arc::new(OpenAiProvider::new(
-            api_key.clone(),
-            model,
-            base_url.clone(),
-        ));
-        let llm_for_memory = llm.clone();
+        let mut base_llm = OpenAiProvider::new(api_key, model, base_url.clone())
+            .with_headers(&headers)?
+            .with_json_mode(json_mode);
+        // Memory's reranker and summarizer reuse the connection settings with
+        // their own models.
+        let provider_template = base_llm.clone();
+        if let Some(limit) = memory_config
+            .as_ref()
+            .and_then(|memory| memory.embedding_limit)
+        {
+            base_llm = base_llm.with_embedding_limit(limit);
+        }
+        let generation = agent_config
+            .as_ref()
+            .map(generation_params)
+            .unwrap_or_default();
+        // Personas get their own sampling settings on the same connection.
+        let persona_template = base_llm.clone();
+        // Memory summaries and embeddings keep the server defaults.
+        let llm_for_memory = Arc::new(base_llm.clone());
+        let llm = Arc::new(base_llm.with_generation(generation));
 
         let skill_markdown = load_markdown_source(config.skill_file.as_deref()).await?;
         let heartbeat_markdown = load_markdown_source(config.heartbeat_file.as_deref()).await?;
-        let instructions = skill_markdown.unwrap_or_else(|| {
+        let mut instructions = skill_markdown.unwrap_or_else(|| {
             "You are Butterfly, a helpful assistant. Follow the skill file and user instructions."
                 .to_string()
         });
-        let specialization = "general".to_string();
+        // Delegates build their prompts on the skill file, not on this agent's extras.
+        let base_instructions = instructions.clone();
+        if let Some(extra) = agent_config
+            .as_ref()
+            .and_then(|agent| agent.instructions.as_deref())
+            .filter(|extra| !extra.trim().is_empty())
+        {
+            instructions = format!("{instructions}\n\n{extra}");
+        }
+        let specialization = agent_config
+            .as_ref()
+            .and_then(|agent| agent.specialization.clone())
+            .unwrap_or_else(|| "general".to_string());
+        let history_window = memory_config
+            .as_ref()
+            .and_then(|memory| memory.history_window)
+            .unwrap_or_default();
+        let memory_search_limit = memory_config
+            .as_ref()
+            .and_then(|memory| memory.search_limit)
+            .unwrap_or(DEFAULT_MEMORY_SEARCH_LIMIT);
+        let memory_context_top_k = memory_config
+            .as_ref()
+            .and_then(|memory| memory.context_top_k)
+            .unwrap_or(memory_search_limit);
+        let shared_namespace = memory_config
+            .as_ref()
+            .and_then(|memory| memory.namespace.clone())
+            .filter(|namespace| !namespace.trim().is_empty());
+        // An agent's own namespace wins over the shared one.
+        let namespace_for = |agent: Option<&AgentConfig>| {
+            agent
+                .and_then(|agent| agent.memory_namespace.clone())
+                .filter(|namespace| !namespace.trim().is_empty())
+                .or_else(|| shared_namespace.clone())
+        };
+        let memory_namespace = namespace_for(agent_config.as_ref());
         let agent = AIAgent {
-            name: "butterfly".to_string(),
+            name: agent_config
+                .as_ref()
+                .map(|agent| agent.name.clone())
+                .unwrap_or_else(|| "butterfly".to_string()),
             instructions,
             specialization,
+            memory_namespace,
         };
 
         let mut brain_manager = BrainManager::new(config_value.clone());
@@ -302,6 +373,23 @@ impl ButterflyBotFactory {
         tool_registry
             .configure_all_tools(config_value.clone())
             .await?;
+        // Audit rows go next to memory unless the tools settings name a database.
+        let audit_db_path = config
+            .tools
+            .as_ref()
+            .and_then(|tools| tools.get("settings"))
+            .and_then(|settings| settings.get("audit_db_path"))
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+            .or_else(|| {
+                memory_config
+                    .as_ref()
+                    .and_then(|memory| memory.sqlite_path.clone())
+            });
+        if let Some(path) = audit_db_path {
+            let store = ToolAuditStore::new(path).await?;
+            tool_registry.set_audit_store(Some(Arc::new(store))).await;
+        }
         let mut registered_tools: Vec<String> = Vec::new();
 
         let tool: Arc<dyn Tool> = Arc::new(SearchInternetTool::new());
@@ -310,7 +398,11 @@ impl ButterflyBotFactory {
             registered_tools.push("search_internet".to_string());
         }
 
-        let tool: Arc<dyn Tool> = Arc::new(RemindersTool::new());
+        // Each tool only sees its own config section, so the other side of a
+        // todo/reminder link is located here.
+        let tool: Arc<dyn Tool> = Arc::new(RemindersTool::new().with_todo_path(
+            resolve_todo_db_path(&config_value).unwrap_or_else(default_todo_db_path),
+        ));
         tool.configure(&config_value)?;
         if tool_registry.register_tool(tool).await {
             registered_tools.push("reminders".to_string());
@@ -346,7 +438,9 @@ impl ButterflyBotFactory {
             registered_tools.push("http_call".to_string());
         }
 
-        let tool: Arc<dyn Tool> = Arc::new(TodoTool::new());
+        let tool: Arc<dyn Tool> = Arc::new(TodoTool::new().with_reminder_path(
+            resolve_reminder_db_path(&config_value).unwrap_or_else(default_reminder_db_path),
+        ));
         tool.configure(&config_value)?;
         if tool_registry.register_tool(tool).await {
             registered_tools.push("todo".to_string());
@@ -364,6 +458,45 @@ impl ButterflyBotFactory {
             registered_tools.push("tasks".to_string());
         }
 
+        let default_tools = registered_tools.clone();
+        if let Some(allowed) = agent_config
+            .as_ref()
+            .map(|agent| &agent.tools)
+            .filter(|tools| !tools.is_empty())
+        {
+            registered_tools = allowed.clone();
+        }
+
+        let delegates = agent_config
+            .as_ref()
+            .map(|agent| agent.delegates.clone())
+            .unwrap_or_default();
+        let mut delegate_tool = None;
+        if !delegates.is_empty() {
+            if let Some(unknown) = delegates
+                .iter()
+                .find(|name| !all_agents.iter().any(|agent| &agent.name == *name))
+            {
+                return Err(ButterflyBotError::Config(format!(
+                    "Delegate '{}' is not a configured agent",
+                    unknown
+                )));
+            }
+            let tool = Arc::new(DelegateTool::new(
+                llm.clone(),
+                all_agents.clone(),
+                delegates,
+                base_instructions.clone(),
+            ));
+            delegate_tool = Some(tool.clone());
+            if tool_registry.register_tool(tool).await
+                && !registered_tools
+                    .iter()
+                    .any(|name| name == DELEGATE_TOOL_NAME)
+            {
+                registered_tools.push(DELEGATE_TOOL_NAME.to_string());
+            }
+        }
         for tool_name in &registered_tools {
             let assigned = tool_registry
                 .assign_tool_to_agent(&agent_name, tool_name)
@@ -377,39 +510,152 @@ impl ButterflyBotFactory {
         }
 
         let agent_service = Arc::new(agent_service);
+        if let Some(tool) = &delegate_tool {
+            tool.attach(&agent_service);
+        }
+        if let Some(max_tool_iterations) = config
+            .tools
+            .as_ref()
+            .and_then(|tools| tools.get("settings"))
+            .and_then(|settings| settings.get("max_tool_iterations"))
+            .and_then(|value| value.as_u64())
+        {
+            agent_service
+                .set_max_tool_iterations(max_tool_iterations as usize)
+                .await;
+        }
+        if let Some(heartbeat_secs) = config
+            .tools
+            .as_ref()
+            .and_then(|tools| tools.get("settings"))
+            .and_then(|settings| settings.get("stream_heartbeat_secs"))
+            .and_then(|value| value.as_u64())
+        {
+            let interval = (heartbeat_secs > 0).then(|| Duration::from_secs(heartbeat_secs));
+            agent_service.set_stream_heartbeat(interval).await;
+        }
+        let blocked_patterns = BlockedPatternGuardrail::from_config(&config_value)?;
+        if !blocked_patterns.is_empty() {
+            agent_service
+                .set_tool_guardrails(vec![Arc::new(blocked_patterns)])
+                .await;
+        }
+        let redact_secrets = config
+            .tools
+            .as_ref()
+            .and_then(|tools| tools.get("settings"))
+            .and_then(|settings| settings.get("redact_secrets"))
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true);
+        if let Some(costs) = config.costs.clone() {
+            let path = costs
+                .sqlite_path
+                .clone()
+                .or_else(|| {
+                    memory_config
+                        .as_ref()
+                        .and_then(|memory| memory.sqlite_path.clone())
+                })
+                .unwrap_or_else(default_usage_db_path);
+            let tracker = CostTracker::new(path, costs, llm.model()).await?;
+            agent_service
+                .set_cost_tracker(Some(Arc::new(tracker)))
+                .await;
+        }
+
+        // Every other configured agent can be switched to per user.
+        let mut personas = Vec::new();
+        for persona_config in all_agents.iter().skip(1) {
+            let mut instructions = base_instructions.clone();
+            if let Some(extra) = persona_config
+                .instructions
+                .as_deref()
+                .filter(|extra| !extra.trim().is_empty())
+            {
+                instructions = format!("{instructions}\n\n{extra}");
+            }
+            let persona = AIAgent {
+                name: persona_config.name.clone(),
+                instructions,
+                specialization: persona_config
+                    .specialization
+                    .clone()
+                    .unwrap_or_else(|| "general".to_string()),
+                memory_namespace: namespace_for(Some(persona_config)),
+            };
+            let persona_llm = Arc::new(
+                persona_template
+                    .clone()
+                    .with_generation(generation_params(persona_config)),
+            );
+            let tools = if persona_config.tools.is_empty() {
+                &default_tools
+            } else {
+                &persona_config.tools
+            };
+            for tool_name in tools {
+                if !tool_registry
+                    .assign_tool_to_agent(&persona.name, tool_name)
+                    .await
+                {
+                    return Err(ButterflyBotError::Config(format!(
+                        "Tool '{}' is not registered",
+                        tool_name
+                    )));
+                }
+            }
+            personas.push(Arc::new(
+                agent_service.with_agent(persona, persona_llm).await,
+            ));
+        }
+        let active_agents = if personas.is_empty() {
+            None
+        } else {
+            let path = memory_config
+                .as_ref()
+                .and_then(|memory| memory.sqlite_path.clone())
+                .unwrap_or_else(crate::paths::default_db_path);
+            Some(Arc::new(ActiveAgentStore::new(path).await?))
+        };
+
         let memory_provider: Arc<dyn crate::interfaces::providers::MemoryProvider> =
             if let Some(memory) = memory_config {
                 if memory.enabled.unwrap_or(true) {
                     let sqlite_path = memory
                         .sqlite_path
-                        .unwrap_or_else(|| "./data/butterfly-bot.db".to_string());
+                        .unwrap_or_else(crate::paths::default_db_path);
                     let lancedb_path = memory
                         .lancedb_path
-                        .unwrap_or_else(|| "./data/lancedb".to_string());
+                        .unwrap_or_else(crate::paths::default_lancedb_path);
                     let reranker = memory.rerank_model.as_ref().map(|rerank_model| {
-                        Arc::new(OpenAiProvider::new(
-                            api_key.clone(),
-                            Some(rerank_model.clone()),
-                            base_url.clone(),
-                        ))
+                        Arc::new(provider_template.clone().with_model(rerank_model.clone()))
                             as Arc<dyn crate::interfaces::providers::LlmProvider>
                     });
                     let summarizer = memory.summary_model.as_ref().map(|summary_model| {
-                        Arc::new(OpenAiProvider::new(
-                            api_key.clone(),
-                            Some(summary_model.clone()),
-                            base_url.clone(),
-                        ))
+                        Arc::new(provider_template.clone().with_model(summary_model.clone()))
                             as Arc<dyn crate::interfaces::providers::LlmProvider>
                     });
                     let mut memory_provider_config = SqliteMemoryProviderConfig::new(sqlite_path);
                     memory_provider_config.lancedb_path = Some(lancedb_path);
-                    memory_provider_config.embedder = Some(llm_for_memory.clone());
+                    memory_provider_config.embedder = Some(Arc::new(CachingProvider::new(
+                        llm_for_memory.clone(),
+                        DEFAULT_EMBEDDING_CACHE_SIZE,
+                    )));
                     memory_provider_config.embedding_model = memory.embedding_model.clone();
                     memory_provider_config.reranker = reranker;
+                    let rerank_template = provider_template.clone();
+                    memory_provider_config.reranker_factory = Some(Arc::new(move |model: &str| {
+                        Arc::new(rerank_template.clone().with_model(model))
+                            as Arc<dyn crate::interfaces::providers::LlmProvider>
+                    }));
+                    memory_provider_config.ollama_tags_url =
+                        base_url.as_deref().and_then(crate::config::ollama_tags_url);
                     memory_provider_config.summarizer = summarizer;
                     memory_provider_config.summary_threshold = memory.summary_threshold;
+                    memory_provider_config.summary_style = memory.summary_style.unwrap_or_default();
                     memory_provider_config.retention_days = memory.retention_days;
+                    memory_provider_config.min_score = memory.min_score;
+                    memory_provider_config.vector_index = memory.vector_index;
                     Arc::new(SqliteMemoryProvider::new(memory_provider_config).await?)
                         as Arc<dyn crate::interfaces::providers::MemoryProvider>
                 } else {
@@ -424,16 +670,36 @@ impl ButterflyBotFactory {
         let reminder_store = if registered_tools.iter().any(|name| name == "reminders") {
             let path =
                 resolve_reminder_db_path(&config_value).unwrap_or_else(default_reminder_db_path);
-            Some(Arc::new(ReminderStore::new(path).await?))
+            Some(Arc::new(
+                ReminderStore::new(path)
+                    .await?
+                    .with_dedupe_window(resolve_dedupe_window(&config_value)),
+            ))
         } else {
             None
         };
 
-        Ok(QueryService::new(
-            agent_service,
-            Some(memory_provider),
-            reminder_store,
-        ))
+        let mut query_service =
+            QueryService::new(agent_service, Some(memory_provider), reminder_store)
+                .with_history_window(history_window)
+                .with_memory_limits(memory_search_limit, memory_context_top_k);
+        if let Some(active_agents) = active_agents {
+            query_service = query_service.with_personas(personas, active_agents);
+        }
+        query_service.enable_pinned_context().await;
+        if redact_secrets {
+            query_service =
+                query_service.with_output_redactor(Arc::new(SecretRedactor::from_vault()));
+        }
+        Ok(query_service)
+    }
+}
+
+fn generation_params(agent: &AgentConfig) -> GenerationParams {
+    GenerationParams {
+        temperature: agent.temperature,
+        top_p: agent.top_p,
+        max_tokens: agent.max_tokens,
     }
 }
 
@@ -1 +1,3 @@
 pub mod pii;
+pub mod secrets;
+pub mod tool_calls;
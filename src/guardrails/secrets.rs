@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+use crate::interfaces::guardrails::OutputGuardrail;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Secret values shorter than this are never matched, so short vault entries
+/// don't mask ordinary words.
+pub const MIN_SECRET_LEN: usize = 8;
+
+/// A streamed token longer than this is checked and released even though it
+/// may continue in the next delta.
+const MAX_PENDING_BYTES: usize = 512;
+
+const TOKEN_PATTERN: &str = r"[A-Za-z0-9_\-.+/=:~]+";
+
+/// Token characters that also separate a secret from what surrounds it, as
+/// in `key=<secret>`, `Bearer:<secret>` or a URL path.
+const SEPARATORS: [char; 3] = ['=', ':', '/'];
+
+/// The fixed start of each of [`KEY_PATTERNS`].
+const KEY_PREFIXES: &[&str] = &[
+    "sk-",
+    "ghp_",
+    "gho_",
+    "ghu_",
+    "ghs_",
+    "ghr_",
+    "github_pat_",
+    "AKIA",
+    "xoxa-",
+    "xoxb-",
+    "xoxp-",
+    "xoxr-",
+    "xoxs-",
+    "AIza",
+];
+
+const KEY_PATTERNS: &[&str] = &[
+    r"sk-[A-Za-z0-9_\-]{20,}",
+    r"gh[pousr]_[A-Za-z0-9]{36,}",
+    r"github_pat_[A-Za-z0-9_]{22,}",
+    r"AKIA[0-9A-Z]{16}",
+    r"xox[abprs]-[A-Za-z0-9\-]{10,}",
+    r"AIza[0-9A-Za-z_\-]{35}",
+];
+
+/// Masks known API key formats and any token whose SHA-256 matches a stored
+/// secret. Only the hashes of the secrets are kept.
+pub struct SecretRedactor {
+    hashes: HashSet<[u8; 32]>,
+    lengths: HashSet<usize>,
+    max_len: usize,
+    token_re: Regex,
+    key_res: Vec<Regex>,
+}
+
+impl SecretRedactor {
+    pub fn new<I, S>(secrets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let token_re = Regex::new(&format!("^{TOKEN_PATTERN}$")).unwrap();
+        let mut hashes = HashSet::new();
+        let mut lengths = HashSet::new();
+        for secret in secrets {
+            let secret = secret.as_ref().trim();
+            // Only single-token values can be matched token by token.
+            if secret.len() >= MIN_SECRET_LEN && token_re.is_match(secret) {
+                hashes.insert(hash(secret));
+                lengths.insert(secret.len());
+            }
+        }
+        Self {
+            max_len: lengths.iter().copied().max().unwrap_or(0),
+            hashes,
+            lengths,
+            token_re: Regex::new(TOKEN_PATTERN).unwrap(),
+            key_res: KEY_PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+        }
+    }
+
+    /// Hashes every secret in the vault. If the vault can't be read, only the
+    /// known key formats are masked.
+    pub fn from_vault() -> Self {
+        let names = crate::vault::list_secret_names().unwrap_or_else(|err| {
+            tracing::warn!(error = %err, "could not list vault secrets for redaction");
+            Vec::new()
+        });
+        let secrets = names
+            .iter()
+            .filter_map(|name| crate::vault::get_secret(name).ok().flatten());
+        Self::new(secrets)
+    }
+
+    pub fn scrub(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for token in self.token_re.find_iter(text) {
+            for span in self.secret_spans(token.as_str()) {
+                out.push_str(&text[last..token.start() + span.start]);
+                out.push_str(REDACTED);
+                last = token.start() + span.end;
+            }
+        }
+        out.push_str(&text[last..]);
+        for re in &self.key_res {
+            if re.is_match(&out) {
+                out = re.replace_all(&out, REDACTED).into_owned();
+            }
+        }
+        out
+    }
+
+    /// A scrubber for a streamed reply, fed one delta at a time.
+    pub fn stream(self: &Arc<Self>) -> SecretStreamScrubber {
+        SecretStreamScrubber {
+            redactor: self.clone(),
+            pending: String::new(),
+        }
+    }
+
+    /// The parts of `token` that are stored secrets. A secret may start at
+    /// the token's start or after a [`SEPARATORS`] character, and end at the
+    /// token's end, before a separator, or before the sentence punctuation
+    /// that the token pattern also swallows. The longest match wins.
+    fn secret_spans(&self, token: &str) -> Vec<Range<usize>> {
+        if self.hashes.is_empty() {
+            return Vec::new();
+        }
+        let mut starts = vec![0];
+        let mut ends = vec![token.len(), token.trim_end_matches(['.', ':']).len()];
+        for (idx, _) in token.match_indices(SEPARATORS) {
+            starts.push(idx + 1);
+            ends.push(idx);
+        }
+        ends.sort_unstable_by(|a, b| b.cmp(a));
+        ends.dedup();
+
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for start in starts {
+            if spans.last().is_some_and(|span| start < span.end) {
+                continue;
+            }
+            let end = ends
+                .iter()
+                .copied()
+                .filter(|&end| end > start)
+                .find(|&end| {
+                    let candidate = &token[start..end];
+                    self.lengths.contains(&candidate.len())
+                        && self.hashes.contains(&hash(candidate))
+                });
+            if let Some(end) = end {
+                spans.push(start..end);
+            }
+        }
+        spans
+    }
+
+    /// Whether more text appended to `token` could still turn it into a match.
+    fn may_continue(&self, token: &str) -> bool {
+        if token.is_empty() {
+            return false;
+        }
+        // A secret may also start after the last separator.
+        let last_start = token.rfind(SEPARATORS).map_or(0, |idx| idx + 1);
+        if self.max_len > 0 && token.len() - last_start <= self.max_len {
+            return true;
+        }
+        KEY_PREFIXES.iter().any(|prefix| {
+            token.contains(prefix) || (1..prefix.len()).any(|len| token.ends_with(&prefix[..len]))
+        })
+    }
+}
+
+/// Holds back the trailing token of the text seen so far while it could still
+/// grow into a match, since a secret may be split across deltas; everything
+/// else is released scrubbed.
+pub struct SecretStreamScrubber {
+    redactor: Arc<SecretRedactor>,
+    pending: String,
+}
+
+impl SecretStreamScrubber {
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let split = self
+            .pending
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_token_char(*c))
+            .map(|(idx, c)| idx + c.len_utf8())
+            .unwrap_or(0);
+        let tail = &self.pending[split..];
+        let split = if tail.len() > MAX_PENDING_BYTES || !self.redactor.may_continue(tail) {
+            self.pending.len()
+        } else {
+            split
+        };
+        let ready: String = self.pending.drain(..split).collect();
+        self.redactor.scrub(&ready)
+    }
+
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.redactor.scrub(&rest)
+    }
+}
+
+#[async_trait]
+impl OutputGuardrail for SecretRedactor {
+    async fn process(&self, output: &str) -> Result<String> {
+        Ok(self.scrub(output))
+    }
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+' | '/' | '=' | ':' | '~')
+}
+
+fn hash(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::error::{ButterflyBotError, Result};
+use crate::interfaces::guardrails::{ToolCallGuardrail, ToolCallVerdict};
+
+/// Rejects a tool call when any string in its arguments matches one of the
+/// patterns configured for that tool under `tools.<tool>.blocked_patterns`.
+/// Patterns are regular expressions matched case-insensitively.
+#[derive(Debug, Default)]
+pub struct BlockedPatternGuardrail {
+    patterns: HashMap<String, Vec<Regex>>,
+}
+
+impl BlockedPatternGuardrail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn block(mut self, tool_name: &str, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(&format!("(?i){pattern}")).map_err(|e| {
+            ButterflyBotError::Config(format!(
+                "tools.{tool_name}.blocked_patterns: invalid pattern '{pattern}': {e}"
+            ))
+        })?;
+        self.patterns
+            .entry(tool_name.to_string())
+            .or_default()
+            .push(regex);
+        Ok(self)
+    }
+
+    pub fn from_config(config: &Value) -> Result<Self> {
+        let mut guardrail = Self::new();
+        let Some(tools) = config.get("tools").and_then(|tools| tools.as_object()) else {
+            return Ok(guardrail);
+        };
+        for (tool_name, tool) in tools {
+            let patterns = tool
+                .get("blocked_patterns")
+                .and_then(|patterns| patterns.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|pattern| pattern.as_str());
+            for pattern in patterns {
+                guardrail = guardrail.block(tool_name, pattern)?;
+            }
+        }
+        Ok(guardrail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+#[async_trait]
+impl ToolCallGuardrail for BlockedPatternGuardrail {
+    async fn check(&self, tool_name: &str, params: &Value) -> ToolCallVerdict {
+        let Some(patterns) = self.patterns.get(tool_name) else {
+            return ToolCallVerdict::Allow;
+        };
+        let mut strings = Vec::new();
+        collect_strings("", params, &mut strings);
+        for (path, value) in strings {
+            if let Some(pattern) = patterns.iter().find(|pattern| pattern.is_match(value)) {
+                let pattern = pattern.as_str().trim_start_matches("(?i)");
+                return ToolCallVerdict::Reject(format!(
+                    "Tool call blocked: argument '{path}' matches blocked pattern '{pattern}'"
+                ));
+            }
+        }
+        ToolCallVerdict::Allow
+    }
+}
+
+/// Every string in `value` with its JSON pointer.
+fn collect_strings<'a>(path: &str, value: &'a Value, out: &mut Vec<(String, &'a str)>) {
+    match value {
+        Value::String(text) => out.push((path.to_string(), text)),
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                collect_strings(&format!("{path}/{index}"), item, out);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map {
+                collect_strings(&format!("{path}/{key}"), item, out);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,34 @@
+use crate::clock::{Clock, SystemClock};
+
+/// Differences smaller than this (in seconds) read as "just now".
+pub const JUST_NOW_SECS: i64 = 10;
+
+/// Describes unix timestamp `ts` relative to `now`: "just now", "in 2 hours",
+/// "5 minutes ago", "yesterday", "in 3 days".
+pub fn humanize_relative(ts: i64, now: i64) -> String {
+    let delta = ts - now;
+    let secs = delta.abs();
+    if secs < JUST_NOW_SECS {
+        return "just now".to_string();
+    }
+    let (count, unit) = match secs {
+        s if s < 60 => (s, "second"),
+        s if s < 3_600 => (s / 60, "minute"),
+        s if s < 86_400 => (s / 3_600, "hour"),
+        s => (s / 86_400, "day"),
+    };
+    if unit == "day" && count == 1 {
+        return if delta > 0 { "tomorrow" } else { "yesterday" }.to_string();
+    }
+    let plural = if count == 1 { "" } else { "s" };
+    if delta > 0 {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    }
+}
+
+/// [`humanize_relative`] against the current time.
+pub fn humanize_from_now(ts: i64) -> String {
+    humanize_relative(ts, SystemClock.now())
+}
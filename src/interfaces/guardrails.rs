@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use serde_json::Value;
 
 use crate::error::Result;
 
@@ -11,3 +12,16 @@ pub trait InputGuardrail: Send + Sync {
 pub trait OutputGuardrail: Send + Sync {
     async fn process(&self, output: &str) -> Result<String>;
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolCallVerdict {
+    Allow,
+    /// The reason is returned to the model in place of the tool's result.
+    Reject(String),
+}
+
+/// Sees each tool call, with the arguments the model wrote, before it runs.
+#[async_trait]
+pub trait ToolCallGuardrail: Send + Sync {
+    async fn check(&self, tool_name: &str, params: &Value) -> ToolCallVerdict;
+}
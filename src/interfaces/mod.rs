@@ -1,4 +1,5 @@
 pub mod brain;
+pub mod guardrails;
 pub mod plugins;
 pub mod providers;
 pub mod scheduler;
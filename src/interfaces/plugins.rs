@@ -29,6 +29,13 @@ pub trait Tool: Send + Sync {
     fn required_secrets_for_config(&self, _config: &Value) -> Vec<ToolSecret> {
         self.required_secrets()
     }
+    /// Top-level config sections `configure` reads besides its own
+    /// `tools.<name>` section and `tools.settings`, which it always gets.
+    fn config_sections(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// Called from async code, possibly while the registry holds its
+    /// reconfiguration lock, so it must not block on I/O or other tasks.
     fn configure(&self, _config: &Value) -> Result<()> {
         Ok(())
     }
@@ -1,30 +1,132 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 use crate::error::Result;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub arguments: Value,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmResponse {
     pub text: String,
     pub tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatEvent {
-    pub event_type: String,
-    pub delta: Option<String>,
-    pub name: Option<String>,
-    pub arguments_delta: Option<String>,
-    pub finish_reason: Option<String>,
-    pub error: Option<String>,
+/// One item of a streamed chat turn. Tagged by `event_type` and keeping the
+/// field names of the old flat struct, so the JSON shape is unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    Content {
+        delta: String,
+    },
+    ToolCall {
+        id: String,
+        name: Option<String>,
+        arguments_delta: Option<String>,
+    },
+    /// Sent by the agent before a tool it is about to run.
+    ToolStart {
+        id: String,
+        name: String,
+    },
+    /// Sent by the agent once that tool has finished.
+    ToolEnd {
+        id: String,
+        name: String,
+    },
+    /// Keeps a quiet stream alive; carries nothing.
+    Heartbeat,
+    MessageEnd {
+        finish_reason: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        usage: Option<TokenUsage>,
+    },
+    Error {
+        #[serde(rename = "error")]
+        message: String,
+    },
+}
+
+impl ChatEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ChatEvent::Content { .. } => "content",
+            ChatEvent::ToolCall { .. } => "tool_call",
+            ChatEvent::ToolStart { .. } => "tool_start",
+            ChatEvent::ToolEnd { .. } => "tool_end",
+            ChatEvent::Heartbeat => "heartbeat",
+            ChatEvent::MessageEnd { .. } => "message_end",
+            ChatEvent::Error { .. } => "error",
+        }
+    }
+}
+
+pub fn stable_tool_call_id(index: usize, name: &str, arguments: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    hasher.update(arguments.to_string().as_bytes());
+    let digest = hasher.finalize();
+    let hex: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+    format!("call_{index}_{hex}")
+}
+
+pub fn assign_tool_call_ids(calls: &mut [ToolCall]) {
+    for (index, call) in calls.iter_mut().enumerate() {
+        if call.id.trim().is_empty() {
+            call.id = stable_tool_call_id(index, &call.name, &call.arguments);
+        }
+    }
+}
+
+/// Sampling settings sent with every chat completion; unset fields are left
+/// to the server's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl GenerationParams {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.top_p.is_none() && self.max_tokens.is_none()
+    }
+
+    /// These settings, falling back to `base` for the ones left unset.
+    pub fn or(self, base: GenerationParams) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature.or(base.temperature),
+            top_p: self.top_p.or(base.top_p),
+            max_tokens: self.max_tokens.or(base.max_tokens),
+        }
+    }
+}
+
+/// A model the provider can serve, as listed by it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owned_by: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +184,79 @@ pub trait LlmProvider: Send + Sync {
     ) -> Result<String>;
 
     async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>>;
+
+    /// Empty for providers that can't list their models.
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Ok(Vec::new())
+    }
+
+    /// A copy of this provider with `overrides` taking precedence over its
+    /// own sampling settings, or `None` for providers that have none.
+    fn with_generation_overrides(
+        &self,
+        _overrides: GenerationParams,
+    ) -> Option<Arc<dyn LlmProvider>> {
+        None
+    }
+}
+
+/// Per-search replacements for the configured embedding and rerank models.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchModels {
+    pub embedding_model: Option<String>,
+    pub rerank_model: Option<String>,
+}
+
+impl SearchModels {
+    pub fn is_empty(&self) -> bool {
+        self.embedding_model.is_none() && self.rerank_model.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReindexReport {
+    pub user_id: String,
+    pub total: u64,
+    pub reindexed: u64,
+    pub last_id: Option<i64>,
+}
+
+/// A history message with the id it is stored under.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredMessage {
+    pub id: i64,
+    pub role: String,
+    pub content: String,
+    pub timestamp: i64,
+}
+
+/// One assistant reply to a user turn. Branch 0 is the reply kept in history;
+/// regenerated alternates are numbered after it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageBranch {
+    pub message_id: i64,
+    pub branch: u32,
+    pub content: String,
+    pub created_at: i64,
+}
+
+/// A fact kept in every turn's system prompt for a user, whatever memory
+/// retrieval finds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedContext {
+    pub id: i64,
+    pub content: String,
+    pub created_at: i64,
+}
+
+/// One user's conversation. Archived chats keep their history but are left
+/// out of the default listing until restored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSummary {
+    pub user_id: String,
+    pub message_count: u64,
+    pub last_active: i64,
+    pub archived_at: Option<i64>,
 }
 
 #[async_trait]
@@ -125,4 +300,123 @@ pub trait MemoryProvider: Send + Sync {
     async fn search(&self, _user_id: &str, _query: &str, _limit: usize) -> Result<Vec<String>> {
         Ok(Vec::new())
     }
+
+    /// Like [`MemoryProvider::search`], but drops results scoring below
+    /// `min_score` (falling back to the provider's configured threshold).
+    /// Providers without scores ignore the threshold.
+    async fn search_with_min_score(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        _min_score: Option<f32>,
+    ) -> Result<Vec<String>> {
+        self.search(user_id, query, limit).await
+    }
+
+    /// Like [`MemoryProvider::search_with_min_score`], but embeds and reranks
+    /// with `models` for this query only. Providers without those steps
+    /// ignore them.
+    async fn search_with_models(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        min_score: Option<f32>,
+        _models: &SearchModels,
+    ) -> Result<Vec<String>> {
+        self.search_with_min_score(user_id, query, limit, min_score)
+            .await
+    }
+
+    async fn get_message(&self, _user_id: &str, _message_id: i64) -> Result<Option<StoredMessage>> {
+        Ok(None)
+    }
+
+    /// The newest `limit` messages (0 for all) older than `before_id`, oldest first.
+    async fn get_messages(
+        &self,
+        _user_id: &str,
+        _before_id: Option<i64>,
+        _limit: usize,
+    ) -> Result<Vec<StoredMessage>> {
+        Ok(Vec::new())
+    }
+
+    /// Stores an alternate reply to the user message `message_id` without
+    /// touching the history.
+    async fn add_branch(
+        &self,
+        _user_id: &str,
+        _message_id: i64,
+        _content: &str,
+    ) -> Result<MessageBranch> {
+        Err(crate::error::ButterflyBotError::Runtime(
+            "This memory provider does not support branches".to_string(),
+        ))
+    }
+
+    async fn get_branches(&self, _user_id: &str, _message_id: i64) -> Result<Vec<MessageBranch>> {
+        Ok(Vec::new())
+    }
+
+    /// Deletes message `message_id` and everything after it, along with any
+    /// memory derived from them. Returns how many messages were removed.
+    async fn truncate_from(&self, _user_id: &str, _message_id: i64) -> Result<u64> {
+        Err(crate::error::ButterflyBotError::Runtime(
+            "This memory provider does not support editing history".to_string(),
+        ))
+    }
+
+    /// Pins are stored apart from history, so retention never prunes them.
+    async fn pin_context(&self, _user_id: &str, _content: &str) -> Result<PinnedContext> {
+        Err(crate::error::ButterflyBotError::Runtime(
+            "This memory provider does not support pinned context".to_string(),
+        ))
+    }
+
+    /// Returns false if `pin_id` isn't one of `user_id`'s pins.
+    async fn unpin_context(&self, _user_id: &str, _pin_id: i64) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Oldest first.
+    async fn pinned_context(&self, _user_id: &str) -> Result<Vec<PinnedContext>> {
+        Ok(Vec::new())
+    }
+
+    /// Most recently active first.
+    async fn list_chats(&self, _include_archived: bool) -> Result<Vec<ChatSummary>> {
+        Ok(Vec::new())
+    }
+
+    /// Returns false if the user has no history or the chat is already
+    /// archived. A new message restores the chat.
+    async fn archive_chat(&self, _user_id: &str) -> Result<bool> {
+        Err(crate::error::ButterflyBotError::Runtime(
+            "This memory provider does not support archiving chats".to_string(),
+        ))
+    }
+
+    async fn unarchive_chat(&self, _user_id: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Archives every chat with no message since `idle_before` (unix seconds)
+    /// and returns their user ids.
+    async fn archive_idle_chats(&self, _idle_before: i64) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn reindex_memory(
+        &self,
+        user_id: &str,
+        resume_after: Option<i64>,
+    ) -> Result<ReindexReport> {
+        Ok(ReindexReport {
+            user_id: user_id.to_string(),
+            last_id: resume_after,
+            ..Default::default()
+        })
+    }
 }
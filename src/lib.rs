@@ -1,13 +1,26 @@
+pub mod active_agents;
+pub mod agents;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod brain;
+pub mod captures;
 pub mod client;
+pub mod clock;
 pub mod config;
 pub mod config_store;
+pub mod cors;
+pub mod costs;
 pub mod daemon;
 pub mod db;
 pub mod domains;
 pub mod error;
 pub mod factories;
+pub mod guardrails;
+pub mod humanize;
 pub mod interfaces;
+pub mod markdown_stream;
+pub mod onboarding;
+pub mod paths;
 pub mod planning;
 pub mod plugins;
 pub mod providers;
@@ -16,15 +29,17 @@ pub mod scheduler;
 pub mod services;
 pub mod tasks;
 pub mod todo;
+pub mod tool_audit;
 pub mod tools;
 pub mod ui;
 pub mod vault;
 pub mod wakeup;
+pub mod webhooks;
 
 pub type Result<T> = std::result::Result<T, error::ButterflyBotError>;
 
 pub use crate::client::ButterflyBot;
 pub use crate::config::Config;
 pub use crate::error::ButterflyBotError;
-pub use crate::interfaces::providers::{ImageData, ImageInput};
+pub use crate::interfaces::providers::{ChatEvent, ImageData, ImageInput};
 pub use crate::services::query::{OutputFormat, ProcessOptions, ProcessResult, UserInput};
@@ -18,7 +18,7 @@ use reqwest::header::AUTHORIZATION;
 #[cfg(not(test))]
 use std::collections::HashMap;
 #[cfg(not(test))]
-use std::io::{self as std_io, BufWriter, Write};
+use std::io::{self as std_io, BufWriter, IsTerminal, Write};
 #[cfg(not(test))]
 use std::process::Command;
 #[cfg(not(test))]
@@ -27,7 +27,11 @@ use syntect::parsing::SyntaxSet;
 use tokio::io::{self, AsyncBufReadExt};
 
 #[cfg(not(test))]
-use butterfly_bot::config::{Config, MemoryConfig, OpenAiConfig};
+use butterfly_bot::agents;
+#[cfg(not(test))]
+use butterfly_bot::config::{
+    assistant_prefix, banner_line, markdown_reply, AgentConfig, Config, DEFAULT_DISPLAY_NAME,
+};
 #[cfg(not(test))]
 use butterfly_bot::config_store;
 #[cfg(not(test))]
@@ -35,8 +39,14 @@ use butterfly_bot::daemon;
 #[cfg(not(test))]
 use butterfly_bot::error::Result;
 #[cfg(not(test))]
+use butterfly_bot::humanize::humanize_from_now;
+#[cfg(not(test))]
 use butterfly_bot::interfaces::plugins::Tool;
 #[cfg(not(test))]
+use butterfly_bot::markdown_stream::{stream_until_cancelled, StreamedReply};
+#[cfg(not(test))]
+use butterfly_bot::onboarding;
+#[cfg(not(test))]
 use butterfly_bot::plugins::registry::ToolRegistry;
 #[cfg(not(test))]
 use butterfly_bot::tools::http_call::HttpCallTool;
@@ -65,6 +75,8 @@ use butterfly_bot::vault;
 #[cfg(not(test))]
 use tokio::sync::oneshot;
 #[cfg(not(test))]
+use tracing_subscriber::fmt::format::FmtSpan;
+#[cfg(not(test))]
 use tracing_subscriber::EnvFilter;
 
 #[cfg(not(test))]
@@ -82,8 +94,13 @@ struct Cli {
     #[arg(long, env = "BUTTERFLY_BOT_CONFIG")]
     config: Option<String>,
 
-    #[arg(long, default_value = "./data/butterfly-bot.db")]
-    db: String,
+    /// Defaults to `butterfly-bot.db` in the active profile's data dir.
+    #[arg(long)]
+    db: Option<String>,
+
+    /// Keeps config, databases and secrets apart from other profiles.
+    #[arg(long, env = "BUTTERFLY_BOT_PROFILE", global = true)]
+    profile: Option<String>,
 
     #[arg(long, default_value = "http://127.0.0.1:7878")]
     daemon: String,
@@ -126,10 +143,111 @@ enum Commands {
         #[arg(long)]
         openai_key: String,
     },
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
     DbKeySet {
         #[arg(long)]
         key: String,
     },
+    /// One-time setup of a master passphrase the database key is derived from.
+    /// The passphrase is prompted for without echo, or read from stdin.
+    DbKeySetup,
+    Agents {
+        #[command(subcommand)]
+        action: AgentsAction,
+    },
+}
+
+#[cfg(not(test))]
+#[derive(clap::Subcommand, Debug)]
+enum SecretsAction {
+    /// Store every secret from a JSON object of name to value.
+    Import {
+        #[arg(long)]
+        path: String,
+    },
+    /// Show the names (never the values) of stored secrets.
+    List,
+}
+
+#[cfg(not(test))]
+#[derive(clap::Subcommand, Debug)]
+enum AgentsAction {
+    List,
+    Add {
+        #[arg(long)]
+        name: String,
+
+        #[arg(long)]
+        specialization: Option<String>,
+
+        #[arg(long)]
+        instructions: Option<String>,
+
+        /// Comma-separated tool names; omit to allow every enabled tool.
+        #[arg(long)]
+        tools: Option<String>,
+    },
+    Edit {
+        #[arg(long)]
+        name: String,
+
+        #[arg(long)]
+        specialization: Option<String>,
+
+        #[arg(long)]
+        instructions: Option<String>,
+
+        #[arg(long)]
+        tools: Option<String>,
+    },
+    Remove {
+        #[arg(long)]
+        name: String,
+    },
+}
+
+#[cfg(not(test))]
+impl AgentsAction {
+    fn to_command(&self) -> agents::AgentsCommand {
+        match self {
+            AgentsAction::List => agents::AgentsCommand::List,
+            AgentsAction::Add {
+                name,
+                specialization,
+                instructions,
+                tools,
+            } => agents::AgentsCommand::Add(AgentConfig {
+                name: name.clone(),
+                specialization: specialization.clone(),
+                instructions: instructions.clone(),
+                tools: tools
+                    .as_deref()
+                    .map(agents::parse_tool_list)
+                    .unwrap_or_default(),
+                temperature: None,
+                top_p: None,
+                max_tokens: None,
+                delegates: Vec::new(),
+                display_name: None,
+                memory_namespace: None,
+            }),
+            AgentsAction::Edit {
+                name,
+                specialization,
+                instructions,
+                tools,
+            } => agents::AgentsCommand::Edit {
+                name: name.clone(),
+                specialization: specialization.clone(),
+                instructions: instructions.clone(),
+                tools: tools.as_deref().map(agents::parse_tool_list),
+            },
+            AgentsAction::Remove { name } => agents::AgentsCommand::Remove { name: name.clone() },
+        }
+    }
 }
 
 #[cfg(not(test))]
@@ -138,7 +256,7 @@ fn rule(width: usize) -> String {
 }
 
 #[cfg(not(test))]
-fn print_banner(daemon: &str, user_id: &str) {
+fn print_banner(config: Option<&Config>, daemon: &str, user_id: &str) {
     let term = Term::stdout();
     let width = term.size().1 as usize;
     let line = rule(width);
@@ -161,11 +279,7 @@ fn print_banner(daemon: &str, user_id: &str) {
     println!("{}", style(&line).color256(214));
     println!(
         "{}",
-        style(format!(
-            "Ollama-ready • Streaming CLI • Daemon: {} • User: {}",
-            daemon, user_id
-        ))
-        .color256(250)
+        style(banner_line(config, daemon, user_id)).color256(250)
     );
     println!();
 }
@@ -231,8 +345,18 @@ async fn start_reminder_listener(cli: &Cli) {
                                     .get("title")
                                     .and_then(|v| v.as_str())
                                     .unwrap_or("Reminder");
+                                let due = value
+                                    .get("due_at")
+                                    .and_then(|v| v.as_i64())
+                                    .map(|due_at| format!(" (due {})", humanize_from_now(due_at)))
+                                    .unwrap_or_default();
                                 let _ = std_io::stdout().write_all(b"\n\n");
-                                println!("{} {}", style("⏰").color256(214), title);
+                                println!(
+                                    "{} {}{}",
+                                    style("⏰").color256(214),
+                                    title,
+                                    style(due).color256(245)
+                                );
                                 if let Err(err) = Notification::new()
                                     .summary("Butterfly Bot")
                                     .body(title)
@@ -252,12 +376,9 @@ async fn start_reminder_listener(cli: &Cli) {
 }
 
 #[cfg(not(test))]
-fn print_assistant_prefix() {
-    print!(
-        "{} {} ",
-        style("✦").color256(214).bold(),
-        style("Butterfly").color256(214).bold()
-    );
+fn print_assistant_prefix(display_name: &str) {
+    let prefix = assistant_prefix(display_name);
+    print!("{} ", style(prefix.trim_end()).color256(214).bold());
 }
 
 #[cfg(not(test))]
@@ -306,15 +427,14 @@ fn should_use_markdown(text: &str) -> bool {
 }
 
 #[cfg(not(test))]
-fn render_response(text: &str) {
+fn render_response(display_name: &str, text: &str) {
     if text.trim().is_empty() {
         return;
     }
     if should_use_markdown(text) {
-        let prefixed = format!("**Butterfly:** {text}");
-        render_markdown(&prefixed);
+        render_markdown(&markdown_reply(display_name, text));
     } else {
-        print_assistant_prefix();
+        print_assistant_prefix(display_name);
         print!("{text}");
     }
 }
@@ -336,26 +456,46 @@ fn clear_streamed_output(response: &str) {
 }
 
 #[cfg(not(test))]
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // Before the runtime starts its threads; see `ui::capture_local_offset`.
+    ui::capture_local_offset();
+    tokio::runtime::Runtime::new()
+        .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?
+        .block_on(run())
+}
+
+#[cfg(not(test))]
+async fn run() -> Result<()> {
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info,butterfly_bot=info,lance=warn,lancedb=warn"));
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
     force_dbusrs();
 
     let cli = Cli::parse();
+    // Set before anything resolves a data path or keyring entry.
+    if let Some(profile) = cli.profile.as_deref() {
+        vault::validate_profile_name(profile)?;
+        std::env::set_var(vault::PROFILE_ENV, profile.trim());
+    }
+    let db = cli
+        .db
+        .clone()
+        .unwrap_or_else(butterfly_bot::paths::default_db_path);
     if let Some(config_path) = cli.config.as_ref() {
         let config = Config::from_file(config_path)?;
-        config_store::save_config(&cli.db, &config)?;
+        config_store::save_config(&db, &config)?;
     }
     if !cli.cli_mode {
-        std::env::set_var("BUTTERFLY_BOT_DB", &cli.db);
+        std::env::set_var("BUTTERFLY_BOT_DB", &db);
         std::env::set_var("BUTTERFLY_BOT_DAEMON", &cli.daemon);
         if let Some(token) = cli.token.as_ref() {
             std::env::set_var("BUTTERFLY_BOT_TOKEN", token);
         }
         std::env::set_var("BUTTERFLY_BOT_USER_ID", &cli.user_id);
-        if let Ok(config) = Config::from_store(&cli.db) {
+        if let Ok(config) = Config::from_store(&db) {
             ensure_ollama_models(&config)?;
         }
         ui::launch_ui();
@@ -363,14 +503,14 @@ async fn main() -> Result<()> {
     }
     let needs_onboarding = !matches!(
         cli.command,
-        Some(Commands::Init) | Some(Commands::ConfigImport { .. })
+        Some(Commands::Init) | Some(Commands::ConfigImport { .. }) | Some(Commands::DbKeySetup)
     );
-    if needs_onboarding && Config::from_store(&cli.db).is_err() {
-        run_onboarding(&cli.db)?;
+    if needs_onboarding && Config::from_store(&db).is_err() {
+        run_onboarding(&db)?;
         println!("Onboarding complete. Run 'butterfly-bot config show' to review.");
     }
 
-    if let Ok(config) = Config::from_store(&cli.db) {
+    if let Ok(config) = Config::from_store(&db) {
         ensure_ollama_models(&config)?;
     }
 
@@ -382,7 +522,7 @@ async fn main() -> Result<()> {
     let _daemon_shutdown = if uses_daemon {
         let (host, port) = parse_daemon_address(&cli.daemon);
         let token = cli.token.clone().unwrap_or_default();
-        let db_path = cli.db.clone();
+        let db_path = db.clone();
         let (tx, rx) = oneshot::channel::<()>();
         tokio::spawn(async move {
             let _ = daemon::run_with_shutdown(&host, port, &db_path, &token, async {
@@ -397,25 +537,25 @@ async fn main() -> Result<()> {
     if let Some(command) = &cli.command {
         match command {
             Commands::Init => {
-                run_onboarding(&cli.db)?;
+                run_onboarding(&db)?;
                 println!("Onboarding complete. Run 'butterfly-bot config show' to review.");
                 return Ok(());
             }
             Commands::ConfigImport { path } => {
                 let config = Config::from_file(path)?;
-                config_store::save_config(&cli.db, &config)?;
-                println!("Config imported into {}", cli.db);
+                config_store::save_config(&db, &config)?;
+                println!("Config imported into {}", db);
                 return Ok(());
             }
             Commands::ConfigExport { path } => {
-                let config = Config::from_store(&cli.db)?;
+                let config = Config::from_store(&db)?;
                 let value = redacted_config_value(&config)?;
                 write_config_file(path, &value)?;
                 println!("Config exported to {path}");
                 return Ok(());
             }
             Commands::ConfigShow => {
-                let config = Config::from_store(&cli.db)?;
+                let config = Config::from_store(&db)?;
                 let value = redacted_config_value(&config)?;
                 println!(
                     "{}",
@@ -428,11 +568,64 @@ async fn main() -> Result<()> {
                 println!("Secret stored in keyring.");
                 return Ok(());
             }
+            Commands::Secrets { action } => {
+                match action {
+                    SecretsAction::Import { path } => {
+                        let names = vault::import_secrets_file(path)?;
+                        println!("Stored {} secret(s) in keyring:", names.len());
+                        for name in names {
+                            println!("- {name}");
+                        }
+                    }
+                    SecretsAction::List => {
+                        let names = vault::list_secret_names()?;
+                        if names.is_empty() {
+                            println!("No secrets stored.");
+                        }
+                        for name in names {
+                            println!("- {name}");
+                        }
+                    }
+                }
+                return Ok(());
+            }
             Commands::DbKeySet { key } => {
                 vault::set_secret("db_encryption_key", key)?;
                 println!("Database key stored in keyring.");
                 return Ok(());
             }
+            Commands::DbKeySetup => {
+                // An existing plaintext database couldn't be opened with the new key.
+                if std::path::Path::new(&db).exists() {
+                    return Err(butterfly_bot::error::ButterflyBotError::Config(format!(
+                        "{db} already exists; set up the passphrase before creating the database"
+                    )));
+                }
+                let passphrase = read_passphrase()?;
+                butterfly_bot::db::setup_db_passphrase(&passphrase)?;
+                println!("Database passphrase stored in keyring.");
+                return Ok(());
+            }
+            Commands::Agents { action } => {
+                let agents = agents::run_agents_command(&db, action.to_command())?;
+                if agents.is_empty() {
+                    println!("No agents configured.");
+                }
+                for agent in agents {
+                    let tools = if agent.tools.is_empty() {
+                        "all tools".to_string()
+                    } else {
+                        agent.tools.join(", ")
+                    };
+                    println!(
+                        "- {} ({}) [{}]",
+                        agent.name,
+                        agent.specialization.as_deref().unwrap_or("general"),
+                        tools
+                    );
+                }
+                return Ok(());
+            }
             Commands::Status => {
                 let status = daemon_status(&cli).await?;
                 println!("{status}");
@@ -442,7 +635,12 @@ async fn main() -> Result<()> {
         }
     }
 
-    print_banner(&cli.daemon, &cli.user_id);
+    let config = Config::from_store(&db).ok();
+    print_banner(config.as_ref(), &cli.daemon, &cli.user_id);
+    let display_name = config
+        .as_ref()
+        .map(Config::display_name)
+        .unwrap_or_else(|| DEFAULT_DISPLAY_NAME.to_string());
 
     if let Some(Commands::MemorySearch { query, limit }) = &cli.command {
         let results = daemon_memory_search(&cli, query, *limit).await?;
@@ -458,9 +656,10 @@ async fn main() -> Result<()> {
     }
 
     if let Some(prompt) = &cli.prompt {
-        ensure_tool_secrets(&cli.db).await?;
-        let response = daemon_process_text_stream(&cli, prompt, None, false).await?;
-        render_response(&response);
+        ensure_tool_secrets(&db).await?;
+        let reply =
+            daemon_process_text_stream(&cli, prompt, None, false, std::future::pending()).await?;
+        render_response(&display_name, &reply.text);
         println!();
         return Ok(());
     }
@@ -470,16 +669,21 @@ async fn main() -> Result<()> {
         style("Enter your prompts (Ctrl+D to exit):").color256(245)
     );
     start_reminder_listener(&cli).await;
-    ensure_tool_secrets(&cli.db).await?;
+    ensure_tool_secrets(&db).await?;
     let stdin = io::BufReader::new(io::stdin());
     let mut lines = stdin.lines();
     loop {
         print_user_prompt()
             .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?;
-        let line = lines
-            .next_line()
-            .await
-            .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?;
+        // Ctrl-C at the prompt starts a fresh line; Ctrl-D is how to leave.
+        let line = tokio::select! {
+            line = lines.next_line() => line
+                .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?,
+            () = ctrl_c() => {
+                println!();
+                continue;
+            }
+        };
         let Some(line) = line else {
             println!("\n{}", style("Goodbye ✨").color256(245));
             break;
@@ -487,13 +691,16 @@ async fn main() -> Result<()> {
         if line.trim().is_empty() {
             continue;
         }
-        print_assistant_prefix();
-        let response = daemon_process_text_stream(&cli, &line, None, true).await?;
+        print_assistant_prefix(&display_name);
+        let reply = daemon_process_text_stream(&cli, &line, None, true, ctrl_c()).await?;
         println!();
-        if should_use_markdown(&response) {
-            clear_streamed_output(&response);
-            let prefixed = format!("**Butterfly:** {response}");
-            render_markdown(&prefixed);
+        if reply.cancelled {
+            println!("{}", style("Cancelled.").color256(245));
+            continue;
+        }
+        if should_use_markdown(&reply.text) {
+            clear_streamed_output(&reply.text);
+            render_markdown(&markdown_reply(&display_name, &reply.text));
             println!();
         }
     }
@@ -689,85 +896,24 @@ fn write_config_file(path: &str, value: &serde_json::Value) -> Result<()> {
 #[cfg(not(test))]
 fn run_onboarding(db_path: &str) -> Result<()> {
     println!("{}", style("ButterFly Bot setup").color256(214).bold());
-    println!("{}", style("Using local Ollama defaults.").color256(245));
+    println!(
+        "{}",
+        style("Press Enter to keep local Ollama defaults.").color256(245)
+    );
     println!();
 
-    let base_url = "http://localhost:11434/v1".to_string();
-    let model = "ministral-3:14b".to_string();
-    let memory_enabled = true;
-
-    let memory = if memory_enabled {
-        let sqlite_path = prompt_with_default("Memory SQLite path", db_path)?;
-        let lancedb_path = prompt_with_default("LanceDB path", "./data/lancedb")?;
-        let embedding_model = "embeddinggemma:latest".to_string();
-        let rerank_model = "qllama/bge-reranker-v2-m3".to_string();
-        let summary_model = model.clone();
-        let summary_threshold = prompt_optional_u32("Summary threshold (messages)")?;
-        let retention_days = prompt_optional_u32("Retention days (blank for unlimited)")?;
-
-        Some(MemoryConfig {
-            enabled: Some(true),
-            sqlite_path: Some(sqlite_path),
-            lancedb_path: Some(lancedb_path),
-            summary_model: Some(summary_model),
-            embedding_model: Some(embedding_model),
-            rerank_model: Some(rerank_model),
-            summary_threshold: summary_threshold.map(|value| value as usize),
-            retention_days,
-        })
-    } else {
-        Some(MemoryConfig {
-            enabled: Some(false),
-            sqlite_path: None,
-            lancedb_path: None,
-            summary_model: None,
-            embedding_model: None,
-            rerank_model: None,
-            summary_threshold: None,
-            retention_days: None,
-        })
-    };
-
-    let config = Config {
-        openai: Some(OpenAiConfig {
-            api_key: None,
-            model: Some(model),
-            base_url: Some(base_url),
-        }),
-        skill_file: Some("./skill.md".to_string()),
-        heartbeat_file: Some("./heartbeat.md".to_string()),
-        memory,
-        tools: None,
-        brains: None,
-    };
-
-    config_store::save_config(db_path, &config)?;
+    onboarding::run_onboarding(&mut StdinPrompter, db_path)?;
     Ok(())
 }
 
 #[cfg(not(test))]
-fn prompt_with_default(label: &str, default: &str) -> Result<String> {
-    let prompt = format!("{} [{}]: ", label, default);
-    let input = prompt_line(&prompt)?;
-    if input.trim().is_empty() {
-        Ok(default.to_string())
-    } else {
-        Ok(input.trim().to_string())
-    }
-}
+struct StdinPrompter;
 
 #[cfg(not(test))]
-fn prompt_optional_u32(label: &str) -> Result<Option<u32>> {
-    let prompt = format!("{}: ", label);
-    let input = prompt_line(&prompt)?;
-    if input.trim().is_empty() {
-        return Ok(None);
+impl onboarding::Prompter for StdinPrompter {
+    fn ask(&mut self, prompt: &str) -> Result<String> {
+        prompt_line(prompt)
     }
-    input
-        .trim()
-        .parse::<u32>()
-        .map(Some)
-        .map_err(|e| butterfly_bot::error::ButterflyBotError::Config(e.to_string()))
 }
 
 #[cfg(not(test))]
@@ -784,6 +930,29 @@ fn prompt_line(prompt: &str) -> Result<String> {
     Ok(input)
 }
 
+/// Prompts twice without echo on a terminal; otherwise takes the first line
+/// of stdin, so the passphrase never lands in shell history or `ps`.
+#[cfg(not(test))]
+fn read_passphrase() -> Result<String> {
+    let runtime =
+        |e: std_io::Error| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string());
+    if !std_io::stdin().is_terminal() {
+        let mut line = String::new();
+        std_io::stdin().read_line(&mut line).map_err(runtime)?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    let term = Term::stderr();
+    term.write_str("Database passphrase: ").map_err(runtime)?;
+    let passphrase = term.read_secure_line().map_err(runtime)?;
+    term.write_str("Repeat passphrase: ").map_err(runtime)?;
+    if term.read_secure_line().map_err(runtime)? != passphrase {
+        return Err(butterfly_bot::error::ButterflyBotError::Config(
+            "Passphrases do not match".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
 #[cfg(not(test))]
 struct DaemonShutdown(Option<oneshot::Sender<()>>);
 
@@ -821,7 +990,7 @@ fn ensure_ollama_models(config: &Config) -> Result<()> {
     let Some(base_url) = &openai.base_url else {
         return Ok(());
     };
-    if !is_ollama_local(base_url) {
+    if !butterfly_bot::config::is_ollama_local(base_url) {
         return Ok(());
     }
 
@@ -867,11 +1036,6 @@ fn ensure_ollama_models(config: &Config) -> Result<()> {
     Ok(())
 }
 
-#[cfg(not(test))]
-fn is_ollama_local(base_url: &str) -> bool {
-    base_url.starts_with("http://localhost:11434") || base_url.starts_with("http://127.0.0.1:11434")
-}
-
 #[cfg(not(test))]
 fn list_ollama_models() -> Result<Vec<String>> {
     let output = Command::new("ollama")
@@ -954,13 +1118,26 @@ async fn daemon_status(cli: &Cli) -> Result<String> {
     Ok(text)
 }
 
+/// Resolves on the next Ctrl-C. If the handler can't be installed it never
+/// resolves, leaving the default behaviour in place.
+#[cfg(not(test))]
+async fn ctrl_c() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Streams the reply until it ends or `cancel` resolves; a cancelled reply
+/// keeps what had arrived.
 #[cfg(not(test))]
 async fn daemon_process_text_stream(
     cli: &Cli,
     text: &str,
     prompt: Option<&str>,
     print_stream: bool,
-) -> Result<String> {
+    cancel: impl std::future::Future<Output = ()>,
+) -> Result<StreamedReply> {
+    let mut cancel = std::pin::pin!(cancel);
     let token = cli.token.as_deref();
     let client = reqwest::Client::new();
     let url = format!("{}/process_text_stream", cli.daemon.trim_end_matches('/'));
@@ -975,11 +1152,16 @@ async fn daemon_process_text_stream(
             request = request.header("authorization", format!("Bearer {token}"));
         }
     }
-    let response = request
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?;
+    let response = tokio::select! {
+        response = request.json(&body).send() => response
+            .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?,
+        () = &mut cancel => {
+            return Ok(StreamedReply {
+                text: String::new(),
+                cancelled: true,
+            });
+        }
+    };
 
     if !response.status().is_success() {
         let value: serde_json::Value = response
@@ -996,21 +1178,16 @@ async fn daemon_process_text_stream(
         ));
     }
 
-    let mut stream = response.bytes_stream();
-    let mut buffer = String::new();
-    while let Some(chunk) = stream.next().await {
-        let chunk =
-            chunk.map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?;
-        let text = String::from_utf8_lossy(&chunk);
-        buffer.push_str(&text);
+    stream_until_cancelled(response.bytes_stream(), cancel, |ready| {
         if print_stream {
-            print!("{text}");
+            print!("{ready}");
             std_io::stdout()
                 .flush()
                 .map_err(|e| butterfly_bot::error::ButterflyBotError::Runtime(e.to_string()))?;
         }
-    }
-    Ok(buffer)
+        Ok(())
+    })
+    .await
 }
 
 #[cfg(not(test))]
@@ -0,0 +1,123 @@
+use std::fmt::Display;
+use std::future::Future;
+
+use futures::{Stream, StreamExt};
+
+use crate::daemon::STREAM_HEARTBEAT;
+use crate::error::{ButterflyBotError, Result};
+
+#[derive(Debug, Default)]
+pub struct MarkdownChunkBuffer {
+    text: String,
+    pending: String,
+    block: String,
+    in_fence: bool,
+}
+
+impl MarkdownChunkBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts a raw stream chunk and returns the part that is safe to print: whole lines,
+    /// with fenced code blocks held back until their closing fence arrives.
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.text.push_str(chunk);
+        self.pending.push_str(chunk);
+
+        let mut ready = String::new();
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            let fence = is_fence(&line);
+            if self.in_fence || fence {
+                self.block.push_str(&line);
+                if fence {
+                    self.in_fence = !self.in_fence;
+                }
+                if !self.in_fence {
+                    ready.push_str(&std::mem::take(&mut self.block));
+                }
+            } else {
+                ready.push_str(&line);
+            }
+        }
+        ready
+    }
+
+    /// Returns whatever is still buffered once the stream has ended.
+    pub fn finish(&mut self) -> String {
+        self.in_fence = false;
+        let mut rest = std::mem::take(&mut self.block);
+        rest.push_str(&std::mem::take(&mut self.pending));
+        rest
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+}
+
+/// The text a streamed reply produced, and whether it was cut short.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamedReply {
+    pub text: String,
+    pub cancelled: bool,
+}
+
+/// Reads `stream` through a [`MarkdownChunkBuffer`], passing printable text to
+/// `on_ready`, until the stream ends or `cancel` resolves. A cancelled reply
+/// keeps whatever had arrived, and the stream is dropped so the request stops.
+/// The daemon's [`STREAM_HEARTBEAT`]s are dropped.
+pub async fn stream_until_cancelled<S, B, E, C, F>(
+    stream: S,
+    cancel: C,
+    mut on_ready: F,
+) -> Result<StreamedReply>
+where
+    S: Stream<Item = std::result::Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Display,
+    C: Future<Output = ()>,
+    F: FnMut(&str) -> Result<()>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut cancel = std::pin::pin!(cancel);
+    let mut buffer = MarkdownChunkBuffer::new();
+    let mut cancelled = false;
+    loop {
+        let chunk = tokio::select! {
+            biased;
+            () = &mut cancel => {
+                cancelled = true;
+                break;
+            }
+            chunk = stream.next() => chunk,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let chunk = chunk.map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let text = String::from_utf8_lossy(chunk.as_ref()).replace(STREAM_HEARTBEAT, "");
+        let ready = buffer.push(&text);
+        if !ready.is_empty() {
+            on_ready(&ready)?;
+        }
+    }
+    let rest = buffer.finish();
+    if !rest.is_empty() {
+        on_ready(&rest)?;
+    }
+    Ok(StreamedReply {
+        text: buffer.into_text(),
+        cancelled,
+    })
+}
+
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
@@ -0,0 +1,214 @@
+use crate::agents::parse_tool_list;
+use crate::config::{AgentConfig, Config, MemoryConfig, OpenAiConfig};
+use crate::config_store;
+use crate::error::{ButterflyBotError, Result};
+use crate::vault;
+
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+pub const OLLAMA_MODEL: &str = "ministral-3:14b";
+pub const OLLAMA_EMBEDDING_MODEL: &str = "embeddinggemma:latest";
+pub const OLLAMA_RERANK_MODEL: &str = "qllama/bge-reranker-v2-m3";
+pub const REMOTE_BASE_URL: &str = "https://api.openai.com/v1";
+pub const REMOTE_MODEL: &str = "gpt-4o-mini";
+pub const REMOTE_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// A model onboarding proposes: `fallback` unless `env` is set to something
+/// non-blank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelDefault {
+    pub env: &'static str,
+    pub fallback: &'static str,
+}
+
+impl ModelDefault {
+    pub fn resolve(&self) -> String {
+        std::env::var(self.env)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| self.fallback.to_string())
+    }
+}
+
+pub const OLLAMA_MODEL_DEFAULT: ModelDefault = ModelDefault {
+    env: "BUTTERFLY_BOT_OLLAMA_MODEL",
+    fallback: OLLAMA_MODEL,
+};
+pub const OLLAMA_EMBEDDING_MODEL_DEFAULT: ModelDefault = ModelDefault {
+    env: "BUTTERFLY_BOT_OLLAMA_EMBEDDING_MODEL",
+    fallback: OLLAMA_EMBEDDING_MODEL,
+};
+pub const OLLAMA_RERANK_MODEL_DEFAULT: ModelDefault = ModelDefault {
+    env: "BUTTERFLY_BOT_OLLAMA_RERANK_MODEL",
+    fallback: OLLAMA_RERANK_MODEL,
+};
+pub const REMOTE_MODEL_DEFAULT: ModelDefault = ModelDefault {
+    env: "BUTTERFLY_BOT_REMOTE_MODEL",
+    fallback: REMOTE_MODEL,
+};
+pub const REMOTE_EMBEDDING_MODEL_DEFAULT: ModelDefault = ModelDefault {
+    env: "BUTTERFLY_BOT_REMOTE_EMBEDDING_MODEL",
+    fallback: REMOTE_EMBEDDING_MODEL,
+};
+
+/// Source of answers for onboarding questions. The CLI reads stdin; tests
+/// script the answers.
+pub trait Prompter {
+    /// Shows `prompt` and returns the raw answer (may be empty).
+    fn ask(&mut self, prompt: &str) -> Result<String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderChoice {
+    Ollama,
+    Remote,
+}
+
+/// Walks through setup, saves the config to `db_path`, and stores a remote
+/// API key (if one was entered) in the vault rather than the config.
+pub fn run_onboarding(prompter: &mut dyn Prompter, db_path: &str) -> Result<Config> {
+    let choice = prompt_provider(prompter)?;
+    let (openai, embedding_model, rerank_model) = match choice {
+        ProviderChoice::Ollama => (
+            OpenAiConfig {
+                api_key: None,
+                model: Some(OLLAMA_MODEL_DEFAULT.resolve()),
+                base_url: Some(OLLAMA_BASE_URL.to_string()),
+                headers: None,
+                json_mode: None,
+            },
+            OLLAMA_EMBEDDING_MODEL_DEFAULT.resolve(),
+            Some(OLLAMA_RERANK_MODEL_DEFAULT.resolve()),
+        ),
+        ProviderChoice::Remote => {
+            let base_url = prompt_with_default(prompter, "Base URL", REMOTE_BASE_URL)?;
+            let model = prompt_with_default(prompter, "Model", &REMOTE_MODEL_DEFAULT.resolve())?;
+            let api_key = prompter.ask("API key (stored in the OS keychain): ")?;
+            let api_key = api_key.trim();
+            if !api_key.is_empty() {
+                vault::set_secret("openai_api_key", api_key)?;
+            }
+            let embedding_model = prompt_with_default(
+                prompter,
+                "Embedding model",
+                &REMOTE_EMBEDDING_MODEL_DEFAULT.resolve(),
+            )?;
+            (
+                OpenAiConfig {
+                    api_key: None,
+                    model: Some(model),
+                    base_url: Some(base_url),
+                    headers: None,
+                    json_mode: None,
+                },
+                embedding_model,
+                None,
+            )
+        }
+    };
+
+    let sqlite_path = prompt_with_default(prompter, "Memory SQLite path", db_path)?;
+    let lancedb_path = prompt_with_default(
+        prompter,
+        "LanceDB path",
+        &crate::paths::default_lancedb_path(),
+    )?;
+    let summary_threshold = prompt_optional_u32(prompter, "Summary threshold (messages)")?;
+    let retention_days = prompt_optional_u32(prompter, "Retention days (blank for unlimited)")?;
+
+    let mut config = Config {
+        memory: Some(MemoryConfig {
+            enabled: Some(true),
+            sqlite_path: Some(sqlite_path),
+            lancedb_path: Some(lancedb_path),
+            summary_model: openai.model.clone(),
+            embedding_model: Some(embedding_model),
+            rerank_model,
+            summary_threshold: summary_threshold.map(|value| value as usize),
+            summary_style: None,
+            retention_days,
+            chat_ttl_days: None,
+            namespace: None,
+            min_score: None,
+            search_limit: None,
+            context_top_k: None,
+            history_window: None,
+            embedding_limit: None,
+            vector_index: None,
+        }),
+        openai: Some(openai),
+        skill_file: Some("./skill.md".to_string()),
+        heartbeat_file: Some("./heartbeat.md".to_string()),
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: Config::from_store(db_path)
+            .ok()
+            .and_then(|existing| existing.agents),
+        scheduler: None,
+        daemon: None,
+    };
+    prompt_agents(prompter, &mut config)?;
+
+    config_store::save_config(db_path, &config)?;
+    Ok(config)
+}
+
+/// Adds agents until a blank name is entered.
+fn prompt_agents(prompter: &mut dyn Prompter, config: &mut Config) -> Result<()> {
+    loop {
+        let name = prompter.ask("Add an agent (name, blank to finish): ")?;
+        if name.trim().is_empty() {
+            return Ok(());
+        }
+        let specialization = prompt_with_default(prompter, "Specialization", "general")?;
+        let instructions = prompter.ask("Instructions (blank to use the skill file): ")?;
+        let tools = prompter.ask("Tools (comma-separated, blank for all): ")?;
+        let agent = AgentConfig {
+            name: name.trim().to_string(),
+            specialization: Some(specialization),
+            instructions: Some(instructions.trim().to_string()).filter(|value| !value.is_empty()),
+            tools: parse_tool_list(&tools),
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            delegates: Vec::new(),
+            display_name: None,
+            memory_namespace: None,
+        };
+        // Invalid entries are dropped and the name prompt comes round again.
+        let _ = config.add_agent(agent);
+    }
+}
+
+fn prompt_provider(prompter: &mut dyn Prompter) -> Result<ProviderChoice> {
+    loop {
+        let input = prompter.ask("Provider (1) local Ollama (2) OpenAI-compatible remote [1]: ")?;
+        match input.trim().to_ascii_lowercase().as_str() {
+            "" | "1" | "ollama" | "local" => return Ok(ProviderChoice::Ollama),
+            "2" | "remote" | "openai" => return Ok(ProviderChoice::Remote),
+            _ => continue,
+        }
+    }
+}
+
+fn prompt_with_default(prompter: &mut dyn Prompter, label: &str, default: &str) -> Result<String> {
+    let input = prompter.ask(&format!("{} [{}]: ", label, default))?;
+    if input.trim().is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(input.trim().to_string())
+    }
+}
+
+fn prompt_optional_u32(prompter: &mut dyn Prompter, label: &str) -> Result<Option<u32>> {
+    let input = prompter.ask(&format!("{}: ", label))?;
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    input
+        .trim()
+        .parse::<u32>()
+        .map(Some)
+        .map_err(|e| ButterflyBotError::Config(e.to_string()))
+}
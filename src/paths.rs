@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::error::{ButterflyBotError, Result};
+
+pub const DATA_DIR_ENV: &str = "BUTTERFLY_BOT_DATA_DIR";
+const APP_DIR_NAME: &str = "butterfly-bot";
+
+/// Directory that holds the default databases, LanceDB tables and audit logs.
+///
+/// Resolution order is `BUTTERFLY_BOT_DATA_DIR`, then `$XDG_DATA_HOME/butterfly-bot`, and
+/// finally `./data` so existing installs keep working. A non-default profile
+/// (`BUTTERFLY_BOT_PROFILE`) gets its own `profiles/<name>` directory under that.
+pub fn data_dir() -> PathBuf {
+    data_dir_for_profile(crate::vault::active_profile().as_deref())
+}
+
+pub fn data_dir_for_profile(profile: Option<&str>) -> PathBuf {
+    let base = if let Some(dir) = non_empty_env(DATA_DIR_ENV) {
+        PathBuf::from(dir)
+    } else if let Some(xdg) = non_empty_env("XDG_DATA_HOME") {
+        PathBuf::from(xdg).join(APP_DIR_NAME)
+    } else {
+        PathBuf::from("./data")
+    };
+    match profile {
+        Some(profile) => base.join("profiles").join(profile),
+        None => base,
+    }
+}
+
+pub fn data_path(name: &str) -> String {
+    data_dir().join(name).to_string_lossy().into_owned()
+}
+
+pub fn default_db_path() -> String {
+    data_path("butterfly-bot.db")
+}
+
+pub fn default_lancedb_path() -> String {
+    data_path("lancedb")
+}
+
+/// Creates the parent directory of `path`. Directories are remembered once created, so the
+/// many stores opened against the same database only touch the filesystem the first time.
+pub fn ensure_parent_dir(path: &str) -> Result<()> {
+    let Some(parent) = Path::new(path).parent() else {
+        return Ok(());
+    };
+    if parent.as_os_str().is_empty() {
+        return Ok(());
+    }
+
+    static CREATED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    let created = CREATED.get_or_init(|| Mutex::new(HashSet::new()));
+    let mut guard = created
+        .lock()
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    if guard.contains(parent) && parent.is_dir() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    guard.insert(parent.to_path_buf());
+    Ok(())
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
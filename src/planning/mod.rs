@@ -1,4 +1,3 @@
-use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use diesel::prelude::*;
@@ -8,16 +7,19 @@ use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::db::retry_on_busy;
 use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
 use schema::plans;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 const PLANS_UP_SQL: &str = include_str!("../../migrations/20260202_create_plans/up.sql");
+const INACTIVE_PLAN_STATUSES: &[&str] = &["completed", "done", "cancelled", "archived"];
 
 type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
 type SqlitePool = Pool<SqliteAsyncConn>;
@@ -35,6 +37,20 @@ pub struct PlanItem {
     pub updated_at: i64,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub index: usize,
+    pub title: String,
+    pub status: String,
+    pub depends_on: Vec<usize>,
+}
+
+impl PlanStep {
+    pub fn is_done(&self) -> bool {
+        matches!(self.status.as_str(), "done" | "completed")
+    }
+}
+
 #[derive(Queryable)]
 struct PlanRow {
     id: i32,
@@ -86,33 +102,36 @@ impl PlanStore {
         steps: Option<&Value>,
         status: Option<&str>,
     ) -> Result<PlanItem> {
-        let now = now_ts();
-        let steps_json = steps.map(|value| value.to_string());
-        let status = status.unwrap_or("draft");
-        let new = NewPlan {
-            user_id,
-            title,
-            goal,
-            steps_json: steps_json.as_deref(),
-            status,
-            created_at: now,
-            updated_at: now,
-        };
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let steps_json = steps.map(|value| value.to_string());
+            let status = status.unwrap_or("draft");
+            let new = NewPlan {
+                user_id,
+                title,
+                goal,
+                steps_json: steps_json.as_deref(),
+                status,
+                created_at: now,
+                updated_at: now,
+            };
 
-        let mut conn = self.conn().await?;
-        diesel::insert_into(plans::table)
-            .values(&new)
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let mut conn = self.conn().await?;
+            diesel::insert_into(plans::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let row: PlanRow = plans::table
-            .filter(plans::user_id.eq(user_id))
-            .order(plans::id.desc())
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+            let row: PlanRow = plans::table
+                .filter(plans::user_id.eq(user_id))
+                .order(plans::id.desc())
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn list_plans(&self, user_id: &str, limit: usize) -> Result<Vec<PlanItem>> {
@@ -127,6 +146,19 @@ impl PlanStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
+    /// Plans count as active until they reach one of the terminal statuses.
+    pub async fn count_active(&self, user_id: &str) -> Result<u64> {
+        let mut conn = self.conn().await?;
+        let count: i64 = plans::table
+            .filter(plans::user_id.eq(user_id))
+            .filter(plans::status.ne_all(INACTIVE_PLAN_STATUSES))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(count as u64)
+    }
+
     pub async fn get_plan(&self, id: i32) -> Result<PlanItem> {
         let mut conn = self.conn().await?;
         let row: PlanRow = plans::table
@@ -137,6 +169,16 @@ impl PlanStore {
         Ok(map_row(row))
     }
 
+    /// Steps that are not yet done and whose `depends_on` steps all are, in plan order.
+    pub async fn ready_steps(&self, plan_id: i32) -> Result<Vec<PlanStep>> {
+        let plan = self.get_plan(plan_id).await?;
+        let steps = match plan.steps.as_ref() {
+            Some(steps) => parse_steps(steps)?,
+            None => Vec::new(),
+        };
+        ready_steps(&steps)
+    }
+
     pub async fn update_plan(
         &self,
         id: i32,
@@ -145,56 +187,62 @@ impl PlanStore {
         steps: Option<&Value>,
         status: Option<&str>,
     ) -> Result<PlanItem> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let mut conn = self.conn().await?;
 
-        if let Some(title) = title {
-            diesel::update(plans::table.filter(plans::id.eq(id)))
-                .set((plans::title.eq(title), plans::updated_at.eq(now)))
-                .execute(&mut conn)
-                .await
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
-        if let Some(goal) = goal {
-            diesel::update(plans::table.filter(plans::id.eq(id)))
-                .set((plans::goal.eq(goal), plans::updated_at.eq(now)))
-                .execute(&mut conn)
-                .await
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
-        if let Some(steps) = steps {
-            diesel::update(plans::table.filter(plans::id.eq(id)))
-                .set((
-                    plans::steps_json.eq(Some(steps.to_string())),
-                    plans::updated_at.eq(now),
-                ))
-                .execute(&mut conn)
-                .await
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
-        if let Some(status) = status {
-            diesel::update(plans::table.filter(plans::id.eq(id)))
-                .set((plans::status.eq(status), plans::updated_at.eq(now)))
-                .execute(&mut conn)
+            if let Some(title) = title {
+                diesel::update(plans::table.filter(plans::id.eq(id)))
+                    .set((plans::title.eq(title), plans::updated_at.eq(now)))
+                    .execute(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+            if let Some(goal) = goal {
+                diesel::update(plans::table.filter(plans::id.eq(id)))
+                    .set((plans::goal.eq(goal), plans::updated_at.eq(now)))
+                    .execute(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+            if let Some(steps) = steps {
+                diesel::update(plans::table.filter(plans::id.eq(id)))
+                    .set((
+                        plans::steps_json.eq(Some(steps.to_string())),
+                        plans::updated_at.eq(now),
+                    ))
+                    .execute(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+            if let Some(status) = status {
+                diesel::update(plans::table.filter(plans::id.eq(id)))
+                    .set((plans::status.eq(status), plans::updated_at.eq(now)))
+                    .execute(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+
+            let row: PlanRow = plans::table
+                .filter(plans::id.eq(id))
+                .first(&mut conn)
                 .await
                 .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
-
-        let row: PlanRow = plans::table
-            .filter(plans::id.eq(id))
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn delete_plan(&self, id: i32) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let count = diesel::delete(plans::table.filter(plans::id.eq(id)))
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(count > 0)
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let count = diesel::delete(plans::table.filter(plans::id.eq(id)))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(count > 0)
+        })
+        .await
     }
 
     async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
@@ -208,6 +256,103 @@ impl PlanStore {
     }
 }
 
+/// Normalizes stored steps, which may be plain strings or objects with `title`, `status` and
+/// `depends_on` (zero-based step indexes).
+pub fn parse_steps(steps: &Value) -> Result<Vec<PlanStep>> {
+    let items = steps
+        .as_array()
+        .ok_or_else(|| ButterflyBotError::Runtime("Plan steps must be an array".to_string()))?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| match item {
+            Value::String(title) => Ok(PlanStep {
+                index,
+                title: title.clone(),
+                status: "pending".to_string(),
+                depends_on: Vec::new(),
+            }),
+            Value::Object(map) => {
+                let title = map
+                    .get("title")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                let status = map
+                    .get("status")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("pending")
+                    .to_string();
+                let depends_on = match map.get("depends_on") {
+                    None | Some(Value::Null) => Vec::new(),
+                    Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+                        ButterflyBotError::Runtime(format!(
+                            "Invalid depends_on for step {index}: {e}"
+                        ))
+                    })?,
+                };
+                Ok(PlanStep {
+                    index,
+                    title,
+                    status,
+                    depends_on,
+                })
+            }
+            _ => Err(ButterflyBotError::Runtime(format!(
+                "Invalid plan step at index {index}"
+            ))),
+        })
+        .collect()
+}
+
+pub fn ready_steps(steps: &[PlanStep]) -> Result<Vec<PlanStep>> {
+    validate_dependencies(steps)?;
+    Ok(steps
+        .iter()
+        .filter(|step| !step.is_done())
+        .filter(|step| step.depends_on.iter().all(|dep| steps[*dep].is_done()))
+        .cloned()
+        .collect())
+}
+
+/// Rejects out-of-range references and dependency cycles (Kahn's algorithm).
+pub fn validate_dependencies(steps: &[PlanStep]) -> Result<()> {
+    let mut remaining = vec![0usize; steps.len()];
+    let mut dependents = vec![Vec::new(); steps.len()];
+    for step in steps {
+        for &dep in &step.depends_on {
+            if dep >= steps.len() || dep == step.index {
+                return Err(ButterflyBotError::Runtime(format!(
+                    "Step {} has invalid dependency {}",
+                    step.index, dep
+                )));
+            }
+            remaining[step.index] += 1;
+            dependents[dep].push(step.index);
+        }
+    }
+
+    let mut queue: Vec<usize> = (0..steps.len()).filter(|i| remaining[*i] == 0).collect();
+    let mut visited = 0;
+    while let Some(index) = queue.pop() {
+        visited += 1;
+        for &next in &dependents[index] {
+            remaining[next] -= 1;
+            if remaining[next] == 0 {
+                queue.push(next);
+            }
+        }
+    }
+    if visited != steps.len() {
+        let cyclic: Vec<usize> = (0..steps.len()).filter(|i| remaining[*i] > 0).collect();
+        return Err(ButterflyBotError::Runtime(format!(
+            "Plan steps have a dependency cycle involving {:?}",
+            cyclic
+        )));
+    }
+    Ok(())
+}
+
 pub fn resolve_plan_db_path(config: &serde_json::Value) -> Option<String> {
     config
         .get("tools")
@@ -219,15 +364,7 @@ pub fn resolve_plan_db_path(config: &serde_json::Value) -> Option<String> {
 }
 
 pub fn default_plan_db_path() -> String {
-    "./data/butterfly-bot.db".to_string()
-}
-
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
+    crate::paths::default_db_path()
 }
 
 async fn run_migrations(database_url: &str) -> Result<()> {
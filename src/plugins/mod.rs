@@ -1,2 +1,3 @@
 pub mod manager;
 pub mod registry;
+pub mod result_format;
@@ -2,13 +2,29 @@ use std::collections::{HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use serde::Serialize;
 use tokio::sync::RwLock;
 
 use crate::config_store;
 use crate::error::{ButterflyBotError, Result};
 use crate::interfaces::plugins::Tool;
+use crate::plugins::result_format::ToolResultFormat;
+use crate::tool_audit::ToolAuditStore;
+
+/// Stands in for argument values masked by `redact_args`.
+pub const REDACTED: &str = "[redacted]";
+
+/// Tool results larger than this are cut down before going back to the model.
+pub const DEFAULT_MAX_TOOL_RESULT_BYTES: usize = 32 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolSchema {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
 
 #[derive(Default)]
 pub struct ToolRegistry {
@@ -16,6 +32,11 @@ pub struct ToolRegistry {
     agent_tools: RwLock<HashMap<String, HashSet<String>>>,
     config: RwLock<serde_json::Value>,
     audit_log_path: RwLock<Option<String>>,
+    max_result_bytes: RwLock<usize>,
+    result_format: RwLock<ToolResultFormat>,
+    audit_store: RwLock<Option<Arc<ToolAuditStore>>>,
+    /// Held shared by running tool calls and exclusively while reconfiguring.
+    execution_gate: RwLock<()>,
 }
 
 impl ToolRegistry {
@@ -24,14 +45,22 @@ impl ToolRegistry {
             tools: RwLock::new(HashMap::new()),
             agent_tools: RwLock::new(HashMap::new()),
             config: RwLock::new(serde_json::Value::Object(Default::default())),
-            audit_log_path: RwLock::new(Some("./data/tool_audit.log".to_string())),
+            audit_log_path: RwLock::new(Some(crate::paths::data_path("tool_audit.log"))),
+            max_result_bytes: RwLock::new(DEFAULT_MAX_TOOL_RESULT_BYTES),
+            result_format: RwLock::new(ToolResultFormat::default()),
+            audit_store: RwLock::new(None),
+            execution_gate: RwLock::new(()),
         }
     }
 
     pub async fn register_tool(&self, tool: Arc<dyn Tool>) -> bool {
         let config = self.config.read().await.clone();
-        if let Err(err) = tool.configure(&config) {
-            let _ = err;
+        let result = if isolate_tool_config(&config) {
+            tool.configure(&tool_config_slice(&config, tool.as_ref()))
+        } else {
+            tool.configure(&config)
+        };
+        if result.is_err() {
             return false;
         }
         let mut tools = self.tools.write().await;
@@ -64,19 +93,127 @@ impl ToolRegistry {
     pub async fn get_agent_tools(&self, agent_name: &str) -> Vec<Arc<dyn Tool>> {
         let agent_tools = self.agent_tools.read().await;
         let tools = self.tools.read().await;
+        let config = self.config.read().await;
         let names = agent_tools.get(agent_name).cloned().unwrap_or_default();
         names
             .into_iter()
+            .filter(|name| tool_enabled(&config, name))
             .filter_map(|name| tools.get(&name).cloned())
             .collect()
     }
 
+    /// Schemas for every enabled tool, or only those on `agent_name`'s allowlist when given.
+    pub async fn tool_schemas(&self, agent_name: Option<&str>) -> Vec<ToolSchema> {
+        let tools = match agent_name {
+            Some(agent_name) => self.get_agent_tools(agent_name).await,
+            None => {
+                let tools = self.tools.read().await;
+                let config = self.config.read().await;
+                tools
+                    .iter()
+                    .filter(|(name, _)| tool_enabled(&config, name))
+                    .map(|(_, tool)| tool.clone())
+                    .collect()
+            }
+        };
+        let mut schemas: Vec<ToolSchema> = tools
+            .into_iter()
+            .map(|tool| ToolSchema {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.parameters(),
+            })
+            .collect();
+        schemas.sort_by(|a, b| a.name.cmp(&b.name));
+        schemas
+    }
+
+    /// Runs a tool call on behalf of `user_id`, the authenticated caller,
+    /// waiting out any reconfiguration so the tool is never observed
+    /// half-configured. Oversized results are truncated, and the run is
+    /// written to the audit store, under `user_id`, when one is set.
+    pub async fn execute_tool(
+        &self,
+        user_id: &str,
+        tool: &dyn Tool,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let _gate = self.execution_gate.read().await;
+        let audit = match self.audit_store.read().await.clone() {
+            Some(store) => Some((store, self.redact_args(tool.name(), &params).await)),
+            None => None,
+        };
+        let started = Instant::now();
+        let outcome = tool.execute(params).await;
+        if let Some((store, params)) = audit {
+            let status = if outcome.is_ok() { "success" } else { "error" };
+            let duration_ms = started.elapsed().as_millis() as i64;
+            let _ = store
+                .record(user_id, tool.name(), &params, status, duration_ms)
+                .await;
+        }
+        let max_bytes = *self.max_result_bytes.read().await;
+        Ok(truncate_tool_result(outcome?, max_bytes))
+    }
+
+    /// `args` with every top-level key listed in `tools.<tool>.redact_args`
+    /// masked, for anything that logs or emits tool arguments.
+    pub async fn redact_args(
+        &self,
+        tool_name: &str,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let config = self.config.read().await;
+        let Some(keys) = config
+            .get("tools")
+            .and_then(|tools| tools.get(tool_name))
+            .and_then(|tool| tool.get("redact_args"))
+            .and_then(|keys| keys.as_array())
+        else {
+            return args.clone();
+        };
+        let mut redacted = args.clone();
+        if let serde_json::Value::Object(map) = &mut redacted {
+            for key in keys.iter().filter_map(|key| key.as_str()) {
+                if let Some(value) = map.get_mut(key) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                }
+            }
+        }
+        redacted
+    }
+
+    pub async fn set_audit_store(&self, store: Option<Arc<ToolAuditStore>>) {
+        *self.audit_store.write().await = store;
+    }
+
+    pub async fn audit_store(&self) -> Option<Arc<ToolAuditStore>> {
+        self.audit_store.read().await.clone()
+    }
+
+    pub async fn set_max_result_bytes(&self, max_bytes: usize) {
+        *self.max_result_bytes.write().await = max_bytes;
+    }
+
+    pub async fn max_result_bytes(&self) -> usize {
+        *self.max_result_bytes.read().await
+    }
+
+    pub async fn set_result_format(&self, format: ToolResultFormat) {
+        *self.result_format.write().await = format;
+    }
+
+    pub async fn result_format(&self) -> ToolResultFormat {
+        *self.result_format.read().await
+    }
+
     pub async fn list_all_tools(&self) -> Vec<String> {
         let tools = self.tools.read().await;
         tools.keys().cloned().collect()
     }
 
     pub async fn configure_all_tools(&self, config: serde_json::Value) -> Result<()> {
+        let _gate = self.execution_gate.write().await;
         {
             let mut cfg = self.config.write().await;
             *cfg = config.clone();
@@ -94,17 +231,58 @@ impl ToolRegistry {
                     *guard = Some(path.to_string());
                 }
             }
+            if let Some(max_bytes) = settings
+                .get("max_tool_result_bytes")
+                .and_then(|v| v.as_u64())
+            {
+                *self.max_result_bytes.write().await = max_bytes as usize;
+            }
+            if let Some(format) = settings.get("tool_result_format").and_then(|v| v.as_str()) {
+                let format = ToolResultFormat::parse(format).ok_or_else(|| {
+                    ButterflyBotError::Config(format!("Unknown tool_result_format '{format}'"))
+                })?;
+                *self.result_format.write().await = format;
+            }
         }
 
+        let isolate = isolate_tool_config(&config);
         let tools = self.tools.read().await;
         for tool in tools.values() {
-            tool.configure(&config)
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let result = if isolate {
+                tool.configure(&tool_config_slice(&config, tool.as_ref()))
+            } else {
+                tool.configure(&config)
+            };
+            result.map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
         }
         Ok(())
     }
 
-    pub async fn audit_tool_call(&self, tool_name: &str, status: &str) -> Result<()> {
+    /// Audits a call that never reached `execute_tool` (invalid arguments, a
+    /// guardrail block, an unknown tool) in both the audit log and the audit
+    /// store, so the store sees every call the model made.
+    pub async fn audit_rejected_call(
+        &self,
+        user_id: &str,
+        tool_name: &str,
+        params: &serde_json::Value,
+        status: &str,
+    ) -> Result<()> {
+        self.audit_tool_call(user_id, tool_name, status).await?;
+        if let Some(store) = self.audit_store().await {
+            let params = self.redact_args(tool_name, params).await;
+            store.record(user_id, tool_name, &params, status, 0).await?;
+        }
+        Ok(())
+    }
+
+    /// Appends a line for `user_id`'s call to the audit log, if one is set.
+    pub async fn audit_tool_call(
+        &self,
+        user_id: &str,
+        tool_name: &str,
+        status: &str,
+    ) -> Result<()> {
         let path = self.audit_log_path.read().await.clone();
         let Some(path) = path else {
             return Ok(());
@@ -117,6 +295,7 @@ impl ToolRegistry {
             .as_secs();
         let payload = serde_json::json!({
             "timestamp": ts,
+            "user_id": user_id,
             "tool": tool_name,
             "status": status,
         });
@@ -130,3 +309,99 @@ impl ToolRegistry {
         Ok(())
     }
 }
+
+/// Whether tools get only [`tool_config_slice`] rather than the whole config
+/// (`tools.settings.isolate_tool_config`, on by default).
+fn isolate_tool_config(config: &serde_json::Value) -> bool {
+    config
+        .get("tools")
+        .and_then(|v| v.get("settings"))
+        .and_then(|v| v.get("isolate_tool_config"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+/// The part of `config` a tool is configured with: its own `tools.<name>`
+/// section, `tools.settings`, and the top-level sections it names in
+/// [`Tool::config_sections`].
+///
+/// Secrets named by [`Tool::required_secrets_for_config`] live in the vault
+/// and are fetched by the tool itself, so they never pass through here; the
+/// slice only has to keep other tools' config sections (and any keys written
+/// inline there) out of reach. `config_sections` is for plain config a tool
+/// falls back on, such as `openai` for `search_internet`.
+pub fn tool_config_slice(config: &serde_json::Value, tool: &dyn Tool) -> serde_json::Value {
+    let mut tools = serde_json::Map::new();
+    for key in [tool.name(), "settings"] {
+        if let Some(section) = config.get("tools").and_then(|v| v.get(key)) {
+            tools.insert(key.to_string(), section.clone());
+        }
+    }
+    let mut slice = serde_json::Map::new();
+    slice.insert("tools".to_string(), serde_json::Value::Object(tools));
+    for key in tool.config_sections() {
+        if let Some(section) = config.get(key) {
+            slice.insert(key.to_string(), section.clone());
+        }
+    }
+    serde_json::Value::Object(slice)
+}
+
+/// Cuts a result whose text (the string itself, or the JSON for anything
+/// else) exceeds `max_bytes` down to that many bytes plus a notice. A limit
+/// of 0 turns truncation off.
+pub fn truncate_tool_result(result: serde_json::Value, max_bytes: usize) -> serde_json::Value {
+    let text = match &result {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return result;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    serde_json::Value::String(format!(
+        "{}\n[truncated: tool result was {} bytes, showing the first {}]",
+        &text[..end],
+        text.len(),
+        end
+    ))
+}
+
+/// A tool is on unless its config section sets `"enabled": false`.
+fn tool_enabled(config: &serde_json::Value, tool_name: &str) -> bool {
+    config
+        .get("tools")
+        .and_then(|tools| tools.get(tool_name))
+        .and_then(|tool| tool.get("enabled"))
+        .and_then(|enabled| enabled.as_bool())
+        .unwrap_or(true)
+}
+
+/// Each way `params` breaks the tool's schema, as `{field, message}`. A schema
+/// that doesn't compile is the tool's bug, not the model's, so it is an error
+/// rather than a pass.
+pub fn validate_tool_params(
+    tool: &dyn Tool,
+    params: &serde_json::Value,
+) -> Result<Vec<serde_json::Value>> {
+    let schema = tool.parameters();
+    let validator = jsonschema::validator_for(&schema).map_err(|err| {
+        ButterflyBotError::Config(format!(
+            "Tool '{}' has an invalid parameter schema: {err}",
+            tool.name()
+        ))
+    })?;
+    Ok(validator
+        .iter_errors(params)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            serde_json::json!({
+                "field": if path.is_empty() { "/".to_string() } else { path },
+                "message": error.to_string(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{ButterflyBotError, Result};
+
+/// How tool results are written into the prompt for the model's next turn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolResultFormat {
+    #[default]
+    Json,
+    Yaml,
+    Markdown,
+}
+
+impl ToolResultFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Renders one round's results (each `{id, tool, status, ...}`).
+    pub fn render(&self, results: &[Value]) -> Result<String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(results)
+                .map_err(|e| ButterflyBotError::Serialization(e.to_string())),
+            Self::Yaml => {
+                let mut out = String::new();
+                write_yaml(&mut out, &Value::Array(results.to_vec()), 0);
+                Ok(out.trim_end().to_string())
+            }
+            Self::Markdown => Ok(results
+                .iter()
+                .map(markdown_section)
+                .collect::<Vec<_>>()
+                .join("\n\n")),
+        }
+    }
+}
+
+fn write_yaml(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                out.push_str(&format!("{pad}{}:", yaml_key(key)));
+                write_yaml_child(out, value, indent);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                out.push_str(&format!("{pad}-"));
+                match item {
+                    // The first key sits on the dash line, the rest line up under it.
+                    Value::Object(map) if !map.is_empty() => {
+                        let mut nested = String::new();
+                        write_yaml(&mut nested, item, indent + 2);
+                        out.push(' ');
+                        out.push_str(nested.trim_start());
+                    }
+                    _ => write_yaml_child(out, item, indent),
+                }
+            }
+        }
+        scalar => out.push_str(&format!("{pad}{}\n", yaml_scalar(scalar))),
+    }
+}
+
+fn write_yaml_child(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_yaml(out, value, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_yaml(out, value, indent + 2);
+        }
+        scalar => out.push_str(&format!(" {}\n", yaml_scalar(scalar))),
+    }
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if plain {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+// JSON string syntax is valid YAML double-quoted style, so strings are
+// always quoted and never mistaken for numbers, booleans or nulls.
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn markdown_section(result: &Value) -> String {
+    let field = |key: &str| result.get(key).and_then(Value::as_str).unwrap_or("");
+    let mut out = format!(
+        "### {} (`{}`): {}",
+        field("tool"),
+        field("id"),
+        field("status")
+    );
+    let Value::Object(map) = result else {
+        return out;
+    };
+    for (key, value) in map {
+        if matches!(key.as_str(), "id" | "tool" | "status") {
+            continue;
+        }
+        match value {
+            Value::String(text) if !text.contains('\n') => {
+                out.push_str(&format!("\n\n**{key}:** {text}"));
+            }
+            Value::String(text) => out.push_str(&format!("\n\n**{key}:**\n\n{text}")),
+            other => {
+                let pretty = serde_json::to_string_pretty(other).unwrap_or_default();
+                out.push_str(&format!("\n\n**{key}:**\n\n```json\n{pretty}\n```"));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use lru::LruCache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::error::{ButterflyBotError, Result};
+use crate::interfaces::providers::{ChatEvent, ImageInput, LlmProvider, LlmResponse, ModelInfo};
+
+pub const DEFAULT_EMBEDDING_CACHE_SIZE: usize = 1024;
+
+/// Wraps an [`LlmProvider`] and answers repeated `embed` inputs from a bounded
+/// LRU keyed by a hash of the model and text. Everything else is passed through.
+pub struct CachingProvider {
+    inner: Arc<dyn LlmProvider>,
+    cache: Mutex<LruCache<String, Vec<f32>>>,
+    persist_path: Option<PathBuf>,
+}
+
+impl CachingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+            persist_path: None,
+        }
+    }
+
+    /// Loads previously cached embeddings from `path` (if it exists) and writes
+    /// the cache back there whenever new embeddings are added.
+    pub fn with_persistence(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            let raw = std::fs::read_to_string(&path)
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let entries: Vec<(String, Vec<f32>)> = serde_json::from_str(&raw)
+                .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+            let cache = self.cache.get_mut();
+            for (key, vector) in entries {
+                cache.put(key, vector);
+            }
+        }
+        self.persist_path = Some(path);
+        Ok(self)
+    }
+
+    pub async fn cached_len(&self) -> usize {
+        self.cache.lock().await.len()
+    }
+
+    fn cache_key(model: Option<&str>, input: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(model.unwrap_or("default").as_bytes());
+        hasher.update([0u8]);
+        hasher.update(input.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn persist(&self, cache: &LruCache<String, Vec<f32>>) -> Result<()> {
+        let Some(path) = &self.persist_path else {
+            return Ok(());
+        };
+        crate::paths::ensure_parent_dir(&path.to_string_lossy())?;
+        // Oldest first, so reloading restores the same recency order.
+        let entries: Vec<(&String, &Vec<f32>)> = cache.iter().rev().collect();
+        let raw = serde_json::to_string(&entries)
+            .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+        std::fs::write(path, raw).map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachingProvider {
+    async fn generate_text(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        tools: Option<Vec<Value>>,
+    ) -> Result<String> {
+        self.inner.generate_text(prompt, system_prompt, tools).await
+    }
+
+    async fn generate_with_tools(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        tools: Vec<Value>,
+    ) -> Result<LlmResponse> {
+        self.inner
+            .generate_with_tools(prompt, system_prompt, tools)
+            .await
+    }
+
+    fn chat_stream(
+        &self,
+        messages: Vec<Value>,
+        tools: Option<Vec<Value>>,
+    ) -> BoxStream<'static, Result<ChatEvent>> {
+        self.inner.chat_stream(messages, tools)
+    }
+
+    async fn parse_structured_output(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        json_schema: Value,
+        tools: Option<Vec<Value>>,
+    ) -> Result<Value> {
+        self.inner
+            .parse_structured_output(prompt, system_prompt, json_schema, tools)
+            .await
+    }
+
+    async fn tts(&self, text: &str, voice: &str, response_format: &str) -> Result<Vec<u8>> {
+        self.inner.tts(text, voice, response_format).await
+    }
+
+    async fn transcribe_audio(&self, audio_bytes: Vec<u8>, input_format: &str) -> Result<String> {
+        self.inner.transcribe_audio(audio_bytes, input_format).await
+    }
+
+    async fn generate_text_with_images(
+        &self,
+        prompt: &str,
+        images: Vec<ImageInput>,
+        system_prompt: &str,
+        detail: &str,
+        tools: Option<Vec<Value>>,
+    ) -> Result<String> {
+        self.inner
+            .generate_text_with_images(prompt, images, system_prompt, detail, tools)
+            .await
+    }
+
+    async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        let keys: Vec<String> = inputs
+            .iter()
+            .map(|input| Self::cache_key(model, input))
+            .collect();
+
+        let mut found: HashMap<String, Vec<f32>> = HashMap::new();
+        let mut misses = Vec::new();
+        let mut miss_keys = Vec::new();
+        {
+            let mut cache = self.cache.lock().await;
+            let mut pending = HashSet::new();
+            for (input, key) in inputs.iter().zip(&keys) {
+                if let Some(vector) = cache.get(key) {
+                    found.insert(key.clone(), vector.clone());
+                } else if pending.insert(key.clone()) {
+                    misses.push(input.clone());
+                    miss_keys.push(key.clone());
+                }
+            }
+        }
+
+        if !misses.is_empty() {
+            let vectors = self.inner.embed(misses, model).await?;
+            if vectors.len() != miss_keys.len() {
+                return Err(ButterflyBotError::Runtime(format!(
+                    "Embedding provider returned {} vectors for {} inputs",
+                    vectors.len(),
+                    miss_keys.len()
+                )));
+            }
+            let mut cache = self.cache.lock().await;
+            for (key, vector) in miss_keys.into_iter().zip(vectors) {
+                cache.put(key.clone(), vector.clone());
+                found.insert(key, vector);
+            }
+            self.persist(&cache)?;
+        }
+
+        Ok(keys
+            .iter()
+            .map(|key| found.get(key).cloned().unwrap_or_default())
+            .collect())
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.inner.list_models().await
+    }
+}
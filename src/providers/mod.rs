@@ -1,3 +1,6 @@
+pub mod caching;
 pub mod memory;
+pub mod namespaced;
 pub mod openai;
+pub mod rate_limit;
 pub mod sqlite;
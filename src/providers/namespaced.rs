@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::interfaces::providers::{
+    ChatSummary, MemoryProvider, MessageBranch, PinnedContext, ReindexReport, SearchModels,
+    StoredMessage,
+};
+
+pub struct NamespacedMemoryProvider {
+    inner: Arc<dyn MemoryProvider>,
+    namespace: String,
+}
+
+impl NamespacedMemoryProvider {
+    pub fn new(inner: Arc<dyn MemoryProvider>, namespace: impl Into<String>) -> Self {
+        Self {
+            inner,
+            namespace: namespace.into(),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn scoped(&self, user_id: &str) -> String {
+        format!("{}::{}", self.namespace, user_id)
+    }
+}
+
+#[async_trait]
+impl MemoryProvider for NamespacedMemoryProvider {
+    async fn append_message(&self, user_id: &str, role: &str, content: &str) -> Result<()> {
+        self.inner
+            .append_message(&self.scoped(user_id), role, content)
+            .await
+    }
+
+    async fn get_history(&self, user_id: &str, limit: usize) -> Result<Vec<String>> {
+        self.inner.get_history(&self.scoped(user_id), limit).await
+    }
+
+    async fn clear_history(&self, user_id: &str) -> Result<()> {
+        self.inner.clear_history(&self.scoped(user_id)).await
+    }
+
+    fn find(
+        &self,
+        collection: &str,
+        query: Value,
+        sort: Option<Vec<(String, i32)>>,
+        limit: Option<u64>,
+        skip: Option<u64>,
+    ) -> Result<Vec<Value>> {
+        self.inner.find(collection, query, sort, limit, skip)
+    }
+
+    fn count_documents(&self, collection: &str, query: Value) -> Result<u64> {
+        self.inner.count_documents(collection, query)
+    }
+
+    async fn search(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<String>> {
+        self.inner.search(&self.scoped(user_id), query, limit).await
+    }
+
+    async fn search_with_min_score(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<String>> {
+        self.inner
+            .search_with_min_score(&self.scoped(user_id), query, limit, min_score)
+            .await
+    }
+
+    async fn search_with_models(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        min_score: Option<f32>,
+        models: &SearchModels,
+    ) -> Result<Vec<String>> {
+        self.inner
+            .search_with_models(&self.scoped(user_id), query, limit, min_score, models)
+            .await
+    }
+
+    async fn get_message(&self, user_id: &str, message_id: i64) -> Result<Option<StoredMessage>> {
+        self.inner
+            .get_message(&self.scoped(user_id), message_id)
+            .await
+    }
+
+    async fn get_messages(
+        &self,
+        user_id: &str,
+        before_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>> {
+        self.inner
+            .get_messages(&self.scoped(user_id), before_id, limit)
+            .await
+    }
+
+    async fn add_branch(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        content: &str,
+    ) -> Result<MessageBranch> {
+        self.inner
+            .add_branch(&self.scoped(user_id), message_id, content)
+            .await
+    }
+
+    async fn get_branches(&self, user_id: &str, message_id: i64) -> Result<Vec<MessageBranch>> {
+        self.inner
+            .get_branches(&self.scoped(user_id), message_id)
+            .await
+    }
+
+    async fn truncate_from(&self, user_id: &str, message_id: i64) -> Result<u64> {
+        self.inner
+            .truncate_from(&self.scoped(user_id), message_id)
+            .await
+    }
+
+    async fn pin_context(&self, user_id: &str, content: &str) -> Result<PinnedContext> {
+        self.inner.pin_context(&self.scoped(user_id), content).await
+    }
+
+    async fn unpin_context(&self, user_id: &str, pin_id: i64) -> Result<bool> {
+        self.inner
+            .unpin_context(&self.scoped(user_id), pin_id)
+            .await
+    }
+
+    async fn pinned_context(&self, user_id: &str) -> Result<Vec<PinnedContext>> {
+        self.inner.pinned_context(&self.scoped(user_id)).await
+    }
+
+    async fn list_chats(&self, include_archived: bool) -> Result<Vec<ChatSummary>> {
+        let prefix = self.scoped("");
+        let chats = self.inner.list_chats(include_archived).await?;
+        Ok(chats
+            .into_iter()
+            .filter_map(|mut chat| {
+                chat.user_id = chat.user_id.strip_prefix(&prefix)?.to_string();
+                Some(chat)
+            })
+            .collect())
+    }
+
+    async fn archive_chat(&self, user_id: &str) -> Result<bool> {
+        self.inner.archive_chat(&self.scoped(user_id)).await
+    }
+
+    async fn unarchive_chat(&self, user_id: &str) -> Result<bool> {
+        self.inner.unarchive_chat(&self.scoped(user_id)).await
+    }
+
+    /// The sweep covers the whole store, so ids come back as stored.
+    async fn archive_idle_chats(&self, idle_before: i64) -> Result<Vec<String>> {
+        self.inner.archive_idle_chats(idle_before).await
+    }
+
+    async fn reindex_memory(
+        &self,
+        user_id: &str,
+        resume_after: Option<i64>,
+    ) -> Result<ReindexReport> {
+        let mut report = self
+            .inner
+            .reindex_memory(&self.scoped(user_id), resume_after)
+            .await?;
+        report.user_id = user_id.to_string();
+        Ok(report)
+    }
+}
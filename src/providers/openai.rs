@@ -1,12 +1,17 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use async_stream::try_stream;
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use futures::stream::BoxStream;
 use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
 
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config as _, OpenAIConfig},
     types::{
         audio::{
             AudioInput, AudioResponseFormat, CreateSpeechRequestArgs,
@@ -18,24 +23,35 @@ use async_openai::{
             ChatCompletionRequestMessageContentPartText, ChatCompletionRequestSystemMessageArgs,
             ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
             ChatCompletionRequestUserMessageContentPart, ChatCompletionTool, ChatCompletionTools,
-            CreateChatCompletionRequestArgs, FunctionCall, FunctionObject, ImageDetail, ImageUrl,
+            CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+            CreateChatCompletionResponse, FunctionCall, FunctionObject, ImageDetail, ImageUrl,
             ResponseFormat, ResponseFormatJsonSchema,
         },
-        embeddings::{CreateEmbeddingRequestArgs, EmbeddingInput},
+        embeddings::{CreateEmbeddingRequestArgs, CreateEmbeddingResponse, EmbeddingInput},
         InputSource,
     },
     Client,
 };
 
+use crate::config::{EmbeddingLimit, EmbeddingOverflow, JsonMode};
+use crate::costs::estimate_tokens;
 use crate::error::{ButterflyBotError, Result};
+pub use crate::interfaces::providers::GenerationParams;
 use crate::interfaces::providers::{
-    ChatEvent, ImageData, ImageInput, LlmProvider, LlmResponse, ToolCall,
+    assign_tool_call_ids, ChatEvent, ImageData, ImageInput, LlmProvider, LlmResponse, ModelInfo,
+    TokenUsage, ToolCall,
 };
+use crate::providers::rate_limit::send_with_retry;
 
 #[derive(Clone)]
 pub struct OpenAiProvider {
     model: String,
     client: Client<OpenAIConfig>,
+    /// Sends the JSON requests that go through [`send_with_retry`].
+    http: reqwest::Client,
+    generation: GenerationParams,
+    embedding_limit: Option<EmbeddingLimit>,
+    json_mode: JsonMode,
 }
 
 impl OpenAiProvider {
@@ -48,6 +64,118 @@ impl OpenAiProvider {
         Self {
             model,
             client: Client::with_config(config),
+            http: reqwest::Client::new(),
+            generation: GenerationParams::default(),
+            embedding_limit: None,
+            json_mode: JsonMode::default(),
+        }
+    }
+
+    /// Sends `headers` with every request, overriding built-in ones of the
+    /// same name.
+    pub fn with_headers(mut self, headers: &HashMap<String, String>) -> Result<Self> {
+        let mut config = self.client.config().clone();
+        for (name, value) in headers {
+            let header =
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                    ButterflyBotError::Config(format!("Invalid provider header name '{name}'"))
+                })?;
+            // The value may hold a secret, so it stays out of the error.
+            config = config.with_header(header, value.as_str()).map_err(|_| {
+                ButterflyBotError::Config(format!("Invalid value for provider header '{name}'"))
+            })?;
+        }
+        self.client = Client::with_config(config);
+        Ok(self)
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn with_generation(mut self, generation: GenerationParams) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn generation(&self) -> GenerationParams {
+        self.generation
+    }
+
+    /// How `parse_structured_output` asks for JSON; see [`JsonMode`].
+    pub fn with_json_mode(mut self, json_mode: JsonMode) -> Self {
+        self.json_mode = json_mode;
+        self
+    }
+
+    /// Applied by `embed` before inputs are sent.
+    pub fn with_embedding_limit(mut self, limit: EmbeddingLimit) -> Self {
+        self.embedding_limit = Some(limit);
+        self
+    }
+
+    async fn embed_raw(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        let model = model.unwrap_or(&self.model).to_string();
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(model)
+            .input(EmbeddingInput::StringArray(inputs))
+            .build()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let response: CreateEmbeddingResponse = self.post_json("/embeddings", &request).await?;
+        let mut data = response.data;
+        data.sort_by_key(|item| item.index);
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+
+    async fn create_chat(
+        &self,
+        request: &CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse> {
+        self.post_json("/chat/completions", request).await
+    }
+
+    /// POSTs `request` to `path` under the API base, waiting out rate limits.
+    async fn post_json<Req, Resp>(&self, path: &str, request: &Req) -> Result<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let config = self.client.config();
+        let url = config.url(path);
+        let headers = config.headers();
+        let response = send_with_retry(|| {
+            self.http
+                .post(&url)
+                .headers(headers.clone())
+                .query(&config.query())
+                .json(request)
+        })
+        .await?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        if !status.is_success() {
+            return Err(ButterflyBotError::Http(api_error_message(status, &body)));
+        }
+        serde_json::from_slice(&body).map_err(|e| ButterflyBotError::Serialization(e.to_string()))
+    }
+
+    fn apply_generation(&self, builder: &mut CreateChatCompletionRequestArgs) {
+        if let Some(temperature) = self.generation.temperature {
+            builder.temperature(temperature);
+        }
+        if let Some(top_p) = self.generation.top_p {
+            builder.top_p(top_p);
+        }
+        if let Some(max_tokens) = self.generation.max_tokens {
+            builder.max_completion_tokens(max_tokens);
         }
     }
 
@@ -176,16 +304,26 @@ impl OpenAiProvider {
             for call in tool_calls {
                 match call {
                     ChatCompletionMessageToolCalls::Function(function_call) => {
+                        let id = function_call.id.clone();
                         let name = function_call.function.name.clone();
                         let args = function_call.function.arguments.clone();
                         let arguments = serde_json::from_str(&args).unwrap_or(Value::String(args));
-                        calls.push(ToolCall { name, arguments });
+                        calls.push(ToolCall {
+                            id,
+                            name,
+                            arguments,
+                        });
                     }
                     ChatCompletionMessageToolCalls::Custom(custom_call) => {
+                        let id = custom_call.id.clone();
                         let name = custom_call.custom_tool.name.clone();
                         let args = custom_call.custom_tool.input.clone();
                         let arguments = serde_json::from_str(&args).unwrap_or(Value::String(args));
-                        calls.push(ToolCall { name, arguments });
+                        calls.push(ToolCall {
+                            id,
+                            name,
+                            arguments,
+                        });
                     }
                 }
             }
@@ -197,12 +335,14 @@ impl OpenAiProvider {
                 let parsed =
                     serde_json::from_str(arguments).unwrap_or(Value::String(arguments.clone()));
                 calls.push(ToolCall {
+                    id: String::new(),
                     name: name.clone(),
                     arguments: parsed,
                 });
             }
         }
 
+        assign_tool_call_ids(&mut calls);
         calls
     }
 
@@ -237,6 +377,7 @@ impl OpenAiProvider {
 
 #[async_trait]
 impl LlmProvider for OpenAiProvider {
+    #[tracing::instrument(name = "llm.generate_text", skip_all, fields(model = %self.model), err)]
     async fn generate_text(
         &self,
         prompt: &str,
@@ -251,6 +392,7 @@ impl LlmProvider for OpenAiProvider {
 
         let mut builder = CreateChatCompletionRequestArgs::default();
         builder.model(self.model.clone());
+        self.apply_generation(&mut builder);
         builder.messages(messages);
 
         if let Some(tools) = tools {
@@ -264,33 +406,58 @@ impl LlmProvider for OpenAiProvider {
             .build()
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await
-            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        let response = self.create_chat(&request).await?;
 
         Self::extract_text_from_response(&response)
     }
 
+    #[tracing::instrument(name = "llm.embed", skip_all, fields(inputs = inputs.len()), err)]
     async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
-        let model = model.unwrap_or(&self.model).to_string();
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(model)
-            .input(EmbeddingInput::StringArray(inputs))
-            .build()
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let Some(limit) = self.embedding_limit else {
+            return self.embed_raw(inputs, model).await;
+        };
+        let pieces = split_for_embedding(&inputs, limit)?;
+        let flat: Vec<String> = pieces.iter().flatten().cloned().collect();
+        let mut vectors = self.embed_raw(flat, model).await?.into_iter();
+        let mut combined = Vec::with_capacity(pieces.len());
+        for chunks in &pieces {
+            let chunk_vectors: Vec<Vec<f32>> = vectors.by_ref().take(chunks.len()).collect();
+            combined.push(weighted_average(chunks, &chunk_vectors));
+        }
+        Ok(combined)
+    }
+
+    /// Ollama serves the same `/models` listing as OpenAI.
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         let response = self
             .client
-            .embeddings()
-            .create(request)
+            .models()
+            .list()
             .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        let mut data = response.data;
-        data.sort_by_key(|item| item.index);
-        Ok(data.into_iter().map(|item| item.embedding).collect())
+            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        Ok(response
+            .data
+            .into_iter()
+            .map(|model| ModelInfo {
+                id: model.id,
+                owned_by: Some(model.owned_by).filter(|owner| !owner.is_empty()),
+            })
+            .collect())
     }
+
+    fn with_generation_overrides(
+        &self,
+        overrides: GenerationParams,
+    ) -> Option<Arc<dyn LlmProvider>> {
+        let generation = overrides.or(self.generation);
+        Some(Arc::new(self.clone().with_generation(generation)))
+    }
+    #[tracing::instrument(
+        name = "llm.generate_with_tools",
+        skip_all,
+        fields(model = %self.model, tools = tools.len()),
+        err
+    )]
     async fn generate_with_tools(
         &self,
         prompt: &str,
@@ -306,6 +473,7 @@ impl LlmProvider for OpenAiProvider {
         let tools = Self::convert_tools(tools);
         let mut builder = CreateChatCompletionRequestArgs::default();
         builder.model(self.model.clone());
+        self.apply_generation(&mut builder);
         builder.messages(messages);
         if !tools.is_empty() {
             builder.tools(tools);
@@ -315,17 +483,20 @@ impl LlmProvider for OpenAiProvider {
             .build()
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await
-            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        let response = self.create_chat(&request).await?;
 
         let text = Self::extract_text_from_response(&response).unwrap_or_default();
         let tool_calls = Self::extract_tool_calls_from_response(&response);
-
-        Ok(LlmResponse { text, tool_calls })
+        let usage = response.usage.as_ref().map(|usage| TokenUsage {
+            prompt_tokens: usage.prompt_tokens as u64,
+            completion_tokens: usage.completion_tokens as u64,
+        });
+
+        Ok(LlmResponse {
+            text,
+            tool_calls,
+            usage,
+        })
     }
 
     fn chat_stream(
@@ -357,6 +528,7 @@ impl LlmProvider for OpenAiProvider {
 
             let mut builder = CreateChatCompletionRequestArgs::default();
             builder.model(provider.model.clone());
+            provider.apply_generation(&mut builder);
             builder.messages(request_messages);
 
             if let Some(tools) = tools {
@@ -377,29 +549,42 @@ impl LlmProvider for OpenAiProvider {
                 .await
                 .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
 
+            let mut tool_call_ids: HashMap<u32, String> = HashMap::new();
             while let Some(item) = stream.next().await {
                 let response = item.map_err(|e| ButterflyBotError::Http(e.to_string()))?;
                 for choice in response.choices {
                     if let Some(delta) = choice.delta.content {
                         if !delta.is_empty() {
-                            yield ChatEvent {
-                                event_type: "content".to_string(),
-                                delta: Some(delta),
-                                name: None,
-                                arguments_delta: None,
-                                finish_reason: None,
-                                error: None,
+                            yield ChatEvent::Content { delta };
+                        }
+                    }
+                    if let Some(chunks) = choice.delta.tool_calls {
+                        for chunk in chunks {
+                            let id = match chunk.id.filter(|id| !id.is_empty()) {
+                                Some(id) => {
+                                    tool_call_ids.insert(chunk.index, id.clone());
+                                    id
+                                }
+                                None => tool_call_ids
+                                    .entry(chunk.index)
+                                    .or_insert_with(|| format!("call_{}", chunk.index))
+                                    .clone(),
+                            };
+                            let (name, arguments_delta) = match chunk.function {
+                                Some(function) => (function.name, function.arguments),
+                                None => (None, None),
+                            };
+                            yield ChatEvent::ToolCall {
+                                id,
+                                name,
+                                arguments_delta,
                             };
                         }
                     }
                     if let Some(reason) = choice.finish_reason {
-                        yield ChatEvent {
-                            event_type: "message_end".to_string(),
-                            delta: None,
-                            name: None,
-                            arguments_delta: None,
+                        yield ChatEvent::MessageEnd {
                             finish_reason: Some(format!("{reason:?}")),
-                            error: None,
+                            usage: None,
                         };
                     }
                 }
@@ -414,30 +599,43 @@ impl LlmProvider for OpenAiProvider {
         json_schema: Value,
         tools: Option<Vec<Value>>,
     ) -> Result<Value> {
+        let (system_prompt, response_format) = match self.json_mode {
+            JsonMode::JsonSchema => {
+                let name = json_schema
+                    .get("title")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("structured_output")
+                    .to_string();
+                let response_format = ResponseFormat::JsonSchema {
+                    json_schema: ResponseFormatJsonSchema {
+                        name,
+                        description: None,
+                        schema: Some(json_schema),
+                        strict: Some(true),
+                    },
+                };
+                (system_prompt.to_string(), Some(response_format))
+            }
+            JsonMode::JsonObject => (
+                schema_instructions(system_prompt, &json_schema),
+                Some(ResponseFormat::JsonObject),
+            ),
+            JsonMode::Prompt => (schema_instructions(system_prompt, &json_schema), None),
+        };
+
         let mut messages = Vec::new();
-        if let Some(system) = Self::build_system_message(system_prompt)? {
+        if let Some(system) = Self::build_system_message(&system_prompt)? {
             messages.push(system);
         }
         messages.push(Self::build_user_text_message(prompt)?);
 
-        let name = json_schema
-            .get("title")
-            .and_then(|v| v.as_str())
-            .unwrap_or("structured_output")
-            .to_string();
-        let response_format = ResponseFormat::JsonSchema {
-            json_schema: ResponseFormatJsonSchema {
-                name,
-                description: None,
-                schema: Some(json_schema),
-                strict: Some(true),
-            },
-        };
-
         let mut builder = CreateChatCompletionRequestArgs::default();
         builder.model(self.model.clone());
+        self.apply_generation(&mut builder);
         builder.messages(messages);
-        builder.response_format(response_format);
+        if let Some(response_format) = response_format {
+            builder.response_format(response_format);
+        }
 
         if let Some(tools) = tools {
             let tools = Self::convert_tools(tools);
@@ -450,15 +648,10 @@ impl LlmProvider for OpenAiProvider {
             .build()
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await
-            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        let response = self.create_chat(&request).await?;
 
         let content = Self::extract_text_from_response(&response)?;
-        let parsed = serde_json::from_str(&content)
+        let parsed = serde_json::from_str(strip_json_fence(&content))
             .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
         Ok(parsed)
     }
@@ -509,6 +702,7 @@ impl LlmProvider for OpenAiProvider {
         Ok(response.text)
     }
 
+    #[tracing::instrument(name = "llm.generate_text_with_images", skip_all, fields(model = %self.model), err)]
     async fn generate_text_with_images(
         &self,
         prompt: &str,
@@ -525,6 +719,7 @@ impl LlmProvider for OpenAiProvider {
 
         let mut builder = CreateChatCompletionRequestArgs::default();
         builder.model(self.model.clone());
+        self.apply_generation(&mut builder);
         builder.messages(messages);
 
         if let Some(tools) = tools {
@@ -538,13 +733,98 @@ impl LlmProvider for OpenAiProvider {
             .build()
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await
-            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        let response = self.create_chat(&request).await?;
 
         Self::extract_text_from_response(&response)
     }
 }
+
+/// The pieces each input is embedded as: one unless it is over the limit
+/// and `limit.overflow` is chunk-average.
+fn split_for_embedding(inputs: &[String], limit: EmbeddingLimit) -> Result<Vec<Vec<String>>> {
+    let max_chars = (limit.max_tokens.max(1) * 4) as usize;
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            if estimate_tokens(input) <= limit.max_tokens {
+                return Ok(vec![input.clone()]);
+            }
+            match limit.overflow {
+                EmbeddingOverflow::Truncate => Ok(vec![input.chars().take(max_chars).collect()]),
+                EmbeddingOverflow::ChunkAverage => {
+                    let chars: Vec<char> = input.chars().collect();
+                    Ok(chars
+                        .chunks(max_chars)
+                        .map(|chunk| chunk.iter().collect())
+                        .collect())
+                }
+                EmbeddingOverflow::Error => Err(ButterflyBotError::Runtime(format!(
+                    "Embedding input {index} is about {} tokens, over the {} token limit",
+                    estimate_tokens(input),
+                    limit.max_tokens
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// The mean of `vectors`, each weighted by the length of its chunk.
+fn weighted_average(chunks: &[String], vectors: &[Vec<f32>]) -> Vec<f32> {
+    if let [vector] = vectors {
+        return vector.clone();
+    }
+    let dim = vectors.first().map(Vec::len).unwrap_or(0);
+    let mut sum = vec![0.0f32; dim];
+    let mut total = 0.0f32;
+    for (chunk, vector) in chunks.iter().zip(vectors) {
+        let weight = chunk.chars().count() as f32;
+        for (acc, value) in sum.iter_mut().zip(vector) {
+            *acc += value * weight;
+        }
+        total += weight;
+    }
+    if total > 0.0 {
+        for value in &mut sum {
+            *value /= total;
+        }
+    }
+    sum
+}
+
+/// `system_prompt` with a request to answer as JSON matching `json_schema`,
+/// for modes where the server doesn't enforce the schema.
+fn schema_instructions(system_prompt: &str, json_schema: &Value) -> String {
+    let instructions =
+        format!("Respond only with a JSON object that matches this JSON schema:\n{json_schema}");
+    if system_prompt.trim().is_empty() {
+        instructions
+    } else {
+        format!("{system_prompt}\n\n{instructions}")
+    }
+}
+
+/// `content` without a surrounding Markdown code fence, which models tend to
+/// add when JSON is only asked for in the prompt.
+fn strip_json_fence(content: &str) -> &str {
+    let trimmed = content.trim();
+    trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.strip_prefix("json").unwrap_or(inner).trim())
+        .unwrap_or(trimmed)
+}
+
+/// The `error.message` of an OpenAI-style error body, else the raw body.
+fn api_error_message(status: reqwest::StatusCode, body: &[u8]) -> String {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| {
+            value
+                .pointer("/error/message")
+                .and_then(|message| message.as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+    format!("{status}: {message}")
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::error::{ButterflyBotError, Result};
+
+/// Retries after the first attempt for a rate-limited or failing request.
+pub const MAX_RETRIES: u32 = 3;
+
+/// Longest wait honored from a `Retry-After` header.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Wait before the first retry when the server doesn't say; doubles after.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// `Retry-After` as delta-seconds or an HTTP-date. Dates in the past mean
+/// "now".
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Sends the request built by `make_request`, retrying 429 and 5xx responses
+/// up to [`MAX_RETRIES`] times. Waits as long as `Retry-After` says (capped at
+/// [`MAX_RETRY_AFTER`]), or backs off exponentially without one. A 429 that
+/// outlasts the retries becomes [`ButterflyBotError::RateLimited`]; other
+/// responses are returned as they are.
+pub async fn send_with_retry<F>(make_request: F) -> Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        let response = make_request()
+            .send()
+            .await
+            .map_err(|e| ButterflyBotError::Http(e.to_string()))?;
+        let status = response.status();
+        if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        if attempt >= MAX_RETRIES {
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let detail = match retry_after {
+                    Some(wait) => format!(
+                        "provider still rate limited after {MAX_RETRIES} retries (retry after {}s)",
+                        wait.as_secs()
+                    ),
+                    None => format!("provider still rate limited after {MAX_RETRIES} retries"),
+                };
+                return Err(ButterflyBotError::RateLimited(detail));
+            }
+            return Ok(response);
+        }
+
+        let wait = retry_after
+            .unwrap_or(BASE_RETRY_DELAY * 2u32.pow(attempt))
+            .min(MAX_RETRY_AFTER);
+        tracing::warn!(
+            status = status.as_u16(),
+            wait_ms = wait.as_millis() as u64,
+            attempt = attempt + 1,
+            "provider request failed; retrying"
+        );
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
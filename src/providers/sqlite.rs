@@ -1,37 +1,53 @@
+use std::collections::HashMap;
 use std::num::NonZeroUsize;
-use std::path::Path;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use arrow_array::{Array, Int64Array, RecordBatch, RecordBatchIterator, StringArray};
+use arrow_array::{Array, Float32Array, Int64Array, RecordBatch, RecordBatchIterator, StringArray};
 use arrow_schema::{DataType, Field, Schema};
 use async_trait::async_trait;
 use diesel::prelude::*;
-use diesel::sql_types::{BigInt, Text};
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
 use diesel::sqlite::SqliteConnection;
 use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use futures::TryStreamExt;
+use lancedb::index::vector::{IvfFlatIndexBuilder, IvfPqIndexBuilder};
+use lancedb::index::Index;
 use lru::LruCache;
 use serde_json::json;
 use time::{macros::format_description, OffsetDateTime};
 
+use crate::config::{SummaryFormat, SummaryStyle, VectorIndexConfig, VectorIndexKind};
 use crate::error::{ButterflyBotError, Result};
-use crate::interfaces::providers::{LlmProvider, MemoryProvider};
+use crate::interfaces::providers::{
+    ChatSummary, LlmProvider, MemoryProvider, MessageBranch, PinnedContext, ReindexReport,
+    SearchModels, StoredMessage,
+};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
-use schema::messages;
+use schema::{archived_chats, message_branches, messages, pinned_context};
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 const MEMORY_UP_SQL: &str = include_str!("../../migrations/20250129_create_memory/up.sql");
+const BRANCHES_UP_SQL: &str = include_str!("../../migrations/20260209_message_branches/up.sql");
+const PINS_UP_SQL: &str = include_str!("../../migrations/20260214_pinned_context/up.sql");
+const CHAT_ARCHIVE_UP_SQL: &str = include_str!("../../migrations/20260216_chat_archive/up.sql");
+const VECTOR_TABLE: &str = "message_vectors";
+const REINDEX_BATCH_SIZE: i64 = 64;
 
 type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
 type SqlitePool = Pool<SqliteAsyncConn>;
 type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
 
+/// Builds a reranker for a model named in a per-search override.
+pub type RerankerFactory = Arc<dyn Fn(&str) -> Arc<dyn LlmProvider> + Send + Sync>;
+
 #[derive(Queryable)]
 struct MessageRow {
     role: String,
@@ -39,6 +55,88 @@ struct MessageRow {
     timestamp: i64,
 }
 
+#[derive(Queryable)]
+struct StoredMessageRow {
+    id: i32,
+    role: String,
+    content: String,
+    timestamp: i64,
+}
+
+impl From<StoredMessageRow> for StoredMessage {
+    fn from(row: StoredMessageRow) -> Self {
+        Self {
+            id: row.id as i64,
+            role: row.role,
+            content: row.content,
+            timestamp: row.timestamp,
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct BranchRow {
+    content: String,
+    created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = message_branches)]
+struct NewBranch<'a> {
+    user_id: &'a str,
+    message_id: i32,
+    content: &'a str,
+    created_at: i64,
+}
+
+#[derive(Queryable)]
+struct PinRow {
+    id: i32,
+    content: String,
+    created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = pinned_context)]
+struct NewPin<'a> {
+    user_id: &'a str,
+    content: &'a str,
+    created_at: i64,
+}
+
+#[derive(QueryableByName)]
+struct ChatRow {
+    #[diesel(sql_type = Text)]
+    user_id: String,
+    #[diesel(sql_type = BigInt)]
+    message_count: i64,
+    #[diesel(sql_type = BigInt)]
+    last_active: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    archived_at: Option<i64>,
+}
+
+#[derive(QueryableByName)]
+struct ChatUserRow {
+    #[diesel(sql_type = Text)]
+    user_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = archived_chats)]
+struct NewArchivedChat<'a> {
+    user_id: &'a str,
+    archived_at: i64,
+}
+
+#[derive(Queryable)]
+struct ReindexRow {
+    id: i32,
+    role: String,
+    content: String,
+    timestamp: i64,
+}
+
 #[derive(QueryableByName)]
 struct RowId {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
@@ -51,6 +149,8 @@ struct SearchRow {
     content: String,
     #[diesel(sql_type = BigInt)]
     timestamp: i64,
+    #[diesel(sql_type = Double)]
+    rank: f64,
 }
 
 #[derive(QueryableByName)]
@@ -122,14 +222,20 @@ struct NewMemoryLink<'a> {
     created_at: i64,
 }
 
+/// Vectors live in one table per embedding dimension, so switching models
+/// never touches vectors that other users haven't reindexed yet.
 #[derive(Clone)]
 struct LanceDbStore {
     db: lancedb::Connection,
-    table: Arc<tokio::sync::Mutex<Option<lancedb::Table>>>,
+    tables: Arc<tokio::sync::Mutex<HashMap<String, lancedb::Table>>>,
+    index: Option<VectorIndexConfig>,
 }
 
+// Product quantization trains 256 centroids per sub-vector.
+const PQ_MIN_TRAINING_ROWS: usize = 256;
+
 impl LanceDbStore {
-    async fn new(path: &str) -> Result<Self> {
+    async fn new(path: &str, index: Option<VectorIndexConfig>) -> Result<Self> {
         ensure_parent_dir(path)?;
         let db = lancedb::connect(path)
             .execute()
@@ -137,111 +243,258 @@ impl LanceDbStore {
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
         Ok(Self {
             db,
-            table: Arc::new(tokio::sync::Mutex::new(None)),
+            tables: Arc::default(),
+            index,
         })
     }
 
-    async fn table_exists(&self, name: &str) -> Result<bool> {
+    /// (Re)builds the configured index over `vector`. Returns false when no
+    /// index is configured or the table has too few rows to train one.
+    async fn build_index(&self, table: &lancedb::Table) -> Result<bool> {
+        let Some(config) = self.index else {
+            return Ok(false);
+        };
+        let rows = table
+            .count_rows(None)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let partitions = config.partitions.unwrap_or(1) as usize;
+        let needed = match config.kind {
+            VectorIndexKind::IvfFlat => partitions,
+            VectorIndexKind::IvfPq => partitions.max(PQ_MIN_TRAINING_ROWS),
+        };
+        if rows < needed {
+            return Ok(false);
+        }
+
+        let index = match config.kind {
+            VectorIndexKind::IvfFlat => {
+                let mut builder = IvfFlatIndexBuilder::default();
+                if let Some(partitions) = config.partitions {
+                    builder = builder.num_partitions(partitions);
+                }
+                Index::IvfFlat(builder)
+            }
+            VectorIndexKind::IvfPq => {
+                let mut builder = IvfPqIndexBuilder::default();
+                if let Some(partitions) = config.partitions {
+                    builder = builder.num_partitions(partitions);
+                }
+                if let Some(sub_vectors) = config.sub_vectors {
+                    builder = builder.num_sub_vectors(sub_vectors);
+                }
+                Index::IvfPq(builder)
+            }
+        };
+        table
+            .create_index(&["vector"], index)
+            .replace(true)
+            .execute()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(true)
+    }
+
+    /// Builds the index on a table opened without one. A failure only costs
+    /// search speed, so it is logged rather than returned.
+    async fn ensure_index(&self, table: &lancedb::Table) {
+        if self.index.is_none() {
+            return;
+        }
+        let indexed = match table.list_indices().await {
+            Ok(indices) => indices
+                .iter()
+                .any(|index| index.columns.iter().any(|column| column == "vector")),
+            Err(err) => {
+                tracing::warn!(error = %err, "could not list LanceDB indices");
+                return;
+            }
+        };
+        if !indexed {
+            if let Err(err) = self.build_index(table).await {
+                tracing::warn!(error = %err, "could not build the LanceDB vector index");
+            }
+        }
+    }
+
+    /// Every vector table: the original `message_vectors` plus the
+    /// `message_vectors_<dim>` tables created for later embedding models.
+    async fn vector_table_names(&self) -> Result<Vec<String>> {
+        let prefix = format!("{VECTOR_TABLE}_");
         let tables = self
             .db
             .table_names()
             .execute()
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(tables.iter().any(|t| t == name))
+        Ok(tables
+            .into_iter()
+            .filter(|name| name == VECTOR_TABLE || name.starts_with(&prefix))
+            .collect())
     }
 
-    async fn get_or_create_table(&self, dim: i32) -> Result<lancedb::Table> {
-        let mut guard = self.table.lock().await;
-        if let Some(table) = guard.clone() {
-            return Ok(table);
+    async fn open_cached(
+        &self,
+        tables: &mut HashMap<String, lancedb::Table>,
+        name: &str,
+    ) -> Result<lancedb::Table> {
+        if let Some(table) = tables.get(name) {
+            return Ok(table.clone());
         }
-
-        let name = "message_vectors";
-        let table = if self.table_exists(name).await? {
-            self.db
-                .open_table(name)
-                .execute()
-                .await
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
-        } else {
-            let schema = Arc::new(Schema::new(vec![
-                Field::new("id", DataType::Int64, false),
-                Field::new("user_id", DataType::Utf8, false),
-                Field::new("role", DataType::Utf8, false),
-                Field::new("content", DataType::Utf8, false),
-                Field::new("timestamp", DataType::Int64, false),
-                Field::new(
-                    "vector",
-                    DataType::FixedSizeList(
-                        Arc::new(Field::new("item", DataType::Float32, true)),
-                        dim,
-                    ),
-                    true,
-                ),
-            ]));
-
-            let batch = RecordBatch::try_new(
-                schema.clone(),
-                vec![
-                    Arc::new(Int64Array::from_iter_values([0i64])),
-                    Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
-                    Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
-                    Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
-                    Arc::new(Int64Array::from_iter_values([0i64])),
-                    Arc::new(arrow_array::FixedSizeListArray::from_iter_primitive::<
-                        arrow_array::types::Float32Type,
-                        _,
-                        _,
-                    >(
-                        vec![Some(vec![Some(0.0); dim as usize])], dim
-                    )),
-                ],
-            )
+        let table = self
+            .db
+            .open_table(name)
+            .execute()
+            .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        self.ensure_index(&table).await;
+        tables.insert(name.to_string(), table.clone());
+        Ok(table)
+    }
 
-            let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema);
+    async fn all_tables(&self) -> Result<Vec<lancedb::Table>> {
+        let mut tables = self.tables.lock().await;
+        let mut opened = Vec::new();
+        for name in self.vector_table_names().await? {
+            opened.push(self.open_cached(&mut tables, &name).await?);
+        }
+        Ok(opened)
+    }
 
-            self.db
-                .create_table(name, batches)
-                .execute()
-                .await
-                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
-        };
+    async fn find_table(
+        &self,
+        tables: &mut HashMap<String, lancedb::Table>,
+        dim: i32,
+    ) -> Result<Option<lancedb::Table>> {
+        for name in self.vector_table_names().await? {
+            let table = self.open_cached(tables, &name).await?;
+            if table_dimension(&table).await? == Some(dim) {
+                return Ok(Some(table));
+            }
+        }
+        Ok(None)
+    }
 
-        *guard = Some(table.clone());
-        Ok(table)
+    /// The table holding `dim`-wide vectors, if any were ever stored.
+    async fn table_if_exists(&self, dim: i32) -> Result<Option<lancedb::Table>> {
+        let mut tables = self.tables.lock().await;
+        self.find_table(&mut tables, dim).await
     }
 
-    async fn open_table_if_exists(&self) -> Result<Option<lancedb::Table>> {
-        let mut guard = self.table.lock().await;
-        if let Some(table) = guard.clone() {
-            return Ok(Some(table));
-        }
-        let name = "message_vectors";
-        if !self.table_exists(name).await? {
-            return Ok(None);
+    /// The table holding `dim`-wide vectors, created on first use.
+    async fn table_for_dimension(&self, dim: i32) -> Result<lancedb::Table> {
+        let mut tables = self.tables.lock().await;
+        if let Some(table) = self.find_table(&mut tables, dim).await? {
+            return Ok(table);
         }
+
+        let name = format!("{VECTOR_TABLE}_{dim}");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("user_id", DataType::Utf8, false),
+            Field::new("role", DataType::Utf8, false),
+            Field::new("content", DataType::Utf8, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), dim),
+                true,
+            ),
+        ]));
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from_iter_values([0i64])),
+                Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
+                Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
+                Arc::new(StringArray::from_iter_values([""])) as Arc<dyn arrow_array::Array>,
+                Arc::new(Int64Array::from_iter_values([0i64])),
+                Arc::new(arrow_array::FixedSizeListArray::from_iter_primitive::<
+                    arrow_array::types::Float32Type,
+                    _,
+                    _,
+                >(
+                    vec![Some(vec![Some(0.0); dim as usize])], dim
+                )),
+            ],
+        )
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+        let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema);
         let table = self
             .db
-            .open_table(name)
+            .create_table(&name, batches)
             .execute()
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        *guard = Some(table.clone());
-        Ok(Some(table))
+        tables.insert(name, table.clone());
+        Ok(table)
+    }
+
+    /// Moves `ids` out of every table but `current`, then drops the ones left
+    /// holding no user's vectors: once the last user has reindexed, the old
+    /// model's table is gone.
+    async fn retire_stale_rows(
+        &self,
+        current: &lancedb::Table,
+        user_id: &str,
+        ids: &str,
+    ) -> Result<()> {
+        let mut tables = self.tables.lock().await;
+        for name in self.vector_table_names().await? {
+            if name == current.name() {
+                continue;
+            }
+            let table = self.open_cached(&mut tables, &name).await?;
+            table
+                .delete(&format!("{} AND id IN ({ids})", user_filter(user_id)))
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let remaining = table
+                .count_rows(Some("user_id != ''".to_string()))
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            if remaining == 0 {
+                self.db
+                    .drop_table(&name, &[])
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+                tables.remove(&name);
+            }
+        }
+        Ok(())
     }
 }
 
+/// Width of the table's `vector` column.
+async fn table_dimension(table: &lancedb::Table) -> Result<Option<i32>> {
+    let schema = table
+        .schema()
+        .await
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    Ok(schema
+        .field_with_name("vector")
+        .ok()
+        .and_then(|field| match field.data_type() {
+            DataType::FixedSizeList(_, size) => Some(*size),
+            _ => None,
+        }))
+}
+
 pub struct SqliteMemoryProvider {
     pool: SqlitePool,
     lancedb: Option<LanceDbStore>,
     embedder: Option<Arc<dyn LlmProvider>>,
     embedding_model: Option<String>,
     reranker: Option<Arc<dyn LlmProvider>>,
+    reranker_factory: Option<RerankerFactory>,
+    ollama_tags_url: Option<String>,
     summarizer: Option<Arc<dyn LlmProvider>>,
     summary_threshold: usize,
+    summary_style: SummaryStyle,
     retention_days: Option<u32>,
+    min_score: Option<f32>,
     embedding_cache: Arc<tokio::sync::Mutex<LruCache<String, Vec<f32>>>>,
 }
 
@@ -253,9 +506,13 @@ impl Clone for SqliteMemoryProvider {
             embedder: self.embedder.clone(),
             embedding_model: self.embedding_model.clone(),
             reranker: self.reranker.clone(),
+            reranker_factory: self.reranker_factory.clone(),
+            ollama_tags_url: self.ollama_tags_url.clone(),
             summarizer: self.summarizer.clone(),
             summary_threshold: self.summary_threshold,
+            summary_style: self.summary_style,
             retention_days: self.retention_days,
+            min_score: self.min_score,
             embedding_cache: Arc::clone(&self.embedding_cache),
         }
     }
@@ -267,9 +524,17 @@ pub struct SqliteMemoryProviderConfig {
     pub embedder: Option<Arc<dyn LlmProvider>>,
     pub embedding_model: Option<String>,
     pub reranker: Option<Arc<dyn LlmProvider>>,
+    /// Needed for `rerank_model` overrides; without it they are rejected.
+    pub reranker_factory: Option<RerankerFactory>,
+    /// Ollama's `/api/tags`; when set, override models must be listed there.
+    pub ollama_tags_url: Option<String>,
     pub summarizer: Option<Arc<dyn LlmProvider>>,
     pub summary_threshold: Option<usize>,
+    pub summary_style: SummaryStyle,
     pub retention_days: Option<u32>,
+    /// Search results scoring below this (0.0-1.0) are dropped.
+    pub min_score: Option<f32>,
+    pub vector_index: Option<VectorIndexConfig>,
 }
 
 impl SqliteMemoryProviderConfig {
@@ -280,9 +545,14 @@ impl SqliteMemoryProviderConfig {
             embedder: None,
             embedding_model: None,
             reranker: None,
+            reranker_factory: None,
+            ollama_tags_url: None,
             summarizer: None,
             summary_threshold: None,
+            summary_style: SummaryStyle::default(),
             retention_days: None,
+            min_score: None,
+            vector_index: None,
         }
     }
 }
@@ -300,8 +570,13 @@ impl SqliteMemoryProvider {
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
+        if let Some(index) = &config.vector_index {
+            index.validate()?;
+        }
         let lancedb = match config.lancedb_path.as_deref() {
-            Some(path) if !path.trim().is_empty() => Some(LanceDbStore::new(path).await?),
+            Some(path) if !path.trim().is_empty() => {
+                Some(LanceDbStore::new(path, config.vector_index).await?)
+            }
             _ => None,
         };
 
@@ -311,9 +586,13 @@ impl SqliteMemoryProvider {
             embedder: config.embedder,
             embedding_model: config.embedding_model,
             reranker: config.reranker,
+            reranker_factory: config.reranker_factory,
+            ollama_tags_url: config.ollama_tags_url,
             summarizer: config.summarizer,
             summary_threshold: config.summary_threshold.unwrap_or(12),
+            summary_style: config.summary_style,
             retention_days: config.retention_days,
+            min_score: config.min_score,
             embedding_cache: Arc::new(tokio::sync::Mutex::new(LruCache::new(
                 NonZeroUsize::new(256).unwrap(),
             ))),
@@ -334,6 +613,12 @@ impl SqliteMemoryProvider {
 const TIMESTAMP_FORMAT: &[time::format_description::FormatItem<'static>] =
     format_description!("[year]-[month]-[day] [hour]:[minute]");
 
+/// A LanceDB predicate matching `user_id`'s rows. Quotes are doubled so a user
+/// id can never widen a filter, least of all a delete, to other users.
+fn user_filter(user_id: &str) -> String {
+    format!("user_id = '{}'", user_id.replace('\'', "''"))
+}
+
 fn format_timestamp(ts: i64) -> String {
     OffsetDateTime::from_unix_timestamp(ts)
         .ok()
@@ -341,12 +626,18 @@ fn format_timestamp(ts: i64) -> String {
         .unwrap_or_else(|| ts.to_string())
 }
 
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+fn summary_instructions(style: SummaryStyle) -> String {
+    let mut instructions = match style.format {
+        SummaryFormat::Narrative => "Write the summary as a short narrative paragraph.",
+        SummaryFormat::Bullets => {
+            "Write the summary as bullet points, one per line, each starting with \"- \"."
+        }
     }
-    Ok(())
+    .to_string();
+    if let Some(max_words) = style.max_words.filter(|words| *words > 0) {
+        instructions.push_str(&format!(" Keep it under {max_words} words."));
+    }
+    instructions
 }
 
 async fn run_migrations(database_url: &str) -> Result<()> {
@@ -396,6 +687,12 @@ async fn ensure_memory_tables(database_url: &str) -> Result<()> {
                 }
             }
         }
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, BRANCHES_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, PINS_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, CHAT_ARCHIVE_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
         Ok::<_, ButterflyBotError>(())
     })
@@ -406,6 +703,7 @@ async fn ensure_memory_tables(database_url: &str) -> Result<()> {
 
 #[async_trait]
 impl MemoryProvider for SqliteMemoryProvider {
+    #[tracing::instrument(name = "memory.append", skip_all, fields(user_id = %user_id, role = %role), err)]
     async fn append_message(&self, user_id: &str, role: &str, content: &str) -> Result<()> {
         let ts = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -428,6 +726,10 @@ impl MemoryProvider for SqliteMemoryProvider {
             .get_result(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        diesel::delete(archived_chats::table.filter(archived_chats::user_id.eq(user_id)))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
         if let (Some(lancedb), Some(embedder)) = (&self.lancedb, &self.embedder) {
             let vectors = embedder
@@ -435,7 +737,7 @@ impl MemoryProvider for SqliteMemoryProvider {
                 .await?;
             if let Some(vector) = vectors.into_iter().next() {
                 let dim = vector.len() as i32;
-                let table = lancedb.get_or_create_table(dim).await?;
+                let table = lancedb.table_for_dimension(dim).await?;
                 let batch = build_lancedb_batch(row_id.id, user_id, role, content, ts, vector)?;
                 let schema = batch.schema();
                 let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema);
@@ -465,6 +767,7 @@ impl MemoryProvider for SqliteMemoryProvider {
         Ok(())
     }
 
+    #[tracing::instrument(name = "memory.history", skip_all, fields(user_id = %user_id, limit = limit), err)]
     async fn get_history(&self, user_id: &str, limit: usize) -> Result<Vec<String>> {
         let mut conn = self.conn().await?;
         let mut query = messages::table
@@ -501,41 +804,579 @@ impl MemoryProvider for SqliteMemoryProvider {
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        diesel::delete(message_branches::table.filter(message_branches::user_id.eq(user_id)))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        diesel::delete(archived_chats::table.filter(archived_chats::user_id.eq(user_id)))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
         Ok(())
     }
 
+    #[tracing::instrument(name = "memory.search", skip_all, fields(user_id = %user_id, limit = limit), err)]
     async fn search(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<String>> {
+        self.search_with_min_score(user_id, query, limit, None)
+            .await
+    }
+
+    async fn search_with_min_score(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<String>> {
+        self.search_with_models(user_id, query, limit, min_score, &SearchModels::default())
+            .await
+    }
+
+    async fn search_with_models(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        min_score: Option<f32>,
+        models: &SearchModels,
+    ) -> Result<Vec<String>> {
+        self.check_override_models(models).await?;
+        let override_reranker = match (&models.rerank_model, &self.reranker_factory) {
+            (Some(model), Some(factory)) => Some(factory(model)),
+            (Some(_), None) => {
+                return Err(ButterflyBotError::Config(
+                    "This memory provider cannot switch rerank models".to_string(),
+                ))
+            }
+            (None, _) => None,
+        };
+        let embedding_model = models
+            .embedding_model
+            .as_deref()
+            .or(self.embedding_model.as_deref());
+
+        let min_score = min_score.or(self.min_score).unwrap_or(0.0);
         let mut fts_results = self.search_fts(user_id, query, limit).await?;
         if fts_results.len() >= limit.max(1) {
-            return Ok(fts_results.into_iter().take(limit.max(1)).collect());
+            return Ok(fts_results
+                .into_iter()
+                .filter(|(_, score)| *score >= min_score)
+                .map(|(item, _)| item)
+                .take(limit.max(1))
+                .collect());
         }
         let trimmed = query.trim();
         let tokens = trimmed.split_whitespace().count();
         let use_vector = tokens >= 4 && trimmed.len() >= 18;
 
         let vector_results = if use_vector {
-            self.search_vector(user_id, query, limit).await?
+            self.search_vector(user_id, query, limit, embedding_model)
+                .await?
         } else {
             Vec::new()
         };
 
-        let mut merged = Vec::new();
-        for item in fts_results.drain(..).chain(vector_results.into_iter()) {
+        let mut merged: Vec<String> = Vec::new();
+        let mut scores: HashMap<String, f32> = HashMap::new();
+        for (item, score) in fts_results.drain(..).chain(vector_results.into_iter()) {
             if !merged.contains(&item) {
-                merged.push(item);
+                merged.push(item.clone());
             }
+            let best = scores.entry(item).or_insert(score);
+            *best = best.max(score);
         }
 
-        if let Some(reranker) = &self.reranker {
-            if merged.len() > limit.max(1) * 2 {
-                let reranked = self
-                    .rerank_with_model(reranker, query, &merged, limit)
+        let ranked = match override_reranker.as_ref().or(self.reranker.as_ref()) {
+            Some(reranker) if merged.len() > limit.max(1) * 2 => {
+                self.rerank_with_model(reranker, query, &merged, limit)
+                    .await?
+            }
+            _ => merged,
+        };
+
+        // The reranker only orders candidates, so filtering on the retrieval
+        // score happens afterwards and may leave fewer than `limit` results.
+        Ok(ranked
+            .into_iter()
+            .filter(|item| scores.get(item).copied().unwrap_or(0.0) >= min_score)
+            .take(limit.max(1))
+            .collect())
+    }
+
+    async fn get_message(&self, user_id: &str, message_id: i64) -> Result<Option<StoredMessage>> {
+        let mut conn = self.conn().await?;
+        let row: Option<StoredMessageRow> = messages::table
+            .filter(
+                messages::user_id
+                    .eq(user_id)
+                    .and(messages::id.eq(message_id as i32)),
+            )
+            .select((
+                messages::id,
+                messages::role,
+                messages::content,
+                messages::timestamp,
+            ))
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(row.map(StoredMessage::from))
+    }
+
+    async fn get_messages(
+        &self,
+        user_id: &str,
+        before_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>> {
+        let mut conn = self.conn().await?;
+        let mut query = messages::table
+            .filter(messages::user_id.eq(user_id))
+            .order(messages::id.desc())
+            .select((
+                messages::id,
+                messages::role,
+                messages::content,
+                messages::timestamp,
+            ))
+            .into_boxed();
+        if let Some(before_id) = before_id {
+            query = query.filter(messages::id.lt(before_id as i32));
+        }
+        if limit > 0 {
+            query = query.limit(limit as i64);
+        }
+        let rows: Vec<StoredMessageRow> = query
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(rows.into_iter().rev().map(StoredMessage::from).collect())
+    }
+
+    async fn add_branch(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        content: &str,
+    ) -> Result<MessageBranch> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            .as_secs() as i64;
+        let mut conn = self.conn().await?;
+        diesel::insert_into(message_branches::table)
+            .values(&NewBranch {
+                user_id,
+                message_id: message_id as i32,
+                content,
+                created_at,
+            })
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        drop(conn);
+
+        self.get_branches(user_id, message_id)
+            .await?
+            .pop()
+            .ok_or_else(|| ButterflyBotError::Runtime("Branch was not stored".to_string()))
+    }
+
+    async fn get_branches(&self, user_id: &str, message_id: i64) -> Result<Vec<MessageBranch>> {
+        let mut conn = self.conn().await?;
+        let mut branches = Vec::new();
+
+        // The original reply is the assistant message directly after the turn.
+        let next: Option<StoredMessageRow> = messages::table
+            .filter(
+                messages::user_id
+                    .eq(user_id)
+                    .and(messages::id.gt(message_id as i32)),
+            )
+            .order(messages::id.asc())
+            .select((
+                messages::id,
+                messages::role,
+                messages::content,
+                messages::timestamp,
+            ))
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        if let Some(original) = next.filter(|row| row.role == "assistant") {
+            branches.push(MessageBranch {
+                message_id,
+                branch: 0,
+                content: original.content,
+                created_at: original.timestamp,
+            });
+        }
+
+        let rows: Vec<BranchRow> = message_branches::table
+            .filter(
+                message_branches::user_id
+                    .eq(user_id)
+                    .and(message_branches::message_id.eq(message_id as i32)),
+            )
+            .order(message_branches::id.asc())
+            .select((message_branches::content, message_branches::created_at))
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        branches.extend(
+            rows.into_iter()
+                .enumerate()
+                .map(|(index, row)| MessageBranch {
+                    message_id,
+                    branch: index as u32 + 1,
+                    content: row.content,
+                    created_at: row.created_at,
+                }),
+        );
+        Ok(branches)
+    }
+
+    async fn pin_context(&self, user_id: &str, content: &str) -> Result<PinnedContext> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            .as_secs() as i64;
+        let mut conn = self.conn().await?;
+        diesel::insert_into(pinned_context::table)
+            .values(&NewPin {
+                user_id,
+                content,
+                created_at,
+            })
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let row: PinRow = pinned_context::table
+            .filter(pinned_context::user_id.eq(user_id))
+            .order(pinned_context::id.desc())
+            .select((
+                pinned_context::id,
+                pinned_context::content,
+                pinned_context::created_at,
+            ))
+            .first(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(PinnedContext {
+            id: row.id as i64,
+            content: row.content,
+            created_at: row.created_at,
+        })
+    }
+
+    async fn unpin_context(&self, user_id: &str, pin_id: i64) -> Result<bool> {
+        let mut conn = self.conn().await?;
+        let deleted = diesel::delete(
+            pinned_context::table.filter(
+                pinned_context::user_id
+                    .eq(user_id)
+                    .and(pinned_context::id.eq(pin_id as i32)),
+            ),
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(deleted > 0)
+    }
+
+    async fn pinned_context(&self, user_id: &str) -> Result<Vec<PinnedContext>> {
+        let mut conn = self.conn().await?;
+        let rows: Vec<PinRow> = pinned_context::table
+            .filter(pinned_context::user_id.eq(user_id))
+            .order(pinned_context::id.asc())
+            .select((
+                pinned_context::id,
+                pinned_context::content,
+                pinned_context::created_at,
+            ))
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| PinnedContext {
+                id: row.id as i64,
+                content: row.content,
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    async fn list_chats(&self, include_archived: bool) -> Result<Vec<ChatSummary>> {
+        let filter = if include_archived {
+            ""
+        } else {
+            "WHERE a.user_id IS NULL"
+        };
+        let query = format!(
+            "SELECT m.user_id AS user_id, COUNT(*) AS message_count, \
+             MAX(m.timestamp) AS last_active, a.archived_at AS archived_at \
+             FROM messages m LEFT JOIN archived_chats a ON a.user_id = m.user_id \
+             {filter} GROUP BY m.user_id ORDER BY last_active DESC, m.user_id ASC"
+        );
+        let mut conn = self.conn().await?;
+        let rows: Vec<ChatRow> = diesel::sql_query(query)
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| ChatSummary {
+                user_id: row.user_id,
+                message_count: row.message_count as u64,
+                last_active: row.last_active,
+                archived_at: row.archived_at,
+            })
+            .collect())
+    }
+
+    async fn archive_chat(&self, user_id: &str) -> Result<bool> {
+        let archived_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            .as_secs() as i64;
+        let mut conn = self.conn().await?;
+        let messages: i64 = messages::table
+            .filter(messages::user_id.eq(user_id))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        if messages == 0 {
+            return Ok(false);
+        }
+        let inserted = diesel::insert_or_ignore_into(archived_chats::table)
+            .values(&NewArchivedChat {
+                user_id,
+                archived_at,
+            })
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(inserted > 0)
+    }
+
+    async fn unarchive_chat(&self, user_id: &str) -> Result<bool> {
+        let mut conn = self.conn().await?;
+        let deleted =
+            diesel::delete(archived_chats::table.filter(archived_chats::user_id.eq(user_id)))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(deleted > 0)
+    }
+
+    async fn archive_idle_chats(&self, idle_before: i64) -> Result<Vec<String>> {
+        let mut conn = self.conn().await?;
+        let rows: Vec<ChatUserRow> = diesel::sql_query(
+            "SELECT user_id FROM messages \
+             WHERE user_id NOT IN (SELECT user_id FROM archived_chats) \
+             GROUP BY user_id HAVING MAX(timestamp) < ? ORDER BY user_id",
+        )
+        .bind::<BigInt, _>(idle_before)
+        .load(&mut conn)
+        .await
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let archived_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            .as_secs() as i64;
+        for row in &rows {
+            diesel::insert_or_ignore_into(archived_chats::table)
+                .values(&NewArchivedChat {
+                    user_id: &row.user_id,
+                    archived_at,
+                })
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        }
+        Ok(rows.into_iter().map(|row| row.user_id).collect())
+    }
+
+    async fn truncate_from(&self, user_id: &str, message_id: i64) -> Result<u64> {
+        let Some(message) = self.get_message(user_id, message_id).await? else {
+            return Err(ButterflyBotError::Config(format!(
+                "No message with id {message_id}"
+            )));
+        };
+
+        // History and what was distilled from it go together or not at all.
+        let mut conn = self.conn().await?;
+        let removed = conn
+            .immediate_transaction(|conn| {
+                async move {
+                    let removed = diesel::delete(
+                        messages::table.filter(
+                            messages::user_id
+                                .eq(user_id)
+                                .and(messages::id.ge(message_id as i32)),
+                        ),
+                    )
+                    .execute(conn)
+                    .await?;
+                    diesel::delete(
+                        message_branches::table.filter(
+                            message_branches::user_id
+                                .eq(user_id)
+                                .and(message_branches::message_id.ge(message_id as i32)),
+                        ),
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    // Summaries don't record their source messages, so anything
+                    // distilled since the discarded tail began is dropped with it.
+                    for table in ["memories", "entities", "facts", "edges", "events"] {
+                        diesel::sql_query(format!(
+                            "DELETE FROM {table} WHERE user_id = ?1 AND created_at >= ?2"
+                        ))
+                        .bind::<Text, _>(user_id)
+                        .bind::<BigInt, _>(message.timestamp)
+                        .execute(conn)
+                        .await?;
+                    }
+                    diesel::sql_query(
+                        "DELETE FROM memory_links WHERE memory_id NOT IN (SELECT id FROM memories)",
+                    )
+                    .execute(conn)
                     .await?;
-                return Ok(reranked);
+                    Ok::<_, ButterflyBotError>(removed)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        drop(conn);
+
+        if let Some(lancedb) = &self.lancedb {
+            for table in lancedb.all_tables().await? {
+                table
+                    .delete(&format!("{} AND id >= {message_id}", user_filter(user_id)))
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
             }
         }
+        Ok(removed as u64)
+    }
 
-        Ok(merged.into_iter().take(limit.max(1)).collect())
+    async fn reindex_memory(
+        &self,
+        user_id: &str,
+        resume_after: Option<i64>,
+    ) -> Result<ReindexReport> {
+        let mut report = ReindexReport {
+            user_id: user_id.to_string(),
+            last_id: resume_after,
+            ..Default::default()
+        };
+        let (Some(lancedb), Some(embedder)) = (&self.lancedb, &self.embedder) else {
+            return Ok(report);
+        };
+
+        let mut conn = self.conn().await?;
+        let count: CountRow =
+            diesel::sql_query("SELECT COUNT(*) as count FROM messages WHERE user_id = ?1")
+                .bind::<Text, _>(user_id)
+                .get_result(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        report.total = count.count as u64;
+
+        self.embedding_cache.lock().await.clear();
+
+        let mut cursor = resume_after.unwrap_or(0);
+        let mut current = None;
+        loop {
+            let rows: Vec<ReindexRow> = messages::table
+                .filter(
+                    messages::user_id
+                        .eq(user_id)
+                        .and(messages::id.gt(cursor as i32)),
+                )
+                .order(messages::id.asc())
+                .select((
+                    messages::id,
+                    messages::role,
+                    messages::content,
+                    messages::timestamp,
+                ))
+                .limit(REINDEX_BATCH_SIZE)
+                .load(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            let last_id = last.id as i64;
+
+            let inputs = rows.iter().map(|row| row.content.clone()).collect();
+            let vectors = embedder
+                .embed(inputs, self.embedding_model.as_deref())
+                .await?;
+            if vectors.len() != rows.len() {
+                return Err(ButterflyBotError::Runtime(format!(
+                    "embedder returned {} vectors for {} messages",
+                    vectors.len(),
+                    rows.len()
+                )));
+            }
+
+            let dim = vectors.iter().map(|v| v.len() as i32).next().unwrap_or(0);
+            let table = lancedb.table_for_dimension(dim).await?;
+            let ids = rows
+                .iter()
+                .map(|row| row.id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            table
+                .delete(&format!("id IN ({ids})"))
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let mut batches = Vec::with_capacity(rows.len());
+            for (row, vector) in rows.iter().zip(vectors) {
+                batches.push(build_lancedb_batch(
+                    row.id as i64,
+                    user_id,
+                    &row.role,
+                    &row.content,
+                    row.timestamp,
+                    vector,
+                )?);
+            }
+            let schema = batches[0].schema();
+            table
+                .add(RecordBatchIterator::new(
+                    batches.into_iter().map(Ok),
+                    schema,
+                ))
+                .execute()
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            lancedb.retire_stale_rows(&table, user_id, &ids).await?;
+            current = Some(table);
+
+            report.reindexed += rows.len() as u64;
+            report.last_id = Some(last_id);
+            cursor = last_id;
+        }
+
+        // Re-cluster so the index reflects the new vectors.
+        if let Some(table) = current {
+            lancedb.build_index(&table).await?;
+        }
+        Ok(report)
     }
 }
 
@@ -557,13 +1398,20 @@ impl SqliteMemoryProvider {
         }
     }
 
-    async fn search_fts(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<String>> {
+    /// FTS hits score `r / (1 + r)`, where `r` is the negated `bm25()` rank, so
+    /// like vector hits they fall in 0..1 and stronger matches approach 1.0.
+    async fn search_fts(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, f32)>> {
         let Some(query) = Self::sanitize_fts_query(query) else {
             return Ok(Vec::new());
         };
         let mut conn = self.conn().await?;
         let rows: Vec<SearchRow> = diesel::sql_query(
-            "SELECT mem.summary as content, mem.created_at as timestamp\n             FROM memories_fts f\n             JOIN memories mem ON mem.id = f.memory_id\n             WHERE f.user_id = ?1 AND f.summary MATCH ?2\n             UNION ALL\n             SELECT m.content as content, m.timestamp as timestamp\n             FROM messages_fts f\n             JOIN messages m ON m.id = f.message_id\n             WHERE f.user_id = ?1 AND f.content MATCH ?2 AND m.role = 'user'\n             ORDER BY timestamp DESC\n             LIMIT ?3",
+            "SELECT mem.summary as content, mem.created_at as timestamp, bm25(memories_fts) as rank\n             FROM memories_fts\n             JOIN memories mem ON mem.id = memories_fts.memory_id\n             WHERE memories_fts.user_id = ?1 AND memories_fts.summary MATCH ?2\n             UNION ALL\n             SELECT m.content as content, m.timestamp as timestamp, bm25(messages_fts) as rank\n             FROM messages_fts\n             JOIN messages m ON m.id = messages_fts.message_id\n             WHERE messages_fts.user_id = ?1 AND messages_fts.content MATCH ?2 AND m.role = 'user'\n             ORDER BY timestamp DESC\n             LIMIT ?3",
         )
         .bind::<Text, _>(user_id)
         .bind::<Text, _>(query)
@@ -573,22 +1421,36 @@ impl SqliteMemoryProvider {
         .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
         Ok(rows
             .into_iter()
-            .map(|row| format!("[{}] {}", format_timestamp(row.timestamp), row.content))
+            .map(|row| {
+                // bm25() is negative, and more negative for better matches.
+                let relevance = (-row.rank).max(0.0) as f32;
+                (
+                    format!("[{}] {}", format_timestamp(row.timestamp), row.content),
+                    relevance / (1.0 + relevance),
+                )
+            })
             .collect())
     }
 
-    async fn search_vector(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<String>> {
+    /// Vector hits score `1 / (1 + distance)`, so closer matches approach 1.0.
+    async fn search_vector(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        embedding_model: Option<&str>,
+    ) -> Result<Vec<(String, f32)>> {
         let Some(lancedb) = &self.lancedb else {
             return Ok(Vec::new());
         };
         let Some(embedder) = &self.embedder else {
             return Ok(Vec::new());
         };
-        let Some(table) = lancedb.open_table_if_exists().await? else {
+        if lancedb.vector_table_names().await?.is_empty() {
             return Ok(Vec::new());
-        };
+        }
 
-        let model_key = self.embedding_model.as_deref().unwrap_or("default");
+        let model_key = embedding_model.unwrap_or("default");
         let cache_key = format!("{model_key}:{query}");
         let cached = {
             let mut cache = self.embedding_cache.lock().await;
@@ -598,7 +1460,7 @@ impl SqliteMemoryProvider {
             vector
         } else {
             let vectors = embedder
-                .embed(vec![query.to_string()], self.embedding_model.as_deref())
+                .embed(vec![query.to_string()], embedding_model)
                 .await?;
             let Some(vector) = vectors.into_iter().next() else {
                 return Ok(Vec::new());
@@ -607,14 +1469,21 @@ impl SqliteMemoryProvider {
             cache.put(cache_key, vector.clone());
             vector
         };
+        // Only vectors from a model of the same width can be compared; users
+        // who haven't reindexed since a model change get no vector hits.
+        let Some(table) = lancedb.table_if_exists(vector.len() as i32).await? else {
+            return Ok(Vec::new());
+        };
 
         use lancedb::query::QueryBase;
-        let query = table
+        let mut query = table
             .query()
             .nearest_to(vector)
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
-            .only_if(format!("user_id = '{user_id}'"))
-            .limit(limit.max(1));
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        if let Some(nprobes) = lancedb.index.and_then(|index| index.nprobes) {
+            query = query.nprobes(nprobes as usize);
+        }
+        let query = query.only_if(user_filter(user_id)).limit(limit.max(1));
         let stream = lancedb::query::ExecutableQuery::execute(&query)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
@@ -632,19 +1501,72 @@ impl SqliteMemoryProvider {
             let ts_array = batch
                 .column_by_name("timestamp")
                 .and_then(|array| array.as_any().downcast_ref::<Int64Array>());
+            let distances = batch
+                .column_by_name("_distance")
+                .and_then(|array| array.as_any().downcast_ref::<Float32Array>());
             if let (Some(strings), Some(timestamps)) = (content_array, ts_array) {
                 for i in 0..strings.len() {
                     if strings.is_null(i) || timestamps.is_null(i) {
                         continue;
                     }
                     let ts = timestamps.value(i);
-                    results.push(format!("[{}] {}", format_timestamp(ts), strings.value(i)));
+                    let distance = distances
+                        .filter(|values| !values.is_null(i))
+                        .map(|values| values.value(i).max(0.0))
+                        .unwrap_or(0.0);
+                    results.push((
+                        format!("[{}] {}", format_timestamp(ts), strings.value(i)),
+                        1.0 / (1.0 + distance),
+                    ));
                 }
             }
         }
         Ok(results)
     }
 
+    /// Rejects override models Ollama doesn't have; a no-op for other servers.
+    async fn check_override_models(&self, models: &SearchModels) -> Result<()> {
+        let Some(url) = self.ollama_tags_url.as_deref() else {
+            return Ok(());
+        };
+        if models.is_empty() {
+            return Ok(());
+        }
+        let tags: serde_json::Value = reqwest::get(url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        let installed: Vec<&str> = tags
+            .get("models")
+            .and_then(|models| models.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|model| model.get("name").and_then(|name| name.as_str()))
+            .collect();
+        for model in [&models.embedding_model, &models.rerank_model]
+            .into_iter()
+            .flatten()
+        {
+            let tagged = if model.contains(':') {
+                model.clone()
+            } else {
+                format!("{model}:latest")
+            };
+            if !installed
+                .iter()
+                .any(|name| *name == model || *name == tagged)
+            {
+                return Err(ButterflyBotError::Config(format!(
+                    "Model '{model}' is not installed in Ollama"
+                )));
+            }
+        }
+        Ok(())
+    }
+
     async fn rerank_with_model(
         &self,
         reranker: &Arc<dyn LlmProvider>,
@@ -705,6 +1627,19 @@ impl SqliteMemoryProvider {
         self.summarize_with_threshold(user_id, 1).await
     }
 
+    /// Rebuilds `memory.vector_index` over everything stored so far. Returns
+    /// false without an index configured, a vector table, or enough rows.
+    pub async fn build_vector_index(&self) -> Result<bool> {
+        let Some(lancedb) = &self.lancedb else {
+            return Ok(false);
+        };
+        let mut built = false;
+        for table in lancedb.all_tables().await? {
+            built |= lancedb.build_index(&table).await?;
+        }
+        Ok(built)
+    }
+
     async fn maybe_summarize(&self, user_id: &str) -> Result<()> {
         self.summarize_with_threshold(user_id, self.summary_threshold)
             .await
@@ -769,8 +1704,10 @@ impl SqliteMemoryProvider {
         });
 
         let system = "You are a memory summarizer. Return JSON only.";
-        let prompt =
-            format!("Summarize the following conversation into a concise memory.\n\n{transcript}");
+        let prompt = format!(
+            "Summarize the following conversation into a concise memory. {}\n\n{transcript}",
+            summary_instructions(self.summary_style)
+        );
         let output = summarizer
             .parse_structured_output(&prompt, system, schema, None)
             .await
@@ -89,3 +89,29 @@ diesel::table! {
         fired_at -> Nullable<BigInt>,
     }
 }
+
+diesel::table! {
+    message_branches (id) {
+        id -> Integer,
+        user_id -> Text,
+        message_id -> Integer,
+        content -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    pinned_context (id) {
+        id -> Integer,
+        user_id -> Text,
+        content -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    archived_chats (user_id) {
+        user_id -> Text,
+        archived_at -> BigInt,
+    }
+}
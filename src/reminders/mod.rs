@@ -1,22 +1,35 @@
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
 
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use serde::Serialize;
 
+use crate::clock::{Clock, SystemClock};
+use crate::db::retry_on_busy;
 use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
 use schema::reminders;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 const REMINDERS_UP_SQL: &str = include_str!("../../migrations/20260130_create_reminders/up.sql");
+const REMINDER_CLAIMS_UP_SQL: &str =
+    include_str!("../../migrations/20260205_reminder_claims/up.sql");
+const REMINDER_LINKS_UP_SQL: &str =
+    include_str!("../../migrations/20260207_reminder_todo_links/up.sql");
+const REMINDER_CLAIM_OWNER_UP_SQL: &str =
+    include_str!("../../migrations/20260217_reminder_claim_owner/up.sql");
+
+pub const DEFAULT_CLAIM_LEASE_SECS: i64 = 300;
+/// Ten minutes, an hour and a day.
+pub const DEFAULT_SNOOZE_PRESETS: [i64; 3] = [600, 3600, 86400];
 
 type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
 type SqlitePool = Pool<SqliteAsyncConn>;
@@ -30,17 +43,22 @@ pub struct ReminderItem {
     pub created_at: i64,
     pub completed_at: Option<i64>,
     pub fired_at: Option<i64>,
+    pub linked_todo_id: Option<i32>,
 }
 
 #[derive(Queryable)]
 struct ReminderRow {
     id: i32,
-    _user_id: String,
+    user_id: String,
     title: String,
     due_at: i64,
     created_at: i64,
     completed_at: Option<i64>,
     fired_at: Option<i64>,
+    _claimed_at: Option<i64>,
+    linked_todo_id: Option<i32>,
+    _claim_token: Option<String>,
+    _lease_expires_at: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -56,6 +74,9 @@ struct NewReminder<'a> {
 
 pub struct ReminderStore {
     pool: SqlitePool,
+    clock: Arc<dyn Clock>,
+    snooze_presets: Vec<i64>,
+    dedupe_window: Option<i64>,
 }
 
 impl ReminderStore {
@@ -70,7 +91,41 @@ impl ReminderStore {
             .build(manager)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            clock: Arc::new(SystemClock),
+            snooze_presets: DEFAULT_SNOOZE_PRESETS.to_vec(),
+            dedupe_window: None,
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Non-positive durations are dropped; an empty list keeps the defaults.
+    pub fn with_snooze_presets(mut self, presets: Vec<i64>) -> Self {
+        let presets: Vec<i64> = presets.into_iter().filter(|secs| *secs > 0).collect();
+        if !presets.is_empty() {
+            self.snooze_presets = presets;
+        }
+        self
+    }
+
+    /// With a window set, creating a reminder whose title matches an open
+    /// one due within `window` seconds returns the existing reminder instead.
+    pub fn with_dedupe_window(mut self, window: Option<i64>) -> Self {
+        self.dedupe_window = window.filter(|secs| *secs >= 0);
+        self
+    }
+
+    pub fn snooze_presets(&self) -> &[i64] {
+        &self.snooze_presets
+    }
+
+    pub fn now(&self) -> i64 {
+        self.clock.now()
     }
 
     pub async fn create_reminder(
@@ -79,30 +134,49 @@ impl ReminderStore {
         title: &str,
         due_at: i64,
     ) -> Result<ReminderItem> {
-        let now = now_ts();
-        let new = NewReminder {
-            user_id,
-            title,
-            due_at,
-            created_at: now,
-            completed_at: None,
-            fired_at: None,
-        };
-
-        let mut conn = self.conn().await?;
-        diesel::insert_into(reminders::table)
-            .values(&new)
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let new = NewReminder {
+                user_id,
+                title,
+                due_at,
+                created_at: now,
+                completed_at: None,
+                fired_at: None,
+            };
+
+            let mut conn = self.conn().await?;
+            if let Some(window) = self.dedupe_window {
+                let candidates: Vec<ReminderRow> = reminders::table
+                    .filter(reminders::user_id.eq(user_id))
+                    .filter(reminders::completed_at.is_null())
+                    .filter(reminders::due_at.between(due_at - window, due_at + window))
+                    .order(reminders::id.asc())
+                    .load(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+                if let Some(existing) = candidates
+                    .into_iter()
+                    .find(|row| row.title.trim().eq_ignore_ascii_case(title.trim()))
+                {
+                    return Ok(map_row(existing));
+                }
+            }
+            diesel::insert_into(reminders::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
 
-        let row: ReminderRow = reminders::table
-            .filter(reminders::user_id.eq(user_id))
-            .order(reminders::id.desc())
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+            let row: ReminderRow = reminders::table
+                .filter(reminders::user_id.eq(user_id))
+                .order(reminders::id.desc())
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn list_reminders(
@@ -138,69 +212,159 @@ impl ReminderStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
+    pub async fn count_open(&self, user_id: &str) -> Result<u64> {
+        let mut conn = self.conn().await?;
+        let count: i64 = reminders::table
+            .filter(reminders::user_id.eq(user_id))
+            .filter(reminders::completed_at.is_null())
+            .count()
+            .get_result(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(count as u64)
+    }
+
     pub async fn complete_reminder(&self, user_id: &str, id: i32) -> Result<bool> {
-        let now = now_ts();
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
+                reminders::table
+                    .filter(reminders::user_id.eq(user_id))
+                    .filter(reminders::id.eq(id)),
+            )
+            .set(reminders::completed_at.eq(Some(now)))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    pub async fn get_reminder(&self, user_id: &str, id: i32) -> Result<Option<ReminderItem>> {
         let mut conn = self.conn().await?;
-        let updated = diesel::update(
-            reminders::table
-                .filter(reminders::user_id.eq(user_id))
-                .filter(reminders::id.eq(id)),
-        )
-        .set(reminders::completed_at.eq(Some(now)))
-        .execute(&mut conn)
+        let row: Option<ReminderRow> = reminders::table
+            .filter(reminders::user_id.eq(user_id))
+            .filter(reminders::id.eq(id))
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(row.map(map_row))
+    }
+
+    /// The owners of the reminders among `ids`, for callers that only hold
+    /// ids such as a worker acking a claim.
+    pub async fn reminder_owners(&self, ids: &[i32]) -> Result<Vec<(i32, String)>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.conn().await?;
+        reminders::table
+            .filter(reminders::id.eq_any(ids))
+            .select((reminders::id, reminders::user_id))
+            .order(reminders::id.asc())
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+    }
+
+    pub async fn set_linked_todo(
+        &self,
+        user_id: &str,
+        id: i32,
+        todo_id: Option<i32>,
+    ) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
+                reminders::table
+                    .filter(reminders::user_id.eq(user_id))
+                    .filter(reminders::id.eq(id)),
+            )
+            .set(reminders::linked_todo_id.eq(todo_id))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(updated > 0)
+        })
         .await
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(updated > 0)
     }
 
     pub async fn delete_reminder(&self, user_id: &str, id: i32) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let deleted = diesel::delete(
-            reminders::table
-                .filter(reminders::user_id.eq(user_id))
-                .filter(reminders::id.eq(id)),
-        )
-        .execute(&mut conn)
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let deleted = diesel::delete(
+                reminders::table
+                    .filter(reminders::user_id.eq(user_id))
+                    .filter(reminders::id.eq(id)),
+            )
+            .execute(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(deleted > 0)
+        })
         .await
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(deleted > 0)
     }
 
     pub async fn delete_all(&self, user_id: &str, include_completed: bool) -> Result<usize> {
-        let mut conn = self.conn().await?;
-        let deleted = if include_completed {
-            diesel::delete(reminders::table.filter(reminders::user_id.eq(user_id)))
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let deleted = if include_completed {
+                diesel::delete(reminders::table.filter(reminders::user_id.eq(user_id)))
+                    .execute(&mut conn)
+                    .await
+                    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
+            } else {
+                diesel::delete(
+                    reminders::table
+                        .filter(reminders::user_id.eq(user_id))
+                        .filter(reminders::completed_at.is_null()),
+                )
                 .execute(&mut conn)
                 .await
                 .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
-        } else {
-            diesel::delete(
+            };
+            Ok(deleted)
+        })
+        .await
+    }
+
+    pub async fn snooze_reminder(&self, user_id: &str, id: i32, due_at: i64) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
                 reminders::table
                     .filter(reminders::user_id.eq(user_id))
-                    .filter(reminders::completed_at.is_null()),
+                    .filter(reminders::id.eq(id)),
             )
+            .set((
+                reminders::due_at.eq(due_at),
+                reminders::fired_at.eq::<Option<i64>>(None),
+                reminders::claimed_at.eq::<Option<i64>>(None),
+                reminders::claim_token.eq::<Option<String>>(None),
+                reminders::lease_expires_at.eq::<Option<i64>>(None),
+            ))
             .execute(&mut conn)
             .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?
-        };
-        Ok(deleted)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(updated > 0)
+        })
+        .await
     }
 
-    pub async fn snooze_reminder(&self, user_id: &str, id: i32, due_at: i64) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let updated = diesel::update(
-            reminders::table
-                .filter(reminders::user_id.eq(user_id))
-                .filter(reminders::id.eq(id)),
-        )
-        .set((
-            reminders::due_at.eq(due_at),
-            reminders::fired_at.eq::<Option<i64>>(None),
-        ))
-        .execute(&mut conn)
-        .await
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(updated > 0)
+    /// Pushes the reminder out by the `preset`-th snooze duration, counted
+    /// from now rather than from its old due time.
+    pub async fn snooze_by(&self, user_id: &str, id: i32, preset: usize) -> Result<bool> {
+        let Some(secs) = self.snooze_presets.get(preset) else {
+            return Err(ButterflyBotError::InvalidInput(format!(
+                "snooze preset {preset} does not exist ({} configured)",
+                self.snooze_presets.len()
+            )));
+        };
+        self.snooze_reminder(user_id, id, self.clock.now() + secs)
+            .await
     }
 
     pub async fn due_reminders(
@@ -209,36 +373,161 @@ impl ReminderStore {
         now: i64,
         limit: usize,
     ) -> Result<Vec<ReminderItem>> {
-        let mut conn = self.conn().await?;
-        let mut query = reminders::table
-            .filter(reminders::user_id.eq(user_id))
-            .filter(reminders::completed_at.is_null())
-            .filter(reminders::due_at.le(now))
-            .filter(reminders::fired_at.is_null())
-            .into_boxed();
-        if limit > 0 {
-            query = query.limit(limit as i64);
-        }
-        let rows: Vec<ReminderRow> = query
-            .order(reminders::due_at.asc())
-            .load(&mut conn)
+        let items = self
+            .claim_reminders(Some(user_id), now, DEFAULT_CLAIM_LEASE_SECS, limit)
+            .await?
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect::<Vec<_>>();
+        let ids: Vec<i32> = items.iter().map(|item| item.id).collect();
+        self.mark_fired(&ids, now).await?;
+        Ok(items)
+    }
+
+    pub async fn claim_due(&self, now: i64, limit: usize) -> Result<Vec<(String, ReminderItem)>> {
+        self.claim_reminders(None, now, DEFAULT_CLAIM_LEASE_SECS, limit)
             .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    }
 
-        if !rows.is_empty() {
-            let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
-            diesel::update(
-                reminders::table
-                    .filter(reminders::user_id.eq(user_id))
-                    .filter(reminders::id.eq_any(&ids)),
-            )
-            .set(reminders::fired_at.eq(Some(now)))
-            .execute(&mut conn)
+    /// First half of delivery: leases due reminders to the caller without marking them fired.
+    /// A lease that has run out is treated as abandoned and the reminder is handed out again,
+    /// so a crash between claim and `mark_fired` delays a reminder instead of losing it.
+    pub async fn claim_reminders(
+        &self,
+        user_id: Option<&str>,
+        now: i64,
+        lease_secs: i64,
+        limit: usize,
+    ) -> Result<Vec<(String, ReminderItem)>> {
+        self.claim_reminders_as(&new_claim_token()?, user_id, now, lease_secs, limit)
             .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
+    }
 
-        Ok(rows.into_iter().map(map_row).collect())
+    /// [`ReminderStore::claim_reminders`] under `claim_token`, which
+    /// [`ReminderStore::ack_reminders`] must present to settle the claim.
+    pub async fn claim_reminders_as(
+        &self,
+        claim_token: &str,
+        user_id: Option<&str>,
+        now: i64,
+        lease_secs: i64,
+        limit: usize,
+    ) -> Result<Vec<(String, ReminderItem)>> {
+        retry_on_busy(|| async move {
+            let lease_secs = lease_secs.max(0);
+            // Claims from before leases were stored only have `claimed_at`.
+            let expired = now - lease_secs;
+            let claimable = reminders::claimed_at
+                .is_null()
+                .or(reminders::lease_expires_at.le(now))
+                .or(reminders::lease_expires_at
+                    .is_null()
+                    .and(reminders::claimed_at.le(expired)));
+            let mut conn = self.conn().await?;
+            let mut query = reminders::table
+                .filter(reminders::completed_at.is_null())
+                .filter(reminders::due_at.le(now))
+                .filter(reminders::fired_at.is_null())
+                .filter(claimable)
+                .into_boxed();
+            if let Some(user_id) = user_id {
+                query = query.filter(reminders::user_id.eq(user_id));
+            }
+            if limit > 0 {
+                query = query.limit(limit as i64);
+            }
+            let rows: Vec<ReminderRow> = query
+                .order(reminders::due_at.asc())
+                .load(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let mut claimed = Vec::with_capacity(rows.len());
+            for row in rows {
+                let updated = diesel::update(
+                    reminders::table
+                        .filter(reminders::id.eq(row.id))
+                        .filter(reminders::fired_at.is_null())
+                        .filter(claimable),
+                )
+                .set((
+                    reminders::claimed_at.eq(Some(now)),
+                    reminders::claim_token.eq(Some(claim_token)),
+                    reminders::lease_expires_at.eq(Some(now + lease_secs)),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+                if updated > 0 {
+                    claimed.push((row.user_id.clone(), map_row(row)));
+                }
+            }
+            Ok(claimed)
+        })
+        .await
+    }
+
+    pub async fn mark_fired(&self, ids: &[i32], now: i64) -> Result<()> {
+        retry_on_busy(|| async move {
+            if ids.is_empty() {
+                return Ok(());
+            }
+            let mut conn = self.conn().await?;
+            diesel::update(reminders::table.filter(reminders::id.eq_any(ids)))
+                .set((
+                    reminders::fired_at.eq(Some(now)),
+                    reminders::claimed_at.eq::<Option<i64>>(None),
+                    reminders::claim_token.eq::<Option<String>>(None),
+                    reminders::lease_expires_at.eq::<Option<i64>>(None),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Second half of delivery for an external worker: marks reminders fired
+    /// that are still leased under `claim_token`. Ids claimed by someone else,
+    /// or whose lease has run out, are skipped, so a late or stray ack can't
+    /// fire a reminder another claimer is delivering. Returns the ids that
+    /// were acknowledged.
+    pub async fn ack_reminders(
+        &self,
+        claim_token: &str,
+        ids: &[i32],
+        now: i64,
+    ) -> Result<Vec<i32>> {
+        retry_on_busy(|| async move {
+            if ids.is_empty() || claim_token.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut conn = self.conn().await?;
+            conn.immediate_transaction(|conn| {
+                async move {
+                    let held = reminders::table
+                        .filter(reminders::id.eq_any(ids))
+                        .filter(reminders::fired_at.is_null())
+                        .filter(reminders::claim_token.eq(claim_token))
+                        .filter(reminders::lease_expires_at.gt(now));
+                    let acked: Vec<i32> = held.select(reminders::id).load(conn).await?;
+                    diesel::update(reminders::table.filter(reminders::id.eq_any(&acked)))
+                        .set((
+                            reminders::fired_at.eq(Some(now)),
+                            reminders::claimed_at.eq::<Option<i64>>(None),
+                            reminders::claim_token.eq::<Option<String>>(None),
+                            reminders::lease_expires_at.eq::<Option<i64>>(None),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    Ok::<_, ButterflyBotError>(acked)
+                }
+                .scope_boxed()
+            })
+            .await
+        })
+        .await
     }
 
     pub async fn peek_due_reminders(
@@ -311,24 +600,10 @@ fn map_row(row: ReminderRow) -> ReminderItem {
         created_at: row.created_at,
         completed_at: row.completed_at,
         fired_at: row.fired_at,
+        linked_todo_id: row.linked_todo_id,
     }
 }
 
-fn now_ts() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64
-}
-
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
-}
-
 async fn run_migrations(database_url: &str) -> Result<()> {
     let database_url = database_url.to_string();
     tokio::task::spawn_blocking(move || {
@@ -367,6 +642,57 @@ async fn ensure_reminders_table(database_url: &str) -> Result<()> {
             }
         }
 
+        let claims = diesel::connection::SimpleConnection::batch_execute(
+            &mut conn,
+            "SELECT claimed_at FROM reminders LIMIT 1",
+        );
+        if let Err(err) = claims {
+            let message = err.to_string();
+            if message.contains("no such column") {
+                diesel::connection::SimpleConnection::batch_execute(
+                    &mut conn,
+                    REMINDER_CLAIMS_UP_SQL,
+                )
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            } else {
+                return Err(ButterflyBotError::Runtime(message));
+            }
+        }
+
+        let owners = diesel::connection::SimpleConnection::batch_execute(
+            &mut conn,
+            "SELECT claim_token FROM reminders LIMIT 1",
+        );
+        if let Err(err) = owners {
+            let message = err.to_string();
+            if message.contains("no such column") {
+                diesel::connection::SimpleConnection::batch_execute(
+                    &mut conn,
+                    REMINDER_CLAIM_OWNER_UP_SQL,
+                )
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            } else {
+                return Err(ButterflyBotError::Runtime(message));
+            }
+        }
+
+        let links = diesel::connection::SimpleConnection::batch_execute(
+            &mut conn,
+            "SELECT linked_todo_id FROM reminders LIMIT 1",
+        );
+        if let Err(err) = links {
+            let message = err.to_string();
+            if message.contains("no such column") {
+                diesel::connection::SimpleConnection::batch_execute(
+                    &mut conn,
+                    REMINDER_LINKS_UP_SQL,
+                )
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            } else {
+                return Err(ButterflyBotError::Runtime(message));
+            }
+        }
+
         Ok::<_, ButterflyBotError>(())
     })
     .await
@@ -374,6 +700,45 @@ async fn ensure_reminders_table(database_url: &str) -> Result<()> {
     Ok(())
 }
 
+/// A random, hex-encoded token naming one claim.
+pub fn new_claim_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// `tools.reminders.snooze_presets` in seconds, or the defaults when unset.
+pub fn resolve_snooze_presets(config: &serde_json::Value) -> Vec<i64> {
+    let presets: Vec<i64> = config
+        .get("tools")
+        .and_then(|v| v.get("reminders"))
+        .and_then(|v| v.get("snooze_presets"))
+        .and_then(|v| v.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_i64())
+                .filter(|secs| *secs > 0)
+                .collect()
+        })
+        .unwrap_or_default();
+    if presets.is_empty() {
+        DEFAULT_SNOOZE_PRESETS.to_vec()
+    } else {
+        presets
+    }
+}
+
+/// `tools.reminders.dedupe_window_secs`; dedupe stays off when unset.
+pub fn resolve_dedupe_window(config: &serde_json::Value) -> Option<i64> {
+    config
+        .get("tools")
+        .and_then(|v| v.get("reminders"))
+        .and_then(|v| v.get("dedupe_window_secs"))
+        .and_then(|v| v.as_i64())
+        .filter(|secs| *secs >= 0)
+}
+
 pub fn resolve_reminder_db_path(config: &serde_json::Value) -> Option<String> {
     let tool_path = config
         .get("tools")
@@ -400,5 +765,5 @@ pub fn resolve_reminder_db_path(config: &serde_json::Value) -> Option<String> {
 }
 
 pub fn default_reminder_db_path() -> String {
-    "./data/butterfly-bot.db".to_string()
+    crate::paths::default_db_path()
 }
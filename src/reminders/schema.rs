@@ -7,5 +7,9 @@ diesel::table! {
         created_at -> BigInt,
         completed_at -> Nullable<BigInt>,
         fired_at -> Nullable<BigInt>,
+        claimed_at -> Nullable<BigInt>,
+        linked_todo_id -> Nullable<Integer>,
+        claim_token -> Nullable<Text>,
+        lease_expires_at -> Nullable<BigInt>,
     }
 }
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::Result;
+use crate::interfaces::scheduler::ScheduledJob;
+use crate::reminders::{ReminderItem, ReminderStore, DEFAULT_CLAIM_LEASE_SECS};
+use crate::tasks::{ScheduledTask, TaskStore};
+use crate::wakeup::{WakeupStore, WakeupTask};
+
+#[derive(Debug, Clone)]
+pub enum DueWork {
+    Task(ScheduledTask),
+    Wakeup(WakeupTask),
+    Reminder { user_id: String, item: ReminderItem },
+}
+
+impl DueWork {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DueWork::Task(_) => "task",
+            DueWork::Wakeup(_) => "wakeup",
+            DueWork::Reminder { .. } => "reminder",
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        match self {
+            DueWork::Task(task) => &task.user_id,
+            DueWork::Wakeup(task) => &task.user_id,
+            DueWork::Reminder { user_id, .. } => user_id,
+        }
+    }
+}
+
+#[async_trait]
+pub trait DueWorkHandler: Send + Sync {
+    async fn handle(&self, work: DueWork) -> Result<()>;
+}
+
+pub struct DueWorkScheduler {
+    handler: Arc<dyn DueWorkHandler>,
+    interval: Duration,
+    max_per_tick: usize,
+    lease_secs: i64,
+    clock: Arc<dyn Clock>,
+    tasks: Option<Arc<TaskStore>>,
+    wakeups: Option<Arc<WakeupStore>>,
+    reminders: Option<Arc<ReminderStore>>,
+    busy: Mutex<()>,
+}
+
+impl DueWorkScheduler {
+    pub fn new(handler: Arc<dyn DueWorkHandler>, interval: Duration) -> Self {
+        Self {
+            handler,
+            interval,
+            max_per_tick: 32,
+            lease_secs: DEFAULT_CLAIM_LEASE_SECS,
+            clock: Arc::new(SystemClock),
+            tasks: None,
+            wakeups: None,
+            reminders: None,
+            busy: Mutex::new(()),
+        }
+    }
+
+    pub fn with_tasks(mut self, store: Arc<TaskStore>) -> Self {
+        self.tasks = Some(store);
+        self
+    }
+
+    pub fn with_wakeups(mut self, store: Arc<WakeupStore>) -> Self {
+        self.wakeups = Some(store);
+        self
+    }
+
+    pub fn with_reminders(mut self, store: Arc<ReminderStore>) -> Self {
+        self.reminders = Some(store);
+        self
+    }
+
+    pub fn with_max_per_tick(mut self, max_per_tick: usize) -> Self {
+        self.max_per_tick = max_per_tick.max(1);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_lease_secs(mut self, lease_secs: i64) -> Self {
+        self.lease_secs = lease_secs.max(1);
+        self
+    }
+
+    /// Dispatches everything due at `now` across all stores, at most `max_per_tick` items per
+    /// call; the rest stay due for the next tick. Each item is leased just before its handler
+    /// runs, so a slow handler can't let later items' leases lapse while they wait, and only
+    /// finalized once the handler succeeds. A crash or a failed handler leaves the item to be
+    /// picked up again after the lease expires.
+    pub async fn poll_once(&self, now: i64) -> Result<usize> {
+        let mut dispatched = 0;
+
+        if let Some(store) = &self.tasks {
+            for task in store.list_due(now, self.max_per_tick).await? {
+                let at = self.claim_time(now);
+                if store.claim(task.id, at, self.lease_secs).await? {
+                    self.dispatch(DueWork::Task(task), at).await?;
+                    dispatched += 1;
+                }
+            }
+        }
+
+        if let Some(store) = &self.wakeups {
+            let budget = self.max_per_tick - dispatched;
+            if budget > 0 {
+                for task in store.list_due(now, budget).await? {
+                    let at = self.claim_time(now);
+                    if store.claim(task.id, at, self.lease_secs).await? {
+                        self.dispatch(DueWork::Wakeup(task), at).await?;
+                        dispatched += 1;
+                    }
+                }
+            }
+        }
+
+        if let Some(store) = &self.reminders {
+            while dispatched < self.max_per_tick {
+                let at = self.claim_time(now);
+                let Some((user_id, item)) = store
+                    .claim_reminders(None, at, self.lease_secs, 1)
+                    .await?
+                    .pop()
+                else {
+                    break;
+                };
+                self.dispatch(DueWork::Reminder { user_id, item }, at)
+                    .await?;
+                dispatched += 1;
+            }
+        }
+
+        Ok(dispatched)
+    }
+
+    /// `now`, or the clock if handling earlier items has already moved past it.
+    fn claim_time(&self, now: i64) -> i64 {
+        now.max(self.clock.now())
+    }
+
+    async fn dispatch(&self, work: DueWork, now: i64) -> Result<()> {
+        if let Err(err) = self.handler.handle(work.clone()).await {
+            tracing::warn!(
+                kind = work.kind(),
+                user_id = work.user_id(),
+                error = %err,
+                "due work failed; it will be retried once its lease expires"
+            );
+            return Ok(());
+        }
+        self.finish(&work, now).await
+    }
+
+    async fn finish(&self, work: &DueWork, now: i64) -> Result<()> {
+        match work {
+            DueWork::Task(task) => {
+                let Some(store) = &self.tasks else {
+                    return Ok(());
+                };
+                match task.interval_minutes {
+                    Some(interval) => {
+                        store
+                            .mark_run(task.id, now, now + interval.max(1) * 60)
+                            .await
+                    }
+                    None => store.complete_one_shot(task.id).await,
+                }
+            }
+            DueWork::Wakeup(task) => {
+                let Some(store) = &self.wakeups else {
+                    return Ok(());
+                };
+                store
+                    .mark_run(task.id, now, now + task.interval_minutes.max(1) * 60)
+                    .await
+            }
+            DueWork::Reminder { item, .. } => {
+                let Some(store) = &self.reminders else {
+                    return Ok(());
+                };
+                store.mark_fired(&[item.id], now).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ScheduledJob for DueWorkScheduler {
+    fn name(&self) -> &str {
+        "due_work"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self) -> Result<()> {
+        // A tick that lands while the previous batch is still being handled is dropped rather
+        // than queued, so a slow LLM can't build up a backlog of overlapping polls.
+        let Ok(_guard) = self.busy.try_lock() else {
+            return Ok(());
+        };
+        self.poll_once(self.clock.now()).await?;
+        Ok(())
+    }
+}
@@ -3,9 +3,16 @@ use std::time::Duration;
 
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 
 use crate::interfaces::scheduler::ScheduledJob;
 
+pub mod due_work;
+pub mod preview;
+
+pub use due_work::{DueWork, DueWorkHandler, DueWorkScheduler};
+pub use preview::{preview_occurrences, CronSchedule, ScheduleRule};
+
 pub struct Scheduler {
     jobs: Vec<Arc<dyn ScheduledJob>>,
     handles: Vec<JoinHandle<()>>,
@@ -39,6 +46,7 @@ impl Scheduler {
         for job in &self.jobs {
             let job = Arc::clone(job);
             let mut tick = tokio::time::interval(job.interval());
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
             let mut rx = rx.clone();
             let handle = tokio::spawn(async move {
                 loop {
@@ -0,0 +1,199 @@
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::error::{ButterflyBotError, Result};
+use crate::todo::TodoRecurrence;
+
+/// A repeating schedule whose upcoming fire times can be listed without
+/// saving anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleRule {
+    Recurrence(TodoRecurrence),
+    Cron(CronSchedule),
+}
+
+impl ScheduleRule {
+    /// Accepts a recurrence (`daily`, `weekly`, `every N days`) or a
+    /// five-field cron expression.
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.split_whitespace().count() == 5 {
+            return CronSchedule::parse(value).map(Self::Cron);
+        }
+        TodoRecurrence::parse(value).map(Self::Recurrence)
+    }
+}
+
+/// The next `count` fire times strictly after `from`, as unix seconds. Cron
+/// expressions are evaluated in UTC; a recurrence steps in whole intervals
+/// from `from` itself.
+pub fn preview_occurrences(rule: &ScheduleRule, from: i64, count: usize) -> Vec<i64> {
+    match rule {
+        ScheduleRule::Recurrence(recurrence) => {
+            let interval = recurrence.interval_secs();
+            (1..=count as i64)
+                .map(|step| from + step * interval)
+                .collect()
+        }
+        ScheduleRule::Cron(cron) => {
+            let mut occurrences = Vec::with_capacity(count);
+            let mut cursor = from;
+            while occurrences.len() < count {
+                match cron.next_after(cursor) {
+                    Some(next) => {
+                        occurrences.push(next);
+                        cursor = next;
+                    }
+                    None => break,
+                }
+            }
+            occurrences
+        }
+    }
+}
+
+/// Standard `minute hour day-of-month month day-of-week` cron. Fields take
+/// `*`, numbers, ranges, steps and comma lists; when both day fields are
+/// restricted a day matching either one fires, as in cron(8).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+// Gives up on expressions that can never fire, such as `0 0 30 2 *`.
+const MAX_SCAN_DAYS: i64 = 366 * 8;
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(cron_error(expression, "expected five fields"));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, expression)?;
+        // Both 0 and 7 mean Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, expression)?,
+            hours: parse_field(hour, 0, 23, expression)?,
+            days: parse_field(day, 1, 31, expression)?,
+            months: parse_field(month, 1, 12, expression)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// The first matching minute strictly after `after`.
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let start = after.div_euclid(60).checked_add(1)? * 60;
+        let mut cursor = OffsetDateTime::from_unix_timestamp(start).ok()?;
+        let limit = cursor + Duration::days(MAX_SCAN_DAYS);
+
+        while cursor < limit {
+            let date = cursor.date();
+            if !has_bit(self.months, u8::from(date.month())) {
+                cursor = first_of_next_month(date)?.midnight().assume_utc();
+                continue;
+            }
+            if !self.day_matches(date) {
+                cursor = date.next_day()?.midnight().assume_utc();
+                continue;
+            }
+            if !has_bit(self.hours, cursor.hour()) {
+                cursor = date
+                    .with_time(Time::from_hms(cursor.hour(), 0, 0).ok()?)
+                    .assume_utc()
+                    + Duration::HOUR;
+                continue;
+            }
+            if !has_bit(self.minutes, cursor.minute()) {
+                cursor += Duration::MINUTE;
+                continue;
+            }
+            return Some(cursor.unix_timestamp());
+        }
+        None
+    }
+
+    fn day_matches(&self, date: Date) -> bool {
+        let day = has_bit(self.days, date.day());
+        let weekday = has_bit(self.weekdays, date.weekday().number_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn parse_field(field: &str, min: u8, max: u8, expression: &str) -> Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| cron_error(expression, &format!("bad step in '{part}'")))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some((low, high)) = range.split_once('-') {
+            (
+                parse_value(low, min, max, expression)?,
+                parse_value(high, min, max, expression)?,
+            )
+        } else {
+            let value = parse_value(range, min, max, expression)?;
+            // `5/15` means "from 5 onwards, every 15".
+            (value, if step > 1 { max } else { value })
+        };
+        if low > high {
+            return Err(cron_error(expression, &format!("empty range '{range}'")));
+        }
+        for value in (low..=high).step_by(usize::from(step)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, min: u8, max: u8, expression: &str) -> Result<u8> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| {
+            cron_error(
+                expression,
+                &format!("'{value}' is not between {min} and {max}"),
+            )
+        })
+}
+
+fn cron_error(expression: &str, reason: &str) -> ButterflyBotError {
+    ButterflyBotError::Runtime(format!("Invalid cron expression '{expression}': {reason}"))
+}
+
+fn has_bit(bits: u64, value: u8) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn first_of_next_month(date: Date) -> Option<Date> {
+    let (year, month) = match date.month() {
+        time::Month::December => (date.year() + 1, time::Month::January),
+        month => (date.year(), month.next()),
+    };
+    Date::from_calendar_date(year, month, 1).ok()
+}
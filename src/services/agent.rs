@@ -1,20 +1,30 @@
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use async_stream::try_stream;
+use futures::future::Either;
 use futures::stream::BoxStream;
 use futures::StreamExt;
 use serde::Serialize;
 use serde_json::json;
 
 use crate::brain::manager::BrainManager;
+use crate::costs::{estimate_tokens, CostTracker};
 use crate::domains::agent::AIAgent;
 use crate::error::{ButterflyBotError, Result};
 use crate::interfaces::brain::{BrainContext, BrainEvent};
-use crate::interfaces::providers::{LlmProvider, ToolCall};
-use crate::plugins::registry::ToolRegistry;
+use crate::interfaces::guardrails::{ToolCallGuardrail, ToolCallVerdict};
+use crate::interfaces::providers::{
+    assign_tool_call_ids, ChatEvent, GenerationParams, LlmProvider, MemoryProvider, ModelInfo,
+    TokenUsage, ToolCall,
+};
+use crate::plugins::registry::{validate_tool_params, ToolRegistry};
+use crate::services::request_context::current_request_id;
 use tokio::sync::broadcast;
+use tokio::sync::mpsc;
 use tokio::sync::RwLock;
+use tracing::field::Empty;
+use tracing::Instrument;
 
 pub struct AgentService {
     llm_provider: Arc<dyn LlmProvider>,
@@ -22,10 +32,21 @@ pub struct AgentService {
     agent: AIAgent,
     heartbeat_markdown: RwLock<Option<String>>,
     brain_manager: Arc<BrainManager>,
-    started: RwLock<bool>,
+    started: Arc<RwLock<bool>>,
     ui_event_tx: Option<broadcast::Sender<UiEvent>>,
+    cost_tracker: RwLock<Option<Arc<CostTracker>>>,
+    max_tool_iterations: RwLock<usize>,
+    stream_heartbeat: RwLock<Option<Duration>>,
+    pinned_context: RwLock<Option<Arc<dyn MemoryProvider>>>,
+    tool_guardrails: RwLock<Vec<Arc<dyn ToolCallGuardrail>>>,
 }
 
+/// Tool round-trips allowed per turn before a final answer is forced.
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 5;
+
+/// Quiet time on an event stream before a `heartbeat` event is sent.
+pub const DEFAULT_STREAM_HEARTBEAT: Duration = Duration::from_secs(15);
+
 #[derive(Clone, Debug, Serialize)]
 pub struct UiEvent {
     pub event_type: String,
@@ -34,12 +55,18 @@ pub struct UiEvent {
     pub status: String,
     pub payload: serde_json::Value,
     pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl AgentService {
     pub fn agent_name(&self) -> &str {
         &self.agent.name
     }
+
+    pub fn memory_namespace(&self) -> Option<&str> {
+        self.agent.memory_namespace.as_deref()
+    }
     pub fn new(
         llm_provider: Arc<dyn LlmProvider>,
         agent: AIAgent,
@@ -53,8 +80,64 @@ impl AgentService {
             agent,
             heartbeat_markdown: RwLock::new(heartbeat_markdown),
             brain_manager,
-            started: RwLock::new(false),
+            started: Arc::new(RwLock::new(false)),
             ui_event_tx,
+            cost_tracker: RwLock::new(None),
+            max_tool_iterations: RwLock::new(DEFAULT_MAX_TOOL_ITERATIONS),
+            stream_heartbeat: RwLock::new(Some(DEFAULT_STREAM_HEARTBEAT)),
+            pinned_context: RwLock::new(None),
+            tool_guardrails: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// A service answering as `agent` through `llm_provider` that shares
+    /// this one's tool registry and brains, and starts from its current
+    /// settings.
+    pub async fn with_agent(&self, agent: AIAgent, llm_provider: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            llm_provider,
+            tool_registry: self.tool_registry.clone(),
+            agent,
+            heartbeat_markdown: RwLock::new(self.heartbeat_markdown.read().await.clone()),
+            brain_manager: self.brain_manager.clone(),
+            started: Arc::new(RwLock::new(false)),
+            ui_event_tx: self.ui_event_tx.clone(),
+            cost_tracker: RwLock::new(self.cost_tracker.read().await.clone()),
+            max_tool_iterations: RwLock::new(*self.max_tool_iterations.read().await),
+            stream_heartbeat: RwLock::new(*self.stream_heartbeat.read().await),
+            pinned_context: RwLock::new(self.pinned_context.read().await.clone()),
+            tool_guardrails: RwLock::new(self.tool_guardrails.read().await.clone()),
+        }
+    }
+
+    /// This service with `overrides` taking precedence over its provider's
+    /// sampling settings, for a single request. `None` when the provider has
+    /// no settings to change. The copy shares this service's brain state.
+    pub async fn with_generation_overrides(&self, overrides: GenerationParams) -> Option<Self> {
+        let llm_provider = self.llm_provider.with_generation_overrides(overrides)?;
+        let mut service = self.with_agent(self.agent.clone(), llm_provider).await;
+        service.started = self.started.clone();
+        Some(service)
+    }
+
+    /// A service answering as `agent` for a delegated sub-query. It shares
+    /// this one's provider, budget, guardrails and settings, so delegated
+    /// calls are charged and checked like the parent's, but starts with its
+    /// own empty tool registry and no brains.
+    pub async fn delegate_service(&self, agent: AIAgent) -> Self {
+        Self {
+            llm_provider: self.llm_provider.clone(),
+            tool_registry: Arc::new(ToolRegistry::new()),
+            agent,
+            heartbeat_markdown: RwLock::new(self.heartbeat_markdown.read().await.clone()),
+            brain_manager: Arc::new(BrainManager::new(json!({}))),
+            started: Arc::new(RwLock::new(false)),
+            ui_event_tx: self.ui_event_tx.clone(),
+            cost_tracker: RwLock::new(self.cost_tracker.read().await.clone()),
+            max_tool_iterations: RwLock::new(*self.max_tool_iterations.read().await),
+            stream_heartbeat: RwLock::new(*self.stream_heartbeat.read().await),
+            pinned_context: RwLock::new(self.pinned_context.read().await.clone()),
+            tool_guardrails: RwLock::new(self.tool_guardrails.read().await.clone()),
         }
     }
 
@@ -63,6 +146,59 @@ impl AgentService {
         *guard = heartbeat_markdown;
     }
 
+    pub async fn set_cost_tracker(&self, cost_tracker: Option<Arc<CostTracker>>) {
+        let mut guard = self.cost_tracker.write().await;
+        *guard = cost_tracker;
+    }
+
+    pub async fn cost_tracker(&self) -> Option<Arc<CostTracker>> {
+        self.cost_tracker.read().await.clone()
+    }
+
+    pub async fn set_max_tool_iterations(&self, max_tool_iterations: usize) {
+        let mut guard = self.max_tool_iterations.write().await;
+        *guard = max_tool_iterations;
+    }
+
+    pub async fn max_tool_iterations(&self) -> usize {
+        *self.max_tool_iterations.read().await
+    }
+
+    /// Consulted in order before every tool call; the first rejection wins.
+    pub async fn set_tool_guardrails(&self, guardrails: Vec<Arc<dyn ToolCallGuardrail>>) {
+        let mut guard = self.tool_guardrails.write().await;
+        *guard = guardrails;
+    }
+
+    /// `None` turns heartbeats off.
+    pub async fn set_stream_heartbeat(&self, interval: Option<Duration>) {
+        let mut guard = self.stream_heartbeat.write().await;
+        *guard = interval;
+    }
+
+    pub async fn stream_heartbeat(&self) -> Option<Duration> {
+        *self.stream_heartbeat.read().await
+    }
+
+    /// Where each user's pinned context is read from for their system prompt.
+    pub async fn set_pinned_context(&self, provider: Option<Arc<dyn MemoryProvider>>) {
+        let mut guard = self.pinned_context.write().await;
+        *guard = provider;
+    }
+
+    async fn check_budget(&self, user_id: &str) -> Result<()> {
+        match self.cost_tracker().await {
+            Some(tracker) => tracker.check_budget(user_id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn record_usage(&self, user_id: &str, usage: TokenUsage) {
+        if let Some(tracker) = self.cost_tracker().await {
+            let _ = tracker.record(user_id, tracker.model(), usage).await;
+        }
+    }
+
     fn emit_tool_event(&self, user_id: &str, tool: &str, status: &str, payload: serde_json::Value) {
         let Some(sender) = &self.ui_event_tx else {
             return;
@@ -74,6 +210,7 @@ impl AgentService {
             status: status.to_string(),
             payload,
             timestamp: now_ts(),
+            request_id: current_request_id(),
         };
         let _ = sender.send(event);
     }
@@ -125,6 +262,28 @@ impl AgentService {
         Ok(system_prompt)
     }
 
+    /// The agent system prompt with `user_id`'s pinned context in front.
+    pub async fn system_prompt_for(&self, user_id: &str) -> Result<String> {
+        let system_prompt = self.get_agent_system_prompt().await?;
+        let provider = self.pinned_context.read().await.clone();
+        let pins = match provider {
+            Some(provider) => provider.pinned_context(user_id).await?,
+            None => Vec::new(),
+        };
+        if pins.is_empty() {
+            return Ok(system_prompt);
+        }
+        let mut pinned = String::from("PINNED CONTEXT (always true for this user):\n");
+        for pin in pins {
+            pinned.push_str("- ");
+            pinned.push_str(&pin.content);
+            pinned.push('\n');
+        }
+        pinned.push('\n');
+        pinned.push_str(&system_prompt);
+        Ok(pinned)
+    }
+
     pub async fn generate_response(
         &self,
         user_id: &str,
@@ -132,6 +291,26 @@ impl AgentService {
         memory_context: &str,
         prompt_override: Option<&str>,
     ) -> Result<String> {
+        self.generate_response_with_tool_limit(
+            user_id,
+            query,
+            memory_context,
+            prompt_override,
+            None,
+        )
+        .await
+    }
+
+    /// `max_tool_iterations` overrides the configured cap for this turn only.
+    pub async fn generate_response_with_tool_limit(
+        &self,
+        user_id: &str,
+        query: &str,
+        memory_context: &str,
+        prompt_override: Option<&str>,
+        max_tool_iterations: Option<usize>,
+    ) -> Result<String> {
+        self.check_budget(user_id).await?;
         self.ensure_brain_started(user_id).await?;
         let ctx = BrainContext {
             agent_name: self.agent.name.clone(),
@@ -148,7 +327,13 @@ impl AgentService {
             .await;
 
         let processed_output = self
-            .generate_response_inner(user_id, query, memory_context, prompt_override)
+            .generate_response_inner(
+                user_id,
+                query,
+                memory_context,
+                prompt_override,
+                max_tool_iterations,
+            )
             .await?;
 
         self.brain_manager
@@ -170,40 +355,64 @@ impl AgentService {
         query: &str,
         memory_context: &str,
         prompt_override: Option<&str>,
+        max_tool_iterations: Option<usize>,
     ) -> Result<String> {
-        let system_prompt = self.get_agent_system_prompt().await?;
-        let mut full_prompt = String::new();
-        if !memory_context.is_empty() {
-            full_prompt.push_str(
-                "PAST CONVERSATION HISTORY (for reference only; do not respond to past messages; assistant statements are not facts about the user):\n",
-            );
-            full_prompt.push_str(memory_context);
-            full_prompt.push_str("\n\n");
-        }
-        if let Some(prompt) = prompt_override {
-            full_prompt.push_str("ADDITIONAL PROMPT:\n");
-            full_prompt.push_str(prompt);
-            full_prompt.push_str("\n\n");
-        }
-        full_prompt.push_str(
-            "INSTRUCTION: If a DUE REMINDERS section is present in the context, surface those reminders first. Then respond only to the CURRENT USER MESSAGE below. If earlier history mentions self-harm but the current message does not, do not output crisis resources.\n\n",
-        );
-        full_prompt.push_str("CURRENT USER MESSAGE:\n");
-        full_prompt.push_str(query);
-        full_prompt.push_str(&format!("\n\nUSER IDENTIFIER: {}", user_id));
+        let system_prompt = self.system_prompt_for(user_id).await?;
+        let full_prompt = turn_prompt(user_id, query, memory_context, prompt_override);
 
         let tools = self.tool_registry.get_agent_tools(&self.agent.name).await;
         let output = if tools.is_empty() {
-            self.llm_provider
+            let output = self
+                .llm_provider
                 .generate_text(&full_prompt, &system_prompt, None)
-                .await?
+                .await?;
+            self.record_usage(
+                user_id,
+                estimate_usage(&system_prompt, &full_prompt, &output),
+            )
+            .await;
+            output
         } else {
-            self.run_tool_loop(&system_prompt, &full_prompt, tools, user_id)
+            let max_iterations = match max_tool_iterations {
+                Some(max_iterations) => max_iterations,
+                None => self.max_tool_iterations().await,
+            };
+            self.run_tool_loop(&system_prompt, &full_prompt, tools, user_id, max_iterations)
                 .await?
         };
         Ok(output)
     }
 
+    /// Asks the model for its tool calls for this turn without executing
+    /// them. No brain events are dispatched; an agent without tools plans
+    /// nothing.
+    pub async fn plan_tool_calls(
+        &self,
+        user_id: &str,
+        query: &str,
+        memory_context: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<Vec<ToolCall>> {
+        self.check_budget(user_id).await?;
+        let tools = self.tool_registry.get_agent_tools(&self.agent.name).await;
+        if tools.is_empty() {
+            return Ok(Vec::new());
+        }
+        let system_prompt = self.system_prompt_for(user_id).await?;
+        let prompt = turn_prompt(user_id, query, memory_context, prompt_override);
+        let response = self
+            .llm_provider
+            .generate_with_tools(&prompt, &system_prompt, tool_specs(&tools))
+            .await?;
+        let usage = response
+            .usage
+            .unwrap_or_else(|| estimate_usage(&system_prompt, &prompt, &response.text));
+        self.record_usage(user_id, usage).await;
+        let mut calls = response.tool_calls;
+        assign_tool_call_ids(&mut calls);
+        Ok(calls)
+    }
+
     pub fn generate_response_stream<'a>(
         &'a self,
         user_id: &'a str,
@@ -211,7 +420,68 @@ impl AgentService {
         memory_context: &'a str,
         prompt_override: Option<&'a str>,
     ) -> BoxStream<'a, Result<String>> {
+        self.response_events(user_id, query, memory_context, prompt_override, None, false)
+            .filter_map(|event| async move {
+                match event {
+                    Ok(ChatEvent::Content { delta }) => Some(Ok(delta)),
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                }
+            })
+            .boxed()
+    }
+
+    /// Like [`Self::generate_response_stream`], but as [`ChatEvent`]s. Tool
+    /// rounds show up as `tool_start`/`tool_end` markers (carrying the call id
+    /// and tool name) between the `content` events of the surrounding turns.
+    /// During tool rounds a `heartbeat` event is sent whenever nothing else has
+    /// been for the configured interval, so idle connections stay open.
+    pub fn generate_response_events<'a>(
+        &'a self,
+        user_id: &'a str,
+        query: &'a str,
+        memory_context: &'a str,
+        prompt_override: Option<&'a str>,
+    ) -> BoxStream<'a, Result<ChatEvent>> {
+        self.generate_response_events_with_tool_limit(
+            user_id,
+            query,
+            memory_context,
+            prompt_override,
+            None,
+        )
+    }
+
+    /// `max_tool_iterations` overrides the configured cap for this turn only.
+    pub fn generate_response_events_with_tool_limit<'a>(
+        &'a self,
+        user_id: &'a str,
+        query: &'a str,
+        memory_context: &'a str,
+        prompt_override: Option<&'a str>,
+        max_tool_iterations: Option<usize>,
+    ) -> BoxStream<'a, Result<ChatEvent>> {
+        self.response_events(
+            user_id,
+            query,
+            memory_context,
+            prompt_override,
+            max_tool_iterations,
+            true,
+        )
+    }
+
+    fn response_events<'a>(
+        &'a self,
+        user_id: &'a str,
+        query: &'a str,
+        memory_context: &'a str,
+        prompt_override: Option<&'a str>,
+        max_tool_iterations: Option<usize>,
+        with_markers: bool,
+    ) -> BoxStream<'a, Result<ChatEvent>> {
         Box::pin(try_stream! {
+            self.check_budget(user_id).await?;
             self.ensure_brain_started(user_id).await?;
             let ctx = BrainContext {
                 agent_name: self.agent.name.clone(),
@@ -227,36 +497,63 @@ impl AgentService {
                 )
                 .await;
 
-            let system_prompt = self.get_agent_system_prompt().await?;
-            let mut full_prompt = String::new();
-            if !memory_context.is_empty() {
-                full_prompt.push_str(
-                    "PAST CONVERSATION HISTORY (for reference only; do not respond to past messages; assistant statements are not facts about the user):\n",
-                );
-                full_prompt.push_str(memory_context);
-                full_prompt.push_str("\n\n");
-            }
-            if let Some(prompt) = prompt_override {
-                full_prompt.push_str("ADDITIONAL PROMPT:\n");
-                full_prompt.push_str(prompt);
-                full_prompt.push_str("\n\n");
-            }
-            full_prompt.push_str(
-                "INSTRUCTION: If a DUE REMINDERS section is present in the context, surface those reminders first. Then respond only to the CURRENT USER MESSAGE below. If earlier history mentions self-harm but the current message does not, do not output crisis resources.\n\n",
-            );
-            full_prompt.push_str("CURRENT USER MESSAGE:\n");
-            full_prompt.push_str(query);
-            full_prompt.push_str(&format!("\n\nUSER IDENTIFIER: {}", user_id));
+            let system_prompt = self.system_prompt_for(user_id).await?;
+            let full_prompt = turn_prompt(user_id, query, memory_context, prompt_override);
 
             let mut response_text = String::new();
             let tools = self.tool_registry.get_agent_tools(&self.agent.name).await;
-            if !tools.is_empty() {
+            if !tools.is_empty() && with_markers {
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let max_iterations = match max_tool_iterations {
+                    Some(max_iterations) => max_iterations,
+                    None => self.max_tool_iterations().await,
+                };
+                let run = self.run_tool_loop_with_events(
+                    &system_prompt,
+                    &full_prompt,
+                    tools,
+                    user_id,
+                    max_iterations,
+                    Some(tx),
+                );
+                tokio::pin!(run);
+                let heartbeat = self.stream_heartbeat().await;
+                let mut last_sent = tokio::time::Instant::now();
+                let output = loop {
+                    let deadline = heartbeat.map(|interval| last_sent + interval);
+                    let beat = async move {
+                        match deadline {
+                            Some(deadline) => tokio::time::sleep_until(deadline).await,
+                            None => std::future::pending().await,
+                        }
+                    };
+                    let next = tokio::select! {
+                        Some(event) = rx.recv() => Some(Either::Left(event)),
+                        output = &mut run => Some(Either::Right(output)),
+                        _ = beat => None,
+                    };
+                    last_sent = tokio::time::Instant::now();
+                    match next {
+                        None => yield ChatEvent::Heartbeat,
+                        Some(Either::Left(event)) => yield event,
+                        Some(Either::Right(output)) => break output?,
+                    }
+                };
+                while let Ok(event) = rx.try_recv() {
+                    yield event;
+                }
+                response_text.push_str(&output);
+            } else if !tools.is_empty() {
+                let max_iterations = match max_tool_iterations {
+                    Some(max_iterations) => max_iterations,
+                    None => self.max_tool_iterations().await,
+                };
                 let output = self
-                    .run_tool_loop(&system_prompt, &full_prompt, tools, user_id)
+                    .run_tool_loop(&system_prompt, &full_prompt, tools, user_id, max_iterations)
                     .await?;
                 if !output.is_empty() {
                     response_text.push_str(&output);
-                    yield output;
+                    yield ChatEvent::Content { delta: output };
                 }
             } else {
                 let mut messages = Vec::new();
@@ -267,17 +564,22 @@ impl AgentService {
 
                 let mut stream = self.llm_provider.chat_stream(messages, None);
                 while let Some(event) = stream.next().await {
-                    let event = event?;
-                    if let Some(error) = event.error {
-                        Err(ButterflyBotError::Runtime(error))?;
-                    }
-                    if let Some(delta) = event.delta {
-                        if !delta.is_empty() {
+                    match event? {
+                        ChatEvent::Error { message } => {
+                            Err(ButterflyBotError::Runtime(message))?;
+                        }
+                        ChatEvent::Content { delta } if !delta.is_empty() => {
                             response_text.push_str(&delta);
-                            yield delta;
+                            yield ChatEvent::Content { delta };
                         }
+                        _ => {}
                     }
                 }
+                self.record_usage(
+                    user_id,
+                    estimate_usage(&system_prompt, &full_prompt, &response_text),
+                )
+                .await;
             }
 
             if !response_text.is_empty() {
@@ -304,31 +606,19 @@ impl AgentService {
         prompt_override: Option<&str>,
         detail: &str,
     ) -> Result<String> {
-        let system_prompt = self.get_agent_system_prompt().await?;
-        let mut full_prompt = String::new();
-        if !memory_context.is_empty() {
-            full_prompt.push_str(
-                "PAST CONVERSATION HISTORY (for reference only; do not respond to past messages; assistant statements are not facts about the user):\n",
-            );
-            full_prompt.push_str(memory_context);
-            full_prompt.push_str("\n\n");
-        }
-        if let Some(prompt) = prompt_override {
-            full_prompt.push_str("ADDITIONAL PROMPT:\n");
-            full_prompt.push_str(prompt);
-            full_prompt.push_str("\n\n");
-        }
-        full_prompt.push_str(
-            "INSTRUCTION: If a DUE REMINDERS section is present in the context, surface those reminders first. Then respond only to the CURRENT USER MESSAGE below. If earlier history mentions self-harm but the current message does not, do not output crisis resources.\n\n",
-        );
-        full_prompt.push_str("CURRENT USER MESSAGE:\n");
-        full_prompt.push_str(query);
-        full_prompt.push_str(&format!("\n\nUSER IDENTIFIER: {}", user_id));
+        let system_prompt = self.system_prompt_for(user_id).await?;
+        let full_prompt = turn_prompt(user_id, query, memory_context, prompt_override);
 
+        self.check_budget(user_id).await?;
         let output = self
             .llm_provider
             .generate_text_with_images(&full_prompt, images, &system_prompt, detail, None)
             .await?;
+        self.record_usage(
+            user_id,
+            estimate_usage(&system_prompt, &full_prompt, &output),
+        )
+        .await;
         Ok(output)
     }
 
@@ -340,30 +630,20 @@ impl AgentService {
         prompt_override: Option<&str>,
         json_schema: serde_json::Value,
     ) -> Result<serde_json::Value> {
-        let system_prompt = self.get_agent_system_prompt().await?;
-        let mut full_prompt = String::new();
-        if !memory_context.is_empty() {
-            full_prompt.push_str(
-                "PAST CONVERSATION HISTORY (for reference only; do not respond to past messages; assistant statements are not facts about the user):\n",
-            );
-            full_prompt.push_str(memory_context);
-            full_prompt.push_str("\n\n");
-        }
-        if let Some(prompt) = prompt_override {
-            full_prompt.push_str("ADDITIONAL PROMPT:\n");
-            full_prompt.push_str(prompt);
-            full_prompt.push_str("\n\n");
-        }
-        full_prompt.push_str(
-            "INSTRUCTION: If a DUE REMINDERS section is present in the context, surface those reminders first. Then respond only to the CURRENT USER MESSAGE below. If earlier history mentions self-harm but the current message does not, do not output crisis resources.\n\n",
-        );
-        full_prompt.push_str("CURRENT USER MESSAGE:\n");
-        full_prompt.push_str(query);
-        full_prompt.push_str(&format!("\n\nUSER IDENTIFIER: {}", user_id));
+        let system_prompt = self.system_prompt_for(user_id).await?;
+        let full_prompt = turn_prompt(user_id, query, memory_context, prompt_override);
 
-        self.llm_provider
+        self.check_budget(user_id).await?;
+        let output = self
+            .llm_provider
             .parse_structured_output(&full_prompt, &system_prompt, json_schema, None)
-            .await
+            .await?;
+        self.record_usage(
+            user_id,
+            estimate_usage(&system_prompt, &full_prompt, &output.to_string()),
+        )
+        .await;
+        Ok(output)
     }
 
     pub async fn transcribe_audio(
@@ -376,6 +656,14 @@ impl AgentService {
             .await
     }
 
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.llm_provider.list_models().await
+    }
+
+    pub async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        self.llm_provider.embed(inputs, model).await
+    }
+
     pub async fn synthesize_audio(
         &self,
         text: &str,
@@ -391,118 +679,311 @@ impl AgentService {
         initial_prompt: &str,
         tools: Vec<Arc<dyn crate::interfaces::plugins::Tool>>,
         user_id: &str,
+        max_iterations: usize,
     ) -> Result<String> {
+        self.run_tool_loop_with_events(
+            system_prompt,
+            initial_prompt,
+            tools,
+            user_id,
+            max_iterations,
+            None,
+        )
+        .await
+    }
+
+    /// With `events`, every non-empty model turn is sent as `content` and each
+    /// tool round is bracketed by `tool_start`/`tool_end` markers, in order.
+    /// After `max_iterations` tool rounds the model must answer without tools.
+    async fn run_tool_loop_with_events(
+        &self,
+        system_prompt: &str,
+        initial_prompt: &str,
+        tools: Vec<Arc<dyn crate::interfaces::plugins::Tool>>,
+        user_id: &str,
+        max_iterations: usize,
+        events: Option<mpsc::UnboundedSender<ChatEvent>>,
+    ) -> Result<String> {
+        let emit = |event: ChatEvent| {
+            if let Some(events) = &events {
+                let _ = events.send(event);
+            }
+        };
         let mut prompt = initial_prompt.to_string();
         let mut last_text = String::new();
-        let mut tool_specs = Vec::new();
-
-        for tool in &tools {
-            tool_specs.push(serde_json::json!({
-                "type": "function",
-                "name": tool.name(),
-                "description": tool.description(),
-                "parameters": tool.parameters(),
-            }));
-        }
+        let tool_specs = tool_specs(&tools);
 
-        for _ in 0..5 {
+        for _ in 0..max_iterations {
             let response = self
                 .llm_provider
                 .generate_with_tools(&prompt, system_prompt, tool_specs.clone())
                 .await?;
+            let usage = response
+                .usage
+                .unwrap_or_else(|| estimate_usage(system_prompt, &prompt, &response.text));
+            self.record_usage(user_id, usage).await;
             if !response.text.is_empty() {
                 last_text = response.text.clone();
+                emit(ChatEvent::Content {
+                    delta: response.text.clone(),
+                });
             }
             if response.tool_calls.is_empty() {
                 return Ok(last_text);
             }
 
-            let results = self
-                .execute_tool_calls(&response.tool_calls, &tools, user_id)
-                .await?;
-            let serialized = serde_json::to_string_pretty(&results)
-                .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+            let mut calls = response.tool_calls.clone();
+            assign_tool_call_ids(&mut calls);
+            for call in &calls {
+                emit(ChatEvent::ToolStart {
+                    id: call.id.clone(),
+                    name: call.name.clone(),
+                });
+            }
+            let results = self.execute_tool_calls(&calls, &tools, user_id).await?;
+            for call in &calls {
+                emit(ChatEvent::ToolEnd {
+                    id: call.id.clone(),
+                    name: call.name.clone(),
+                });
+            }
+            let serialized = self.tool_registry.result_format().await.render(&results)?;
             prompt.push_str("\n\nTOOL_RESULTS:\n");
             prompt.push_str(&serialized);
         }
 
-        Ok(last_text)
+        prompt.push_str(
+            "\n\nTOOL LIMIT REACHED: no more tool calls are available this turn. Answer the user now using the results above.",
+        );
+        let output = self
+            .llm_provider
+            .generate_text(&prompt, system_prompt, None)
+            .await?;
+        self.record_usage(user_id, estimate_usage(system_prompt, &prompt, &output))
+            .await;
+        if output.is_empty() {
+            return Ok(last_text);
+        }
+        emit(ChatEvent::Content {
+            delta: output.clone(),
+        });
+        Ok(output)
     }
 
+    async fn guardrail_rejection(
+        &self,
+        tool_name: &str,
+        params: &serde_json::Value,
+    ) -> Option<String> {
+        let guardrails = self.tool_guardrails.read().await.clone();
+        for guardrail in guardrails {
+            if let ToolCallVerdict::Reject(reason) = guardrail.check(tool_name, params).await {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    /// Runs the calls concurrently; results come back in call order, not
+    /// completion order, so the next model turn sees a reproducible prompt.
     async fn execute_tool_calls(
         &self,
         calls: &[ToolCall],
         tools: &[Arc<dyn crate::interfaces::plugins::Tool>],
         user_id: &str,
     ) -> Result<Vec<serde_json::Value>> {
-        let mut results = Vec::new();
-        for call in calls {
-            let tool = tools.iter().find(|t| t.name() == call.name);
-            match tool {
-                Some(tool) => {
-                    let mut args = call.arguments.clone();
-                    if let serde_json::Value::Object(ref mut map) = args {
-                        if !map.contains_key("user_id") {
-                            map.insert(
-                                "user_id".to_string(),
-                                serde_json::Value::String(user_id.to_string()),
-                            );
-                        }
+        futures::future::join_all(
+            calls
+                .iter()
+                .map(|call| self.execute_tool_call(call, tools, user_id)),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    async fn execute_tool_call(
+        &self,
+        call: &ToolCall,
+        tools: &[Arc<dyn crate::interfaces::plugins::Tool>],
+        user_id: &str,
+    ) -> Result<serde_json::Value> {
+        let tool = tools.iter().find(|t| t.name() == call.name);
+        let span = tracing::info_span!(
+            "tool.execute",
+            tool = %call.name,
+            call_id = %call.id,
+            user_id = %user_id,
+            status = Empty,
+            duration_ms = Empty,
+        );
+        let logged_args = self
+            .tool_registry
+            .redact_args(&call.name, &call.arguments)
+            .await;
+        match tool {
+            Some(tool) => {
+                let mut args = call.arguments.clone();
+                if let serde_json::Value::Object(ref mut map) = args {
+                    if !map.contains_key("user_id") {
+                        map.insert(
+                            "user_id".to_string(),
+                            serde_json::Value::String(user_id.to_string()),
+                        );
                     }
-                    match tool.execute(args).await {
-                        Ok(result) => {
-                            let _ = self
-                                .tool_registry
-                                .audit_tool_call(&call.name, "success")
-                                .await;
-                            let result_clone = result.clone();
-                            self.emit_tool_event(
-                                user_id,
-                                &call.name,
-                                "success",
-                                serde_json::json!({ "args": call.arguments.clone(), "result": result_clone }),
-                            );
-                            results.push(serde_json::json!({
-                                "tool": call.name,
-                                "status": "success",
-                                "result": result,
-                            }));
-                        }
-                        Err(err) => {
-                            let _ = self
-                                .tool_registry
-                                .audit_tool_call(&call.name, "error")
-                                .await;
-                            self.emit_tool_event(
-                                user_id,
-                                &call.name,
-                                "error",
-                                serde_json::json!({ "args": call.arguments.clone(), "error": err.to_string() }),
-                            );
-                            return Err(err);
-                        }
+                }
+                let declares_user_id = tool
+                    .parameters()
+                    .get("properties")
+                    .and_then(|props| props.get("user_id"))
+                    .is_some();
+                let validation_target = if declares_user_id {
+                    &args
+                } else {
+                    &call.arguments
+                };
+                let errors = match validate_tool_params(tool.as_ref(), validation_target) {
+                    Ok(errors) => errors,
+                    Err(err) => {
+                        span.record("status", "error");
+                        tracing::warn!(tool = %call.name, error = %err, "tool schema does not compile");
+                        let _ = self
+                            .tool_registry
+                            .audit_rejected_call(user_id, &call.name, &call.arguments, "error")
+                            .await;
+                        self.emit_tool_event(
+                            user_id,
+                            &call.name,
+                            "error",
+                            serde_json::json!({ "id": call.id, "args": logged_args, "error": err.to_string() }),
+                        );
+                        return Err(err);
                     }
+                };
+                if !errors.is_empty() {
+                    span.record("status", "invalid_arguments");
+                    let _ = self
+                        .tool_registry
+                        .audit_rejected_call(
+                            user_id,
+                            &call.name,
+                            &call.arguments,
+                            "invalid_arguments",
+                        )
+                        .await;
+                    self.emit_tool_event(
+                        user_id,
+                        &call.name,
+                        "invalid_arguments",
+                        serde_json::json!({ "id": call.id, "args": logged_args, "errors": errors.clone() }),
+                    );
+                    return Ok(serde_json::json!({
+                        "id": call.id,
+                        "tool": call.name,
+                        "status": "invalid_arguments",
+                        "errors": errors,
+                    }));
                 }
-                None => {
+                if let Some(reason) = self.guardrail_rejection(&call.name, &call.arguments).await {
+                    span.record("status", "blocked");
                     let _ = self
                         .tool_registry
-                        .audit_tool_call(&call.name, "not_found")
+                        .audit_rejected_call(user_id, &call.name, &call.arguments, "blocked")
                         .await;
                     self.emit_tool_event(
                         user_id,
                         &call.name,
-                        "not_found",
-                        serde_json::json!({ "args": call.arguments.clone(), "message": "Tool not found" }),
+                        "blocked",
+                        serde_json::json!({ "id": call.id, "args": logged_args, "reason": reason.clone() }),
                     );
-                    results.push(serde_json::json!({
+                    return Ok(serde_json::json!({
+                        "id": call.id,
                         "tool": call.name,
-                        "status": "error",
-                        "message": "Tool not found",
+                        "status": "blocked",
+                        "reason": reason,
                     }));
                 }
+                let started = Instant::now();
+                let outcome = self
+                    .tool_registry
+                    .execute_tool(user_id, tool.as_ref(), args)
+                    .instrument(span.clone())
+                    .await;
+                span.record("duration_ms", started.elapsed().as_millis() as u64);
+                span.record("status", if outcome.is_ok() { "success" } else { "error" });
+                match outcome {
+                    Ok(result) => {
+                        let _ = self
+                            .tool_registry
+                            .audit_tool_call(user_id, &call.name, "success")
+                            .await;
+                        let result_clone = result.clone();
+                        self.emit_tool_event(
+                            user_id,
+                            &call.name,
+                            "success",
+                            serde_json::json!({ "id": call.id, "args": logged_args, "result": result_clone }),
+                        );
+                        Ok(serde_json::json!({
+                            "id": call.id,
+                            "tool": call.name,
+                            "status": "success",
+                            "result": result,
+                        }))
+                    }
+                    Err(err) => {
+                        let _ = self
+                            .tool_registry
+                            .audit_tool_call(user_id, &call.name, "error")
+                            .await;
+                        self.emit_tool_event(
+                            user_id,
+                            &call.name,
+                            "error",
+                            serde_json::json!({ "id": call.id, "args": logged_args, "error": err.to_string() }),
+                        );
+                        Err(err)
+                    }
+                }
+            }
+            // Unregistered, disabled and unassigned tools all end up here; the
+            // model gets the names it may use instead so it can recover.
+            None => {
+                span.record("status", "unknown");
+                let available: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+                tracing::warn!(
+                    tool = %call.name,
+                    call_id = %call.id,
+                    user_id = %user_id,
+                    "model called a tool that is not available"
+                );
+                let _ = self
+                    .tool_registry
+                    .audit_rejected_call(user_id, &call.name, &call.arguments, "unknown")
+                    .await;
+                let message = format!("Tool '{}' is not available", call.name);
+                self.emit_tool_event(
+                    user_id,
+                    &call.name,
+                    "unknown",
+                    serde_json::json!({ "id": call.id, "args": logged_args, "message": message }),
+                );
+                Ok(serde_json::json!({
+                    "id": call.id,
+                    "tool": call.name,
+                    "status": "unknown",
+                    "message": message,
+                    "available_tools": available,
+                }))
             }
         }
-        Ok(results)
+    }
+}
+
+fn estimate_usage(system_prompt: &str, prompt: &str, output: &str) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: estimate_tokens(system_prompt) + estimate_tokens(prompt),
+        completion_tokens: estimate_tokens(output),
     }
 }
 
@@ -512,3 +993,45 @@ fn now_ts() -> i64 {
         .unwrap_or_default()
         .as_secs() as i64
 }
+
+fn tool_specs(tools: &[Arc<dyn crate::interfaces::plugins::Tool>]) -> Vec<serde_json::Value> {
+    tools
+        .iter()
+        .map(|tool| {
+            serde_json::json!({
+                "type": "function",
+                "name": tool.name(),
+                "description": tool.description(),
+                "parameters": tool.parameters(),
+            })
+        })
+        .collect()
+}
+
+fn turn_prompt(
+    user_id: &str,
+    query: &str,
+    memory_context: &str,
+    prompt_override: Option<&str>,
+) -> String {
+    let mut full_prompt = String::new();
+    if !memory_context.is_empty() {
+        full_prompt.push_str(
+            "PAST CONVERSATION HISTORY (for reference only; do not respond to past messages; assistant statements are not facts about the user):\n",
+        );
+        full_prompt.push_str(memory_context);
+        full_prompt.push_str("\n\n");
+    }
+    if let Some(prompt) = prompt_override {
+        full_prompt.push_str("ADDITIONAL PROMPT:\n");
+        full_prompt.push_str(prompt);
+        full_prompt.push_str("\n\n");
+    }
+    full_prompt.push_str(
+        "INSTRUCTION: If a DUE REMINDERS section is present in the context, surface those reminders first. Then respond only to the CURRENT USER MESSAGE below. If earlier history mentions self-harm but the current message does not, do not output crisis resources.\n\n",
+    );
+    full_prompt.push_str("CURRENT USER MESSAGE:\n");
+    full_prompt.push_str(query);
+    full_prompt.push_str(&format!("\n\nUSER IDENTIFIER: {}", user_id));
+    full_prompt
+}
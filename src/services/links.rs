@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::error::{ButterflyBotError, Result};
+use crate::reminders::ReminderStore;
+use crate::todo::{TodoStatus, TodoStore};
+
+/// One side of a todo/reminder pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkedId {
+    Todo(i32),
+    Reminder(i32),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LinkedCompletion {
+    pub todo_id: Option<i32>,
+    pub reminder_id: Option<i32>,
+}
+
+/// Keeps todos and reminders that refer to each other in step. The two
+/// stores may live in different databases, so links are checked here rather
+/// than by foreign keys.
+pub struct LinkService {
+    reminders: Arc<ReminderStore>,
+    todos: Arc<TodoStore>,
+}
+
+impl LinkService {
+    pub fn new(reminders: Arc<ReminderStore>, todos: Arc<TodoStore>) -> Self {
+        Self { reminders, todos }
+    }
+
+    /// Links a todo and a reminder owned by `user_id`, replacing any earlier
+    /// link on either side.
+    pub async fn link(&self, user_id: &str, todo_id: i32, reminder_id: i32) -> Result<()> {
+        let todo = self
+            .todos
+            .get_item(todo_id)
+            .await?
+            .filter(|todo| todo.user_id == user_id)
+            .ok_or_else(|| ButterflyBotError::Runtime(format!("Todo {} not found", todo_id)))?;
+        let reminder = self
+            .reminders
+            .get_reminder(user_id, reminder_id)
+            .await?
+            .ok_or_else(|| {
+                ButterflyBotError::Runtime(format!("Reminder {} not found", reminder_id))
+            })?;
+
+        if let Some(previous) = todo.linked_reminder_id.filter(|id| *id != reminder_id) {
+            self.reminders
+                .set_linked_todo(user_id, previous, None)
+                .await?;
+        }
+        if let Some(previous) = reminder.linked_todo_id.filter(|id| *id != todo_id) {
+            self.todos.set_linked_reminder(previous, None).await?;
+        }
+        self.todos
+            .set_linked_reminder(todo_id, Some(reminder_id))
+            .await?;
+        self.reminders
+            .set_linked_todo(user_id, reminder_id, Some(todo_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Completes `id` and, when it is linked, the other side too. A link whose
+    /// other side no longer exists is cleared instead of failing.
+    pub async fn complete_linked(&self, user_id: &str, id: LinkedId) -> Result<LinkedCompletion> {
+        let (todo_id, reminder_id) = match id {
+            LinkedId::Todo(todo_id) => {
+                let todo = self
+                    .todos
+                    .get_item(todo_id)
+                    .await?
+                    .filter(|todo| todo.user_id == user_id)
+                    .ok_or_else(|| {
+                        ButterflyBotError::Runtime(format!("Todo {} not found", todo_id))
+                    })?;
+                (Some(todo_id), todo.linked_reminder_id)
+            }
+            LinkedId::Reminder(reminder_id) => {
+                let reminder = self
+                    .reminders
+                    .get_reminder(user_id, reminder_id)
+                    .await?
+                    .ok_or_else(|| {
+                        ButterflyBotError::Runtime(format!("Reminder {} not found", reminder_id))
+                    })?;
+                (reminder.linked_todo_id, Some(reminder_id))
+            }
+        };
+
+        let mut completion = LinkedCompletion::default();
+        if let Some(todo_id) = todo_id {
+            match self.todos.get_item(todo_id).await? {
+                Some(todo) if todo.user_id == user_id => {
+                    if todo.completed_at.is_none() {
+                        self.todos.set_completed(todo_id, true).await?;
+                    }
+                    completion.todo_id = Some(todo_id);
+                }
+                _ => {
+                    if let Some(reminder_id) = reminder_id {
+                        self.reminders
+                            .set_linked_todo(user_id, reminder_id, None)
+                            .await?;
+                    }
+                }
+            }
+        }
+        if let Some(reminder_id) = reminder_id {
+            if self
+                .reminders
+                .complete_reminder(user_id, reminder_id)
+                .await?
+            {
+                completion.reminder_id = Some(reminder_id);
+            } else if let Some(todo_id) = completion.todo_id {
+                self.todos.set_linked_reminder(todo_id, None).await?;
+            }
+        }
+        Ok(completion)
+    }
+
+    /// Completes every open todo of `user_id` along with the reminders linked
+    /// to them. Returns how many todos were completed.
+    pub async fn complete_all_todos(&self, user_id: &str) -> Result<usize> {
+        let linked: Vec<i32> = self
+            .todos
+            .list_items(user_id, TodoStatus::Open, i64::MAX as usize)
+            .await?
+            .into_iter()
+            .filter_map(|todo| todo.linked_reminder_id)
+            .collect();
+        let count = self.todos.complete_all(user_id).await?;
+        for reminder_id in linked {
+            self.reminders
+                .complete_reminder(user_id, reminder_id)
+                .await?;
+        }
+        Ok(count)
+    }
+
+    /// Completes reminders that were just delivered, e.g. acked by a worker,
+    /// along with their linked todos. Ids that no longer exist are skipped.
+    pub async fn complete_reminders(&self, ids: &[i32]) -> Result<Vec<LinkedCompletion>> {
+        let mut completions = Vec::new();
+        for (id, user_id) in self.reminders.reminder_owners(ids).await? {
+            completions.push(
+                self.complete_linked(&user_id, LinkedId::Reminder(id))
+                    .await?,
+            );
+        }
+        Ok(completions)
+    }
+}
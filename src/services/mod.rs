@@ -1,2 +1,8 @@
 pub mod agent;
+pub mod links;
+pub mod overview;
+pub mod planner;
 pub mod query;
+pub mod request_context;
+pub mod snippets;
+pub mod ui_events;
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::planning::PlanStore;
+use crate::reminders::ReminderStore;
+use crate::tasks::TaskStore;
+use crate::todo::TodoStore;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UserOverview {
+    pub open_reminders: u64,
+    pub open_todos: u64,
+    pub active_plans: u64,
+    pub enabled_tasks: u64,
+}
+
+pub struct OverviewService {
+    reminders: Arc<ReminderStore>,
+    todos: Arc<TodoStore>,
+    plans: Arc<PlanStore>,
+    tasks: Arc<TaskStore>,
+}
+
+impl OverviewService {
+    pub fn new(
+        reminders: Arc<ReminderStore>,
+        todos: Arc<TodoStore>,
+        plans: Arc<PlanStore>,
+        tasks: Arc<TaskStore>,
+    ) -> Self {
+        Self {
+            reminders,
+            todos,
+            plans,
+            tasks,
+        }
+    }
+
+    pub async fn user_overview(&self, user_id: &str) -> Result<UserOverview> {
+        let (open_reminders, open_todos, active_plans, enabled_tasks) = tokio::try_join!(
+            self.reminders.count_open(user_id),
+            self.todos.count_open(user_id),
+            self.plans.count_active(user_id),
+            self.tasks.count_enabled(user_id),
+        )?;
+        Ok(UserOverview {
+            open_reminders,
+            open_todos,
+            active_plans,
+            enabled_tasks,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::error::{ButterflyBotError, Result};
+use crate::planning::{validate_dependencies, PlanItem, PlanStep, PlanStore};
+use crate::services::agent::AgentService;
+
+const PLAN_PROMPT: &str = "Turn the user's goal into a short, concrete project plan. Return a title and an ordered list of steps. Use depends_on (zero-based step indexes) only when a step truly cannot start until earlier steps finish.";
+const MAX_STEPS: usize = 20;
+
+#[derive(Deserialize)]
+struct DraftPlan {
+    title: String,
+    steps: Vec<DraftStep>,
+}
+
+#[derive(Deserialize)]
+struct DraftStep {
+    title: String,
+    #[serde(default)]
+    depends_on: Vec<usize>,
+}
+
+pub fn plan_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": { "type": "string" },
+            "steps": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "depends_on": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["title", "depends_on"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["title", "steps"],
+        "additionalProperties": false
+    })
+}
+
+pub struct PlanGenerator {
+    agent: Arc<AgentService>,
+    store: Arc<PlanStore>,
+}
+
+impl PlanGenerator {
+    pub fn new(agent: Arc<AgentService>, store: Arc<PlanStore>) -> Self {
+        Self { agent, store }
+    }
+
+    /// Asks the model for a structured plan, validates it and stores it as a draft.
+    pub async fn generate_plan(&self, user_id: &str, goal: &str) -> Result<PlanItem> {
+        let goal = goal.trim();
+        if goal.is_empty() {
+            return Err(ButterflyBotError::Runtime("Goal is required".to_string()));
+        }
+
+        let output = self
+            .agent
+            .generate_structured_response(user_id, goal, "", Some(PLAN_PROMPT), plan_schema())
+            .await?;
+        let draft: DraftPlan = serde_json::from_value(output).map_err(|e| {
+            ButterflyBotError::Runtime(format!("Model returned an invalid plan: {e}"))
+        })?;
+
+        let steps: Vec<PlanStep> = draft
+            .steps
+            .into_iter()
+            .enumerate()
+            .map(|(index, step)| PlanStep {
+                index,
+                title: step.title.trim().to_string(),
+                status: "pending".to_string(),
+                depends_on: step.depends_on,
+            })
+            .collect();
+        if steps.is_empty() || steps.len() > MAX_STEPS {
+            return Err(ButterflyBotError::Runtime(format!(
+                "Generated plan must have between 1 and {MAX_STEPS} steps"
+            )));
+        }
+        if steps.iter().any(|step| step.title.is_empty()) {
+            return Err(ButterflyBotError::Runtime(
+                "Generated plan has an empty step".to_string(),
+            ));
+        }
+        validate_dependencies(&steps)?;
+
+        let title = match draft.title.trim() {
+            "" => goal,
+            title => title,
+        };
+        let steps_json = json!(steps
+            .iter()
+            .map(|step| json!({
+                "title": step.title,
+                "status": step.status,
+                "depends_on": step.depends_on,
+            }))
+            .collect::<Vec<_>>());
+        self.store
+            .create_plan(user_id, title, goal, Some(&steps_json), Some("draft"))
+            .await
+    }
+}
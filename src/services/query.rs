@@ -1,13 +1,23 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_stream::try_stream;
 use futures::stream::BoxStream;
 use futures::StreamExt;
 
-use crate::error::Result;
-use crate::interfaces::providers::{ImageInput, MemoryProvider};
+use crate::active_agents::ActiveAgentStore;
+use crate::config::HistoryWindow;
+use crate::costs::estimate_tokens;
+use crate::error::{ButterflyBotError, Result};
+use crate::guardrails::secrets::SecretRedactor;
+use crate::interfaces::providers::{
+    ChatEvent, ChatSummary, GenerationParams, ImageInput, MemoryProvider, MessageBranch,
+    PinnedContext, ReindexReport, SearchModels, StoredMessage, ToolCall,
+};
+use crate::providers::namespaced::NamespacedMemoryProvider;
 use crate::reminders::ReminderStore;
 use crate::services::agent::AgentService;
+use crate::tools::search_internet::SearchResult;
 
 #[derive(Debug, Clone)]
 pub enum UserInput {
@@ -31,6 +41,41 @@ pub struct ProcessOptions {
     pub output_format: OutputFormat,
     pub image_detail: String,
     pub json_schema: Option<serde_json::Value>,
+    /// Overrides the configured tool round-trip cap for this request.
+    pub max_tool_iterations: Option<usize>,
+    /// When false, the turn is answered but not written to memory/history.
+    pub persist: bool,
+    /// Overrides the configured memory `min_score` for this request.
+    pub min_score: Option<f32>,
+    /// Overrides the configured history window for this request.
+    pub history_window: Option<HistoryWindow>,
+    /// Answers as this configured agent instead of the user's active one.
+    pub agent: Option<String>,
+    /// Returns the tool calls the model asks for as
+    /// [`ProcessResult::ToolCalls`] without executing them. Nothing is
+    /// written to memory.
+    pub dry_run: bool,
+    /// Sampling settings for this request; unset fields keep the agent's.
+    pub generation: GenerationParams,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            prompt: None,
+            images: Vec::new(),
+            output_format: OutputFormat::Text,
+            image_detail: "auto".to_string(),
+            json_schema: None,
+            max_tool_iterations: None,
+            persist: true,
+            min_score: None,
+            history_window: None,
+            agent: None,
+            dry_run: false,
+            generation: GenerationParams::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,12 +83,20 @@ pub enum ProcessResult {
     Text(String),
     Audio(Vec<u8>),
     Structured(serde_json::Value),
+    ToolCalls(Vec<ToolCall>),
 }
 
 pub struct QueryService {
     agent_service: Arc<AgentService>,
     memory_provider: Option<Arc<dyn MemoryProvider>>,
+    shared_memory: Option<Arc<dyn MemoryProvider>>,
     reminder_store: Option<Arc<ReminderStore>>,
+    history_window: HistoryWindow,
+    memory_search_limit: usize,
+    memory_context_top_k: usize,
+    output_redactor: Option<Arc<SecretRedactor>>,
+    personas: HashMap<String, Arc<AgentService>>,
+    active_agents: Option<Arc<ActiveAgentStore>>,
 }
 
 impl QueryService {
@@ -52,10 +105,152 @@ impl QueryService {
         memory_provider: Option<Arc<dyn MemoryProvider>>,
         reminder_store: Option<Arc<ReminderStore>>,
     ) -> Self {
+        let shared_memory = memory_provider;
+        let memory_provider =
+            scoped_memory(shared_memory.clone(), agent_service.memory_namespace());
         Self {
             agent_service,
             memory_provider,
+            shared_memory,
             reminder_store,
+            history_window: HistoryWindow::default(),
+            memory_search_limit: DEFAULT_MEMORY_SEARCH_LIMIT,
+            memory_context_top_k: DEFAULT_MEMORY_SEARCH_LIMIT,
+            output_redactor: None,
+            personas: HashMap::new(),
+            active_agents: None,
+        }
+    }
+
+    pub fn with_history_window(mut self, history_window: HistoryWindow) -> Self {
+        self.history_window = history_window;
+        self
+    }
+
+    /// Retrieves (and reranks) `search_limit` memories per turn but puts only
+    /// the best `context_top_k` of them in the prompt.
+    pub fn with_memory_limits(mut self, search_limit: usize, context_top_k: usize) -> Self {
+        self.memory_search_limit = search_limit;
+        self.memory_context_top_k = context_top_k;
+        self
+    }
+
+    /// Masks secrets in replies, including streamed ones, before they are
+    /// returned or stored.
+    pub fn with_output_redactor(mut self, redactor: Arc<SecretRedactor>) -> Self {
+        self.output_redactor = Some(redactor);
+        self
+    }
+
+    /// Other configured agents a user can switch to, with the store that
+    /// remembers each user's choice.
+    pub fn with_personas(
+        mut self,
+        personas: Vec<Arc<AgentService>>,
+        active_agents: Arc<ActiveAgentStore>,
+    ) -> Self {
+        self.personas = personas
+            .into_iter()
+            .map(|persona| (persona.agent_name().to_string(), persona))
+            .collect();
+        self.active_agents = Some(active_agents);
+        self
+    }
+
+    /// The default agent first, then the others in name order.
+    pub fn agent_names(&self) -> Vec<String> {
+        let mut personas: Vec<String> = self.personas.keys().cloned().collect();
+        personas.sort();
+        let mut names = vec![self.agent_service.agent_name().to_string()];
+        names.extend(personas);
+        names
+    }
+
+    /// Makes `agent` answer `user_id`'s later turns; `None` goes back to the
+    /// default agent.
+    pub async fn set_active_agent(&self, user_id: &str, agent: Option<&str>) -> Result<()> {
+        if let Some(name) = agent {
+            if !self.agent_names().iter().any(|known| known == name) {
+                return Err(ButterflyBotError::Config(format!(
+                    "Agent '{}' is not configured",
+                    name
+                )));
+            }
+        }
+        let Some(store) = &self.active_agents else {
+            return Ok(());
+        };
+        match agent {
+            Some(name) if name != self.agent_service.agent_name() => store.set(user_id, name).await,
+            _ => store.clear(user_id).await,
+        }
+    }
+
+    pub async fn active_agent(&self, user_id: &str) -> Result<String> {
+        Ok(self
+            .agent_for(user_id, None)
+            .await?
+            .agent_name()
+            .to_string())
+    }
+
+    /// The requested agent, else the user's active one, else the default.
+    async fn agent_for(&self, user_id: &str, requested: Option<&str>) -> Result<Arc<AgentService>> {
+        let name = match (requested, &self.active_agents) {
+            (Some(name), _) => Some(name.to_string()),
+            (None, Some(store)) => store.get(user_id).await?,
+            (None, None) => None,
+        };
+        let Some(name) = name else {
+            return Ok(self.agent_service.clone());
+        };
+        if name == self.agent_service.agent_name() {
+            return Ok(self.agent_service.clone());
+        }
+        match self.personas.get(&name) {
+            Some(persona) => Ok(persona.clone()),
+            None if requested.is_some() => Err(ButterflyBotError::Config(format!(
+                "Agent '{}' is not configured",
+                name
+            ))),
+            // A stored choice whose agent was removed from the config.
+            None => Ok(self.agent_service.clone()),
+        }
+    }
+
+    /// The agent answering one request, with the request's sampling settings
+    /// applied when its provider supports them.
+    async fn agent_for_request(
+        &self,
+        user_id: &str,
+        options: &ProcessOptions,
+    ) -> Result<Arc<AgentService>> {
+        let agent = self.agent_for(user_id, options.agent.as_deref()).await?;
+        if options.generation.is_empty() {
+            return Ok(agent);
+        }
+        let overridden = agent.with_generation_overrides(options.generation).await;
+        Ok(overridden.map(Arc::new).unwrap_or(agent))
+    }
+
+    /// `agent`'s view of memory: the shared store under its own namespace.
+    fn memory_for(&self, agent: &AgentService) -> Option<Arc<dyn MemoryProvider>> {
+        if agent.agent_name() == self.agent_service.agent_name() {
+            return self.memory_provider.clone();
+        }
+        scoped_memory(self.shared_memory.clone(), agent.memory_namespace())
+    }
+
+    /// The memory of the agent currently answering `user_id`.
+    async fn user_memory(&self, user_id: &str) -> Result<Option<Arc<dyn MemoryProvider>>> {
+        let agent = self.agent_for(user_id, None).await?;
+        Ok(self.memory_for(&agent))
+    }
+
+    fn redact(&self, text: String) -> String {
+        match &self.output_redactor {
+            Some(redactor) => redactor.scrub(&text),
+            None => text,
         }
     }
 
@@ -65,13 +260,16 @@ impl QueryService {
         query: &str,
         prompt: Option<&str>,
     ) -> Result<String> {
+        ensure_message(query, &[])?;
         let processed_query = query.to_string();
+        let agent = self.agent_for(user_id, None).await?;
+        let memory = self.memory_for(&agent);
 
         if let Some(response) = self
             .try_handle_search_command(user_id, &processed_query)
             .await?
         {
-            if let Some(provider) = &self.memory_provider {
+            if let Some(provider) = &memory {
                 provider
                     .append_message(user_id, "user", &processed_query)
                     .await?;
@@ -87,29 +285,33 @@ impl QueryService {
         } else {
             None
         };
-        let memory_context = if let Some(provider) = &self.memory_provider {
+        let memory_context = if let Some(provider) = &memory {
             let include_semantic = should_include_semantic_memory(&processed_query);
-            let history_future = provider.get_history(user_id, 12);
+            let history_future =
+                load_history(provider.as_ref(), user_id, self.history_window, None);
             let semantic_future = async {
                 if include_semantic {
-                    provider.search(user_id, &processed_query, 5).await
+                    provider
+                        .search(user_id, &processed_query, self.memory_search_limit)
+                        .await
                 } else {
                     Ok(Vec::new())
                 }
             };
-            let (history, semantic) = tokio::try_join!(history_future, semantic_future)?;
+            let (history, mut semantic) = tokio::try_join!(history_future, semantic_future)?;
+            semantic.truncate(self.memory_context_top_k);
             let history = history.join("\n");
             build_memory_context(history, semantic, reminder_context)
         } else {
             reminder_context.unwrap_or_default()
         };
 
-        let response = self
-            .agent_service
+        let response = agent
             .generate_response(user_id, &processed_query, &memory_context, prompt)
             .await?;
+        let response = self.redact(response);
 
-        if let Some(provider) = &self.memory_provider {
+        if let Some(provider) = &memory {
             provider
                 .append_message(user_id, "user", &processed_query)
                 .await?;
@@ -138,9 +340,16 @@ impl QueryService {
                     .await?
             }
         };
+        ensure_message(&text, &options.images)?;
+        let agent = self.agent_for_request(user_id, &options).await?;
+        let memory = self.memory_for(&agent);
+
+        let memory_sink = memory
+            .as_ref()
+            .filter(|_| options.persist && !options.dry_run);
 
         if let Some(response) = self.try_handle_search_command(user_id, &text).await? {
-            if let Some(provider) = &self.memory_provider {
+            if let Some(provider) = memory_sink {
                 provider.append_message(user_id, "user", &text).await?;
                 provider
                     .append_message(user_id, "assistant", &response)
@@ -154,26 +363,43 @@ impl QueryService {
         } else {
             None
         };
-        let memory_context = if let Some(provider) = &self.memory_provider {
+        let memory_context = if let Some(provider) = &memory {
             let include_semantic = should_include_semantic_memory(&text);
-            let history_future = provider.get_history(user_id, 12);
+            let history_future = load_history(
+                provider.as_ref(),
+                user_id,
+                options.history_window.unwrap_or(self.history_window),
+                None,
+            );
             let semantic_future = async {
                 if include_semantic {
-                    provider.search(user_id, &text, 5).await
+                    provider
+                        .search_with_min_score(
+                            user_id,
+                            &text,
+                            self.memory_search_limit,
+                            options.min_score,
+                        )
+                        .await
                 } else {
                     Ok(Vec::new())
                 }
             };
-            let (history, semantic) = tokio::try_join!(history_future, semantic_future)?;
+            let (history, mut semantic) = tokio::try_join!(history_future, semantic_future)?;
+            semantic.truncate(self.memory_context_top_k);
             let history = history.join("\n");
             build_memory_context(history, semantic, reminder_context)
         } else {
             reminder_context.unwrap_or_default()
         };
 
-        let result = if let Some(schema) = options.json_schema {
-            let structured = self
-                .agent_service
+        let result = if options.dry_run {
+            let calls = agent
+                .plan_tool_calls(user_id, &text, &memory_context, options.prompt.as_deref())
+                .await?;
+            ProcessResult::ToolCalls(calls)
+        } else if let Some(schema) = options.json_schema {
+            let structured = agent
                 .generate_structured_response(
                     user_id,
                     &text,
@@ -184,8 +410,7 @@ impl QueryService {
                 .await?;
             ProcessResult::Structured(structured)
         } else if !options.images.is_empty() {
-            let response = self
-                .agent_service
+            let response = agent
                 .generate_response_with_images(
                     user_id,
                     &text,
@@ -195,13 +420,18 @@ impl QueryService {
                     &options.image_detail,
                 )
                 .await?;
-            ProcessResult::Text(response)
+            ProcessResult::Text(self.redact(response))
         } else {
-            let response = self
-                .agent_service
-                .generate_response(user_id, &text, &memory_context, options.prompt.as_deref())
+            let response = agent
+                .generate_response_with_tool_limit(
+                    user_id,
+                    &text,
+                    &memory_context,
+                    options.prompt.as_deref(),
+                    options.max_tool_iterations,
+                )
                 .await?;
-            ProcessResult::Text(response)
+            ProcessResult::Text(self.redact(response))
         };
 
         let output = match (result, options.output_format) {
@@ -215,7 +445,7 @@ impl QueryService {
             (other, _) => other,
         };
 
-        if let Some(provider) = &self.memory_provider {
+        if let Some(provider) = memory_sink {
             provider.append_message(user_id, "user", &text).await?;
             if let ProcessResult::Text(ref message) = output {
                 provider
@@ -227,66 +457,140 @@ impl QueryService {
         Ok(output)
     }
 
+    /// The content deltas of [`Self::process_stream`] with default options,
+    /// so the turn is remembered. Use `process_stream` to change that.
     pub fn process_text_stream<'a>(
         &'a self,
         user_id: &'a str,
         query: &'a str,
         prompt: Option<&'a str>,
     ) -> BoxStream<'a, Result<String>> {
-        Box::pin(try_stream! {
-            let processed_query = query.to_string();
-
-            if let Some(response) = self.try_handle_search_command(user_id, &processed_query).await? {
-                if let Some(provider) = &self.memory_provider {
-                    provider.append_message(user_id, "user", &processed_query).await?;
-                    provider.append_message(user_id, "assistant", &response).await?;
+        let options = ProcessOptions {
+            prompt: prompt.map(str::to_string),
+            ..Default::default()
+        };
+        self.process_stream(user_id, UserInput::Text(query.to_string()), options)
+            .filter_map(|event| async move {
+                match event {
+                    Ok(ChatEvent::Content { delta }) => Some(Ok(delta)),
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
                 }
-                yield response;
-                return;
-            }
+            })
+            .boxed()
+    }
 
-            let reminder_context = if let Some(store) = &self.reminder_store {
-                build_reminder_context(store, user_id).await
-            } else {
-                None
-            };
-            let memory_context = if let Some(provider) = &self.memory_provider {
-                let include_semantic = should_include_semantic_memory(&processed_query);
-                let history_future = provider.get_history(user_id, 12);
-                let semantic_future = async {
-                    if include_semantic {
-                        provider.search(user_id, &processed_query, 5).await
-                    } else {
-                        Ok(Vec::new())
-                    }
-                };
-                let (history, semantic) = tokio::try_join!(history_future, semantic_future)?;
-                let history = history.join("\n");
-                build_memory_context(history, semantic, reminder_context)
-            } else {
-                reminder_context.unwrap_or_default()
+    /// Streams a turn as [`ChatEvent`]s: `content` deltas with any
+    /// `tool_start`/`tool_end` markers and heartbeats between them, then one
+    /// `message_end`. Images, JSON schemas, dry runs and audio output need a
+    /// whole response, so they are rejected here; use [`Self::process`].
+    pub fn process_stream<'a>(
+        &'a self,
+        user_id: &'a str,
+        input: UserInput,
+        options: ProcessOptions,
+    ) -> BoxStream<'a, Result<ChatEvent>> {
+        Box::pin(try_stream! {
+            ensure_streamable(&options)?;
+            let text = match input {
+                UserInput::Text(value) => value,
+                UserInput::Audio {
+                    bytes,
+                    input_format,
+                } => {
+                    self.agent_service
+                        .transcribe_audio(bytes, &input_format)
+                        .await?
+                }
             };
+            ensure_message(&text, &[])?;
+            let agent = self.agent_for_request(user_id, &options).await?;
+            let memory = self.memory_for(&agent);
+            let memory_sink = memory.as_ref().filter(|_| options.persist);
 
             let mut response_text = String::new();
-            let mut stream = self.agent_service.generate_response_stream(
-                user_id,
-                &processed_query,
-                &memory_context,
-                prompt,
-            );
+            if let Some(response) = self.try_handle_search_command(user_id, &text).await? {
+                response_text.push_str(&response);
+                yield ChatEvent::Content { delta: response };
+            } else {
+                let reminder_context = if let Some(store) = &self.reminder_store {
+                    build_reminder_context(store, user_id).await
+                } else {
+                    None
+                };
+                let memory_context = if let Some(provider) = &memory {
+                    let include_semantic = should_include_semantic_memory(&text);
+                    let history_future = load_history(
+                        provider.as_ref(),
+                        user_id,
+                        options.history_window.unwrap_or(self.history_window),
+                        None,
+                    );
+                    let semantic_future = async {
+                        if include_semantic {
+                            provider
+                                .search_with_min_score(
+                                    user_id,
+                                    &text,
+                                    self.memory_search_limit,
+                                    options.min_score,
+                                )
+                                .await
+                        } else {
+                            Ok(Vec::new())
+                        }
+                    };
+                    let (history, mut semantic) =
+                        tokio::try_join!(history_future, semantic_future)?;
+                    semantic.truncate(self.memory_context_top_k);
+                    let history = history.join("\n");
+                    build_memory_context(history, semantic, reminder_context)
+                } else {
+                    reminder_context.unwrap_or_default()
+                };
 
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk?;
-                response_text.push_str(&chunk);
-                yield chunk;
+                let mut events = agent.generate_response_events_with_tool_limit(
+                    user_id,
+                    &text,
+                    &memory_context,
+                    options.prompt.as_deref(),
+                    options.max_tool_iterations,
+                );
+                let mut scrubber = self.output_redactor.as_ref().map(|redactor| redactor.stream());
+                while let Some(event) = events.next().await {
+                    match event? {
+                        ChatEvent::Content { delta } => {
+                            let delta = match scrubber.as_mut() {
+                                Some(scrubber) => scrubber.push(&delta),
+                                None => delta,
+                            };
+                            if delta.is_empty() {
+                                continue;
+                            }
+                            response_text.push_str(&delta);
+                            yield ChatEvent::Content { delta };
+                        }
+                        other => yield other,
+                    }
+                }
+                if let Some(rest) = scrubber.as_mut().map(|scrubber| scrubber.finish()) {
+                    if !rest.is_empty() {
+                        response_text.push_str(&rest);
+                        yield ChatEvent::Content { delta: rest };
+                    }
+                }
             }
 
-            if let Some(provider) = &self.memory_provider {
-                provider.append_message(user_id, "user", &processed_query).await?;
+            if let Some(provider) = memory_sink {
+                provider.append_message(user_id, "user", &text).await?;
                 if !response_text.is_empty() {
                     provider.append_message(user_id, "assistant", &response_text).await?;
                 }
             }
+            yield ChatEvent::MessageEnd {
+                finish_reason: Some("stop".to_string()),
+                usage: None,
+            };
         })
     }
 
@@ -295,30 +599,283 @@ impl QueryService {
     }
 
     pub async fn delete_user_history(&self, user_id: &str) -> Result<()> {
-        if let Some(provider) = &self.memory_provider {
+        if let Some(provider) = self.user_memory(user_id).await? {
             provider.clear_history(user_id).await?;
         }
         Ok(())
     }
 
     pub async fn get_user_history(&self, user_id: &str, limit: usize) -> Result<Vec<String>> {
-        if let Some(provider) = &self.memory_provider {
+        if let Some(provider) = self.user_memory(user_id).await? {
             return provider.get_history(user_id, limit).await;
         }
         Ok(Vec::new())
     }
 
+    /// Stored turns with their ids, oldest first; see [`MemoryProvider::get_messages`].
+    pub async fn get_messages(
+        &self,
+        user_id: &str,
+        before_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>> {
+        if let Some(provider) = self.user_memory(user_id).await? {
+            return provider.get_messages(user_id, before_id, limit).await;
+        }
+        Ok(Vec::new())
+    }
+
     pub async fn search_memory(
         &self,
         user_id: &str,
         query: &str,
         limit: usize,
     ) -> Result<Vec<String>> {
-        if let Some(provider) = &self.memory_provider {
+        if let Some(provider) = self.user_memory(user_id).await? {
             return provider.search(user_id, query, limit).await;
         }
         Ok(Vec::new())
     }
+
+    /// [`Self::search_memory`] with the embedding/rerank models swapped for
+    /// this query only.
+    pub async fn search_memory_with_models(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: usize,
+        models: &SearchModels,
+    ) -> Result<Vec<String>> {
+        if let Some(provider) = self.user_memory(user_id).await? {
+            return provider
+                .search_with_models(user_id, query, limit, None, models)
+                .await;
+        }
+        Ok(Vec::new())
+    }
+
+    /// Answers the stored user message `message_id` again and keeps the new
+    /// reply as a sibling branch; the original reply stays in history.
+    pub async fn regenerate(&self, user_id: &str, message_id: i64) -> Result<MessageBranch> {
+        let agent = self.agent_for(user_id, None).await?;
+        let provider = self.memory_for(&agent).ok_or_else(|| {
+            ButterflyBotError::Config("Regenerating requires a memory provider".to_string())
+        })?;
+        let message = provider
+            .get_message(user_id, message_id)
+            .await?
+            .filter(|message| message.role == "user")
+            .ok_or_else(|| {
+                ButterflyBotError::NotFound(format!("No user message with id {message_id}"))
+            })?;
+
+        let history = load_history(
+            provider.as_ref(),
+            user_id,
+            self.history_window,
+            Some(message_id),
+        )
+        .await?
+        .join("\n");
+        let memory_context = build_memory_context(history, Vec::new(), None);
+        let response = agent
+            .generate_response(user_id, &message.content, &memory_context, None)
+            .await?;
+        let response = self.redact(response);
+        provider.add_branch(user_id, message_id, &response).await
+    }
+
+    /// Replaces the stored user message `message_id` with `new_text`, drops
+    /// everything after it, and answers the edited message as a fresh turn.
+    pub async fn edit_and_resubmit(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        new_text: &str,
+    ) -> Result<String> {
+        // Checked before anything is discarded, so a blank edit loses nothing.
+        ensure_message(new_text, &[])?;
+        let provider = self.user_memory(user_id).await?.ok_or_else(|| {
+            ButterflyBotError::Config("Editing history requires a memory provider".to_string())
+        })?;
+        provider
+            .get_message(user_id, message_id)
+            .await?
+            .filter(|message| message.role == "user")
+            .ok_or_else(|| {
+                ButterflyBotError::NotFound(format!("No user message with id {message_id}"))
+            })?;
+        provider.truncate_from(user_id, message_id).await?;
+        self.process_text(user_id, new_text, None).await
+    }
+
+    /// Has every agent read pinned context from this service's memory.
+    pub async fn enable_pinned_context(&self) {
+        self.agent_service
+            .set_pinned_context(self.memory_provider.clone())
+            .await;
+        for persona in self.personas.values() {
+            persona.set_pinned_context(self.memory_for(persona)).await;
+        }
+    }
+
+    pub async fn pin_context(&self, user_id: &str, content: &str) -> Result<PinnedContext> {
+        let provider = self.user_memory(user_id).await?.ok_or_else(|| {
+            ButterflyBotError::Config("Pinning context requires a memory provider".to_string())
+        })?;
+        provider.pin_context(user_id, content).await
+    }
+
+    pub async fn unpin_context(&self, user_id: &str, pin_id: i64) -> Result<bool> {
+        match self.user_memory(user_id).await? {
+            Some(provider) => provider.unpin_context(user_id, pin_id).await,
+            None => Ok(false),
+        }
+    }
+
+    pub async fn pinned_context(&self, user_id: &str) -> Result<Vec<PinnedContext>> {
+        match self.user_memory(user_id).await? {
+            Some(provider) => provider.pinned_context(user_id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn list_chats(&self, include_archived: bool) -> Result<Vec<ChatSummary>> {
+        match &self.memory_provider {
+            Some(provider) => provider.list_chats(include_archived).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn archive_chat(&self, user_id: &str) -> Result<bool> {
+        let provider = self.memory_provider.as_ref().ok_or_else(|| {
+            ButterflyBotError::Config("Archiving chats requires a memory provider".to_string())
+        })?;
+        provider.archive_chat(user_id).await
+    }
+
+    pub async fn unarchive_chat(&self, user_id: &str) -> Result<bool> {
+        match &self.memory_provider {
+            Some(provider) => provider.unarchive_chat(user_id).await,
+            None => Ok(false),
+        }
+    }
+
+    pub async fn archive_idle_chats(&self, idle_before: i64) -> Result<Vec<String>> {
+        match &self.memory_provider {
+            Some(provider) => provider.archive_idle_chats(idle_before).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn get_branches(&self, user_id: &str, message_id: i64) -> Result<Vec<MessageBranch>> {
+        if let Some(provider) = self.user_memory(user_id).await? {
+            return provider.get_branches(user_id, message_id).await;
+        }
+        Ok(Vec::new())
+    }
+
+    pub async fn reindex_memory(
+        &self,
+        user_id: &str,
+        resume_after: Option<i64>,
+    ) -> Result<ReindexReport> {
+        if let Some(provider) = self.user_memory(user_id).await? {
+            return provider.reindex_memory(user_id, resume_after).await;
+        }
+        Ok(ReindexReport {
+            user_id: user_id.to_string(),
+            last_id: resume_after,
+            ..Default::default()
+        })
+    }
+}
+
+/// `memory` under `namespace`, or as is without one.
+fn scoped_memory(
+    memory: Option<Arc<dyn MemoryProvider>>,
+    namespace: Option<&str>,
+) -> Option<Arc<dyn MemoryProvider>> {
+    match namespace {
+        Some(namespace) => memory.map(|provider| {
+            Arc::new(NamespacedMemoryProvider::new(provider, namespace)) as Arc<dyn MemoryProvider>
+        }),
+        None => memory,
+    }
+}
+
+/// Memories a turn retrieves unless `memory.search_limit` says otherwise.
+pub const DEFAULT_MEMORY_SEARCH_LIMIT: usize = 5;
+
+const DEFAULT_HISTORY_MESSAGES: usize = 12;
+
+/// Rejects options [`QueryService::process_stream`] can't honour, so callers
+/// can refuse a request before they start streaming a response.
+pub fn ensure_streamable(options: &ProcessOptions) -> Result<()> {
+    match unstreamable_option(options) {
+        Some(option) => Err(ButterflyBotError::InvalidInput(format!(
+            "{option} is not supported when streaming"
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn unstreamable_option(options: &ProcessOptions) -> Option<&'static str> {
+    if !options.images.is_empty() {
+        Some("images")
+    } else if options.json_schema.is_some() {
+        Some("json_schema")
+    } else if options.dry_run {
+        Some("dry_run")
+    } else if matches!(options.output_format, OutputFormat::Audio { .. }) {
+        Some("audio output")
+    } else {
+        None
+    }
+}
+
+/// Rejects a turn with nothing to answer: blank text and no images. Every
+/// entry point checks this before touching memory or the provider.
+pub fn ensure_message(text: &str, images: &[ImageInput]) -> Result<()> {
+    if text.trim().is_empty() && images.is_empty() {
+        return Err(ButterflyBotError::InvalidInput(
+            "message is empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The most recent history that fits `window`, or with `before_id` the history
+/// leading up to that message.
+async fn load_history(
+    provider: &dyn MemoryProvider,
+    user_id: &str,
+    window: HistoryWindow,
+    before_id: Option<i64>,
+) -> Result<Vec<String>> {
+    let messages = window.messages.unwrap_or(DEFAULT_HISTORY_MESSAGES);
+    if messages == 0 {
+        return Ok(Vec::new());
+    }
+    let mut history = match before_id {
+        Some(before_id) => provider
+            .get_messages(user_id, Some(before_id), messages)
+            .await?
+            .into_iter()
+            .map(|earlier| format!("{}: {}", earlier.role, earlier.content))
+            .collect(),
+        None => provider.get_history(user_id, messages).await?,
+    };
+    if let Some(budget) = window.max_tokens {
+        let mut used: u64 = history.iter().map(|line| estimate_tokens(line)).sum();
+        let mut skip = 0;
+        while used > budget && skip < history.len() {
+            used -= estimate_tokens(&history[skip]);
+            skip += 1;
+        }
+        history.drain(..skip);
+    }
+    Ok(history)
 }
 
 fn build_memory_context(
@@ -353,11 +910,10 @@ fn build_memory_context(
 }
 
 async fn build_reminder_context(store: &ReminderStore, user_id: &str) -> Option<String> {
-    let now_ts = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-    let items = store.peek_due_reminders(user_id, now_ts, 5).await.ok()?;
+    let items = store
+        .peek_due_reminders(user_id, store.now(), 5)
+        .await
+        .ok()?;
     if items.is_empty() {
         return None;
     }
@@ -420,16 +976,20 @@ impl QueryService {
             text.to_string()
         };
 
-        let result = tool
-            .execute(serde_json::json!({"query": query, "user_id": user_id}))
+        let result = self
+            .agent_service
+            .tool_registry
+            .execute_tool(
+                user_id,
+                tool.as_ref(),
+                serde_json::json!({"query": query, "user_id": user_id}),
+            )
             .await?;
         let status = result.get("status").and_then(|v| v.as_str()).unwrap_or("");
         if status == "success" {
-            let content = result
-                .get("result")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
+            let content = serde_json::from_value::<SearchResult>(result)
+                .map(|search| search.render())
+                .unwrap_or_default();
             if content.is_empty() {
                 return Ok(Some(
                     "Search completed, but no results were returned.".to_string(),
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_SNIPPET_CHARS: usize = 160;
+
+/// A query-term hit inside a snippet, as char offsets into `MemorySnippet::snippet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnippetMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemorySnippet {
+    pub text: String,
+    pub snippet: String,
+    pub matches: Vec<SnippetMatch>,
+}
+
+impl MemorySnippet {
+    /// The snippet with every match wrapped in `<mark>` tags.
+    pub fn highlighted(&self) -> String {
+        let chars: Vec<char> = self.snippet.chars().collect();
+        let mut out = String::with_capacity(self.snippet.len() + self.matches.len() * 13);
+        let mut cursor = 0;
+        for hit in &self.matches {
+            out.extend(&chars[cursor..hit.start]);
+            out.push_str("<mark>");
+            out.extend(&chars[hit.start..hit.end]);
+            out.push_str("</mark>");
+            cursor = hit.end;
+        }
+        out.extend(&chars[cursor..]);
+        out
+    }
+}
+
+/// Cuts a passage of at most `max_chars` out of `text`, centred on the
+/// stretch with the most query-term hits. Text without any hit (e.g. a
+/// vector-only result) falls back to its opening passage.
+pub fn build_snippet(text: &str, query: &str, max_chars: usize) -> MemorySnippet {
+    let chars: Vec<char> = text.chars().collect();
+    let max_chars = max_chars.max(1);
+    let hits = find_hits(&chars, query);
+
+    let window = max_chars.min(chars.len());
+    let mut start = 0;
+    let mut best = 0;
+    for hit in &hits {
+        let centre = (hit.start + hit.end) / 2;
+        let candidate = centre.saturating_sub(window / 2).min(chars.len() - window);
+        let covered = hits
+            .iter()
+            .filter(|other| other.start >= candidate && other.end <= candidate + window)
+            .count();
+        if covered > best {
+            best = covered;
+            start = candidate;
+        }
+    }
+    let end = start + window;
+
+    let matches = hits
+        .iter()
+        .filter(|hit| hit.start >= start && hit.end <= end)
+        .map(|hit| SnippetMatch {
+            start: hit.start - start,
+            end: hit.end - start,
+        })
+        .collect();
+
+    MemorySnippet {
+        text: text.to_string(),
+        snippet: chars[start..end].iter().collect(),
+        matches,
+    }
+}
+
+/// Non-overlapping, case-insensitive occurrences of each query term, in text order.
+fn find_hits(chars: &[char], query: &str) -> Vec<SnippetMatch> {
+    let lowered: Vec<char> = chars.iter().map(|c| fold(*c)).collect();
+    let terms: Vec<Vec<char>> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.chars().map(fold).collect())
+        .collect();
+
+    let mut hits = Vec::new();
+    let mut index = 0;
+    while index < lowered.len() {
+        let found = terms
+            .iter()
+            .filter(|term| lowered[index..].starts_with(term))
+            .map(Vec::len)
+            .max();
+        match found {
+            Some(len) => {
+                hits.push(SnippetMatch {
+                    start: index,
+                    end: index + len,
+                });
+                index += len;
+            }
+            None => index += 1,
+        }
+    }
+    hits
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
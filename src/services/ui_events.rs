@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast;
+
+use crate::services::agent::UiEvent;
+
+/// Events buffered per user before the slowest subscriber starts missing them.
+pub const DEFAULT_UI_EVENT_BUFFER: usize = 64;
+
+/// Splits the shared UI event stream into one bounded channel per user.
+///
+/// Publishing never waits on subscribers: one that falls more than the buffer
+/// behind loses its oldest events (and sees `RecvError::Lagged`), while other
+/// subscribers, and other users, are unaffected.
+pub struct UiEventHub {
+    capacity: usize,
+    channels: Mutex<HashMap<String, broadcast::Sender<UiEvent>>>,
+}
+
+impl UiEventHub {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity: capacity.max(1),
+            channels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn subscribe(&self, user_id: &str) -> broadcast::Receiver<UiEvent> {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        channels
+            .entry(user_id.to_string())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe()
+    }
+
+    /// Live subscriptions for `user_id`, i.e. how many clients an event
+    /// published for them right now would reach.
+    pub fn subscriber_count(&self, user_id: &str) -> usize {
+        let channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        channels
+            .get(user_id)
+            .map_or(0, |sender| sender.receiver_count())
+    }
+
+    pub fn publish(&self, event: UiEvent) {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(sender) = channels.get(&event.user_id) else {
+            return;
+        };
+        let user_id = event.user_id.clone();
+        if sender.send(event).is_err() {
+            // Every subscriber for this user has gone away.
+            channels.remove(&user_id);
+        }
+    }
+
+    /// Republishes everything from `source` until it closes.
+    pub fn forward_from(self: &Arc<Self>, mut source: broadcast::Receiver<UiEvent>) {
+        let hub = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match source.recv().await {
+                    Ok(event) => hub.publish(event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "UI event fan-out fell behind");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
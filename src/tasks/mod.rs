@@ -1,5 +1,4 @@
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
 
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
@@ -8,21 +7,30 @@ use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+use crate::clock::{Clock, SystemClock};
+use crate::db::retry_on_busy;
 use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
 use schema::scheduled_tasks;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 const TASKS_UP_SQL: &str = include_str!("../../migrations/20260203_create_tasks/up.sql");
+const TASK_RUN_STATE_UP_SQL: &str = include_str!("../../migrations/20260215_task_run_state/up.sql");
+const COMPLETED: &str = "completed";
+const INTERRUPTED: &str = "interrupted";
+const FAILED: &str = "failed";
+const MAX_INTERVAL_MINUTES: i64 = 366 * 24 * 60;
+const MAX_SCHEDULE_AHEAD_SECS: i64 = 10 * 366 * 24 * 60 * 60;
 
 type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
 type SqlitePool = Pool<SqliteAsyncConn>;
 type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScheduledTask {
     pub id: i32,
     pub user_id: String,
@@ -35,6 +43,10 @@ pub struct ScheduledTask {
     pub updated_at: i64,
     pub last_run_at: Option<i64>,
     pub next_run_at: i64,
+    /// When the run in flight was claimed; `None` when the task is idle.
+    pub running_since: Option<i64>,
+    /// How the last run ended: `completed`, `interrupted` or `failed`.
+    pub last_status: Option<String>,
 }
 
 #[derive(Queryable)]
@@ -50,6 +62,8 @@ struct TaskRow {
     updated_at: i64,
     last_run_at: Option<i64>,
     next_run_at: i64,
+    running_since: Option<i64>,
+    last_status: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -84,8 +98,28 @@ impl TaskStatus {
     }
 }
 
+/// What startup does with a task whose run was cut off by a restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptedRunPolicy {
+    /// Make the task due again so the next poll runs it.
+    Rerun,
+    /// Count the run as failed: one-shot tasks are disabled and recurring
+    /// ones wait for their next interval.
+    Fail,
+}
+
+impl InterruptedRunPolicy {
+    pub fn from_option(value: Option<&str>) -> Self {
+        match value {
+            Some("fail") => Self::Fail,
+            _ => Self::Rerun,
+        }
+    }
+}
+
 pub struct TaskStore {
     pool: SqlitePool,
+    clock: Arc<dyn Clock>,
 }
 
 impl TaskStore {
@@ -100,7 +134,19 @@ impl TaskStore {
             .build(manager)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn now(&self) -> i64 {
+        self.clock.now()
     }
 
     pub async fn create_task(
@@ -111,36 +157,39 @@ impl TaskStore {
         run_at: i64,
         interval_minutes: Option<i64>,
     ) -> Result<ScheduledTask> {
-        let now = now_ts();
-        let interval_minutes = interval_minutes.filter(|v| *v > 0);
-        let next_run_at = run_at.max(now);
-        let new = NewTask {
-            user_id,
-            name,
-            prompt,
-            run_at,
-            interval_minutes,
-            enabled: true,
-            created_at: now,
-            updated_at: now,
-            last_run_at: None,
-            next_run_at,
-        };
-
-        let mut conn = self.conn().await?;
-        diesel::insert_into(scheduled_tasks::table)
-            .values(&new)
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-
-        let row: TaskRow = scheduled_tasks::table
-            .filter(scheduled_tasks::user_id.eq(user_id))
-            .order(scheduled_tasks::id.desc())
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let interval_minutes = interval_minutes.filter(|v| *v > 0);
+            let next_run_at = run_at.max(now);
+            let new = NewTask {
+                user_id,
+                name,
+                prompt,
+                run_at,
+                interval_minutes,
+                enabled: true,
+                created_at: now,
+                updated_at: now,
+                last_run_at: None,
+                next_run_at,
+            };
+
+            let mut conn = self.conn().await?;
+            diesel::insert_into(scheduled_tasks::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let row: TaskRow = scheduled_tasks::table
+                .filter(scheduled_tasks::user_id.eq(user_id))
+                .order(scheduled_tasks::id.desc())
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn list_tasks(
@@ -169,10 +218,44 @@ impl TaskStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
-    pub async fn set_enabled(&self, id: i32, enabled: bool) -> Result<ScheduledTask> {
-        let now = now_ts();
+    pub async fn count_enabled(&self, user_id: &str) -> Result<u64> {
         let mut conn = self.conn().await?;
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
+        let count: i64 = scheduled_tasks::table
+            .filter(scheduled_tasks::user_id.eq(user_id))
+            .filter(scheduled_tasks::enabled.eq(true))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(count as u64)
+    }
+
+    pub async fn get_task(&self, id: i32) -> Result<Option<ScheduledTask>> {
+        let mut conn = self.conn().await?;
+        let row: Option<TaskRow> = scheduled_tasks::table
+            .filter(scheduled_tasks::id.eq(id))
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(row.map(map_row))
+    }
+
+    /// Enables or disables `user_id`'s task `id`; `None` if they have no such task.
+    pub async fn set_enabled(
+        &self,
+        user_id: &str,
+        id: i32,
+        enabled: bool,
+    ) -> Result<Option<ScheduledTask>> {
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
+                scheduled_tasks::table
+                    .filter(scheduled_tasks::id.eq(id))
+                    .filter(scheduled_tasks::user_id.eq(user_id)),
+            )
             .set((
                 scheduled_tasks::enabled.eq(enabled),
                 scheduled_tasks::updated_at.eq(now),
@@ -180,22 +263,57 @@ impl TaskStore {
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            if updated == 0 {
+                return Ok(None);
+            }
 
-        let row: TaskRow = scheduled_tasks::table
-            .filter(scheduled_tasks::id.eq(id))
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+            let row: TaskRow = scheduled_tasks::table
+                .filter(scheduled_tasks::id.eq(id))
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(Some(map_row(row)))
+        })
+        .await
     }
 
-    pub async fn delete_task(&self, id: i32) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let count = diesel::delete(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
+    pub async fn delete_task(&self, user_id: &str, id: i32) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let count = diesel::delete(
+                scheduled_tasks::table
+                    .filter(scheduled_tasks::id.eq(id))
+                    .filter(scheduled_tasks::user_id.eq(user_id)),
+            )
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(count > 0)
+            Ok(count > 0)
+        })
+        .await
+    }
+
+    /// Rejects a `run_at` that isn't a positive timestamp or lies more than ten
+    /// years past this store's clock, and an interval outside one minute to a year.
+    pub fn validate_schedule(&self, run_at: i64, interval_minutes: Option<i64>) -> Result<()> {
+        if run_at <= 0 {
+            return Err(ButterflyBotError::InvalidInput(
+                "run_at must be a positive unix timestamp".to_string(),
+            ));
+        }
+        if run_at > self.clock.now() + MAX_SCHEDULE_AHEAD_SECS {
+            return Err(ButterflyBotError::InvalidInput(
+                "run_at is too far in the future".to_string(),
+            ));
+        }
+        if let Some(interval) = interval_minutes {
+            if !(1..=MAX_INTERVAL_MINUTES).contains(&interval) {
+                return Err(ButterflyBotError::InvalidInput(format!(
+                    "interval_minutes must be between 1 and {MAX_INTERVAL_MINUTES}"
+                )));
+            }
+        }
+        Ok(())
     }
 
     pub async fn list_due(&self, now: i64, limit: usize) -> Result<Vec<ScheduledTask>> {
@@ -211,33 +329,131 @@ impl TaskStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
-    pub async fn mark_run(&self, id: i32, last_run_at: i64, next_run_at: i64) -> Result<()> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
+    /// Leases a due item by pushing `next_run_at` out by `lease_secs` and records it as
+    /// running. If the process dies before `mark_run`, the item simply becomes due again once
+    /// the lease runs out, or sooner through `reconcile_interrupted`.
+    pub async fn claim(&self, id: i32, now: i64, lease_secs: i64) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
+                scheduled_tasks::table
+                    .filter(scheduled_tasks::id.eq(id))
+                    .filter(scheduled_tasks::enabled.eq(true))
+                    .filter(scheduled_tasks::next_run_at.le(now)),
+            )
             .set((
-                scheduled_tasks::last_run_at.eq(Some(last_run_at)),
-                scheduled_tasks::next_run_at.eq(next_run_at),
-                scheduled_tasks::updated_at.eq(now),
+                scheduled_tasks::next_run_at.eq(now + lease_secs.max(1)),
+                scheduled_tasks::running_since.eq(Some(now)),
             ))
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(())
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    pub async fn mark_run(&self, id: i32, last_run_at: i64, next_run_at: i64) -> Result<()> {
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
+                .set((
+                    scheduled_tasks::last_run_at.eq(Some(last_run_at)),
+                    scheduled_tasks::next_run_at.eq(next_run_at),
+                    scheduled_tasks::running_since.eq::<Option<i64>>(None),
+                    scheduled_tasks::last_status.eq(Some(COMPLETED)),
+                    scheduled_tasks::updated_at.eq(now),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
     }
 
     pub async fn complete_one_shot(&self, id: i32) -> Result<()> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
-            .set((
-                scheduled_tasks::enabled.eq(false),
-                scheduled_tasks::updated_at.eq(now),
-            ))
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(())
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(id)))
+                .set((
+                    scheduled_tasks::enabled.eq(false),
+                    scheduled_tasks::running_since.eq::<Option<i64>>(None),
+                    scheduled_tasks::last_status.eq(Some(COMPLETED)),
+                    scheduled_tasks::updated_at.eq(now),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Settles every task still marked running, which after a restart means its run never
+    /// finished. Returns the tasks as they were found.
+    pub async fn reconcile_interrupted(
+        &self,
+        policy: InterruptedRunPolicy,
+    ) -> Result<Vec<ScheduledTask>> {
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            let rows: Vec<TaskRow> = scheduled_tasks::table
+                .filter(scheduled_tasks::running_since.is_not_null())
+                .load(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            let interrupted: Vec<ScheduledTask> = rows.into_iter().map(map_row).collect();
+
+            for task in &interrupted {
+                let target = scheduled_tasks::table.filter(scheduled_tasks::id.eq(task.id));
+                let started = task.running_since.unwrap_or(now);
+                let updated = match (policy, task.interval_minutes) {
+                    (InterruptedRunPolicy::Rerun, _) => {
+                        diesel::update(target)
+                            .set((
+                                scheduled_tasks::next_run_at.eq(now),
+                                scheduled_tasks::running_since.eq::<Option<i64>>(None),
+                                scheduled_tasks::last_status.eq(Some(INTERRUPTED)),
+                                scheduled_tasks::updated_at.eq(now),
+                            ))
+                            .execute(&mut conn)
+                            .await
+                    }
+                    (InterruptedRunPolicy::Fail, Some(interval)) => {
+                        diesel::update(target)
+                            .set((
+                                scheduled_tasks::last_run_at.eq(Some(started)),
+                                scheduled_tasks::next_run_at
+                                    .eq((started + interval.max(1) * 60).max(now)),
+                                scheduled_tasks::running_since.eq::<Option<i64>>(None),
+                                scheduled_tasks::last_status.eq(Some(FAILED)),
+                                scheduled_tasks::updated_at.eq(now),
+                            ))
+                            .execute(&mut conn)
+                            .await
+                    }
+                    (InterruptedRunPolicy::Fail, None) => {
+                        diesel::update(target)
+                            .set((
+                                scheduled_tasks::enabled.eq(false),
+                                scheduled_tasks::last_run_at.eq(Some(started)),
+                                scheduled_tasks::running_since.eq::<Option<i64>>(None),
+                                scheduled_tasks::last_status.eq(Some(FAILED)),
+                                scheduled_tasks::updated_at.eq(now),
+                            ))
+                            .execute(&mut conn)
+                            .await
+                    }
+                };
+                updated.map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+            Ok(interrupted)
+        })
+        .await
     }
 
     async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
@@ -251,6 +467,17 @@ impl TaskStore {
     }
 }
 
+/// `tools.tasks.on_interrupted`: `"rerun"` (the default) or `"fail"`.
+pub fn resolve_interrupted_policy(config: &serde_json::Value) -> InterruptedRunPolicy {
+    InterruptedRunPolicy::from_option(
+        config
+            .get("tools")
+            .and_then(|v| v.get("tasks"))
+            .and_then(|v| v.get("on_interrupted"))
+            .and_then(|v| v.as_str()),
+    )
+}
+
 pub fn resolve_task_db_path(config: &serde_json::Value) -> Option<String> {
     config
         .get("tools")
@@ -262,15 +489,7 @@ pub fn resolve_task_db_path(config: &serde_json::Value) -> Option<String> {
 }
 
 pub fn default_task_db_path() -> String {
-    "./data/butterfly-bot.db".to_string()
-}
-
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
+    crate::paths::default_db_path()
 }
 
 async fn run_migrations(database_url: &str) -> Result<()> {
@@ -311,6 +530,23 @@ async fn ensure_tasks_table(database_url: &str) -> Result<()> {
             }
         }
 
+        let run_state = diesel::connection::SimpleConnection::batch_execute(
+            &mut conn,
+            "SELECT running_since FROM scheduled_tasks LIMIT 1",
+        );
+        if let Err(err) = run_state {
+            let message = err.to_string();
+            if message.contains("no such column") {
+                diesel::connection::SimpleConnection::batch_execute(
+                    &mut conn,
+                    TASK_RUN_STATE_UP_SQL,
+                )
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            } else {
+                return Err(ButterflyBotError::Runtime(message));
+            }
+        }
+
         Ok::<_, ButterflyBotError>(())
     })
     .await
@@ -331,12 +567,7 @@ fn map_row(row: TaskRow) -> ScheduledTask {
         updated_at: row.updated_at,
         last_run_at: row.last_run_at,
         next_run_at: row.next_run_at,
+        running_since: row.running_since,
+        last_status: row.last_status,
     }
 }
-
-fn now_ts() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64
-}
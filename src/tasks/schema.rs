@@ -11,5 +11,7 @@ diesel::table! {
         updated_at -> BigInt,
         last_run_at -> Nullable<BigInt>,
         next_run_at -> BigInt,
+        running_since -> Nullable<BigInt>,
+        last_status -> Nullable<Text>,
     }
 }
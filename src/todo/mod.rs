@@ -1,4 +1,3 @@
-use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use diesel::dsl::max;
@@ -6,12 +5,15 @@ use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use serde::Serialize;
 
+use crate::db::retry_on_busy;
 use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
 use schema::todo_items;
@@ -33,6 +35,65 @@ pub struct TodoItem {
     pub created_at: i64,
     pub updated_at: i64,
     pub completed_at: Option<i64>,
+    pub due_at: Option<i64>,
+    pub recurrence: Option<TodoRecurrence>,
+    pub linked_reminder_id: Option<i32>,
+    /// The recurring todo whose completion opened this one.
+    pub spawned_from: Option<i32>,
+}
+
+/// How often a todo comes back. Completing a recurring todo leaves it done
+/// and opens the next occurrence, due one interval later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoRecurrence {
+    Daily,
+    Weekly,
+    EveryDays(u32),
+}
+
+impl TodoRecurrence {
+    /// Accepts `daily`, `weekly` or `every N days`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "daily" => return Ok(Self::Daily),
+            "weekly" => return Ok(Self::Weekly),
+            _ => {}
+        }
+        let days = value
+            .strip_prefix("every ")
+            .and_then(|rest| {
+                rest.strip_suffix(" days")
+                    .or_else(|| rest.strip_suffix(" day"))
+            })
+            .and_then(|days| days.trim().parse::<u32>().ok())
+            .filter(|days| *days > 0)
+            .ok_or_else(|| {
+                ButterflyBotError::Runtime(format!(
+                    "Invalid recurrence '{}' (use daily, weekly or every N days)",
+                    value
+                ))
+            })?;
+        Ok(Self::EveryDays(days))
+    }
+
+    pub fn interval_secs(&self) -> i64 {
+        let days = match self {
+            Self::Daily => 1,
+            Self::Weekly => 7,
+            Self::EveryDays(days) => i64::from(*days),
+        };
+        days * 24 * 60 * 60
+    }
+
+    fn to_db(self) -> String {
+        match self {
+            Self::Daily => "daily".to_string(),
+            Self::Weekly => "weekly".to_string(),
+            Self::EveryDays(days) => format!("every {} days", days),
+        }
+    }
 }
 
 #[derive(Queryable)]
@@ -45,6 +106,10 @@ struct TodoRow {
     created_at: i64,
     updated_at: i64,
     completed_at: Option<i64>,
+    due_at: Option<i64>,
+    recurrence: Option<String>,
+    linked_reminder_id: Option<i32>,
+    spawned_from: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -57,6 +122,9 @@ struct NewTodo<'a> {
     created_at: i64,
     updated_at: i64,
     completed_at: Option<i64>,
+    due_at: Option<i64>,
+    recurrence: Option<String>,
+    spawned_from: Option<i32>,
 }
 
 #[derive(Clone, Copy)]
@@ -101,39 +169,45 @@ impl TodoStore {
         title: &str,
         notes: Option<&str>,
     ) -> Result<TodoItem> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        let max_pos: Option<i32> = todo_items::table
-            .filter(todo_items::user_id.eq(user_id))
-            .select(max(todo_items::position))
-            .first::<Option<i32>>(&mut conn)
+        self.create_scheduled_item(user_id, title, notes, None, None)
             .await
-            .unwrap_or(None);
-        let position = max_pos.unwrap_or(0) + 1;
-
-        let new = NewTodo {
-            user_id,
-            title,
-            notes,
-            position,
-            created_at: now,
-            updated_at: now,
-            completed_at: None,
-        };
-
-        diesel::insert_into(todo_items::table)
-            .values(&new)
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    }
 
-        let row: TodoRow = todo_items::table
-            .filter(todo_items::user_id.eq(user_id))
-            .order(todo_items::id.desc())
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+    /// Like [`Self::create_item`], with an optional due date and recurrence.
+    pub async fn create_scheduled_item(
+        &self,
+        user_id: &str,
+        title: &str,
+        notes: Option<&str>,
+        due_at: Option<i64>,
+        recurrence: Option<TodoRecurrence>,
+    ) -> Result<TodoItem> {
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let mut conn = self.conn().await?;
+            let row = conn
+                .immediate_transaction(|conn| {
+                    async move {
+                        let new = NewTodo {
+                            user_id,
+                            title,
+                            notes,
+                            position: 0,
+                            created_at: now,
+                            updated_at: now,
+                            completed_at: None,
+                            due_at,
+                            recurrence: recurrence.map(TodoRecurrence::to_db),
+                            spawned_from: None,
+                        };
+                        insert_item(conn, new).await
+                    }
+                    .scope_boxed()
+                })
+                .await?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn list_items(
@@ -166,54 +240,212 @@ impl TodoStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
-    pub async fn set_completed(&self, id: i32, completed: bool) -> Result<TodoItem> {
-        let now = now_ts();
-        let completed_at = if completed { Some(now) } else { None };
+    pub async fn count_open(&self, user_id: &str) -> Result<u64> {
         let mut conn = self.conn().await?;
-        diesel::update(todo_items::table.filter(todo_items::id.eq(id)))
-            .set((
-                todo_items::completed_at.eq(completed_at),
-                todo_items::updated_at.eq(now),
-            ))
-            .execute(&mut conn)
+        let count: i64 = todo_items::table
+            .filter(todo_items::user_id.eq(user_id))
+            .filter(todo_items::completed_at.is_null())
+            .count()
+            .get_result(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(count as u64)
+    }
+
+    /// Completing an open recurring todo also creates its next occurrence,
+    /// once: completing it again after reopening it doesn't add another. The
+    /// read, the update and the new occurrence share one `BEGIN IMMEDIATE`
+    /// transaction, so concurrent completions can't both add one.
+    pub async fn set_completed(&self, id: i32, completed: bool) -> Result<TodoItem> {
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let completed_at = if completed { Some(now) } else { None };
+            let mut conn = self.conn().await?;
+            let row = conn
+                .immediate_transaction(|conn| {
+                    async move {
+                        let previous: TodoRow = todo_items::table
+                            .filter(todo_items::id.eq(id))
+                            .first(conn)
+                            .await?;
+                        diesel::update(todo_items::table.filter(todo_items::id.eq(id)))
+                            .set((
+                                todo_items::completed_at.eq(completed_at),
+                                todo_items::updated_at.eq(now),
+                            ))
+                            .execute(conn)
+                            .await?;
+                        if completed && previous.completed_at.is_none() {
+                            insert_next_occurrence(conn, &previous, now).await?;
+                        }
+                        let row: TodoRow = todo_items::table
+                            .filter(todo_items::id.eq(id))
+                            .first(conn)
+                            .await?;
+                        Ok::<_, ButterflyBotError>(row)
+                    }
+                    .scope_boxed()
+                })
+                .await?;
+            Ok(map_row(row))
+        })
+        .await
+    }
 
-        let row: TodoRow = todo_items::table
+    pub async fn get_item(&self, id: i32) -> Result<Option<TodoItem>> {
+        let mut conn = self.conn().await?;
+        let row: Option<TodoRow> = todo_items::table
             .filter(todo_items::id.eq(id))
             .first(&mut conn)
             .await
+            .optional()
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+        Ok(row.map(map_row))
+    }
+
+    pub async fn set_linked_reminder(&self, id: i32, reminder_id: Option<i32>) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(todo_items::table.filter(todo_items::id.eq(id)))
+                .set((
+                    todo_items::linked_reminder_id.eq(reminder_id),
+                    todo_items::updated_at.eq(now_ts()),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(updated > 0)
+        })
+        .await
     }
 
     pub async fn delete_item(&self, id: i32) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let count = diesel::delete(todo_items::table.filter(todo_items::id.eq(id)))
-            .execute(&mut conn)
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let count = diesel::delete(todo_items::table.filter(todo_items::id.eq(id)))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(count > 0)
+        })
+        .await
+    }
+
+    /// Completes every open todo of `user_id` and returns how many changed.
+    /// Recurring ones open their next occurrence, as in [`Self::set_completed`].
+    pub async fn complete_all(&self, user_id: &str) -> Result<usize> {
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let mut conn = self.conn().await?;
+            conn.immediate_transaction(|conn| {
+                async move {
+                    let recurring: Vec<TodoRow> = todo_items::table
+                        .filter(todo_items::user_id.eq(user_id))
+                        .filter(todo_items::completed_at.is_null())
+                        .filter(todo_items::recurrence.is_not_null())
+                        .order(todo_items::position.asc())
+                        .load(conn)
+                        .await?;
+                    let count = diesel::update(
+                        todo_items::table
+                            .filter(todo_items::user_id.eq(user_id))
+                            .filter(todo_items::completed_at.is_null()),
+                    )
+                    .set((
+                        todo_items::completed_at.eq(Some(now)),
+                        todo_items::updated_at.eq(now),
+                    ))
+                    .execute(conn)
+                    .await?;
+                    for previous in &recurring {
+                        insert_next_occurrence(conn, previous, now).await?;
+                    }
+                    Ok(count)
+                }
+                .scope_boxed()
+            })
             .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(count > 0)
+        })
+        .await
     }
 
-    pub async fn reorder(&self, user_id: &str, ordered_ids: &[i32]) -> Result<()> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        for (idx, id) in ordered_ids.iter().enumerate() {
-            diesel::update(
+    /// Deletes `user_id`'s completed todos and renumbers the rest 1..n in
+    /// their current order. Returns how many were deleted.
+    pub async fn clear_completed(&self, user_id: &str) -> Result<usize> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let count = diesel::delete(
                 todo_items::table
                     .filter(todo_items::user_id.eq(user_id))
-                    .filter(todo_items::id.eq(*id)),
+                    .filter(todo_items::completed_at.is_not_null()),
             )
-            .set((
-                todo_items::position.eq((idx + 1) as i32),
-                todo_items::updated_at.eq(now),
-            ))
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        }
-        Ok(())
+            if count > 0 {
+                diesel::sql_query(
+                    "UPDATE todo_items SET position = (
+                    SELECT COUNT(*) FROM todo_items AS earlier
+                    WHERE earlier.user_id = todo_items.user_id
+                      AND (earlier.position < todo_items.position
+                        OR (earlier.position = todo_items.position AND earlier.id <= todo_items.id))
+                ) WHERE user_id = ?1",
+                )
+                .bind::<diesel::sql_types::Text, _>(user_id)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            }
+            Ok(count)
+        })
+        .await
+    }
+
+    /// Renumbers the user's items 1..n in the given order. `ordered_ids` must
+    /// name each of the user's items exactly once. The check and the updates
+    /// share one `BEGIN IMMEDIATE` transaction, so concurrent reorders apply
+    /// one after the other instead of interleaving.
+    pub async fn reorder(&self, user_id: &str, ordered_ids: &[i32]) -> Result<()> {
+        retry_on_busy(|| async move {
+            let now = now_ts();
+            let mut conn = self.conn().await?;
+            conn.immediate_transaction(|conn| {
+                async move {
+                    let mut existing: Vec<i32> = todo_items::table
+                        .filter(todo_items::user_id.eq(user_id))
+                        .select(todo_items::id)
+                        .load(conn)
+                        .await?;
+                    existing.sort_unstable();
+                    let mut requested = ordered_ids.to_vec();
+                    requested.sort_unstable();
+                    if requested != existing {
+                        return Err(ButterflyBotError::InvalidInput(format!(
+                            "ordered_ids must list each of the user's {} todo items exactly once",
+                            existing.len()
+                        )));
+                    }
+
+                    for (idx, id) in ordered_ids.iter().enumerate() {
+                        diesel::update(
+                            todo_items::table
+                                .filter(todo_items::user_id.eq(user_id))
+                                .filter(todo_items::id.eq(*id)),
+                        )
+                        .set((
+                            todo_items::position.eq((idx + 1) as i32),
+                            todo_items::updated_at.eq(now),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        })
+        .await
     }
 
     async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
@@ -238,15 +470,7 @@ pub fn resolve_todo_db_path(config: &serde_json::Value) -> Option<String> {
 }
 
 pub fn default_todo_db_path() -> String {
-    "./data/butterfly-bot.db".to_string()
-}
-
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
+    crate::paths::default_db_path()
 }
 
 async fn run_migrations(database_url: &str) -> Result<()> {
@@ -294,6 +518,69 @@ async fn ensure_todo_table(database_url: &str) -> Result<()> {
     Ok(())
 }
 
+/// Inserts `new` at the end of its user's list and returns the stored row.
+/// Meant to run inside a transaction, so the position and id it reads are
+/// still its own.
+async fn insert_item(conn: &mut SqliteAsyncConn, new: NewTodo<'_>) -> Result<TodoRow> {
+    let max_pos: Option<i32> = todo_items::table
+        .filter(todo_items::user_id.eq(new.user_id))
+        .select(max(todo_items::position))
+        .first(conn)
+        .await?;
+    let new = NewTodo {
+        position: max_pos.unwrap_or(0) + 1,
+        ..new
+    };
+    diesel::insert_into(todo_items::table)
+        .values(&new)
+        .execute(conn)
+        .await?;
+    let row: TodoRow = todo_items::table
+        .filter(todo_items::user_id.eq(new.user_id))
+        .order(todo_items::id.desc())
+        .first(conn)
+        .await?;
+    Ok(row)
+}
+
+/// Opens the occurrence after `previous`, due one interval after it, unless
+/// `previous` isn't recurring or has already opened one.
+async fn insert_next_occurrence(
+    conn: &mut SqliteAsyncConn,
+    previous: &TodoRow,
+    now: i64,
+) -> Result<()> {
+    let Some(recurrence) = previous
+        .recurrence
+        .as_deref()
+        .and_then(|value| TodoRecurrence::parse(value).ok())
+    else {
+        return Ok(());
+    };
+    let spawned: i64 = todo_items::table
+        .filter(todo_items::spawned_from.eq(previous.id))
+        .count()
+        .get_result(conn)
+        .await?;
+    if spawned > 0 {
+        return Ok(());
+    }
+    let new = NewTodo {
+        user_id: &previous.user_id,
+        title: &previous.title,
+        notes: previous.notes.as_deref(),
+        position: 0,
+        created_at: now,
+        updated_at: now,
+        completed_at: None,
+        due_at: Some(previous.due_at.unwrap_or(now) + recurrence.interval_secs()),
+        recurrence: Some(recurrence.to_db()),
+        spawned_from: Some(previous.id),
+    };
+    insert_item(conn, new).await?;
+    Ok(())
+}
+
 fn map_row(row: TodoRow) -> TodoItem {
     TodoItem {
         id: row.id,
@@ -304,6 +591,13 @@ fn map_row(row: TodoRow) -> TodoItem {
         created_at: row.created_at,
         updated_at: row.updated_at,
         completed_at: row.completed_at,
+        due_at: row.due_at,
+        recurrence: row
+            .recurrence
+            .as_deref()
+            .and_then(|value| TodoRecurrence::parse(value).ok()),
+        linked_reminder_id: row.linked_reminder_id,
+        spawned_from: row.spawned_from,
     }
 }
 
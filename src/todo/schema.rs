@@ -8,5 +8,9 @@ diesel::table! {
         created_at -> BigInt,
         updated_at -> BigInt,
         completed_at -> Nullable<BigInt>,
+        due_at -> Nullable<BigInt>,
+        recurrence -> Nullable<Text>,
+        linked_reminder_id -> Nullable<Integer>,
+        spawned_from -> Nullable<Integer>,
     }
 }
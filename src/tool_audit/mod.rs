@@ -0,0 +1,166 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use diesel_async::pooled_connection::bb8::{Pool, PooledConnection};
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::RunQueryDsl;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::db::retry_on_busy;
+use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
+
+mod schema;
+use schema::tool_audit;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+const TOOL_AUDIT_UP_SQL: &str = include_str!("../../migrations/20260211_create_tool_audit/up.sql");
+
+type SqliteAsyncConn = SyncConnectionWrapper<SqliteConnection>;
+type SqlitePool = Pool<SqliteAsyncConn>;
+type SqlitePooledConn<'a> = PooledConnection<'a, SqliteAsyncConn>;
+
+/// One tool execution. Arguments are kept only as a hash, so secrets passed
+/// to a tool never land in the audit table.
+#[derive(Debug, Clone, PartialEq, Serialize, Queryable)]
+pub struct ToolAuditEntry {
+    pub id: i32,
+    pub user_id: String,
+    pub tool: String,
+    pub params_hash: String,
+    pub status: String,
+    pub duration_ms: i64,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tool_audit)]
+struct NewToolAudit<'a> {
+    user_id: &'a str,
+    tool: &'a str,
+    params_hash: &'a str,
+    status: &'a str,
+    duration_ms: i64,
+    created_at: i64,
+}
+
+/// Append-only record of tool executions; rows are never updated or removed.
+pub struct ToolAuditStore {
+    pool: SqlitePool,
+}
+
+impl ToolAuditStore {
+    pub async fn new(sqlite_path: impl AsRef<str>) -> Result<Self> {
+        let sqlite_path = sqlite_path.as_ref();
+        ensure_parent_dir(sqlite_path)?;
+        run_migrations(sqlite_path).await?;
+        ensure_tool_audit_table(sqlite_path).await?;
+
+        let manager = AsyncDieselConnectionManager::<SqliteAsyncConn>::new(sqlite_path);
+        let pool: SqlitePool = Pool::builder()
+            .build(manager)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok(Self { pool })
+    }
+
+    pub async fn record(
+        &self,
+        user_id: &str,
+        tool: &str,
+        params: &serde_json::Value,
+        status: &str,
+        duration_ms: i64,
+    ) -> Result<()> {
+        retry_on_busy(|| async move {
+            let params_hash = hash_params(params);
+            let new = NewToolAudit {
+                user_id,
+                tool,
+                params_hash: &params_hash,
+                status,
+                duration_ms,
+                created_at: now_ts(),
+            };
+            let mut conn = self.conn().await?;
+            diesel::insert_into(tool_audit::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The newest `limit` entries for `user_id`, newest first.
+    pub async fn list(&self, user_id: &str, limit: usize) -> Result<Vec<ToolAuditEntry>> {
+        let mut conn = self.conn().await?;
+        tool_audit::table
+            .filter(tool_audit::user_id.eq(user_id))
+            .order(tool_audit::id.desc())
+            .limit(limit as i64)
+            .load(&mut conn)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+    }
+
+    async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_async(&mut conn).await?;
+        Ok(conn)
+    }
+}
+
+/// Hex SHA-256 of the arguments' JSON: enough to spot repeated calls
+/// without storing what was passed.
+pub fn hash_params(params: &serde_json::Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(params.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+async fn run_migrations(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        conn.run_pending_migrations(MIGRATIONS)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+async fn ensure_tool_audit_table(database_url: &str) -> Result<()> {
+    let database_url = database_url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        crate::db::apply_sqlcipher_key_sync(&mut conn)?;
+        diesel::connection::SimpleConnection::batch_execute(&mut conn, TOOL_AUDIT_UP_SQL)
+            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        Ok::<_, ButterflyBotError>(())
+    })
+    .await
+    .map_err(|e| ButterflyBotError::Runtime(e.to_string()))??;
+    Ok(())
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
@@ -0,0 +1,11 @@
+diesel::table! {
+    tool_audit (id) {
+        id -> Integer,
+        user_id -> Text,
+        tool -> Text,
+        params_hash -> Text,
+        status -> Text,
+        duration_ms -> BigInt,
+        created_at -> BigInt,
+    }
+}
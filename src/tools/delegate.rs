@@ -0,0 +1,184 @@
+use std::sync::{Arc, OnceLock, Weak};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::brain::manager::BrainManager;
+use crate::config::AgentConfig;
+use crate::domains::agent::AIAgent;
+use crate::error::{ButterflyBotError, Result};
+use crate::interfaces::plugins::Tool;
+use crate::interfaces::providers::LlmProvider;
+use crate::services::agent::AgentService;
+
+pub const DELEGATE_TOOL_NAME: &str = "delegate_to";
+
+/// How many hand-offs deep one request may go before `delegate_to` refuses.
+pub const DEFAULT_MAX_DELEGATION_DEPTH: usize = 3;
+
+/// Hands a sub-query to one of an agent's `delegates` and returns that
+/// agent's answer. Sub-agents only get `delegate_to` themselves (for their
+/// own delegates), one level deeper, until the depth cap is reached.
+///
+/// Once [attached](Self::attach) to the calling agent, sub-agents are built
+/// from it and share its budget, guardrails and settings.
+pub struct DelegateTool {
+    llm: Arc<dyn LlmProvider>,
+    parent: OnceLock<Weak<AgentService>>,
+    agents: Vec<AgentConfig>,
+    delegates: Vec<String>,
+    base_instructions: String,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl DelegateTool {
+    /// `agents` is every configured agent; `delegates` the names the calling
+    /// agent may reach. Sub-agents' instructions are appended to
+    /// `base_instructions`, as the factory does for the main agent.
+    pub fn new(
+        llm: Arc<dyn LlmProvider>,
+        agents: Vec<AgentConfig>,
+        delegates: Vec<String>,
+        base_instructions: String,
+    ) -> Self {
+        Self {
+            llm,
+            parent: OnceLock::new(),
+            agents,
+            delegates,
+            base_instructions,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DELEGATION_DEPTH,
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Builds sub-agents from `parent`, the service this tool is registered
+    /// on. Only the first call has an effect.
+    pub fn attach(&self, parent: &Arc<AgentService>) {
+        let _ = self.parent.set(Arc::downgrade(parent));
+    }
+
+    async fn build_delegate(&self, agent: &AgentConfig) -> Arc<AgentService> {
+        let mut instructions = self.base_instructions.clone();
+        if let Some(extra) = agent
+            .instructions
+            .as_deref()
+            .filter(|extra| !extra.trim().is_empty())
+        {
+            instructions = format!("{instructions}\n\n{extra}");
+        }
+        let delegate = AIAgent {
+            name: agent.name.clone(),
+            instructions,
+            specialization: agent
+                .specialization
+                .clone()
+                .unwrap_or_else(|| "general".to_string()),
+            memory_namespace: None,
+        };
+        let service = match self.parent.get().and_then(Weak::upgrade) {
+            Some(parent) => parent.delegate_service(delegate).await,
+            None => AgentService::new(
+                self.llm.clone(),
+                delegate,
+                None,
+                Arc::new(BrainManager::new(json!({}))),
+                None,
+            ),
+        };
+        let service = Arc::new(service);
+        if !agent.delegates.is_empty() && self.depth + 1 < self.max_depth {
+            let nested = DelegateTool {
+                llm: self.llm.clone(),
+                parent: OnceLock::new(),
+                agents: self.agents.clone(),
+                delegates: agent.delegates.clone(),
+                base_instructions: self.base_instructions.clone(),
+                depth: self.depth + 1,
+                max_depth: self.max_depth,
+            };
+            nested.attach(&service);
+            let registry = service.tool_registry.clone();
+            if registry.register_tool(Arc::new(nested)).await {
+                registry
+                    .assign_tool_to_agent(&agent.name, DELEGATE_TOOL_NAME)
+                    .await;
+            }
+        }
+        service
+    }
+}
+
+#[async_trait]
+impl Tool for DelegateTool {
+    fn name(&self) -> &str {
+        DELEGATE_TOOL_NAME
+    }
+
+    fn description(&self) -> &str {
+        "Hand a self-contained sub-question to a more specialized agent and get its answer back."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "agent": { "type": "string", "enum": self.delegates },
+                "prompt": { "type": "string", "description": "Everything the agent needs to answer" },
+                "user_id": { "type": "string" }
+            },
+            "required": ["agent", "prompt"]
+        })
+    }
+
+    async fn execute(&self, params: Value) -> Result<Value> {
+        if self.depth >= self.max_depth {
+            return Err(ButterflyBotError::Runtime(format!(
+                "Delegation depth limit ({}) reached",
+                self.max_depth
+            )));
+        }
+        let name = params
+            .get("agent")
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .ok_or_else(|| ButterflyBotError::Runtime("Missing agent".to_string()))?;
+        let prompt = params
+            .get("prompt")
+            .and_then(|value| value.as_str())
+            .filter(|prompt| !prompt.trim().is_empty())
+            .ok_or_else(|| ButterflyBotError::Runtime("Missing prompt".to_string()))?;
+        let user_id = params
+            .get("user_id")
+            .and_then(|value| value.as_str())
+            .unwrap_or("unknown");
+        if !self.delegates.iter().any(|delegate| delegate == name) {
+            return Err(ButterflyBotError::Runtime(format!(
+                "'{}' is not a delegate of this agent",
+                name
+            )));
+        }
+        let agent = self
+            .agents
+            .iter()
+            .find(|agent| agent.name == name)
+            .ok_or_else(|| ButterflyBotError::Runtime(format!("Agent '{}' not found", name)))?;
+
+        let delegate = self.build_delegate(agent).await;
+        let response = delegate
+            .generate_response(user_id, prompt, "", None)
+            .await?;
+        Ok(json!({ "agent": name, "response": response }))
+    }
+}
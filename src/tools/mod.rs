@@ -1,9 +1,11 @@
 pub mod http_call;
 pub mod github;
 pub mod coding;
+pub mod delegate;
 pub mod mcp;
 pub mod planning;
 pub mod reminders;
+pub mod sandbox;
 pub mod search_internet;
 pub mod tasks;
 pub mod todo;
@@ -58,13 +58,32 @@ impl Tool for PlanningTool {
             "properties": {
                 "action": {
                     "type": "string",
-                    "enum": ["create", "list", "get", "update", "delete"]
+                    "enum": ["create", "list", "get", "update", "delete", "ready"]
                 },
                 "user_id": { "type": "string" },
                 "id": { "type": "integer" },
                 "title": { "type": "string" },
                 "goal": { "type": "string" },
-                "steps": { "type": "array", "items": { "type": "string" } },
+                "steps": {
+                    "type": "array",
+                    "items": {
+                        "anyOf": [
+                            { "type": "string" },
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "title": { "type": "string" },
+                                    "status": { "type": "string" },
+                                    "depends_on": {
+                                        "type": "array",
+                                        "items": { "type": "integer", "minimum": 0 }
+                                    }
+                                },
+                                "required": ["title"]
+                            }
+                        ]
+                    }
+                },
                 "status": { "type": "string" },
                 "limit": { "type": "integer" }
             },
@@ -139,6 +158,15 @@ impl Tool for PlanningTool {
                 let plan = store.update_plan(id, title, goal, steps, status).await?;
                 Ok(json!({"status": "ok", "plan": plan}))
             }
+            "ready" => {
+                let id = params
+                    .get("id")
+                    .and_then(|v| v.as_i64())
+                    .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
+                    as i32;
+                let steps = store.ready_steps(id).await?;
+                Ok(json!({"status": "ok", "steps": steps}))
+            }
             "delete" => {
                 let id = params
                     .get("id")
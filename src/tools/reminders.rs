@@ -7,12 +7,18 @@ use tokio::sync::RwLock;
 use crate::error::{ButterflyBotError, Result};
 use crate::interfaces::plugins::Tool;
 use crate::reminders::{
-    default_reminder_db_path, resolve_reminder_db_path, ReminderStatus, ReminderStore,
+    default_reminder_db_path, resolve_dedupe_window, resolve_reminder_db_path, ReminderStatus,
+    ReminderStore,
 };
+use crate::services::links::{LinkService, LinkedId};
+use crate::todo::TodoStore;
 
 pub struct RemindersTool {
     sqlite_path: RwLock<Option<String>>,
+    dedupe_window: RwLock<Option<i64>>,
     store: RwLock<Option<std::sync::Arc<ReminderStore>>>,
+    todo_path: Option<String>,
+    links: RwLock<Option<std::sync::Arc<LinkService>>>,
 }
 
 impl Default for RemindersTool {
@@ -25,10 +31,34 @@ impl RemindersTool {
     pub fn new() -> Self {
         Self {
             sqlite_path: RwLock::new(None),
+            dedupe_window: RwLock::new(None),
             store: RwLock::new(None),
+            todo_path: None,
+            links: RwLock::new(None),
         }
     }
 
+    /// Where linked todos live. With it, completing a reminder also
+    /// completes its linked todo.
+    pub fn with_todo_path(mut self, path: impl Into<String>) -> Self {
+        self.todo_path = Some(path.into());
+        self
+    }
+
+    async fn get_links(&self) -> Result<Option<std::sync::Arc<LinkService>>> {
+        let Some(todo_path) = &self.todo_path else {
+            return Ok(None);
+        };
+        if let Some(links) = self.links.read().await.as_ref() {
+            return Ok(Some(links.clone()));
+        }
+        let todos = std::sync::Arc::new(TodoStore::new(todo_path).await?);
+        let links = std::sync::Arc::new(LinkService::new(self.get_store().await?, todos));
+        let mut guard = self.links.write().await;
+        *guard = Some(links.clone());
+        Ok(Some(links))
+    }
+
     async fn get_store(&self) -> Result<std::sync::Arc<ReminderStore>> {
         if let Some(store) = self.store.read().await.as_ref() {
             return Ok(store.clone());
@@ -39,7 +69,12 @@ impl RemindersTool {
             .await
             .clone()
             .unwrap_or_else(default_reminder_db_path);
-        let store = std::sync::Arc::new(ReminderStore::new(path).await?);
+        let dedupe_window = *self.dedupe_window.read().await;
+        let store = std::sync::Arc::new(
+            ReminderStore::new(path)
+                .await?
+                .with_dedupe_window(dedupe_window),
+        );
         let mut guard = self.store.write().await;
         *guard = Some(store.clone());
         Ok(store)
@@ -105,6 +140,11 @@ impl Tool for RemindersTool {
         })
     }
 
+    fn config_sections(&self) -> Vec<&'static str> {
+        // The store defaults to `memory.sqlite_path`.
+        vec!["memory"]
+    }
+
     fn configure(&self, config: &Value) -> Result<()> {
         let path = resolve_reminder_db_path(config);
         let mut guard = self
@@ -112,6 +152,11 @@ impl Tool for RemindersTool {
             .try_write()
             .map_err(|_| ButterflyBotError::Runtime("Reminders tool lock busy".to_string()))?;
         *guard = path;
+        let mut guard = self
+            .dedupe_window
+            .try_write()
+            .map_err(|_| ButterflyBotError::Runtime("Reminders tool lock busy".to_string()))?;
+        *guard = resolve_dedupe_window(config);
         Ok(())
     }
 
@@ -171,7 +216,18 @@ impl Tool for RemindersTool {
                     .and_then(|v| v.as_i64())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
                     as i32;
-                let updated = store.complete_reminder(user_id, id).await?;
+                let links = match store.get_reminder(user_id, id).await? {
+                    Some(_) => self.get_links().await?,
+                    None => None,
+                };
+                let updated = match links {
+                    Some(links) => links
+                        .complete_linked(user_id, LinkedId::Reminder(id))
+                        .await?
+                        .reminder_id
+                        .is_some(),
+                    None => store.complete_reminder(user_id, id).await?,
+                };
                 Ok(json!({"status": "ok", "completed": updated}))
             }
             "delete" => {
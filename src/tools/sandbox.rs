@@ -0,0 +1,127 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::error::{ButterflyBotError, Result};
+
+/// Confines a tool's file access to one directory. Every path is resolved
+/// against the canonical root, with symlinks followed, and rejected if it
+/// lands outside it.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// The root must already exist.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let root = root.canonicalize().map_err(|e| {
+            ButterflyBotError::Config(format!(
+                "sandbox_root '{}' is not usable: {e}",
+                root.display()
+            ))
+        })?;
+        if !root.is_dir() {
+            return Err(ButterflyBotError::Config(format!(
+                "sandbox_root '{}' is not a directory",
+                root.display()
+            )));
+        }
+        Ok(Self { root })
+    }
+
+    /// `tools.settings.sandbox_root`, or `None` when it isn't set.
+    pub fn from_config(config: &Value) -> Result<Option<Self>> {
+        config
+            .get("tools")
+            .and_then(|tools| tools.get("settings"))
+            .and_then(|settings| settings.get("sandbox_root"))
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .filter(|root| !root.is_empty())
+            .map(Self::new)
+            .transpose()
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The absolute path `path` refers to, which need not exist yet. Relative
+    /// paths are taken from the root.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let requested = path.as_ref();
+        let joined = self.root.join(requested);
+
+        // Canonicalize the deepest part that exists; the rest is appended as
+        // written, so it must not step back up. `symlink_metadata` doesn't
+        // follow links, so a dangling one counts as existing and is refused
+        // below rather than written through.
+        let mut existing = joined.as_path();
+        let mut rest = Vec::new();
+        while existing.symlink_metadata().is_err() {
+            let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                return Err(self.escape_error(requested));
+            };
+            rest.push(name.to_owned());
+            existing = parent;
+        }
+        let mut resolved = existing.canonicalize().map_err(|e| {
+            if existing.is_symlink() {
+                ButterflyBotError::Runtime(format!(
+                    "Path '{}' goes through a dangling symlink",
+                    requested.display()
+                ))
+            } else {
+                ButterflyBotError::Runtime(e.to_string())
+            }
+        })?;
+        for name in rest.into_iter().rev() {
+            match Path::new(&name).components().next() {
+                Some(Component::Normal(_)) => resolved.push(name),
+                Some(Component::CurDir) => {}
+                _ => return Err(self.escape_error(requested)),
+            }
+        }
+
+        if !resolved.starts_with(&self.root) {
+            return Err(self.escape_error(requested));
+        }
+        Ok(resolved)
+    }
+
+    pub async fn read_to_string(&self, path: impl AsRef<Path>) -> Result<String> {
+        let path = self.resolve(path)?;
+        tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(format!("{}: {e}", path.display())))
+    }
+
+    /// Writes `contents`, creating missing parent directories inside the root.
+    pub async fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+        let requested = path.as_ref();
+        let path = self.resolve(requested)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+        }
+        // The tree may have changed since `resolve`; check again what the
+        // write will actually go through.
+        if self.resolve(requested)? != path {
+            return Err(self.escape_error(requested));
+        }
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| ButterflyBotError::Runtime(format!("{}: {e}", path.display())))
+    }
+
+    fn escape_error(&self, requested: &Path) -> ButterflyBotError {
+        ButterflyBotError::Runtime(format!(
+            "Path '{}' is outside the sandbox root '{}'",
+            requested.display(),
+            self.root.display()
+        ))
+    }
+}
@@ -3,12 +3,207 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use crate::error::Result;
 use crate::interfaces::plugins::{Tool, ToolSecret};
 use crate::vault;
 
+/// A search answer with the sources it cites, whichever provider answered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchResult {
+    pub answer: String,
+    pub citations: Vec<Citation>,
+}
+
+/// One cited source. `title` and `snippet` are empty when the provider
+/// doesn't give them; the snippet is the answer text the source backs, or the
+/// provider's own excerpt.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Citation {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+impl SearchResult {
+    /// A chat completion from an OpenAI search model, with `url_citation`
+    /// annotations on the message.
+    pub fn from_openai(data: &Value) -> Self {
+        let message = data
+            .get("choices")
+            .and_then(|v| v.get(0))
+            .and_then(|v| v.get("message"));
+        let answer = message
+            .and_then(|v| v.get("content"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let mut citations = Vec::new();
+        if let Some(annotations) = message
+            .and_then(|v| v.get("annotations"))
+            .and_then(|v| v.as_array())
+        {
+            for annotation in annotations {
+                if let Some(citation) = url_citation(annotation, &answer) {
+                    push_unique(&mut citations, citation);
+                }
+            }
+        }
+        Self { answer, citations }
+    }
+
+    /// A Perplexity chat completion. Sources come from `search_results` when
+    /// present, else from the bare `citations` list; a trailing `Sources:`
+    /// block in the text is dropped.
+    pub fn from_perplexity(data: &Value) -> Self {
+        let content = data
+            .get("choices")
+            .and_then(|v| v.get(0))
+            .and_then(|v| v.get("message"))
+            .and_then(|v| v.get("content"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let answer = content
+            .split("Sources:")
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_string();
+
+        let mut citations = Vec::new();
+        let search_results = data.get("search_results").and_then(|v| v.as_array());
+        let sources = search_results
+            .filter(|items| !items.is_empty())
+            .or_else(|| data.get("citations").and_then(|v| v.as_array()));
+        for item in sources.into_iter().flatten() {
+            let citation = match item.as_str() {
+                Some(url) => Citation {
+                    url: url.to_string(),
+                    ..Citation::default()
+                },
+                None => Citation {
+                    title: string_field(item, "title"),
+                    url: string_field(item, "url"),
+                    snippet: string_field(item, "snippet"),
+                },
+            };
+            push_unique(&mut citations, citation);
+        }
+        Self { answer, citations }
+    }
+
+    /// An xAI Responses API result: the first `output_text` of the first
+    /// message, with its `url_citation` annotations.
+    pub fn from_grok(data: &Value) -> Self {
+        let part = data
+            .get("output")
+            .and_then(|v| v.as_array())
+            .and_then(|items| {
+                items
+                    .iter()
+                    .find(|item| item.get("type").and_then(|v| v.as_str()) == Some("message"))
+            })
+            .and_then(|message| message.get("content"))
+            .and_then(|v| v.as_array())
+            .and_then(|parts| {
+                parts
+                    .iter()
+                    .find(|part| part.get("type").and_then(|v| v.as_str()) == Some("output_text"))
+            });
+        let answer = part
+            .and_then(|part| part.get("text"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let mut citations = Vec::new();
+        if let Some(annotations) = part
+            .and_then(|part| part.get("annotations"))
+            .and_then(|v| v.as_array())
+        {
+            for annotation in annotations {
+                if let Some(citation) = url_citation(annotation, &answer) {
+                    push_unique(&mut citations, citation);
+                }
+            }
+        }
+        Self { answer, citations }
+    }
+
+    /// The answer followed by a numbered source list, for showing to a user.
+    pub fn render(&self) -> String {
+        let mut out = self.answer.clone();
+        if self.citations.is_empty() {
+            return out;
+        }
+        out.push_str("\n\n**Sources:**");
+        for (idx, citation) in self.citations.iter().enumerate() {
+            if citation.title.is_empty() {
+                out.push_str(&format!("\n[{}] {}", idx + 1, citation.url));
+            } else {
+                out.push_str(&format!(
+                    "\n[{}] {} - {}",
+                    idx + 1,
+                    citation.title,
+                    citation.url
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// A `url_citation` annotation, either flat (xAI) or nested under
+/// `url_citation` (OpenAI). Its index range into `text` becomes the snippet.
+fn url_citation(annotation: &Value, text: &str) -> Option<Citation> {
+    if annotation.get("type").and_then(|v| v.as_str()) != Some("url_citation") {
+        return None;
+    }
+    let fields = annotation.get("url_citation").unwrap_or(annotation);
+    let url = string_field(fields, "url");
+    if url.is_empty() {
+        return None;
+    }
+    let index = |key: &str| {
+        fields
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .map(|value| value as usize)
+    };
+    let snippet = match (index("start_index"), index("end_index")) {
+        (Some(start), Some(end)) if start < end => text
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>()
+            .trim()
+            .to_string(),
+        _ => String::new(),
+    };
+    Some(Citation {
+        title: string_field(fields, "title"),
+        url,
+        snippet,
+    })
+}
+
+fn string_field(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn push_unique(citations: &mut Vec<Citation>, citation: Citation) {
+    if !citation.url.is_empty() && !citations.iter().any(|c| c.url == citation.url) {
+        citations.push(citation);
+    }
+}
+
 #[derive(Debug, Clone)]
 struct SearchInternetState {
     api_key: Option<String>,
@@ -123,18 +318,16 @@ impl SearchInternetTool {
             .filter(|v| !v.trim().is_empty())
     }
 
-    fn format_sources(label: &str, sources: &[String]) -> String {
-        if sources.is_empty() {
-            return String::new();
-        }
-        let mut out = String::new();
-        out.push_str("\n\n");
-        out.push_str(label);
-        out.push('\n');
-        for (idx, url) in sources.iter().enumerate() {
-            out.push_str(&format!("[{}] {}\n", idx + 1, url));
+    fn success_value(mut result: SearchResult, state: &SearchInternetState) -> Value {
+        if !state.citations {
+            result.citations.clear();
         }
-        out.trim_end().to_string()
+        json!({
+            "status": "success",
+            "answer": result.answer,
+            "citations": result.citations,
+            "model_used": state.model,
+        })
     }
 
     async fn search_openai(&self, query: &str, state: &SearchInternetState) -> Result<Value> {
@@ -194,20 +387,7 @@ impl SearchInternetTool {
         }
 
         let data: Value = response.json().await.unwrap_or(Value::Null);
-        let content = data
-            .get("choices")
-            .and_then(|v| v.get(0))
-            .and_then(|v| v.get("message"))
-            .and_then(|v| v.get("content"))
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
-
-        Ok(json!({
-            "status": "success",
-            "result": content,
-            "model_used": state.model,
-        }))
+        Ok(Self::success_value(SearchResult::from_openai(&data), state))
     }
 
     async fn search_perplexity(&self, query: &str, state: &SearchInternetState) -> Result<Value> {
@@ -271,45 +451,10 @@ impl SearchInternetTool {
         }
 
         let data: Value = response.json().await.unwrap_or(Value::Null);
-        let mut content = data
-            .get("choices")
-            .and_then(|v| v.get(0))
-            .and_then(|v| v.get("message"))
-            .and_then(|v| v.get("content"))
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
-
-        if state.citations {
-            if let Some(split) = content.split("Sources:").next() {
-                content = split.trim().to_string();
-            }
-            let citations: Vec<String> = data
-                .get("citations")
-                .and_then(|v| v.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|item| {
-                            if let Some(url) = item.as_str() {
-                                Some(url.to_string())
-                            } else {
-                                item.get("url")
-                                    .and_then(|u| u.as_str())
-                                    .map(|u| u.to_string())
-                            }
-                        })
-                        .collect()
-                })
-                .unwrap_or_default();
-            let sources = Self::format_sources("**Sources:**", &citations);
-            content.push_str(&sources);
-        }
-
-        Ok(json!({
-            "status": "success",
-            "result": content,
-            "model_used": state.model,
-        }))
+        Ok(Self::success_value(
+            SearchResult::from_perplexity(&data),
+            state,
+        ))
     }
 
     async fn search_grok(&self, query: &str, state: &SearchInternetState) -> Result<Value> {
@@ -388,53 +533,7 @@ impl SearchInternetTool {
         }
 
         let data: Value = response.json().await.unwrap_or(Value::Null);
-        let mut content = String::new();
-        let mut sources = Vec::new();
-        if let Some(items) = data.get("output").and_then(|v| v.as_array()) {
-            for item in items {
-                if item.get("type").and_then(|v| v.as_str()) == Some("message") {
-                    if let Some(parts) = item.get("content").and_then(|v| v.as_array()) {
-                        for part in parts {
-                            if part.get("type").and_then(|v| v.as_str()) == Some("output_text") {
-                                if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-                                    content = text.to_string();
-                                }
-                                if let Some(annotations) =
-                                    part.get("annotations").and_then(|v| v.as_array())
-                                {
-                                    for annotation in annotations {
-                                        if annotation.get("type").and_then(|v| v.as_str())
-                                            == Some("url_citation")
-                                        {
-                                            if let Some(url) =
-                                                annotation.get("url").and_then(|v| v.as_str())
-                                            {
-                                                if !sources.contains(&url.to_string()) {
-                                                    sources.push(url.to_string());
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                break;
-                            }
-                        }
-                    }
-                    break;
-                }
-            }
-        }
-
-        if state.citations {
-            let formatted = Self::format_sources("**Sources:**", &sources);
-            content.push_str(&formatted);
-        }
-
-        Ok(json!({
-            "status": "success",
-            "result": content,
-            "model_used": state.model,
-        }))
+        Ok(Self::success_value(SearchResult::from_grok(&data), state))
     }
 }
 
@@ -489,6 +588,11 @@ impl Tool for SearchInternetTool {
         }
     }
 
+    fn config_sections(&self) -> Vec<&'static str> {
+        // `openai.api_key` is the last fallback for the API key.
+        vec!["openai"]
+    }
+
     fn configure(&self, config: &Value) -> Result<()> {
         let mut state = self.state.lock().map_err(|_| {
             crate::error::ButterflyBotError::Runtime("Failed to lock tool state".to_string())
@@ -128,7 +128,10 @@ impl Tool for TasksTool {
                     .and_then(|v| v.as_i64())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
                     as i32;
-                let task = store.set_enabled(id, false).await?;
+                let task = store
+                    .set_enabled(user_id, id, false)
+                    .await?
+                    .ok_or_else(|| ButterflyBotError::Runtime(format!("Task {id} not found")))?;
                 Ok(json!({"status": "ok", "task": task}))
             }
             "enable" => {
@@ -137,7 +140,10 @@ impl Tool for TasksTool {
                     .and_then(|v| v.as_i64())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
                     as i32;
-                let task = store.set_enabled(id, true).await?;
+                let task = store
+                    .set_enabled(user_id, id, true)
+                    .await?
+                    .ok_or_else(|| ButterflyBotError::Runtime(format!("Task {id} not found")))?;
                 Ok(json!({"status": "ok", "task": task}))
             }
             "delete" => {
@@ -146,7 +152,7 @@ impl Tool for TasksTool {
                     .and_then(|v| v.as_i64())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
                     as i32;
-                let deleted = store.delete_task(id).await?;
+                let deleted = store.delete_task(user_id, id).await?;
                 Ok(json!({"status": "ok", "deleted": deleted}))
             }
             _ => Err(ButterflyBotError::Runtime("Unsupported action".to_string())),
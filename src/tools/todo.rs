@@ -4,11 +4,17 @@ use tokio::sync::RwLock;
 
 use crate::error::{ButterflyBotError, Result};
 use crate::interfaces::plugins::Tool;
-use crate::todo::{default_todo_db_path, resolve_todo_db_path, TodoStatus, TodoStore};
+use crate::reminders::ReminderStore;
+use crate::services::links::{LinkService, LinkedId};
+use crate::todo::{
+    default_todo_db_path, resolve_todo_db_path, TodoRecurrence, TodoStatus, TodoStore,
+};
 
 pub struct TodoTool {
     sqlite_path: RwLock<Option<String>>,
     store: RwLock<Option<std::sync::Arc<TodoStore>>>,
+    reminder_path: Option<String>,
+    links: RwLock<Option<std::sync::Arc<LinkService>>>,
 }
 
 impl Default for TodoTool {
@@ -22,9 +28,32 @@ impl TodoTool {
         Self {
             sqlite_path: RwLock::new(None),
             store: RwLock::new(None),
+            reminder_path: None,
+            links: RwLock::new(None),
         }
     }
 
+    /// Where linked reminders live. With it, completing a todo also
+    /// completes its linked reminder.
+    pub fn with_reminder_path(mut self, path: impl Into<String>) -> Self {
+        self.reminder_path = Some(path.into());
+        self
+    }
+
+    async fn get_links(&self) -> Result<Option<std::sync::Arc<LinkService>>> {
+        let Some(reminder_path) = &self.reminder_path else {
+            return Ok(None);
+        };
+        if let Some(links) = self.links.read().await.as_ref() {
+            return Ok(Some(links.clone()));
+        }
+        let reminders = std::sync::Arc::new(ReminderStore::new(reminder_path).await?);
+        let links = std::sync::Arc::new(LinkService::new(reminders, self.get_store().await?));
+        let mut guard = self.links.write().await;
+        *guard = Some(links.clone());
+        Ok(Some(links))
+    }
+
     async fn get_store(&self) -> Result<std::sync::Arc<TodoStore>> {
         if let Some(store) = self.store.read().await.as_ref() {
             return Ok(store.clone());
@@ -49,7 +78,7 @@ impl Tool for TodoTool {
     }
 
     fn description(&self) -> &str {
-        "Manage an ordered todo list (create, list, reorder, complete, delete)."
+        "Manage an ordered todo list (create, list, reorder, complete, delete). Todos can repeat daily, weekly or every N days."
     }
 
     fn parameters(&self) -> Value {
@@ -63,6 +92,11 @@ impl Tool for TodoTool {
                 "user_id": { "type": "string" },
                 "title": { "type": "string" },
                 "notes": { "type": "string" },
+                "due_at": { "type": "integer", "description": "Unix timestamp (seconds)" },
+                "recurrence": {
+                    "type": "string",
+                    "description": "daily, weekly or every N days; completing the todo opens the next one"
+                },
                 "items": {
                     "type": "array",
                     "items": {
@@ -75,7 +109,11 @@ impl Tool for TodoTool {
                 "status": { "type": "string", "enum": ["open", "completed", "all"] },
                 "limit": { "type": "integer" },
                 "id": { "type": "integer" },
-                "ordered_ids": { "type": "array", "items": { "type": "integer" } }
+                "ordered_ids": {
+                    "type": "array",
+                    "items": { "type": "integer" },
+                    "description": "Every todo id of the user, completed ones included, in the new order"
+                }
             },
             "required": ["action", "user_id"]
         })
@@ -119,7 +157,15 @@ impl Tool for TodoTool {
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing title".to_string()))?;
                 let notes = params.get("notes").and_then(|v| v.as_str());
-                let item = store.create_item(user_id, title, notes).await?;
+                let due_at = params.get("due_at").and_then(|v| v.as_i64());
+                let recurrence = params
+                    .get("recurrence")
+                    .and_then(|v| v.as_str())
+                    .map(TodoRecurrence::parse)
+                    .transpose()?;
+                let item = store
+                    .create_scheduled_item(user_id, title, notes, due_at, recurrence)
+                    .await?;
                 Ok(json!({"status": "ok", "item": item}))
             }
             "create_many" => {
@@ -166,7 +212,13 @@ impl Tool for TodoTool {
                     .and_then(|v| v.as_i64())
                     .ok_or_else(|| ButterflyBotError::Runtime("Missing id".to_string()))?
                     as i32;
-                let item = store.set_completed(id, true).await?;
+                let item = match self.get_links().await? {
+                    Some(links) => {
+                        links.complete_linked(user_id, LinkedId::Todo(id)).await?;
+                        store.get_item(id).await?
+                    }
+                    None => Some(store.set_completed(id, true).await?),
+                };
                 Ok(json!({"status": "ok", "item": item}))
             }
             "reopen" => {
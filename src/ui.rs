@@ -10,15 +10,18 @@ use dioxus::prelude::*;
 use futures::StreamExt;
 use notify_rust::Notification;
 use pulldown_cmark::{html, Options, Parser};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::env;
+use std::sync::OnceLock;
 use std::thread;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::ThemeSet;
 use syntect::html::styled_line_to_highlighted_html;
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
 use tokio::fs;
 use tokio::time::{sleep, timeout, Duration};
 
@@ -29,11 +32,24 @@ struct ProcessTextRequest {
     prompt: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct HistoryResponse {
+    messages: Vec<HistoryMessage>,
+}
+
+#[derive(Deserialize)]
+struct HistoryMessage {
+    role: String,
+    content: String,
+    timestamp: i64,
+}
+
 #[derive(Clone)]
 struct ChatMessage {
     id: u64,
     role: MessageRole,
     text: String,
+    sent_at: OffsetDateTime,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -72,6 +88,32 @@ async fn load_markdown_source(source: &str) -> Result<String, String> {
         .map_err(|err| err.to_string())
 }
 
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Reads the local UTC offset once. On Unix `time` refuses to read it once the
+/// process has more than one thread, so call this before starting a runtime;
+/// later calls keep the first result.
+pub fn capture_local_offset() -> UtcOffset {
+    *LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC))
+}
+
+/// The current time in the offset captured by [`capture_local_offset`].
+fn now_timestamp() -> OffsetDateTime {
+    OffsetDateTime::now_utc().to_offset(capture_local_offset())
+}
+
+/// `HH:MM` for a message from `now`'s day, with the date in front otherwise.
+/// `sent_at` is shown in `now`'s offset.
+fn format_message_time(sent_at: OffsetDateTime, now: OffsetDateTime) -> String {
+    let sent_at = sent_at.to_offset(now.offset());
+    let format = if sent_at.date() == now.date() {
+        format_description!("[hour]:[minute]")
+    } else {
+        format_description!("[year]-[month]-[day] [hour]:[minute]")
+    };
+    sent_at.format(format).unwrap_or_default()
+}
+
 fn markdown_to_html(input: &str) -> String {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
@@ -128,6 +170,7 @@ fn highlight_json_html(input: &str) -> String {
 }
 
 pub fn launch_ui() {
+    capture_local_offset();
     force_dbusrs();
     start_local_daemon();
     launch(app_view);
@@ -160,8 +203,7 @@ fn start_local_daemon() {
     let daemon_url =
         env::var("BUTTERFLY_BOT_DAEMON").unwrap_or_else(|_| "http://127.0.0.1:7878".to_string());
     let (host, port) = parse_daemon_address(&daemon_url);
-    let db_path =
-        env::var("BUTTERFLY_BOT_DB").unwrap_or_else(|_| "./data/butterfly-bot.db".to_string());
+    let db_path = env::var("BUTTERFLY_BOT_DB").unwrap_or_else(|_| crate::paths::default_db_path());
     let token = env::var("BUTTERFLY_BOT_TOKEN").unwrap_or_default();
 
     thread::spawn(move || {
@@ -190,8 +232,7 @@ fn parse_daemon_address(daemon: &str) -> (String, u16) {
 }
 
 fn app_view() -> Element {
-    let db_path =
-        env::var("BUTTERFLY_BOT_DB").unwrap_or_else(|_| "./data/butterfly-bot.db".to_string());
+    let db_path = env::var("BUTTERFLY_BOT_DB").unwrap_or_else(|_| crate::paths::default_db_path());
     let daemon_url = use_signal(|| {
         env::var("BUTTERFLY_BOT_DAEMON").unwrap_or_else(|_| "http://127.0.0.1:7878".to_string())
     });
@@ -206,6 +247,7 @@ fn app_view() -> Element {
     let next_id = use_signal(|| 1u64);
     let active_tab = use_signal(|| UiTab::Chat);
     let reminders_listening = use_signal(|| false);
+    let history_loaded = use_signal(|| false);
     let ui_events_listening = use_signal(|| false);
 
     let tools_loaded = use_signal(|| false);
@@ -231,6 +273,23 @@ fn app_view() -> Element {
     let search_default_deny = use_signal(|| false);
     let search_api_key_status = use_signal(String::new);
 
+    let model_label = use_signal({
+        let db_path = db_path.clone();
+        move || {
+            crate::config::Config::from_store(&db_path)
+                .ok()
+                .and_then(|config| config.model_label())
+                .unwrap_or_default()
+        }
+    });
+    let display_name = use_signal({
+        let db_path = db_path.clone();
+        move || {
+            crate::config::Config::from_store(&db_path)
+                .map(|config| config.display_name())
+                .unwrap_or_else(|_| crate::config::DEFAULT_DISPLAY_NAME.to_string())
+        }
+    });
     let reminders_sqlite_path = use_signal(String::new);
     let memory_enabled = use_signal(|| true);
 
@@ -291,11 +350,13 @@ fn app_view() -> Element {
                     id: user_message_id,
                     role: MessageRole::User,
                     text: text.clone(),
+                    sent_at: now_timestamp(),
                 });
                 messages.write().push(ChatMessage {
                     id: bot_message_id,
                     role: MessageRole::Bot,
                     text: String::new(),
+                    sent_at: now_timestamp(),
                 });
 
                 input.set(String::new());
@@ -348,6 +409,8 @@ fn app_view() -> Element {
                                 match chunk {
                                     Ok(bytes) => {
                                         if let Ok(text_chunk) = std::str::from_utf8(&bytes) {
+                                            let text_chunk = text_chunk
+                                                .replace(crate::daemon::STREAM_HEARTBEAT, "");
                                             if !text_chunk.is_empty() {
                                                 let mut list = messages.write();
                                                 if let Some(last) = list
@@ -355,7 +418,7 @@ fn app_view() -> Element {
                                                     .rev()
                                                     .find(|msg| msg.id == bot_message_id)
                                                 {
-                                                    last.text.push_str(text_chunk);
+                                                    last.text.push_str(&text_chunk);
                                                 }
                                             }
                                         }
@@ -406,6 +469,10 @@ fn app_view() -> Element {
                                             Ok(bytes) => {
                                                 if let Ok(text_chunk) = std::str::from_utf8(&bytes)
                                                 {
+                                                    let text_chunk = text_chunk.replace(
+                                                        crate::daemon::STREAM_HEARTBEAT,
+                                                        "",
+                                                    );
                                                     if !text_chunk.is_empty() {
                                                         let mut list = messages.write();
                                                         if let Some(last) = list
@@ -413,7 +480,7 @@ fn app_view() -> Element {
                                                             .rev()
                                                             .find(|msg| msg.id == bot_message_id)
                                                         {
-                                                            last.text.push_str(text_chunk);
+                                                            last.text.push_str(&text_chunk);
                                                         }
                                                     }
                                                 }
@@ -518,12 +585,23 @@ fn app_view() -> Element {
                                         .get("title")
                                         .and_then(|v| v.as_str())
                                         .unwrap_or("Reminder");
+                                    let due = value
+                                        .get("due_at")
+                                        .and_then(|v| v.as_i64())
+                                        .map(|due_at| {
+                                            format!(
+                                                " (due {})",
+                                                crate::humanize::humanize_from_now(due_at)
+                                            )
+                                        })
+                                        .unwrap_or_default();
                                     let id = next_id();
                                     next_id.set(id + 1);
                                     messages.write().push(ChatMessage {
                                         id,
                                         role: MessageRole::Bot,
-                                        text: format!("⏰ {title}"),
+                                        text: format!("⏰ {title}{due}"),
+                                        sent_at: now_timestamp(),
                                     });
                                     scroll_chat_to_bottom().await;
                                     if let Err(err) = Notification::new()
@@ -543,6 +621,71 @@ fn app_view() -> Element {
         });
     }
 
+    if !*history_loaded.read() {
+        let history_loaded = history_loaded.clone();
+        let daemon_url = daemon_url.clone();
+        let token = token.clone();
+        let user_id = user_id.clone();
+        let messages = messages.clone();
+        let next_id = next_id.clone();
+
+        spawn(async move {
+            let mut history_loaded = history_loaded;
+            let mut messages = messages;
+            let mut next_id = next_id;
+
+            history_loaded.set(true);
+            let client = reqwest::Client::new();
+            // The daemon may still be starting, so give it a few tries.
+            for _ in 0..5 {
+                let url = format!(
+                    "{}/history?user_id={}",
+                    daemon_url().trim_end_matches('/'),
+                    user_id()
+                );
+                let mut request = client.get(&url);
+                let token_value = token();
+                if !token_value.trim().is_empty() {
+                    request = request.header("authorization", format!("Bearer {token_value}"));
+                }
+                let history = match request.send().await {
+                    Ok(response) if response.status().is_success() => {
+                        response.json::<HistoryResponse>().await.ok()
+                    }
+                    _ => None,
+                };
+                let Some(history) = history else {
+                    sleep(Duration::from_secs(2)).await;
+                    continue;
+                };
+
+                let restored: Vec<ChatMessage> = history
+                    .messages
+                    .into_iter()
+                    .map(|message| {
+                        let id = next_id();
+                        next_id.set(id + 1);
+                        ChatMessage {
+                            id,
+                            role: if message.role == "user" {
+                                MessageRole::User
+                            } else {
+                                MessageRole::Bot
+                            },
+                            text: message.content,
+                            sent_at: OffsetDateTime::from_unix_timestamp(message.timestamp)
+                                .unwrap_or_else(|_| now_timestamp()),
+                        }
+                    })
+                    .collect();
+                // Anything sent while this was loading stays after the restored turns.
+                messages.write().splice(0..0, restored);
+                scroll_chat_after_render().await;
+                break;
+            }
+        });
+    }
+
     if !*ui_events_listening.read() {
         let ui_events_listening = ui_events_listening.clone();
         let daemon_url = daemon_url.clone();
@@ -641,6 +784,7 @@ fn app_view() -> Element {
                                         id,
                                         role: MessageRole::Bot,
                                         text,
+                                        sent_at: now_timestamp(),
                                     });
                                     scroll_chat_to_bottom().await;
                                 }
@@ -1215,6 +1359,7 @@ fn app_view() -> Element {
             .nav button {{ background: rgba(255,255,255,0.08); }}
             .nav button.active {{ background: rgba(99,102,241,0.6); }}
             .title {{ font-size: 18px; font-weight: 700; letter-spacing: 0.2px; }}
+            .model-label {{ margin-left: 10px; font-size: 12px; font-weight: 500; opacity: 0.75; }}
             .chat {{ flex: 1; min-height: 0; overflow-y: auto; padding: 20px; background: transparent; }}
             .bubble {{
                 max-width: 72%;
@@ -1231,6 +1376,7 @@ fn app_view() -> Element {
                 box-shadow: inset 0 1px 0 rgba(255,255,255,0.08), 0 10px 30px rgba(0,0,0,0.18);
             }}
             .bubble.user {{ margin-left: auto; background: rgba(99,102,241,0.55); color: white; border-bottom-right-radius: 6px; }}
+            .bubble .timestamp {{ margin-top: 4px; font-size: 11px; text-align: right; opacity: 0.7; }}
             .bubble.bot {{ margin-right: auto; background: rgba(124,58,237,0.45); color: white; border-bottom-left-radius: 6px; }}
             .composer {{
                 padding: 16px 20px;
@@ -1382,7 +1528,12 @@ fn app_view() -> Element {
         "# }
         div { class: "container",
             div { class: "header",
-                div { class: "title", "ButterFly Bot" }
+                div { class: "title",
+                    "ButterFly Bot"
+                    if !model_label.read().is_empty() {
+                        span { class: "model-label", "{model_label}" }
+                    }
+                }
                 div { class: "nav",
                     button {
                         class: if *active_tab.read() == UiTab::Chat { "active" } else { "" },
@@ -1434,11 +1585,14 @@ fn app_view() -> Element {
                             } else {
                                 "bubble bot"
                             },
-                            dangerous_inner_html: markdown_to_html(&message.text),
+                            div { dangerous_inner_html: markdown_to_html(&message.text) }
+                            div { class: "timestamp",
+                                "{format_message_time(message.sent_at, now_timestamp())}"
+                            }
                         }
                     }
                     if *busy.read() {
-                        div { class: "hint", "Bot is typing…" }
+                        div { class: "hint", "{display_name} is typing…" }
                     }
                 }
                 div { class: "composer",
@@ -1627,3 +1781,30 @@ fn app_view() -> Element {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn same_day_messages_show_only_the_time() {
+        let now = datetime!(2026-03-10 18:30 +02:00);
+        let sent_at = datetime!(2026-03-10 07:05 UTC);
+        assert_eq!(format_message_time(sent_at, now), "09:05");
+    }
+
+    #[test]
+    fn earlier_messages_include_the_date() {
+        let now = datetime!(2026-03-10 01:00 +02:00);
+        // 23:30 UTC on the 9th is already the 10th locally.
+        assert_eq!(
+            format_message_time(datetime!(2026-03-09 23:30 UTC), now),
+            "01:30"
+        );
+        assert_eq!(
+            format_message_time(datetime!(2026-03-09 21:30 UTC), now),
+            "2026-03-09 23:30"
+        );
+    }
+}
@@ -1,19 +1,172 @@
+use std::sync::Mutex;
+
 use crate::error::{ButterflyBotError, Result};
 
 const SERVICE: &str = "butterfly-bot";
 
+const INDEX_NAME: &str = "secret_names";
+
+/// Secrets the app itself stores. Entries written before the name index
+/// existed are only found by probing these.
+const KNOWN_SECRET_NAMES: &[&str] = &[
+    "app_config_json",
+    "coding_openai_api_key",
+    "db_encryption_key",
+    "db_key_salt",
+    "db_master_passphrase",
+    "github_pat",
+    "openai_api_key",
+    "search_internet_grok_api_key",
+    "search_internet_openai_api_key",
+    "search_internet_perplexity_api_key",
+];
+
+/// Serializes read-modify-write updates of the name index.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+pub const PROFILE_ENV: &str = "BUTTERFLY_BOT_PROFILE";
+
+pub fn service_for_profile(profile: Option<&str>) -> String {
+    match profile.map(str::trim) {
+        Some(profile) if !profile.is_empty() && profile != "default" => {
+            format!("{SERVICE}-{profile}")
+        }
+        _ => SERVICE.to_string(),
+    }
+}
+
+/// Profile names become part of a directory and a keyring service, so they
+/// are limited to ASCII letters, digits, `-` and `_`.
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ButterflyBotError::Config(format!(
+            "Invalid profile name '{}'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// The profile named by `BUTTERFLY_BOT_PROFILE`, or `None` for the default
+/// one. An invalid name is ignored rather than used in a path.
+pub fn active_profile() -> Option<String> {
+    let profile = std::env::var(PROFILE_ENV).ok()?;
+    let profile = profile.trim();
+    if profile.is_empty() || profile == "default" {
+        return None;
+    }
+    if validate_profile_name(profile).is_err() {
+        tracing::warn!(profile, "ignoring invalid {PROFILE_ENV}");
+        return None;
+    }
+    Some(profile.to_string())
+}
+
+/// Keyring service for the active profile (`BUTTERFLY_BOT_PROFILE`), so
+/// separate profiles on one machine keep separate secrets.
+pub fn service_name() -> String {
+    service_for_profile(active_profile().as_deref())
+}
+
+fn entry(name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(&service_name(), name)
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+}
+
 pub fn set_secret(name: &str, value: &str) -> Result<()> {
-    let entry = keyring::Entry::new(SERVICE, name)
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    entry
+    write_entry(name, value)?;
+    remember_name(name)
+}
+
+fn write_entry(name: &str, value: &str) -> Result<()> {
+    entry(name)?
         .set_password(value)
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    Ok(())
+        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))
+}
+
+/// Names of the stored secrets. The keychain can't be enumerated, so the
+/// names are tracked in an index entry of their own, plus the app's own
+/// secrets that were stored before the index existed.
+pub fn list_secret_names() -> Result<Vec<String>> {
+    let mut names = indexed_names()?;
+    for known in KNOWN_SECRET_NAMES {
+        if !names.iter().any(|name| name == known) && get_secret(known)?.is_some() {
+            names.push(known.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn indexed_names() -> Result<Vec<String>> {
+    let Some(raw) = get_secret(INDEX_NAME)? else {
+        return Ok(Vec::new());
+    };
+    serde_json::from_str(&raw).map_err(|e| ButterflyBotError::Serialization(e.to_string()))
+}
+
+/// Stores every entry of a JSON object of secret-name to value and returns
+/// the names that were set. Nothing is stored unless the whole file is valid.
+pub fn import_secrets_file(path: &str) -> Result<Vec<String>> {
+    let raw =
+        std::fs::read_to_string(path).map_err(|e| ButterflyBotError::Config(e.to_string()))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&raw).map_err(|e| ButterflyBotError::Config(e.to_string()))?;
+    let serde_json::Value::Object(map) = value else {
+        return Err(ButterflyBotError::Config(
+            "Secrets file must be a JSON object of name to value".to_string(),
+        ));
+    };
+    let mut secrets = Vec::with_capacity(map.len());
+    for (name, value) in map {
+        let Some(value) = value.as_str() else {
+            return Err(ButterflyBotError::Config(format!(
+                "Secret '{}' must be a string",
+                name
+            )));
+        };
+        if name.trim().is_empty() || name == INDEX_NAME {
+            return Err(ButterflyBotError::Config(format!(
+                "Invalid secret name '{}'",
+                name
+            )));
+        }
+        secrets.push((name, value.to_string()));
+    }
+    for (name, value) in &secrets {
+        set_secret(name, value)?;
+    }
+    Ok(secrets.into_iter().map(|(name, _)| name).collect())
+}
+
+/// Adds `name` to the index, seeding it with any known secrets on the way.
+/// Updates within this process are serialized so concurrent writes don't
+/// drop each other's names.
+fn remember_name(name: &str) -> Result<()> {
+    if name == INDEX_NAME {
+        return Ok(());
+    }
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    if indexed_names()?.iter().any(|existing| existing == name) {
+        return Ok(());
+    }
+    let mut names = list_secret_names()?;
+    if !names.iter().any(|existing| existing == name) {
+        names.push(name.to_string());
+        names.sort();
+    }
+    let raw = serde_json::to_string(&names)
+        .map_err(|e| ButterflyBotError::Serialization(e.to_string()))?;
+    write_entry(INDEX_NAME, &raw)
 }
 
 pub fn get_secret(name: &str) -> Result<Option<String>> {
-    let entry = keyring::Entry::new(SERVICE, name)
-        .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+    let entry = entry(name)?;
     match entry.get_password() {
         Ok(value) => Ok(Some(value)),
         Err(keyring::Error::NoEntry) => Ok(None),
@@ -1,5 +1,4 @@
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
 
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
@@ -10,7 +9,10 @@ use diesel_async::RunQueryDsl;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use serde::Serialize;
 
+use crate::clock::{Clock, SystemClock};
+use crate::db::retry_on_busy;
 use crate::error::{ButterflyBotError, Result};
+use crate::paths::ensure_parent_dir;
 
 mod schema;
 use schema::wakeup_tasks;
@@ -83,6 +85,7 @@ impl WakeupStatus {
 
 pub struct WakeupStore {
     pool: SqlitePool,
+    clock: Arc<dyn Clock>,
 }
 
 impl WakeupStore {
@@ -97,7 +100,19 @@ impl WakeupStore {
             .build(manager)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn now(&self) -> i64 {
+        self.clock.now()
     }
 
     pub async fn create_task(
@@ -107,34 +122,37 @@ impl WakeupStore {
         prompt: &str,
         interval_minutes: i64,
     ) -> Result<WakeupTask> {
-        let now = now_ts();
-        let next_run_at = now + interval_minutes.max(1) * 60;
-        let new = NewWakeup {
-            user_id,
-            name,
-            prompt,
-            interval_minutes: interval_minutes.max(1),
-            enabled: true,
-            created_at: now,
-            updated_at: now,
-            last_run_at: None,
-            next_run_at,
-        };
-
-        let mut conn = self.conn().await?;
-        diesel::insert_into(wakeup_tasks::table)
-            .values(&new)
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-
-        let row: WakeupRow = wakeup_tasks::table
-            .filter(wakeup_tasks::user_id.eq(user_id))
-            .order(wakeup_tasks::id.desc())
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let next_run_at = now + interval_minutes.max(1) * 60;
+            let new = NewWakeup {
+                user_id,
+                name,
+                prompt,
+                interval_minutes: interval_minutes.max(1),
+                enabled: true,
+                created_at: now,
+                updated_at: now,
+                last_run_at: None,
+                next_run_at,
+            };
+
+            let mut conn = self.conn().await?;
+            diesel::insert_into(wakeup_tasks::table)
+                .values(&new)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let row: WakeupRow = wakeup_tasks::table
+                .filter(wakeup_tasks::user_id.eq(user_id))
+                .order(wakeup_tasks::id.desc())
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn list_tasks(
@@ -168,32 +186,38 @@ impl WakeupStore {
     }
 
     pub async fn set_enabled(&self, id: i32, enabled: bool) -> Result<WakeupTask> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        diesel::update(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
-            .set((
-                wakeup_tasks::enabled.eq(enabled),
-                wakeup_tasks::updated_at.eq(now),
-            ))
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-
-        let row: WakeupRow = wakeup_tasks::table
-            .filter(wakeup_tasks::id.eq(id))
-            .first(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(map_row(row))
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            diesel::update(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
+                .set((
+                    wakeup_tasks::enabled.eq(enabled),
+                    wakeup_tasks::updated_at.eq(now),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+
+            let row: WakeupRow = wakeup_tasks::table
+                .filter(wakeup_tasks::id.eq(id))
+                .first(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(map_row(row))
+        })
+        .await
     }
 
     pub async fn delete_task(&self, id: i32) -> Result<bool> {
-        let mut conn = self.conn().await?;
-        let count = diesel::delete(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
-            .execute(&mut conn)
-            .await
-            .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(count > 0)
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let count = diesel::delete(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(count > 0)
+        })
+        .await
     }
 
     pub async fn list_due(&self, now: i64, limit: usize) -> Result<Vec<WakeupTask>> {
@@ -209,19 +233,41 @@ impl WakeupStore {
         Ok(rows.into_iter().map(map_row).collect())
     }
 
-    pub async fn mark_run(&self, id: i32, last_run_at: i64, next_run_at: i64) -> Result<()> {
-        let now = now_ts();
-        let mut conn = self.conn().await?;
-        diesel::update(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
-            .set((
-                wakeup_tasks::last_run_at.eq(Some(last_run_at)),
-                wakeup_tasks::next_run_at.eq(next_run_at),
-                wakeup_tasks::updated_at.eq(now),
-            ))
+    /// Same lease semantics as `TaskStore::claim`.
+    pub async fn claim(&self, id: i32, now: i64, lease_secs: i64) -> Result<bool> {
+        retry_on_busy(|| async move {
+            let mut conn = self.conn().await?;
+            let updated = diesel::update(
+                wakeup_tasks::table
+                    .filter(wakeup_tasks::id.eq(id))
+                    .filter(wakeup_tasks::enabled.eq(true))
+                    .filter(wakeup_tasks::next_run_at.le(now)),
+            )
+            .set(wakeup_tasks::next_run_at.eq(now + lease_secs.max(1)))
             .execute(&mut conn)
             .await
             .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-        Ok(())
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    pub async fn mark_run(&self, id: i32, last_run_at: i64, next_run_at: i64) -> Result<()> {
+        retry_on_busy(|| async move {
+            let now = self.clock.now();
+            let mut conn = self.conn().await?;
+            diesel::update(wakeup_tasks::table.filter(wakeup_tasks::id.eq(id)))
+                .set((
+                    wakeup_tasks::last_run_at.eq(Some(last_run_at)),
+                    wakeup_tasks::next_run_at.eq(next_run_at),
+                    wakeup_tasks::updated_at.eq(now),
+                ))
+                .execute(&mut conn)
+                .await
+                .map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
+            Ok(())
+        })
+        .await
     }
 
     async fn conn(&self) -> Result<SqlitePooledConn<'_>> {
@@ -246,15 +292,7 @@ pub fn resolve_wakeup_db_path(config: &serde_json::Value) -> Option<String> {
 }
 
 pub fn default_wakeup_db_path() -> String {
-    "./data/butterfly-bot.db".to_string()
-}
-
-fn ensure_parent_dir(path: &str) -> Result<()> {
-    let path = Path::new(path);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| ButterflyBotError::Runtime(e.to_string()))?;
-    }
-    Ok(())
+    crate::paths::default_db_path()
 }
 
 async fn run_migrations(database_url: &str) -> Result<()> {
@@ -316,10 +354,3 @@ fn map_row(row: WakeupRow) -> WakeupTask {
         next_run_at: row.next_run_at,
     }
 }
-
-fn now_ts() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64
-}
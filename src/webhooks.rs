@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{ButterflyBotError, Result};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_RETRIES: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub kind: String,
+    pub id: i32,
+    pub title: String,
+    pub user_id: String,
+    pub fired_at: i64,
+}
+
+/// Posts fired reminders and tasks to an external automation endpoint.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Reads `tools.settings.webhook_url` (plus optional `webhook_timeout_seconds` and
+    /// `webhook_retries`); returns `None` when no URL is configured.
+    pub fn from_config(config: &Value) -> Option<Self> {
+        let settings = config.get("tools")?.get("settings")?;
+        let url = settings
+            .get("webhook_url")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())?;
+        let mut notifier = Self::new(url);
+        if let Some(secs) = settings
+            .get("webhook_timeout_seconds")
+            .and_then(|v| v.as_u64())
+        {
+            notifier = notifier.with_timeout(Duration::from_secs(secs.max(1)));
+        }
+        if let Some(retries) = settings.get("webhook_retries").and_then(|v| v.as_u64()) {
+            notifier = notifier.with_retries(retries as u32);
+        }
+        Some(notifier)
+    }
+
+    pub async fn send(&self, payload: &WebhookPayload) -> Result<()> {
+        let mut last_error = String::new();
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_millis(250 * u64::from(attempt))).await;
+            }
+            let response = self
+                .client
+                .post(&self.url)
+                .timeout(self.timeout)
+                .json(payload)
+                .send()
+                .await;
+            match response {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => last_error = format!("webhook returned {}", response.status()),
+                Err(err) => last_error = err.to_string(),
+            }
+        }
+        Err(ButterflyBotError::Http(last_error))
+    }
+
+    /// Sends in the background so a slow or failing endpoint never holds up local delivery.
+    pub fn dispatch(self: &Arc<Self>, payload: WebhookPayload) {
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = notifier.send(&payload).await {
+                tracing::warn!(
+                    kind = %payload.kind,
+                    id = payload.id,
+                    error = %err,
+                    "webhook delivery failed"
+                );
+            }
+        });
+    }
+}
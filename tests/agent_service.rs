@@ -2,13 +2,24 @@ mod common;
 
 use std::sync::Arc;
 
+use futures::StreamExt;
 use serde_json::json;
 
 use butterfly_bot::brain::manager::BrainManager;
+use butterfly_bot::config::{AgentConfig, CostConfig};
+use butterfly_bot::costs::CostTracker;
 use butterfly_bot::domains::agent::AIAgent;
+use butterfly_bot::guardrails::tool_calls::BlockedPatternGuardrail;
 use butterfly_bot::interfaces::brain::{BrainContext, BrainEvent, BrainPlugin};
-use butterfly_bot::interfaces::providers::{ImageData, ImageInput, LlmResponse, ToolCall};
+use butterfly_bot::interfaces::plugins::Tool;
+use butterfly_bot::interfaces::providers::{
+    ChatEvent, ImageData, ImageInput, LlmResponse, ToolCall,
+};
+use butterfly_bot::plugins::registry::REDACTED;
+use butterfly_bot::plugins::result_format::ToolResultFormat;
 use butterfly_bot::services::agent::AgentService;
+use butterfly_bot::tool_audit::{hash_params, ToolAuditStore};
+use butterfly_bot::tools::delegate::{DelegateTool, DELEGATE_TOOL_NAME};
 
 use common::{DummyTool, QueueLlmProvider};
 use std::sync::Mutex;
@@ -21,18 +32,22 @@ async fn routing_and_agent_service() {
             text: "tool response".to_string(),
             tool_calls: vec![
                 ToolCall {
+                    id: String::new(),
                     name: "tool1".to_string(),
                     arguments: json!({"value": 1}),
                 },
                 ToolCall {
+                    id: String::new(),
                     name: "missing".to_string(),
                     arguments: json!({}),
                 },
             ],
+            usage: None,
         },
         LlmResponse {
             text: "done".to_string(),
             tool_calls: Vec::new(),
+            usage: None,
         },
     ]));
 
@@ -40,6 +55,7 @@ async fn routing_and_agent_service() {
         name: "agent1".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
 
     let service = AgentService::new(llm, agent, None, brain_manager, None);
@@ -115,9 +131,11 @@ async fn routing_and_agent_service() {
         responses.push(LlmResponse {
             text: format!("step {idx}"),
             tool_calls: vec![ToolCall {
+                id: String::new(),
                 name: "tool1".to_string(),
                 arguments: json!({"value": idx}),
             }],
+            usage: None,
         });
     }
 
@@ -127,6 +145,7 @@ async fn routing_and_agent_service() {
         name: "agent-loop".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
     let looping_service = AgentService::new(looping_llm, looping_agent, None, looping_brain, None);
     let registry = looping_service.tool_registry.clone();
@@ -142,7 +161,7 @@ async fn routing_and_agent_service() {
         .generate_response("u1", "query", "", None)
         .await
         .unwrap();
-    assert_eq!(response, "step 4");
+    assert_eq!(response, "mock text");
 }
 
 struct RecordingBrain {
@@ -192,6 +211,7 @@ async fn agent_service_dispatches_brain_events() {
         name: "agent".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
     let service = AgentService::new(llm, agent, None, brain, None);
 
@@ -224,6 +244,7 @@ async fn agent_service_brain_tick_dispatches() {
         name: "agent".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
     let service = AgentService::new(llm, agent, None, brain, None);
 
@@ -232,3 +253,1112 @@ async fn agent_service_brain_tick_dispatches() {
     let guard = events.lock().unwrap();
     assert_eq!(guard.as_slice(), ["tick"]);
 }
+
+struct QueryTool {
+    calls: Arc<Mutex<usize>>,
+}
+
+#[async_trait::async_trait]
+impl butterfly_bot::interfaces::plugins::Tool for QueryTool {
+    fn name(&self) -> &str {
+        "lookup"
+    }
+
+    fn description(&self) -> &str {
+        "lookup"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        *self.calls.lock().unwrap() += 1;
+        Ok(json!({"ok": true}))
+    }
+}
+
+#[tokio::test]
+async fn agent_service_rejects_invalid_tool_arguments() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![
+                ToolCall {
+                    id: String::new(),
+                    name: "lookup".to_string(),
+                    arguments: json!({}),
+                },
+                ToolCall {
+                    id: String::new(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": 42}),
+                },
+            ],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, Some(tx));
+    let calls = Arc::new(Mutex::new(0));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(QueryTool {
+                calls: calls.clone(),
+            }))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "lookup")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+    assert_eq!(*calls.lock().unwrap(), 0);
+
+    let missing = rx.try_recv().unwrap();
+    assert_eq!(missing.status, "invalid_arguments");
+    let errors = missing.payload["errors"].as_array().unwrap();
+    assert!(errors[0]["message"].as_str().unwrap().contains("query"));
+
+    let wrong_type = rx.try_recv().unwrap();
+    assert_eq!(wrong_type.status, "invalid_arguments");
+    assert_eq!(wrong_type.payload["errors"][0]["field"], "/query");
+}
+
+#[tokio::test]
+async fn guardrail_blocks_a_tool_call_before_it_runs() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![
+                ToolCall {
+                    id: "call_blocked".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": "how to pick a Lock"}),
+                },
+                ToolCall {
+                    id: "call_ok".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": "weather in Lisbon"}),
+                },
+            ],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, Some(tx));
+    let calls = Arc::new(Mutex::new(0));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(QueryTool {
+                calls: calls.clone(),
+            }))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "lookup")
+            .await
+    );
+    let guardrail = BlockedPatternGuardrail::from_config(&json!({
+        "tools": {"lookup": {"blocked_patterns": ["pick a lock"]}}
+    }))
+    .unwrap();
+    service.set_tool_guardrails(vec![Arc::new(guardrail)]).await;
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+    assert_eq!(*calls.lock().unwrap(), 1);
+
+    let statuses: Vec<String> = std::iter::from_fn(|| rx.try_recv().ok())
+        .map(|event| event.status)
+        .collect();
+    assert!(statuses.contains(&"blocked".to_string()));
+
+    let prompts = llm.prompts.lock().await;
+    let results: serde_json::Value = serde_json::from_str(
+        prompts
+            .last()
+            .unwrap()
+            .split("TOOL_RESULTS:\n")
+            .nth(1)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        results[0],
+        json!({
+            "id": "call_blocked",
+            "tool": "lookup",
+            "status": "blocked",
+            "reason": "Tool call blocked: argument '/query' matches blocked pattern 'pick a lock'",
+        })
+    );
+    assert_eq!(results[1]["status"], "success");
+}
+
+struct EchoTool;
+
+#[async_trait::async_trait]
+impl butterfly_bot::interfaces::plugins::Tool for EchoTool {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn description(&self) -> &str {
+        "echo"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {"value": {"type": "integer"}}})
+    }
+
+    async fn execute(&self, params: serde_json::Value) -> butterfly_bot::Result<serde_json::Value> {
+        Ok(json!({"value": params["value"]}))
+    }
+}
+
+#[tokio::test]
+async fn agent_service_correlates_parallel_tool_calls_by_id() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![
+                ToolCall {
+                    id: "call_a".to_string(),
+                    name: "echo".to_string(),
+                    arguments: json!({"value": 1}),
+                },
+                ToolCall {
+                    id: String::new(),
+                    name: "echo".to_string(),
+                    arguments: json!({"value": 2}),
+                },
+            ],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, Some(tx));
+    let registry = service.tool_registry.clone();
+    assert!(registry.register_tool(Arc::new(EchoTool)).await);
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "echo")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+
+    let first = rx.try_recv().unwrap();
+    assert_eq!(first.payload["id"], "call_a");
+    assert_eq!(first.payload["result"]["value"], 1);
+
+    let second = rx.try_recv().unwrap();
+    let generated =
+        butterfly_bot::interfaces::providers::stable_tool_call_id(1, "echo", &json!({"value": 2}));
+    assert_eq!(second.payload["id"], generated.as_str());
+    assert_eq!(second.payload["result"]["value"], 2);
+    assert_ne!(first.payload["id"], second.payload["id"]);
+}
+
+#[tokio::test]
+async fn rejected_tool_calls_are_written_to_the_audit_store() {
+    let call = |id: &str, name: &str, arguments: serde_json::Value| ToolCall {
+        id: id.to_string(),
+        name: name.to_string(),
+        arguments,
+    };
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![
+                call("call_invalid", "lookup", json!({})),
+                call("call_blocked", "lookup", json!({"query": "pick a lock"})),
+                call("call_unknown", "launch_rocket", json!({"target": "moon"})),
+            ],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(
+        ToolAuditStore::new(dir.path().join("audit.db").to_str().unwrap())
+            .await
+            .unwrap(),
+    );
+    registry.set_audit_store(Some(store.clone())).await;
+    let calls = Arc::new(Mutex::new(0));
+    assert!(
+        registry
+            .register_tool(Arc::new(QueryTool {
+                calls: calls.clone(),
+            }))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "lookup")
+            .await
+    );
+    let guardrail = BlockedPatternGuardrail::from_config(&json!({
+        "tools": {"lookup": {"blocked_patterns": ["pick a lock"]}}
+    }))
+    .unwrap();
+    service.set_tool_guardrails(vec![Arc::new(guardrail)]).await;
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+    assert_eq!(*calls.lock().unwrap(), 0);
+
+    let mut audited: Vec<(String, String)> = store
+        .list("u1", 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.tool, entry.status))
+        .collect();
+    audited.sort();
+    assert_eq!(
+        audited,
+        vec![
+            ("launch_rocket".to_string(), "unknown".to_string()),
+            ("lookup".to_string(), "blocked".to_string()),
+            ("lookup".to_string(), "invalid_arguments".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unknown_tool_calls_get_a_not_available_result() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_x".to_string(),
+                name: "launch_rocket".to_string(),
+                arguments: json!({"target": "moon"}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "I can't launch rockets, but echo works.".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, Some(tx));
+    let registry = service.tool_registry.clone();
+    assert!(registry.register_tool(Arc::new(EchoTool)).await);
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "echo")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "launch it", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "I can't launch rockets, but echo works.");
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.tool, "launch_rocket");
+    assert_eq!(event.status, "unknown");
+    assert_eq!(event.payload["id"], "call_x");
+
+    let prompts = llm.prompts.lock().await;
+    let results: serde_json::Value = serde_json::from_str(
+        prompts
+            .last()
+            .unwrap()
+            .split("TOOL_RESULTS:\n")
+            .nth(1)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        results,
+        json!([{
+            "id": "call_x",
+            "tool": "launch_rocket",
+            "status": "unknown",
+            "message": "Tool 'launch_rocket' is not available",
+            "available_tools": ["echo"],
+        }])
+    );
+}
+
+struct DelayTool {
+    name: &'static str,
+    delay_ms: u64,
+    finished: Arc<Mutex<Vec<&'static str>>>,
+}
+
+#[async_trait::async_trait]
+impl butterfly_bot::interfaces::plugins::Tool for DelayTool {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        "delay"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {}})
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        tokio::time::sleep(std::time::Duration::from_millis(self.delay_ms)).await;
+        self.finished.lock().unwrap().push(self.name);
+        Ok(json!({"tool": self.name}))
+    }
+}
+
+#[tokio::test]
+async fn concurrent_tool_results_keep_call_order() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![
+                ToolCall {
+                    id: "call_slow".to_string(),
+                    name: "slow".to_string(),
+                    arguments: json!({}),
+                },
+                ToolCall {
+                    id: "call_fast".to_string(),
+                    name: "fast".to_string(),
+                    arguments: json!({}),
+                },
+            ],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, None);
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let registry = service.tool_registry.clone();
+    for (name, delay_ms) in [("slow", 50), ("fast", 0)] {
+        assert!(
+            registry
+                .register_tool(Arc::new(DelayTool {
+                    name,
+                    delay_ms,
+                    finished: finished.clone(),
+                }))
+                .await
+        );
+        assert!(
+            registry
+                .assign_tool_to_agent(service.agent_name(), name)
+                .await
+        );
+    }
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+    assert_eq!(*finished.lock().unwrap(), vec!["fast", "slow"]);
+
+    let prompts = llm.prompts.lock().await;
+    let follow_up = &prompts[1];
+    let slow = follow_up.find("call_slow").unwrap();
+    let fast = follow_up.find("call_fast").unwrap();
+    assert!(slow < fast);
+}
+
+#[tokio::test]
+async fn response_events_bracket_tool_calls_with_markers() {
+    let responses = || {
+        vec![
+            LlmResponse {
+                text: "checking".to_string(),
+                tool_calls: vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "search_internet".to_string(),
+                    arguments: json!({}),
+                }],
+                usage: None,
+            },
+            LlmResponse {
+                text: "found it".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+            },
+        ]
+    };
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(
+        Arc::new(QueueLlmProvider::new(responses())),
+        agent.clone(),
+        None,
+        brain.clone(),
+        None,
+    );
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("search_internet")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "search_internet")
+            .await
+    );
+
+    let events: Vec<_> = service
+        .generate_response_events("u1", "query", "", None)
+        .map(|event| event.unwrap())
+        .collect()
+        .await;
+    let summary: Vec<(String, Option<String>, Option<String>)> = events
+        .into_iter()
+        .map(|event| match event {
+            ChatEvent::Content { delta } => ("content".to_string(), Some(delta), None),
+            ChatEvent::ToolStart { name, .. } => ("tool_start".to_string(), None, Some(name)),
+            ChatEvent::ToolEnd { name, .. } => ("tool_end".to_string(), None, Some(name)),
+            other => (other.event_type().to_string(), None, None),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("content".to_string(), Some("checking".to_string()), None),
+            (
+                "tool_start".to_string(),
+                None,
+                Some("search_internet".to_string())
+            ),
+            (
+                "tool_end".to_string(),
+                None,
+                Some("search_internet".to_string())
+            ),
+            ("content".to_string(), Some("found it".to_string()), None),
+        ]
+    );
+
+    let service = AgentService::new(
+        Arc::new(QueueLlmProvider::new(responses())),
+        agent,
+        None,
+        brain,
+        None,
+    );
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("search_internet")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "search_internet")
+            .await
+    );
+    let chunks: Vec<String> = service
+        .generate_response_stream("u1", "query", "", None)
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    assert_eq!(chunks, vec!["found it".to_string()]);
+}
+
+#[tokio::test]
+async fn tool_loop_stops_at_max_tool_iterations() {
+    let responses = (0..10)
+        .map(|idx| LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: format!("call_{idx}"),
+                name: "tool1".to_string(),
+                arguments: json!({}),
+            }],
+            usage: None,
+        })
+        .collect();
+    let llm = Arc::new(QueueLlmProvider::new(responses));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool1")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool1")
+            .await
+    );
+
+    service.set_max_tool_iterations(2).await;
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "mock text");
+    assert_eq!(llm.prompts.lock().await.len(), 2);
+
+    let response = service
+        .generate_response_with_tool_limit("u1", "query", "", None, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(response, "mock text");
+    assert_eq!(llm.prompts.lock().await.len(), 3);
+}
+
+#[tokio::test]
+async fn slow_tools_emit_heartbeat_events() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "slow".to_string(),
+                arguments: json!({}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, None);
+    service
+        .set_stream_heartbeat(Some(std::time::Duration::from_millis(100)))
+        .await;
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DelayTool {
+                name: "slow",
+                delay_ms: 450,
+                finished: Arc::new(Mutex::new(Vec::new())),
+            }))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "slow")
+            .await
+    );
+
+    let kinds: Vec<String> = service
+        .generate_response_events("u1", "query", "", None)
+        .map(|event| event.unwrap().event_type().to_string())
+        .collect()
+        .await;
+    let start = kinds.iter().position(|kind| kind == "tool_start").unwrap();
+    let end = kinds.iter().position(|kind| kind == "tool_end").unwrap();
+    let beats = kinds[start..end]
+        .iter()
+        .filter(|kind| *kind == "heartbeat")
+        .count();
+    assert!((3..=5).contains(&beats), "{kinds:?}");
+    assert_eq!(kinds.last().map(String::as_str), Some("content"));
+
+    service.set_stream_heartbeat(None).await;
+    assert_eq!(service.stream_heartbeat().await, None);
+}
+
+struct PageTool;
+
+#[async_trait::async_trait]
+impl butterfly_bot::interfaces::plugins::Tool for PageTool {
+    fn name(&self) -> &str {
+        "fetch_page"
+    }
+
+    fn description(&self) -> &str {
+        "returns a whole web page"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {}})
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        Ok(json!(format!("<html>{}</html>", "y".repeat(50_000))))
+    }
+}
+
+#[tokio::test]
+async fn oversized_tool_results_are_truncated_before_reprompting() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "fetch_page".to_string(),
+                arguments: json!({}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "summarized".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    registry
+        .configure_all_tools(json!({"tools": {"settings": {"max_tool_result_bytes": 1000}}}))
+        .await
+        .unwrap();
+    assert_eq!(registry.max_result_bytes().await, 1000);
+    assert!(registry.register_tool(Arc::new(PageTool)).await);
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "fetch_page")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "read the page", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "summarized");
+
+    let prompts = llm.prompts.lock().await;
+    let reprompt = prompts.last().unwrap();
+    let results = reprompt.split("TOOL_RESULTS:\n").nth(1).unwrap();
+    let results: serde_json::Value = serde_json::from_str(results).unwrap();
+    let fed_back = results[0]["result"].as_str().unwrap();
+    let (content, notice) = fed_back.split_once("\n[truncated").unwrap();
+    assert_eq!(content.len(), 1000);
+    assert!(content.starts_with("<html>yyy"));
+    assert!(notice.contains("50013 bytes"));
+}
+
+#[tokio::test]
+async fn configured_tool_arguments_are_redacted_in_events_and_audit() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "tool".to_string(),
+                arguments: json!({"query": "my card is 4111", "page": 2}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, Some(tx));
+    let registry = service.tool_registry.clone();
+    registry
+        .configure_all_tools(json!({"tools": {"tool": {"redact_args": ["query"]}}}))
+        .await
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(
+        ToolAuditStore::new(dir.path().join("audit.db").to_str().unwrap())
+            .await
+            .unwrap(),
+    );
+    registry.set_audit_store(Some(store.clone())).await;
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "look it up", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.status, "success");
+    assert_eq!(event.payload["args"]["query"], REDACTED);
+    assert_eq!(event.payload["args"]["page"], 2);
+
+    let entries = store.list("u1", 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        entries[0].params_hash,
+        hash_params(&json!({"query": REDACTED, "page": 2, "user_id": "u1"}))
+    );
+}
+
+#[tokio::test]
+async fn tool_results_are_reinjected_in_the_configured_format() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: json!({}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    registry
+        .configure_all_tools(json!({"tools": {"settings": {"tool_result_format": "yaml"}}}))
+        .await
+        .unwrap();
+    assert_eq!(registry.result_format().await, ToolResultFormat::Yaml);
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("lookup")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "lookup")
+            .await
+    );
+
+    service
+        .generate_response("u1", "look it up", "", None)
+        .await
+        .unwrap();
+
+    let prompts = llm.prompts.lock().await;
+    let results = prompts
+        .last()
+        .unwrap()
+        .split("TOOL_RESULTS:\n")
+        .nth(1)
+        .unwrap();
+    assert_eq!(
+        results,
+        "- id: \"call_1\"\n  result:\n    ok: true\n  status: \"success\"\n  tool: \"lookup\""
+    );
+
+    let markdown = ToolResultFormat::Markdown
+        .render(&[json!({"id": "call_1", "tool": "lookup", "status": "error", "message": "Tool not found"})])
+        .unwrap();
+    assert_eq!(
+        markdown,
+        "### lookup (`call_1`): error\n\n**message:** Tool not found"
+    );
+    assert!(registry
+        .configure_all_tools(json!({"tools": {"settings": {"tool_result_format": "xml"}}}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn parent_agent_delegates_to_child_and_uses_its_answer() {
+    let mut provider = QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: DELEGATE_TOOL_NAME.to_string(),
+                arguments: json!({"agent": "researcher", "prompt": "Find the release date"}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "It ships in May.".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]);
+    // The child has no tools, so it answers through `generate_text`.
+    provider.text = "Release is planned for May 3.".to_string();
+    let llm = Arc::new(provider);
+    let child = AgentConfig {
+        name: "researcher".to_string(),
+        specialization: Some("research".to_string()),
+        instructions: Some("Cite sources.".to_string()),
+        ..AgentConfig::default()
+    };
+    let agent = AIAgent {
+        name: "lead".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm.clone(), agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    let delegate = DelegateTool::new(
+        llm.clone(),
+        vec![child.clone()],
+        vec!["researcher".to_string()],
+        "inst".to_string(),
+    );
+    assert!(registry.register_tool(Arc::new(delegate)).await);
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), DELEGATE_TOOL_NAME)
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "when does it ship?", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "It ships in May.");
+    let prompts = llm.prompts.lock().await;
+    let results = prompts
+        .last()
+        .unwrap()
+        .split("TOOL_RESULTS:\n")
+        .nth(1)
+        .unwrap();
+    let results: serde_json::Value = serde_json::from_str(results).unwrap();
+    assert_eq!(results[0]["status"], "success");
+    assert_eq!(
+        results[0]["result"],
+        json!({"agent": "researcher", "response": "Release is planned for May 3."})
+    );
+
+    // Past the depth cap, or to an agent not on the list, nothing is run.
+    let capped = DelegateTool::new(
+        llm.clone(),
+        vec![child],
+        vec!["researcher".to_string()],
+        "inst".to_string(),
+    )
+    .with_max_depth(2)
+    .with_depth(2);
+    let err = capped
+        .execute(json!({"agent": "researcher", "prompt": "again"}))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("depth limit"));
+    let uncapped = DelegateTool::new(llm.clone(), Vec::new(), Vec::new(), String::new());
+    assert!(uncapped
+        .execute(json!({"agent": "researcher", "prompt": "again"}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn delegated_usage_is_charged_to_the_user() {
+    let mut provider = QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: DELEGATE_TOOL_NAME.to_string(),
+                arguments: json!({"agent": "researcher", "prompt": "Find the release date"}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "It ships in May.".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]);
+    provider.text = "Release is planned for May 3.".to_string();
+    let llm = Arc::new(provider);
+    let child = AgentConfig {
+        name: "researcher".to_string(),
+        ..AgentConfig::default()
+    };
+    let agent = AIAgent {
+        name: "lead".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let tracker = Arc::new(
+        CostTracker::new(
+            db.path().to_str().unwrap(),
+            CostConfig::default(),
+            "test-model",
+        )
+        .await
+        .unwrap(),
+    );
+    service.set_cost_tracker(Some(tracker.clone())).await;
+    let delegate = Arc::new(DelegateTool::new(
+        llm.clone(),
+        vec![child],
+        vec!["researcher".to_string()],
+        "inst".to_string(),
+    ));
+    delegate.attach(&service);
+    let registry = service.tool_registry.clone();
+    assert!(registry.register_tool(delegate).await);
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), DELEGATE_TOOL_NAME)
+            .await
+    );
+
+    service
+        .generate_response("u1", "when does it ship?", "", None)
+        .await
+        .unwrap();
+    // Two parent model calls plus the child's answer.
+    assert_eq!(tracker.summary("u1").await.unwrap().requests, 3);
+}
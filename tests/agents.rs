@@ -0,0 +1,360 @@
+mod common;
+
+use butterfly_bot::agents::{self, AgentsCommand};
+use butterfly_bot::config::{AgentConfig, Config, MemoryConfig, OpenAiConfig};
+use butterfly_bot::config_store;
+use butterfly_bot::interfaces::providers::GenerationParams;
+use butterfly_bot::{ButterflyBot, ProcessOptions, ProcessResult, UserInput};
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use serde_json::json;
+use tempfile::{tempdir, NamedTempFile};
+
+fn empty_config() -> Config {
+    Config {
+        openai: None,
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    }
+}
+
+fn agent(name: &str) -> AgentConfig {
+    AgentConfig {
+        name: name.to_string(),
+        specialization: Some("research".to_string()),
+        instructions: None,
+        tools: agents::parse_tool_list("search_internet, todo,"),
+        temperature: None,
+        top_p: None,
+        max_tokens: None,
+        delegates: Vec::new(),
+        display_name: None,
+        memory_namespace: None,
+    }
+}
+
+/// In-process memory, with agent choices kept in `db_path`.
+fn memory_at(db_path: &str) -> MemoryConfig {
+    MemoryConfig {
+        enabled: Some(false),
+        sqlite_path: Some(db_path.to_string()),
+        lancedb_path: None,
+        summary_model: None,
+        embedding_model: None,
+        rerank_model: None,
+        summary_threshold: None,
+        summary_style: None,
+        retention_days: None,
+        chat_ttl_days: None,
+        namespace: None,
+        min_score: None,
+        search_limit: None,
+        context_top_k: None,
+        history_window: None,
+        embedding_limit: None,
+        vector_index: None,
+    }
+}
+
+#[test]
+fn agents_command_mutates_stored_config() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+    config_store::save_config(&db_path, &empty_config()).unwrap();
+
+    let listed = agents::run_agents_command(&db_path, AgentsCommand::List).unwrap();
+    assert!(listed.is_empty());
+
+    agents::run_agents_command(&db_path, AgentsCommand::Add(agent("scout"))).unwrap();
+    let stored = Config::from_store(&db_path).unwrap();
+    assert_eq!(stored.agents().len(), 1);
+    assert_eq!(stored.agents()[0].tools, vec!["search_internet", "todo"]);
+
+    agents::run_agents_command(
+        &db_path,
+        AgentsCommand::Edit {
+            name: "scout".to_string(),
+            specialization: None,
+            instructions: Some("Cite sources.".to_string()),
+            tools: Some(Vec::new()),
+        },
+    )
+    .unwrap();
+    let stored = Config::from_store(&db_path).unwrap();
+    let scout = &stored.agents()[0];
+    assert_eq!(scout.specialization.as_deref(), Some("research"));
+    assert_eq!(scout.instructions.as_deref(), Some("Cite sources."));
+    assert!(scout.tools.is_empty());
+
+    let remaining = agents::run_agents_command(
+        &db_path,
+        AgentsCommand::Remove {
+            name: "scout".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(remaining.is_empty());
+    assert!(Config::from_store(&db_path).unwrap().agents().is_empty());
+
+    assert!(agents::run_agents_command(
+        &db_path,
+        AgentsCommand::Remove {
+            name: "scout".to_string(),
+        },
+    )
+    .is_err());
+}
+
+#[test]
+fn duplicate_agent_names_are_rejected() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+    config_store::save_config(&db_path, &empty_config()).unwrap();
+
+    agents::run_agents_command(&db_path, AgentsCommand::Add(agent("scout"))).unwrap();
+    let err =
+        agents::run_agents_command(&db_path, AgentsCommand::Add(agent(" scout "))).unwrap_err();
+    assert!(err.to_string().contains("already exists"));
+    assert!(agents::run_agents_command(&db_path, AgentsCommand::Add(agent("  "))).is_err());
+
+    let stored = Config::from_store(&db_path).unwrap();
+    assert_eq!(stored.agents().len(), 1);
+}
+
+#[tokio::test]
+async fn agent_generation_defaults_reach_the_provider() {
+    let server = MockServer::start_async().await;
+    let chat_mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .json_body_partial(
+                    json!({"temperature": 1.5, "top_p": 0.5, "max_completion_tokens": 256})
+                        .to_string(),
+                );
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "ideas"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+
+    let mut config = empty_config();
+    config.openai = Some(OpenAiConfig {
+        api_key: Some("key".to_string()),
+        model: Some("gpt-4o-mini".to_string()),
+        base_url: Some(server.base_url()),
+        headers: None,
+        json_mode: None,
+    });
+    config.agents = Some(vec![AgentConfig {
+        temperature: Some(1.5),
+        top_p: Some(0.5),
+        max_tokens: Some(256),
+        ..agent("brainstorm")
+    }]);
+
+    let bot = ButterflyBot::from_config(config).await.unwrap();
+    let options = ProcessOptions {
+        persist: false,
+        ..Default::default()
+    };
+    let reply = bot
+        .process("u1", UserInput::Text("give me ideas".to_string()), options)
+        .await
+        .unwrap();
+    assert!(matches!(reply, ProcessResult::Text(ref text) if text == "ideas"));
+    chat_mock.assert_hits(1);
+}
+
+#[tokio::test]
+async fn each_agent_uses_its_own_generation_defaults() {
+    let server = MockServer::start_async().await;
+    let reply = json!({
+        "id": "chatcmpl-test",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "gpt-4o-mini",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "ideas"},
+            "finish_reason": "stop"
+        }]
+    });
+    let persona_mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .json_body_partial(json!({"temperature": 0.2}).to_string());
+            then.status(200).json_body(reply.clone());
+        })
+        .await;
+
+    let db = NamedTempFile::new().unwrap();
+    let mut config = empty_config();
+    config.openai = Some(OpenAiConfig {
+        api_key: Some("key".to_string()),
+        model: Some("gpt-4o-mini".to_string()),
+        base_url: Some(server.base_url()),
+        headers: None,
+        json_mode: None,
+    });
+    config.memory = Some(memory_at(db.path().to_str().unwrap()));
+    config.agents = Some(vec![
+        AgentConfig {
+            temperature: Some(1.5),
+            ..agent("brainstorm")
+        },
+        AgentConfig {
+            temperature: Some(0.2),
+            ..agent("editor")
+        },
+    ]);
+
+    let bot = ButterflyBot::from_config(config).await.unwrap();
+    let options = ProcessOptions {
+        persist: false,
+        agent: Some("editor".to_string()),
+        ..Default::default()
+    };
+    let reply = bot
+        .process("u1", UserInput::Text("tighten this".to_string()), options)
+        .await
+        .unwrap();
+    assert!(matches!(reply, ProcessResult::Text(ref text) if text == "ideas"));
+    persona_mock.assert_hits(1);
+}
+
+#[tokio::test]
+async fn request_generation_settings_take_precedence() {
+    let server = MockServer::start_async().await;
+    let overridden_mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .json_body_partial(
+                    json!({"temperature": 1.5, "top_p": 0.5, "max_completion_tokens": 64})
+                        .to_string(),
+                );
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "loose"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+
+    let db = NamedTempFile::new().unwrap();
+    let mut config = empty_config();
+    config.openai = Some(OpenAiConfig {
+        api_key: Some("key".to_string()),
+        model: Some("gpt-4o-mini".to_string()),
+        base_url: Some(server.base_url()),
+        headers: None,
+        json_mode: None,
+    });
+    config.memory = Some(memory_at(db.path().to_str().unwrap()));
+    config.agents = Some(vec![AgentConfig {
+        temperature: Some(0.25),
+        top_p: Some(0.5),
+        ..agent("editor")
+    }]);
+
+    let bot = ButterflyBot::from_config(config).await.unwrap();
+    let options = ProcessOptions {
+        persist: false,
+        generation: GenerationParams {
+            temperature: Some(1.5),
+            max_tokens: Some(64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let reply = bot
+        .process("u1", UserInput::Text("riff on this".to_string()), options)
+        .await
+        .unwrap();
+    assert!(matches!(reply, ProcessResult::Text(ref text) if text == "loose"));
+    overridden_mock.assert_hits(1);
+}
+
+#[tokio::test]
+async fn agents_with_their_own_namespace_keep_separate_memories() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "noted"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+
+    let db = NamedTempFile::new().unwrap();
+    let mut config = empty_config();
+    config.openai = Some(OpenAiConfig {
+        api_key: Some("key".to_string()),
+        model: Some("gpt-4o-mini".to_string()),
+        base_url: Some(server.base_url()),
+        headers: None,
+        json_mode: None,
+    });
+    config.memory = Some(memory_at(db.path().to_str().unwrap()));
+    config.agents = Some(vec![
+        AgentConfig {
+            memory_namespace: Some("work".to_string()),
+            ..agent("assistant")
+        },
+        AgentConfig {
+            memory_namespace: Some("home".to_string()),
+            ..agent("coach")
+        },
+    ]);
+
+    let bot = ButterflyBot::from_config(config).await.unwrap();
+    let options = ProcessOptions::default();
+    bot.process(
+        "u1",
+        UserInput::Text("the launch is on Friday".to_string()),
+        options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(bot.get_user_history("u1", 10).await.unwrap().len(), 2);
+
+    bot.set_active_agent("u1", Some("coach")).await.unwrap();
+    assert!(bot.get_user_history("u1", 10).await.unwrap().is_empty());
+
+    bot.set_active_agent("u1", None).await.unwrap();
+    assert_eq!(bot.get_user_history("u1", 10).await.unwrap().len(), 2);
+}
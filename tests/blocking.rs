@@ -0,0 +1,82 @@
+#![cfg(feature = "blocking")]
+
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use serde_json::json;
+
+use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::{ProcessOptions, ProcessResult, UserInput};
+
+fn config(server: &MockServer) -> Config {
+    Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    }
+}
+
+fn options() -> ProcessOptions {
+    ProcessOptions::default()
+}
+
+fn text(result: ProcessResult) -> String {
+    match result {
+        ProcessResult::Text(text) => text,
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn blocking_client_matches_async_client() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(200).json_body(json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o-mini",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "hello"},
+                "finish_reason": "stop"
+            }]
+        }));
+    });
+
+    let blocking = butterfly_bot::blocking::ButterflyBot::from_config(config(&server)).unwrap();
+    let blocking_reply = text(
+        blocking
+            .process("user", UserInput::Text("hi".to_string()), options())
+            .unwrap(),
+    );
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let async_reply = runtime.block_on(async {
+        let agent = butterfly_bot::ButterflyBot::from_config(config(&server))
+            .await
+            .unwrap();
+        text(
+            agent
+                .process("user", UserInput::Text("hi".to_string()), options())
+                .await
+                .unwrap(),
+        )
+    });
+
+    assert_eq!(blocking_reply, "hello");
+    assert_eq!(blocking_reply, async_reply);
+}
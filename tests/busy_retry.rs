@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use tempfile::NamedTempFile;
+
+use butterfly_bot::db::{retry_on_busy, BUSY_RETRY_ATTEMPTS};
+use butterfly_bot::error::ButterflyBotError;
+use butterfly_bot::tasks::TaskStore;
+
+#[tokio::test]
+async fn busy_errors_are_retried_until_the_write_succeeds() {
+    let calls = AtomicU32::new(0);
+    let value = retry_on_busy(|| async {
+        if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+            Err(ButterflyBotError::Runtime("database is locked".to_string()))
+        } else {
+            Ok(7)
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(value, 7);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    let calls = AtomicU32::new(0);
+    let err = retry_on_busy(|| async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err::<(), _>(ButterflyBotError::Runtime("database is locked".to_string()))
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, ButterflyBotError::Database(_)));
+    assert_eq!(calls.load(Ordering::SeqCst), BUSY_RETRY_ATTEMPTS);
+
+    let calls = AtomicU32::new(0);
+    let err = retry_on_busy(|| async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err::<(), _>(ButterflyBotError::Runtime("no such table".to_string()))
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, ButterflyBotError::Runtime(_)));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn store_write_waits_out_a_short_exclusive_lock() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap().to_string();
+    let tasks = TaskStore::new(&path).await.unwrap();
+
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let holder = std::thread::spawn(move || {
+        let mut conn = SqliteConnection::establish(&path).unwrap();
+        conn.batch_execute("BEGIN EXCLUSIVE").unwrap();
+        locked_tx.send(()).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        conn.batch_execute("COMMIT").unwrap();
+    });
+    locked_rx.recv().unwrap();
+
+    let task = tasks
+        .create_task("u1", "report", "send report", 0, None)
+        .await
+        .unwrap();
+    assert_eq!(task.name, "report");
+    holder.join().unwrap();
+}
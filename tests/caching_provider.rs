@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+
+use butterfly_bot::interfaces::providers::LlmProvider;
+use butterfly_bot::providers::caching::CachingProvider;
+use tempfile::tempdir;
+
+use common::QueueLlmProvider;
+
+#[tokio::test]
+async fn repeated_embeddings_are_served_from_cache() {
+    let inner = Arc::new(QueueLlmProvider::new(Vec::new()));
+    let provider = CachingProvider::new(inner.clone(), 8);
+
+    let first = provider
+        .embed(vec!["hello".to_string()], Some("embed-model"))
+        .await
+        .unwrap();
+    let second = provider
+        .embed(vec!["hello".to_string()], Some("embed-model"))
+        .await
+        .unwrap();
+    assert_eq!(first, second);
+    assert_eq!(inner.embed_calls.lock().await.len(), 1);
+
+    let mixed = provider
+        .embed(
+            vec![
+                "hello".to_string(),
+                "world!".to_string(),
+                "world!".to_string(),
+            ],
+            Some("embed-model"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(mixed.len(), 3);
+    assert_eq!(mixed[0], first[0]);
+    assert_eq!(mixed[1], vec![6.0, 1.0]);
+    assert_eq!(mixed[1], mixed[2]);
+    {
+        let calls = inner.embed_calls.lock().await;
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1], vec!["world!".to_string()]);
+    }
+
+    provider
+        .embed(vec!["hello".to_string()], Some("other-model"))
+        .await
+        .unwrap();
+    assert_eq!(inner.embed_calls.lock().await.len(), 3);
+    assert_eq!(provider.cached_len().await, 3);
+}
+
+#[tokio::test]
+async fn embedding_cache_persists_to_disk() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("embeddings.json");
+
+    let inner = Arc::new(QueueLlmProvider::new(Vec::new()));
+    let provider = CachingProvider::new(inner.clone(), 8)
+        .with_persistence(&path)
+        .unwrap();
+    provider
+        .embed(vec!["persisted".to_string()], None)
+        .await
+        .unwrap();
+    assert!(path.exists());
+
+    let inner = Arc::new(QueueLlmProvider::new(Vec::new()));
+    let reloaded = CachingProvider::new(inner.clone(), 8)
+        .with_persistence(&path)
+        .unwrap();
+    let vectors = reloaded
+        .embed(vec!["persisted".to_string()], None)
+        .await
+        .unwrap();
+    assert_eq!(vectors, vec![vec![9.0, 1.0]]);
+    assert!(inner.embed_calls.lock().await.is_empty());
+}
@@ -0,0 +1,95 @@
+use serde_json::json;
+
+use butterfly_bot::interfaces::providers::{ChatEvent, TokenUsage};
+
+#[test]
+fn chat_event_variants_keep_the_flat_wire_shape() {
+    let cases = vec![
+        (
+            ChatEvent::Content {
+                delta: "hi".to_string(),
+            },
+            json!({"event_type": "content", "delta": "hi"}),
+        ),
+        (
+            ChatEvent::ToolCall {
+                id: "call_0".to_string(),
+                name: Some("search_internet".to_string()),
+                arguments_delta: Some("{\"q\":".to_string()),
+            },
+            json!({
+                "event_type": "tool_call",
+                "id": "call_0",
+                "name": "search_internet",
+                "arguments_delta": "{\"q\":"
+            }),
+        ),
+        (
+            ChatEvent::ToolStart {
+                id: "call_0".to_string(),
+                name: "todo".to_string(),
+            },
+            json!({"event_type": "tool_start", "id": "call_0", "name": "todo"}),
+        ),
+        (
+            ChatEvent::ToolEnd {
+                id: "call_0".to_string(),
+                name: "todo".to_string(),
+            },
+            json!({"event_type": "tool_end", "id": "call_0", "name": "todo"}),
+        ),
+        (ChatEvent::Heartbeat, json!({"event_type": "heartbeat"})),
+        (
+            ChatEvent::MessageEnd {
+                finish_reason: Some("stop".to_string()),
+                usage: None,
+            },
+            json!({"event_type": "message_end", "finish_reason": "stop"}),
+        ),
+        (
+            ChatEvent::MessageEnd {
+                finish_reason: None,
+                usage: Some(TokenUsage {
+                    prompt_tokens: 3,
+                    completion_tokens: 5,
+                }),
+            },
+            json!({
+                "event_type": "message_end",
+                "finish_reason": null,
+                "usage": {"prompt_tokens": 3, "completion_tokens": 5}
+            }),
+        ),
+        (
+            ChatEvent::Error {
+                message: "boom".to_string(),
+            },
+            json!({"event_type": "error", "error": "boom"}),
+        ),
+    ];
+
+    for (event, wire) in cases {
+        assert_eq!(serde_json::to_value(&event).unwrap(), wire);
+        assert_eq!(event.event_type(), wire["event_type"]);
+        assert_eq!(serde_json::from_value::<ChatEvent>(wire).unwrap(), event);
+    }
+}
+
+#[test]
+fn chat_event_reads_old_struct_payloads() {
+    let old = json!({
+        "event_type": "content",
+        "id": null,
+        "delta": "hello",
+        "name": null,
+        "arguments_delta": null,
+        "finish_reason": null,
+        "error": null
+    });
+    assert_eq!(
+        serde_json::from_value::<ChatEvent>(old).unwrap(),
+        ChatEvent::Content {
+            delta: "hello".to_string()
+        }
+    );
+}
@@ -14,6 +14,9 @@ use butterfly_bot::plugins::registry::ToolRegistry;
 
 pub struct QueueLlmProvider {
     queue: Mutex<VecDeque<LlmResponse>>,
+    pub prompts: Mutex<Vec<String>>,
+    pub system_prompts: Mutex<Vec<String>>,
+    pub embed_calls: Mutex<Vec<Vec<String>>>,
     pub text: String,
     pub structured: serde_json::Value,
     pub tts_bytes: Vec<u8>,
@@ -25,6 +28,9 @@ impl QueueLlmProvider {
     pub fn new(queue: Vec<LlmResponse>) -> Self {
         Self {
             queue: Mutex::new(VecDeque::from(queue)),
+            prompts: Mutex::new(Vec::new()),
+            system_prompts: Mutex::new(Vec::new()),
+            embed_calls: Mutex::new(Vec::new()),
             text: "mock text".to_string(),
             structured: json!({"ok": true}),
             tts_bytes: b"audio".to_vec(),
@@ -47,14 +53,20 @@ impl LlmProvider for QueueLlmProvider {
 
     async fn generate_with_tools(
         &self,
-        _prompt: &str,
-        _system_prompt: &str,
+        prompt: &str,
+        system_prompt: &str,
         _tools: Vec<serde_json::Value>,
     ) -> Result<LlmResponse> {
+        self.prompts.lock().await.push(prompt.to_string());
+        self.system_prompts
+            .lock()
+            .await
+            .push(system_prompt.to_string());
         let mut guard = self.queue.lock().await;
         Ok(guard.pop_front().unwrap_or(LlmResponse {
             text: self.text.clone(),
             tool_calls: Vec::new(),
+            usage: None,
         }))
     }
 
@@ -66,21 +78,10 @@ impl LlmProvider for QueueLlmProvider {
         use async_stream::try_stream;
         let text = self.text.clone();
         Box::pin(try_stream! {
-            yield ChatEvent {
-                event_type: "content".to_string(),
-                delta: Some(text),
-                name: None,
-                arguments_delta: None,
-                finish_reason: None,
-                error: None,
-            };
-            yield ChatEvent {
-                event_type: "message_end".to_string(),
-                delta: None,
-                name: None,
-                arguments_delta: None,
+            yield ChatEvent::Content { delta: text };
+            yield ChatEvent::MessageEnd {
                 finish_reason: Some("stop".to_string()),
-                error: None,
+                usage: None,
             };
         })
     }
@@ -114,21 +115,26 @@ impl LlmProvider for QueueLlmProvider {
         Ok(self.image_text.clone())
     }
 
-    async fn embed(&self, _inputs: Vec<String>, _model: Option<&str>) -> Result<Vec<Vec<f32>>> {
-        Ok(vec![vec![0.0, 1.0]])
+    async fn embed(&self, inputs: Vec<String>, _model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        let vectors = inputs
+            .iter()
+            .map(|input| vec![input.len() as f32, 1.0])
+            .collect();
+        self.embed_calls.lock().await.push(inputs);
+        Ok(vectors)
     }
 }
 
 pub struct DummyTool {
     name: String,
-    configured: Mutex<bool>,
+    configured: std::sync::Mutex<bool>,
 }
 
 impl DummyTool {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            configured: Mutex::new(false),
+            configured: std::sync::Mutex::new(false),
         }
     }
 }
@@ -148,8 +154,7 @@ impl Tool for DummyTool {
     }
 
     fn configure(&self, _config: &serde_json::Value) -> Result<()> {
-        let mut guard = futures::executor::block_on(self.configured.lock());
-        *guard = true;
+        *self.configured.lock().unwrap() = true;
         Ok(())
     }
 
@@ -257,7 +262,8 @@ impl Tool for ConditionalTool {
     }
 
     fn configure(&self, config: &serde_json::Value) -> Result<()> {
-        if config.get("fail").is_some() {
+        let section = config.get("tools").and_then(|tools| tools.get(&self.name));
+        if section.and_then(|section| section.get("fail")).is_some() {
             return Err(ButterflyBotError::Runtime("fail".to_string()));
         }
         Ok(())
@@ -317,3 +323,75 @@ impl Plugin for ConfigurablePlugin {
         true
     }
 }
+
+type KeyringStore =
+    std::sync::Arc<std::sync::Mutex<std::collections::HashMap<(String, String), Vec<u8>>>>;
+
+#[derive(Debug)]
+struct MapCredential {
+    store: KeyringStore,
+    key: (String, String),
+}
+
+impl keyring::credential::CredentialApi for MapCredential {
+    fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+        self.store
+            .lock()
+            .unwrap()
+            .insert(self.key.clone(), secret.to_vec());
+        Ok(())
+    }
+
+    fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+        self.store
+            .lock()
+            .unwrap()
+            .get(&self.key)
+            .cloned()
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    fn delete_credential(&self) -> keyring::Result<()> {
+        self.store
+            .lock()
+            .unwrap()
+            .remove(&self.key)
+            .map(|_| ())
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct MapBuilder {
+    store: KeyringStore,
+}
+
+impl keyring::credential::CredentialBuilderApi for MapBuilder {
+    fn build(
+        &self,
+        _target: Option<&str>,
+        service: &str,
+        user: &str,
+    ) -> keyring::Result<Box<keyring::credential::Credential>> {
+        Ok(Box::new(MapCredential {
+            store: self.store.clone(),
+            key: (service.to_string(), user.to_string()),
+        }))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// In-memory keyring so vault tests never touch the OS keychain.
+pub fn install_mock_keyring() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        keyring::set_default_credential_builder(Box::new(MapBuilder::default()));
+    });
+}
@@ -1,8 +1,13 @@
 mod common;
 
+use std::time::Duration;
+
 use serde_json::json;
 
-use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::config::{
+    assistant_prefix, banner_line, is_ollama_local, markdown_reply, normalize_base_url, Config,
+    OpenAiConfig, DEFAULT_DISPLAY_NAME,
+};
 use butterfly_bot::error::ButterflyBotError;
 use butterfly_bot::factories::agent_factory::ButterflyBotFactory;
 
@@ -29,12 +34,18 @@ async fn config_from_file_and_factory_errors() {
             api_key: None,
             model: None,
             base_url: Some("http://localhost:11434/v1".to_string()),
+            headers: None,
+            json_mode: None,
         }),
         skill_file: None,
         heartbeat_file: None,
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
     let _ = ButterflyBotFactory::create_from_config(no_key_with_base_url)
         .await
@@ -45,12 +56,18 @@ async fn config_from_file_and_factory_errors() {
             api_key: None,
             model: None,
             base_url: None,
+            headers: None,
+            json_mode: None,
         }),
         skill_file: None,
         heartbeat_file: None,
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
     let err = ButterflyBotFactory::create_from_config(missing_key)
         .await
@@ -73,6 +90,10 @@ async fn config_from_file_and_factory_errors() {
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
     let err = ButterflyBotFactory::create_from_config(missing)
         .await
@@ -84,3 +105,136 @@ async fn config_from_file_and_factory_errors() {
     let err = ButterflyBotError::Runtime("boom".to_string());
     assert!(format!("{err}").contains("boom"));
 }
+
+#[test]
+fn banner_line_reflects_configured_provider_and_model() {
+    let with_openai = |model: Option<&str>, base_url: Option<&str>| Config {
+        openai: Some(OpenAiConfig {
+            api_key: None,
+            model: model.map(str::to_string),
+            base_url: base_url.map(str::to_string),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    };
+
+    let remote = with_openai(Some("gpt-4o-mini"), Some("https://api.openai.com/v1"));
+    assert_eq!(
+        banner_line(Some(&remote), "http://127.0.0.1:7878", "u"),
+        "gpt-4o-mini via OpenAI • Streaming CLI • Daemon: http://127.0.0.1:7878 • User: u"
+    );
+
+    let local = with_openai(Some("ministral-3:14b"), Some("http://localhost:11434/v1"));
+    assert!(banner_line(Some(&local), "d", "u").starts_with("ministral-3:14b via Ollama •"));
+
+    let other = with_openai(None, Some("https://llm.example.com/v1"));
+    assert!(
+        banner_line(Some(&other), "d", "u").starts_with("default model via OpenAI-compatible •")
+    );
+
+    assert!(banner_line(None, "d", "u").starts_with("No model configured •"));
+}
+
+#[test]
+fn custom_display_name_labels_replies() {
+    let mut config: Config = serde_json::from_value(json!({
+        "openai": {"api_key": "key", "model": null, "base_url": null},
+        "agents": [{"name": "ops", "display_name": "Monarch"}]
+    }))
+    .unwrap();
+    assert_eq!(config.display_name(), "Monarch");
+    assert_eq!(assistant_prefix(&config.display_name()), "✦ Monarch ");
+    assert_eq!(
+        markdown_reply(&config.display_name(), "| a |"),
+        "**Monarch:** | a |"
+    );
+
+    config.agents.as_mut().unwrap()[0].display_name = None;
+    assert_eq!(config.display_name(), "ops");
+    config.agents = None;
+    assert_eq!(config.display_name(), DEFAULT_DISPLAY_NAME);
+    assert_eq!(assistant_prefix(DEFAULT_DISPLAY_NAME), "✦ Butterfly ");
+}
+
+#[test]
+fn malformed_base_urls_normalize() {
+    for (raw, expected) in [
+        ("http://localhost:11434", "http://localhost:11434/v1"),
+        ("http://localhost:11434/", "http://localhost:11434/v1"),
+        (
+            "  http://127.0.0.1:11434//v1/ ",
+            "http://127.0.0.1:11434/v1",
+        ),
+        ("HTTPS://api.openai.com", "https://api.openai.com/v1"),
+        (
+            "https://llm.example.com//openai//v1//",
+            "https://llm.example.com/openai/v1",
+        ),
+        // No way to tell where an unknown server mounts its API.
+        ("https://llm.example.com", "https://llm.example.com"),
+    ] {
+        assert_eq!(normalize_base_url(raw).unwrap(), expected, "for {raw:?}");
+    }
+
+    for raw in [
+        "localhost:11434",
+        "ftp://example.com/v1",
+        "http://",
+        "not a url",
+    ] {
+        assert!(
+            matches!(normalize_base_url(raw), Err(ButterflyBotError::Config(_))),
+            "{raw:?} should be rejected"
+        );
+    }
+
+    assert!(is_ollama_local("http://localhost:11434"));
+    assert!(is_ollama_local("http://127.0.0.1:11434//v1/"));
+    assert!(!is_ollama_local("http://localhost:8080/v1"));
+    assert!(!is_ollama_local("https://api.openai.com/v1"));
+
+    let tmp = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        tmp.path(),
+        json!({"openai": {"api_key": null, "model": null, "base_url": "http://localhost:11434/"}})
+            .to_string(),
+    )
+    .unwrap();
+    let config = Config::from_file(tmp.path()).unwrap();
+    assert_eq!(
+        config.openai.unwrap().base_url.as_deref(),
+        Some("http://localhost:11434/v1")
+    );
+}
+
+#[test]
+fn daemon_section_parses_typed_settings() {
+    let config: Config = serde_json::from_value(json!({
+        "daemon": {
+            "cors_origins": ["https://app.example"],
+            "max_body_bytes": 4096,
+            "sse_keep_alive_secs": 0
+        }
+    }))
+    .unwrap();
+    let daemon = config.daemon.unwrap();
+    assert_eq!(
+        daemon.cors_origins,
+        Some(vec!["https://app.example".to_string()])
+    );
+    assert_eq!(daemon.max_body_bytes, Some(4096));
+    assert_eq!(daemon.max_audio_body_bytes, None);
+    assert_eq!(daemon.sse_keep_alive(), Some(Duration::from_secs(1)));
+
+    let invalid = serde_json::from_value::<Config>(json!({"daemon": {"max_body_bytes": "big"}}));
+    assert!(invalid.is_err());
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use tempfile::NamedTempFile;
+
+use butterfly_bot::config::{CostConfig, ModelPrice};
+use butterfly_bot::costs::CostTracker;
+use butterfly_bot::error::ButterflyBotError;
+use butterfly_bot::interfaces::providers::TokenUsage;
+
+fn cost_config(daily_budget: Option<f64>) -> CostConfig {
+    let mut prices = HashMap::new();
+    prices.insert(
+        "test-model".to_string(),
+        ModelPrice {
+            input_per_million: 1_000.0,
+            output_per_million: 2_000.0,
+        },
+    );
+    CostConfig {
+        prices: Some(prices),
+        daily_budget,
+        sqlite_path: None,
+    }
+}
+
+#[tokio::test]
+async fn cost_tracker_accumulates_spend_per_user() {
+    let db = NamedTempFile::new().unwrap();
+    let tracker = CostTracker::new(db.path().to_str().unwrap(), cost_config(None), "test-model")
+        .await
+        .unwrap();
+
+    let usage = TokenUsage {
+        prompt_tokens: 100,
+        completion_tokens: 50,
+    };
+    let cost = tracker.record("u1", "test-model", usage).await.unwrap();
+    assert!((cost - 0.2).abs() < 1e-9);
+    tracker.record("u1", "test-model", usage).await.unwrap();
+    tracker.record("u2", "test-model", usage).await.unwrap();
+    let unpriced = tracker.record("u1", "other-model", usage).await.unwrap();
+    assert_eq!(unpriced, 0.0);
+
+    let summary = tracker.summary("u1").await.unwrap();
+    assert_eq!(summary.requests, 3);
+    assert_eq!(summary.prompt_tokens, 300);
+    assert_eq!(summary.completion_tokens, 150);
+    assert!((summary.total_cost - 0.4).abs() < 1e-9);
+    assert!((summary.today_cost - 0.4).abs() < 1e-9);
+
+    let other = tracker.summary("u2").await.unwrap();
+    assert_eq!(other.requests, 1);
+}
+
+#[tokio::test]
+async fn cost_tracker_blocks_requests_past_daily_budget() {
+    let db = NamedTempFile::new().unwrap();
+    let tracker = CostTracker::new(
+        db.path().to_str().unwrap(),
+        cost_config(Some(0.3)),
+        "test-model",
+    )
+    .await
+    .unwrap();
+    let usage = TokenUsage {
+        prompt_tokens: 100,
+        completion_tokens: 50,
+    };
+
+    tracker.check_budget("u1").await.unwrap();
+    tracker.record("u1", "test-model", usage).await.unwrap();
+    tracker.check_budget("u1").await.unwrap();
+    tracker.record("u1", "test-model", usage).await.unwrap();
+
+    let err = tracker.check_budget("u1").await.unwrap_err();
+    assert!(matches!(err, ButterflyBotError::BudgetExceeded(_)));
+    tracker.check_budget("u2").await.unwrap();
+}
@@ -1,35 +1,95 @@
+mod common;
+
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::body::Body;
 use axum::http::{Request, StatusCode};
 use http_body_util::BodyExt;
-use httpmock::Method::POST;
+use httpmock::Method::{GET, POST};
 use httpmock::MockServer;
 use serde_json::json;
 use tempfile::NamedTempFile;
 use tokio::sync::{broadcast, RwLock};
+use tokio::time::timeout;
 use tower::ServiceExt;
 
+use butterfly_bot::captures::CaptureStore;
 use butterfly_bot::client::ButterflyBot;
-use butterfly_bot::config::{Config, OpenAiConfig};
-use butterfly_bot::daemon::{build_router, AppState};
+use butterfly_bot::clock::MockClock;
+use butterfly_bot::config::{
+    AgentConfig, Config, CostConfig, DaemonConfig, MemoryConfig, ModelPrice, OpenAiConfig,
+};
+use butterfly_bot::cors::CorsPolicy;
+use butterfly_bot::daemon::{build_router, AppState, BodyLimits, STREAM_HEARTBEAT};
+use butterfly_bot::interfaces::providers::MemoryProvider;
+use butterfly_bot::planning::PlanStore;
+use butterfly_bot::providers::sqlite::{SqliteMemoryProvider, SqliteMemoryProviderConfig};
 use butterfly_bot::reminders::ReminderStore;
+use butterfly_bot::services::agent::UiEvent;
+use butterfly_bot::services::links::LinkService;
+use butterfly_bot::tasks::{ScheduledTask, TaskStore};
+use butterfly_bot::todo::TodoStore;
 
 async fn make_agent(server: &MockServer) -> ButterflyBot {
+    make_agent_with_events(server, None).await
+}
+
+async fn make_agent_with_events(
+    server: &MockServer,
+    ui_event_tx: Option<broadcast::Sender<UiEvent>>,
+) -> ButterflyBot {
     let config = Config {
         openai: Some(OpenAiConfig {
             api_key: Some("key".to_string()),
             model: Some("gpt-4o-mini".to_string()),
             base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
         }),
         skill_file: None,
         heartbeat_file: None,
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
 
-    ButterflyBot::from_config(config).await.unwrap()
+    ButterflyBot::from_config_with_events(config, ui_event_tx)
+        .await
+        .unwrap()
+}
+
+async fn make_state(agent: ButterflyBot, db_path: &str) -> AppState {
+    let (ui_event_tx, _) = broadcast::channel(16);
+    AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path: db_path.to_string(),
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    }
+}
+
+fn stream_request(body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/process_text_stream")
+        .header("authorization", "Bearer token")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
 }
 
 #[tokio::test]
@@ -45,9 +105,21 @@ async fn daemon_health_and_auth() {
     let state = AppState {
         agent: Arc::new(RwLock::new(Arc::new(agent))),
         reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(
+            TaskStore::new(reminder_db.path().to_str().unwrap())
+                .await
+                .unwrap(),
+        ),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
         token: "token".to_string(),
         ui_event_tx,
         db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
     };
     let app = build_router(state);
 
@@ -109,9 +181,21 @@ async fn daemon_process_text_and_memory_search() {
     let state = AppState {
         agent: Arc::new(RwLock::new(Arc::new(agent))),
         reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(
+            TaskStore::new(reminder_db.path().to_str().unwrap())
+                .await
+                .unwrap(),
+        ),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
         token: "token".to_string(),
         ui_event_tx,
         db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
     };
     let app = build_router(state);
 
@@ -155,4 +239,1898 @@ async fn daemon_process_text_and_memory_search() {
     let bytes = response.into_body().collect().await.unwrap().to_bytes();
     let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
     assert!(value.get("results").and_then(|v| v.as_array()).is_some());
+    assert!(value.get("snippets").and_then(|v| v.as_array()).is_some());
+}
+
+#[tokio::test]
+async fn daemon_usage_and_budget() {
+    let server = MockServer::start_async().await;
+    let chat_mock = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "hello"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 1000, "completion_tokens": 500, "total_tokens": 1500}
+            }));
+        })
+        .await;
+
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let mut prices = std::collections::HashMap::new();
+    prices.insert(
+        "gpt-4o-mini".to_string(),
+        ModelPrice {
+            input_per_million: 1_000.0,
+            output_per_million: 2_000.0,
+        },
+    );
+    let config = Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: Some(CostConfig {
+            prices: Some(prices),
+            daily_budget: Some(1.0),
+            sqlite_path: Some(db_path.clone()),
+        }),
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    };
+    let agent = ButterflyBot::from_config(config).await.unwrap();
+    let reminder_store = ReminderStore::new(&db_path).await.unwrap();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let process = || {
+        Request::builder()
+            .method("POST")
+            .uri("/process_text")
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({"user_id":"u","text":"hello"}).to_string(),
+            ))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(process()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/usage?user_id=u")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(value["requests"], 1);
+    assert_eq!(value["prompt_tokens"], 1000);
+    assert!((value["today_cost"].as_f64().unwrap() - 2.0).abs() < 1e-9);
+
+    let response = app.clone().oneshot(process()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    chat_mock.assert_hits(1);
+}
+
+#[tokio::test]
+async fn daemon_task_endpoints() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let request = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", "Bearer token");
+        match body {
+            Some(body) => builder
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        }
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            "/tasks",
+            Some(json!({"user_id": "u", "name": "bad", "prompt": "p", "run_at": 0})),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            "/tasks",
+            Some(json!({
+                "user_id": "u",
+                "name": "bad interval",
+                "prompt": "p",
+                "run_at": 1_700_000_000,
+                "interval_minutes": -5
+            })),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let mut ids = Vec::new();
+    for name in ["daily digest", "standup"] {
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/tasks",
+                Some(json!({
+                    "user_id": "u",
+                    "name": name,
+                    "prompt": "summarize",
+                    "run_at": 1_700_000_000,
+                    "interval_minutes": 60
+                })),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let task: ScheduledTask = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(task.name, name);
+        assert!(task.enabled);
+        ids.push(task.id);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            &format!("/tasks/{}/enable?user_id=u", ids[0]),
+            Some(json!({"enabled": false})),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let task: ScheduledTask = serde_json::from_slice(&bytes).unwrap();
+    assert!(!task.enabled);
+
+    for (method, uri) in [
+        ("DELETE", format!("/tasks/{}?user_id=intruder", ids[0])),
+        ("POST", format!("/tasks/{}/enable?user_id=intruder", ids[0])),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(request(method, &uri, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+    }
+
+    let list = |status: &str| {
+        let app = app.clone();
+        let uri = format!("/tasks?user_id=u&status={status}");
+        async move {
+            let response = app.oneshot(request("GET", &uri, None)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            serde_json::from_value::<Vec<ScheduledTask>>(value["tasks"].clone()).unwrap()
+        }
+    };
+
+    let disabled = list("disabled").await;
+    assert_eq!(disabled.len(), 1);
+    assert_eq!(disabled[0].id, ids[0]);
+    let enabled = list("enabled").await;
+    assert_eq!(enabled.len(), 1);
+    assert_eq!(enabled[0].id, ids[1]);
+    assert_eq!(list("all").await.len(), 2);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "DELETE",
+            &format!("/tasks/{}?user_id=u", ids[1]),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(request(
+            "DELETE",
+            &format!("/tasks/{}?user_id=u", ids[1]),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(list("all").await.len(), 1);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            &format!("/tasks/{}/enable?user_id=u", ids[1]),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn daemon_status_includes_user_overview() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+
+    let reminder_store = ReminderStore::new(&db_path).await.unwrap();
+    reminder_store
+        .create_reminder("u", "one", 100)
+        .await
+        .unwrap();
+    let done = reminder_store
+        .create_reminder("u", "two", 200)
+        .await
+        .unwrap();
+    reminder_store
+        .complete_reminder("u", done.id)
+        .await
+        .unwrap();
+    reminder_store
+        .create_reminder("other", "three", 300)
+        .await
+        .unwrap();
+
+    let todo_store = TodoStore::new(&db_path).await.unwrap();
+    todo_store.create_item("u", "a", None).await.unwrap();
+    todo_store.create_item("u", "b", None).await.unwrap();
+    let finished = todo_store.create_item("u", "c", None).await.unwrap();
+    todo_store.set_completed(finished.id, true).await.unwrap();
+
+    let plan_store = PlanStore::new(&db_path).await.unwrap();
+    plan_store
+        .create_plan("u", "draft", "goal", None, None)
+        .await
+        .unwrap();
+    plan_store
+        .create_plan("u", "shipped", "goal", None, Some("completed"))
+        .await
+        .unwrap();
+
+    let task_store = TaskStore::new(&db_path).await.unwrap();
+    let paused = task_store
+        .create_task("u", "paused", "p", 0, Some(60))
+        .await
+        .unwrap();
+    task_store.set_enabled("u", paused.id, false).await.unwrap();
+    task_store
+        .create_task("u", "daily", "p", 0, Some(1440))
+        .await
+        .unwrap();
+
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(task_store),
+        todo_store: Arc::new(todo_store),
+        plan_store: Arc::new(plan_store),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/status?user_id=u")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        value["overview"],
+        json!({
+            "open_reminders": 1,
+            "open_todos": 2,
+            "active_plans": 1,
+            "enabled_tasks": 1,
+        })
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/status")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(value, json!({"status": "ok"}));
+}
+
+#[tokio::test]
+async fn daemon_request_id_tags_response_and_tool_events() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions").matches(|req| {
+                let body = req.body.as_deref().unwrap_or_default();
+                !String::from_utf8_lossy(body).contains("TOOL_RESULTS")
+            });
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-tools",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": {"name": "missing_tool", "arguments": "{}"}
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            }));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .body_contains("TOOL_RESULTS");
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-done",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "done"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+
+    let (ui_event_tx, mut ui_event_rx) = broadcast::channel(16);
+    let agent = make_agent_with_events(&server, Some(ui_event_tx.clone())).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/process_text")
+                .header("authorization", "Bearer token")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({"user_id":"u","text":"hello"}).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = response
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .expect("x-request-id header");
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(value["text"], "done");
+    assert_eq!(value["request_id"], header.as_str());
+
+    let event = ui_event_rx.try_recv().unwrap();
+    assert_eq!(event.tool, "missing_tool");
+    assert_eq!(event.request_id.as_deref(), Some(header.as_str()));
+}
+
+#[tokio::test]
+async fn daemon_webhook_ingest_creates_items() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let reminder_store = Arc::new(ReminderStore::new(&db_path).await.unwrap());
+    let todo_store = Arc::new(TodoStore::new(&db_path).await.unwrap());
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: reminder_store.clone(),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: todo_store.clone(),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let ingest = |body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/webhook/ingest")
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(ingest(json!({
+            "kind": "reminder",
+            "user_id": "u",
+            "title": "call back",
+            "due_at": 1_700_000_000
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(value["kind"], "reminder");
+    assert_eq!(value["item"]["title"], "call back");
+    assert_eq!(value["item"]["due_at"], 1_700_000_000);
+    assert_eq!(reminder_store.count_open("u").await.unwrap(), 1);
+
+    let response = app
+        .clone()
+        .oneshot(ingest(
+            json!({"kind": "todo", "user_id": "u", "title": "buy milk"}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(todo_store.count_open("u").await.unwrap(), 1);
+
+    for malformed in [
+        json!({"kind": "reminder", "user_id": "u", "title": "no time"}),
+        json!({"kind": "reminder", "user_id": "u", "due_at": 1}),
+        json!({"kind": "calendar", "user_id": "u", "title": "x"}),
+        json!({"kind": "todo", "user_id": "u", "title": "  "}),
+    ] {
+        let response = app.clone().oneshot(ingest(malformed)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(reminder_store.count_open("u").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn daemon_tool_schema_endpoint_omits_disabled_tools() {
+    let server = MockServer::start_async().await;
+    let agent = ButterflyBot::from_config(Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: Some(json!({"search_internet": {"enabled": false}})),
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    })
+    .await
+    .unwrap();
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/tools/schema")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let tools = value["tools"].as_array().unwrap();
+    let todo = tools
+        .iter()
+        .find(|tool| tool["name"] == "todo")
+        .expect("todo tool listed");
+    assert_eq!(todo["parameters"]["type"], "object");
+    assert!(todo["description"].as_str().is_some_and(|d| !d.is_empty()));
+    assert!(tools.iter().all(|tool| tool["name"] != "search_internet"));
+}
+
+#[tokio::test]
+async fn daemon_ui_events_stream_frames_incrementally() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx: ui_event_tx.clone(),
+        db_path,
+        webhook: None,
+        sse_keep_alive: Some(Duration::from_millis(50)),
+        cors: None,
+        body_limits: None,
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+
+    let mut response = reqwest::Client::new()
+        .get(format!("http://{addr}/ui_events"))
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+
+    // Nothing has been sent yet, so the first frame must be a keep-alive comment.
+    let first = timeout(Duration::from_secs(5), response.chunk())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(first.starts_with(b":"));
+
+    let event = |tool: &str| UiEvent {
+        event_type: "tool".to_string(),
+        user_id: "u1".to_string(),
+        tool: tool.to_string(),
+        status: "success".to_string(),
+        payload: json!({}),
+        timestamp: 0,
+        request_id: None,
+    };
+    for tool in ["first", "second"] {
+        ui_event_tx.send(event(tool)).unwrap();
+        let frame = loop {
+            let chunk = timeout(Duration::from_secs(5), response.chunk())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let text = String::from_utf8(chunk.to_vec()).unwrap();
+            if text.starts_with("data:") {
+                break text;
+            }
+        };
+        // Each frame arrives before the next event is even sent.
+        assert!(frame.contains(&format!("\"tool\":\"{tool}\"")));
+        assert!(frame.ends_with("\n\n"));
+    }
+}
+
+#[tokio::test]
+async fn daemon_embed_returns_vectors_in_input_order() {
+    let server = MockServer::start_async().await;
+    let embed_mock = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/embeddings").json_body_partial(
+                json!({"input": ["alpha", "beta", "gamma"], "model": "embed-small"}).to_string(),
+            );
+            then.status(200).json_body(json!({
+                "object": "list",
+                "model": "embed-small",
+                "data": [
+                    {"object": "embedding", "index": 2, "embedding": [2.0, 2.5]},
+                    {"object": "embedding", "index": 0, "embedding": [0.0, 0.5]},
+                    {"object": "embedding", "index": 1, "embedding": [1.0, 1.5]}
+                ],
+                "usage": {"prompt_tokens": 3, "total_tokens": 3}
+            }));
+        })
+        .await;
+
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+    let post = |body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/embed")
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(post(
+            json!({"inputs": ["alpha", "beta", "gamma"], "model": "embed-small"}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(
+        value["vectors"],
+        json!([[0.0, 0.5], [1.0, 1.5], [2.0, 2.5]])
+    );
+    embed_mock.assert_hits(1);
+
+    let response = app
+        .clone()
+        .oneshot(post(json!({"inputs": []})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let too_many = vec!["x"; butterfly_bot::daemon::MAX_EMBED_INPUTS + 1];
+    let response = app
+        .oneshot(post(json!({"inputs": too_many})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    embed_mock.assert_hits(1);
+}
+
+#[tokio::test]
+async fn daemon_errors_share_a_json_envelope() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let unauthorized = Request::builder()
+        .method("POST")
+        .uri("/process_text")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({"user_id": "u", "text": "hi"}).to_string(),
+        ))
+        .unwrap();
+    let cases = vec![
+        (unauthorized, StatusCode::UNAUTHORIZED, "unauthorized"),
+        (
+            request("POST", "/plans/generate", r#"{"user_id":"u","goal":" "}"#),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            request("DELETE", "/tasks/999?user_id=u", ""),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+        (
+            request("POST", "/process_text", "{not json"),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            request("POST", "/process_text", r#"{"user_id":"u","text":"  "}"#),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            request("POST", "/process_text", r#"{"text":"missing user"}"#),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_body",
+        ),
+        (
+            request("GET", "/process_text", ""),
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+        ),
+        (
+            request("GET", "/no/such/route", ""),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+    ];
+
+    for (request, status, code) in cases {
+        let uri = request.uri().clone();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), status, "{uri}");
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("application/json"));
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(value["code"], code, "{uri}");
+        assert!(
+            value["error"]
+                .as_str()
+                .is_some_and(|error| !error.is_empty()),
+            "{uri}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn daemon_cors_allows_configured_origins_only() {
+    assert!(!CorsPolicy::default().allows_origin("http://localhost:3000"));
+    let wildcard = CorsPolicy::from_config(Some(&DaemonConfig {
+        cors_origins: Some(vec!["*".to_string()]),
+        ..DaemonConfig::default()
+    }));
+    assert_eq!(
+        wildcard.response_headers("http://anything")["access-control-allow-origin"],
+        "*"
+    );
+
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let config = DaemonConfig {
+        cors_origins: Some(vec!["http://app.example".to_string()]),
+        ..DaemonConfig::default()
+    };
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: Some(Arc::new(CorsPolicy::from_config(Some(&config)))),
+        body_limits: None,
+    };
+    let tokenless = build_router(AppState {
+        token: String::new(),
+        ..state.clone()
+    });
+    let app = build_router(state);
+    let health = |origin: &str| {
+        Request::builder()
+            .method("GET")
+            .uri("/health")
+            .header("origin", origin)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(health("http://app.example"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "http://app.example"
+    );
+
+    let response = app
+        .clone()
+        .oneshot(health("http://evil.example"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    let preflight = |origin: &str| {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/process_text")
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header(
+                "access-control-request-headers",
+                "authorization, content-type",
+            )
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app
+        .clone()
+        .oneshot(preflight("http://app.example"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "http://app.example"
+    );
+    let methods = response.headers()["access-control-allow-methods"]
+        .to_str()
+        .unwrap();
+    assert!(methods.contains("POST"));
+    let headers = response.headers()["access-control-allow-headers"]
+        .to_str()
+        .unwrap();
+    assert!(headers.contains("authorization"));
+
+    let response = app.oneshot(preflight("http://evil.example")).await.unwrap();
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    let response = tokenless
+        .clone()
+        .oneshot(health("http://app.example"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = tokenless
+        .oneshot(
+            Request::builder()
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn daemon_history_returns_stored_turns_in_order() {
+    let server = MockServer::start_async().await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let lancedb_dir = tempfile::tempdir().unwrap();
+
+    let seed = SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(&db_path))
+        .await
+        .unwrap();
+    for (role, content) in [
+        ("user", "first question"),
+        ("assistant", "first answer"),
+        ("user", "second question"),
+        ("assistant", "second answer"),
+    ] {
+        seed.append_message("u", role, content).await.unwrap();
+    }
+    seed.append_message("other", "user", "not mine")
+        .await
+        .unwrap();
+
+    let config = Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: Some(MemoryConfig {
+            enabled: Some(true),
+            sqlite_path: Some(db_path.clone()),
+            lancedb_path: Some(lancedb_dir.path().to_str().unwrap().to_string()),
+            summary_model: None,
+            embedding_model: None,
+            rerank_model: None,
+            summary_threshold: None,
+            summary_style: None,
+            retention_days: None,
+            chat_ttl_days: None,
+            namespace: None,
+            min_score: None,
+            search_limit: None,
+            context_top_k: None,
+            history_window: None,
+            embedding_limit: None,
+            vector_index: None,
+        }),
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    };
+    let agent = ButterflyBot::from_config(config).await.unwrap();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+    let history = |query: &str| {
+        Request::builder()
+            .method("GET")
+            .uri(format!("/history?{query}"))
+            .header("authorization", "Bearer token")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(history("user_id=u")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let messages = value["messages"].as_array().unwrap();
+    let turns: Vec<(&str, &str)> = messages
+        .iter()
+        .map(|m| (m["role"].as_str().unwrap(), m["content"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        turns,
+        vec![
+            ("user", "first question"),
+            ("assistant", "first answer"),
+            ("user", "second question"),
+            ("assistant", "second answer"),
+        ]
+    );
+
+    let before_id = messages[2]["id"].as_i64().unwrap();
+    let response = app
+        .oneshot(history(&format!("user_id=u&before_id={before_id}&limit=1")))
+        .await
+        .unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(value["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(value["messages"][0]["content"], "first answer");
+}
+
+#[tokio::test]
+async fn daemon_captures_lists_recorded_records() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let capture_store = CaptureStore::new(&db_path).await.unwrap();
+    capture_store
+        .record("u1", "mood", &json!({"mood": "tired", "score": 3}))
+        .await
+        .unwrap();
+    capture_store
+        .record("u1", "expense", &json!({"amount": 12.5}))
+        .await
+        .unwrap();
+    capture_store
+        .record("u2", "mood", &json!({"mood": "great", "score": 9}))
+        .await
+        .unwrap();
+    capture_store
+        .record("u1", "mood", &json!({"mood": "rested", "score": 8}))
+        .await
+        .unwrap();
+
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(capture_store),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/captures?user_id=u1&name=mood")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let captures = value["captures"].as_array().unwrap();
+    assert_eq!(captures.len(), 2);
+    assert_eq!(captures[0]["data"]["mood"], "tired");
+    assert_eq!(captures[1]["data"]["mood"], "rested");
+    assert!(captures
+        .iter()
+        .all(|capture| capture["user_id"] == "u1" && capture["name"] == "mood"));
+    assert!(captures[0]["created_at"].as_i64().is_some());
+}
+
+#[tokio::test]
+async fn daemon_rejects_oversized_bodies_with_413() {
+    let server = MockServer::start_async().await;
+    let transcription = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/audio/transcriptions");
+            then.status(200).json_body(json!({
+                "text": "hello there",
+                "usage": {"type": "duration", "seconds": 1.0}
+            }));
+        })
+        .await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: Some(BodyLimits {
+            max_body_bytes: 1024,
+            max_audio_body_bytes: 8 * 1024,
+        }),
+    };
+    let app = build_router(state);
+    let post = |uri: &str, body: Vec<u8>| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let text = json!({"user_id": "u", "text": "x".repeat(2048)}).to_string();
+    let response = app
+        .clone()
+        .oneshot(post("/process_text", text.into_bytes()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = app
+        .clone()
+        .oneshot(post("/transcribe?format=wav", vec![0u8; 16 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    transcription.assert_hits_async(0).await;
+
+    // Audio above the general limit but under the audio one goes through.
+    let response = app
+        .oneshot(post("/transcribe?format=wav", vec![0u8; 4 * 1024]))
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(status, StatusCode::OK, "{value}");
+    assert_eq!(value["text"], "hello there");
+}
+
+#[tokio::test]
+async fn daemon_active_agent_switches_persona_per_user() {
+    let server = MockServer::start_async().await;
+    let reply = |marker: &'static str, content: &'static str| {
+        let server = &server;
+        async move {
+            server
+                .mock_async(|when, then| {
+                    when.method(POST)
+                        .path("/chat/completions")
+                        .body_contains(marker);
+                    then.status(200).json_body(json!({
+                        "id": "chatcmpl-test",
+                        "object": "chat.completion",
+                        "created": 1,
+                        "model": "gpt-4o-mini",
+                        "choices": [{
+                            "index": 0,
+                            "message": {"role": "assistant", "content": content},
+                            "finish_reason": "stop"
+                        }]
+                    }));
+                })
+                .await
+        }
+    };
+    let helper_mock = reply("HELPER-RULES", "helper reply").await;
+    let pirate_mock = reply("PIRATE-RULES", "arr").await;
+
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let agent_config = |name: &str, instructions: &str| AgentConfig {
+        name: name.to_string(),
+        specialization: None,
+        instructions: Some(instructions.to_string()),
+        tools: Vec::new(),
+        temperature: None,
+        top_p: None,
+        max_tokens: None,
+        delegates: Vec::new(),
+        display_name: None,
+        memory_namespace: None,
+    };
+    let config = Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: Some(MemoryConfig {
+            enabled: Some(false),
+            sqlite_path: Some(db_path.clone()),
+            lancedb_path: None,
+            summary_model: None,
+            embedding_model: None,
+            rerank_model: None,
+            summary_threshold: None,
+            summary_style: None,
+            retention_days: None,
+            chat_ttl_days: None,
+            namespace: None,
+            min_score: None,
+            search_limit: None,
+            context_top_k: None,
+            history_window: None,
+            embedding_limit: None,
+            vector_index: None,
+        }),
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: Some(vec![
+            agent_config("helper", "HELPER-RULES"),
+            agent_config("pirate", "PIRATE-RULES"),
+        ]),
+        scheduler: None,
+        daemon: None,
+    };
+    let agent = ButterflyBot::from_config(config).await.unwrap();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+    let post = |uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let send = |request: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            (status, value)
+        }
+    };
+
+    let (_, value) = send(post("/process_text", json!({"user_id":"u","text":"hi"}))).await;
+    assert_eq!(value["text"], "helper reply");
+
+    let (status, value) = send(post(
+        "/agent/active",
+        json!({"user_id":"u","agent":"pirate"}),
+    ))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value["agent"], "pirate");
+    assert_eq!(value["agents"], json!(["helper", "pirate"]));
+
+    let (_, value) = send(post("/process_text", json!({"user_id":"u","text":"hi"}))).await;
+    assert_eq!(value["text"], "arr");
+    // Other users keep the default agent.
+    let (_, value) = send(post("/process_text", json!({"user_id":"v","text":"hi"}))).await;
+    assert_eq!(value["text"], "helper reply");
+    // A single request can still pick its agent.
+    let (_, value) = send(post(
+        "/process_text",
+        json!({"user_id":"u","text":"hi","agent":"helper"}),
+    ))
+    .await;
+    assert_eq!(value["text"], "helper reply");
+    pirate_mock.assert_hits_async(1).await;
+    helper_mock.assert_hits_async(3).await;
+
+    let (status, _) = send(post(
+        "/agent/active",
+        json!({"user_id":"u","agent":"ghost"}),
+    ))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, value) = send(post("/agent/active", json!({"user_id":"u","agent":null}))).await;
+    assert_eq!(value["agent"], "helper");
+    let (_, value) = send(
+        Request::builder()
+            .uri("/agent/active?user_id=u")
+            .header("authorization", "Bearer token")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(value["agent"], "helper");
+}
+
+#[tokio::test]
+async fn daemon_models_lists_provider_models() {
+    let server = MockServer::start_async().await;
+    let models_mock = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/models");
+            then.status(200).json_body(json!({
+                "object": "list",
+                "data": [
+                    {"id": "gpt-4o-mini", "object": "model", "created": 1, "owned_by": "openai"},
+                    {"id": "llama3.2:latest", "object": "model", "created": 2, "owned_by": ""}
+                ]
+            }));
+        })
+        .await;
+
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/models")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(
+        value,
+        json!({"models": [
+            {"id": "gpt-4o-mini", "owned_by": "openai"},
+            {"id": "llama3.2:latest"}
+        ]})
+    );
+    models_mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn daemon_schedule_preview_lists_upcoming_times() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let request = |body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/schedule/preview")
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            json!({"rule": "30 9 * * 1", "from": 1_700_000_000, "count": 2}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(
+        value,
+        json!({"occurrences": [1_700_472_600, 1_701_077_400]})
+    );
+
+    let response = app
+        .clone()
+        .oneshot(request(json!({"rule": "every day at noon"})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn daemon_snoozes_reminders_with_configured_presets() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let reminder_store = ReminderStore::new(&db_path)
+        .await
+        .unwrap()
+        .with_snooze_presets(vec![120, 0, 900]);
+    let reminder = reminder_store
+        .create_reminder("user", "stand up", reminder_store.now())
+        .await
+        .unwrap();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/reminders/snooze_presets")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(value, json!({"presets": [120, 900]}));
+
+    let snooze = |user_id: &str, preset: usize| {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/reminders/{}/snooze", reminder.id))
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({"user_id": user_id, "preset": preset}).to_string(),
+            ))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(snooze("user", 1)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.clone().oneshot(snooze("user", 2)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.clone().oneshot(snooze("someone", 0)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn daemon_reminder_claims_are_leased_until_acked() {
+    let server = MockServer::start_async().await;
+    let agent = make_agent(&server).await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let reminder_store = ReminderStore::new(&db_path)
+        .await
+        .unwrap()
+        .with_clock(clock.clone());
+    let first = reminder_store
+        .create_reminder("user", "water plants", 999_990)
+        .await
+        .unwrap();
+    let second = reminder_store
+        .create_reminder("user", "call back", 999_995)
+        .await
+        .unwrap();
+    reminder_store
+        .create_reminder("user", "later", 1_000_500)
+        .await
+        .unwrap();
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(reminder_store),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx,
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let post = |uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let call = |request: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            (status, value)
+        }
+    };
+    let claimed_ids = |value: &serde_json::Value| -> Vec<i64> {
+        value["reminders"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["id"].as_i64().unwrap())
+            .collect()
+    };
+
+    let (status, claimed) = call(post("/reminders/claim", json!({"lease_secs": 60}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        claimed_ids(&claimed),
+        vec![first.id as i64, second.id as i64]
+    );
+    assert_eq!(claimed["reminders"][0]["user_id"], "user");
+    assert_eq!(claimed["lease_expires_at"], 1_000_060);
+
+    // A second worker gets nothing while the lease holds.
+    let (_, again) = call(post("/reminders/claim", json!({"user_id": "user"}))).await;
+    assert!(claimed_ids(&again).is_empty());
+
+    let token = claimed["claim_token"].as_str().unwrap().to_string();
+    let ack = |token: &str, ids: Vec<i32>| {
+        post("/reminders/ack", json!({"claim_token": token, "ids": ids}))
+    };
+
+    // Only the claimer's token settles the claim.
+    let (_, acked) = call(ack("someone-else", vec![first.id])).await;
+    assert_eq!(acked, json!({"acked": []}));
+    let (status, acked) = call(ack(&token, vec![first.id])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(acked, json!({"acked": [first.id]}));
+
+    // The unacked reminder comes back once the lease expires; the acked one doesn't.
+    clock.advance(60);
+    let (_, acked) = call(ack(&token, vec![second.id])).await;
+    assert_eq!(acked, json!({"acked": []}), "the lease has run out");
+    let (_, reclaimed) = call(post("/reminders/claim", json!({"lease_secs": 60}))).await;
+    assert_eq!(claimed_ids(&reclaimed), vec![second.id as i64]);
+
+    // Acking something that isn't leased is a no-op, and so is the old token.
+    let (_, acked) = call(ack(&token, vec![first.id, second.id])).await;
+    assert_eq!(acked, json!({"acked": []}));
+
+    let (status, _) = call(post("/reminders/claim", json!({"lease_secs": 0}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn daemon_completion_routes_complete_linked_items() {
+    let server = MockServer::start_async().await;
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap();
+    let state = make_state(make_agent(&server).await, db_path).await;
+    let todos = state.todo_store.clone();
+    let reminders = state.reminder_store.clone();
+    let links = LinkService::new(reminders.clone(), todos.clone());
+    let app = build_router(state);
+
+    let due = reminders.now() - 10;
+    let mut pairs = Vec::new();
+    for title in ["water plants", "call back"] {
+        let todo = todos.create_item("user", title, None).await.unwrap();
+        let reminder = reminders.create_reminder("user", title, due).await.unwrap();
+        links.link("user", todo.id, reminder.id).await.unwrap();
+        pairs.push((todo.id, reminder.id));
+    }
+    let post = |uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(post("/reminders/claim", json!({"user_id": "user"})))
+        .await
+        .unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let claimed: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let token = claimed["claim_token"].as_str().unwrap();
+    let response = app
+        .clone()
+        .oneshot(post(
+            "/reminders/ack",
+            json!({"claim_token": token, "ids": [pairs[0].1], "complete": true}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let todo = todos.get_item(pairs[0].0).await.unwrap().unwrap();
+    assert!(
+        todo.completed_at.is_some(),
+        "acking completes the linked todo"
+    );
+
+    let response = app
+        .clone()
+        .oneshot(post("/todos/complete_all", json!({"user_id": "user"})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let reminder = reminders
+        .get_reminder("user", pairs[1].1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(reminder.completed_at.is_some());
+}
+
+#[tokio::test]
+async fn daemon_stream_rejects_invalid_requests_before_streaming() {
+    let server = MockServer::start_async().await;
+    let chat_mock = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(500);
+        })
+        .await;
+    let db = NamedTempFile::new().unwrap();
+    let state = make_state(make_agent(&server).await, db.path().to_str().unwrap()).await;
+    let app = build_router(state);
+
+    let cases = [
+        (
+            json!({"user_id": "u", "text": "clean up", "dry_run": true}),
+            "dry_run",
+        ),
+        (json!({"user_id": "u", "text": "   "}), "empty"),
+        (
+            json!({"user_id": "u", "text": "hi", "agent": "nobody"}),
+            "nobody",
+        ),
+    ];
+    for (request, error) in cases {
+        let response = app.clone().oneshot(stream_request(request)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{error}");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"].as_str().unwrap().contains(error), "{body}");
+    }
+    chat_mock.assert_hits(0);
+}
+
+#[tokio::test]
+async fn daemon_stream_writes_heartbeats_during_slow_tool_rounds() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(200)
+                .delay(Duration::from_millis(1500))
+                .json_body(json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion",
+                    "created": 1,
+                    "model": "gpt-4o-mini",
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "done"},
+                        "finish_reason": "stop"
+                    }]
+                }));
+        })
+        .await;
+    let config = Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some(server.base_url()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: Some(json!({"settings": {"stream_heartbeat_secs": 1}})),
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    };
+    let agent = ButterflyBot::from_config(config).await.unwrap();
+    // Tool rounds are where heartbeats are sent.
+    assert!(agent
+        .register_tool(Arc::new(common::DummyTool::new("noop")))
+        .await
+        .unwrap());
+    let db = NamedTempFile::new().unwrap();
+    let app = build_router(make_state(agent, db.path().to_str().unwrap()).await);
+
+    let response = app
+        .oneshot(stream_request(
+            json!({"user_id": "u", "text": "take your time", "persist": false}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.starts_with(STREAM_HEARTBEAT), "{body:?}");
+    assert_eq!(body.replace(STREAM_HEARTBEAT, ""), "done");
 }
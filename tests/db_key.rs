@@ -0,0 +1,52 @@
+mod common;
+
+use base64::{engine::general_purpose, Engine as _};
+use butterfly_bot::{db, vault};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+fn open_with_key(path: &str, key: &str) -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(path).unwrap();
+    conn.batch_execute(&format!("PRAGMA key = \"{key}\";"))
+        .unwrap();
+    conn
+}
+
+#[test]
+fn derived_key_reopens_database_only_with_same_passphrase() {
+    common::install_mock_keyring();
+    std::env::remove_var("BUTTERFLY_BOT_DB_KEY");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret.db").to_string_lossy().to_string();
+
+    assert_eq!(db::get_sqlcipher_key().unwrap(), None);
+    db::setup_db_passphrase("correct horse battery staple").unwrap();
+    assert!(db::setup_db_passphrase("another one").is_err());
+
+    let mut conn = SqliteConnection::establish(&path).unwrap();
+    db::apply_sqlcipher_key_sync(&mut conn).unwrap();
+    conn.batch_execute("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('hi');")
+        .unwrap();
+    drop(conn);
+
+    let key = db::get_sqlcipher_key().unwrap().unwrap();
+    assert!(key.starts_with("x'") && key.len() == 67);
+    assert!(!key.contains("correct horse"));
+
+    let mut conn = SqliteConnection::establish(&path).unwrap();
+    db::apply_sqlcipher_key_sync(&mut conn).unwrap();
+    conn.batch_execute("SELECT count(*) FROM notes").unwrap();
+    drop(conn);
+
+    let salt = vault::get_secret("db_key_salt").unwrap().unwrap();
+    let salt = general_purpose::STANDARD.decode(salt).unwrap();
+    assert_eq!(
+        db::derive_sqlcipher_key("correct horse battery staple", &salt).unwrap(),
+        key
+    );
+    // Same salt, different passphrase: SQLCipher can't read the file.
+    let wrong = db::derive_sqlcipher_key("wrong passphrase", &salt).unwrap();
+    let mut conn = open_with_key(&path, &wrong);
+    assert!(conn.batch_execute("SELECT count(*) FROM notes").is_err());
+}
@@ -0,0 +1,592 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use tempfile::NamedTempFile;
+
+use butterfly_bot::clock::{Clock, MockClock};
+use butterfly_bot::config::{Config, SchedulerConfig, MIN_SCHEDULER_TICK_SECS};
+use butterfly_bot::interfaces::scheduler::ScheduledJob;
+use butterfly_bot::reminders::{
+    resolve_dedupe_window, resolve_snooze_presets, ReminderStatus, ReminderStore,
+    DEFAULT_SNOOZE_PRESETS,
+};
+use butterfly_bot::scheduler::{DueWork, DueWorkHandler, DueWorkScheduler, Scheduler};
+use butterfly_bot::tasks::{InterruptedRunPolicy, TaskStore};
+use butterfly_bot::wakeup::WakeupStore;
+use butterfly_bot::{ButterflyBotError, Result};
+
+type Seen = Arc<Mutex<Vec<(String, String)>>>;
+
+struct RecordingHandler {
+    seen: Seen,
+}
+
+#[async_trait]
+impl DueWorkHandler for RecordingHandler {
+    async fn handle(&self, work: DueWork) -> Result<()> {
+        let mut guard = self.seen.lock().unwrap();
+        guard.push((work.kind().to_string(), work.user_id().to_string()));
+        Ok(())
+    }
+}
+
+fn now_ts() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[tokio::test]
+async fn due_work_scheduler_dispatches_each_kind_once_per_tick() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let tasks = Arc::new(TaskStore::new(path).await.unwrap());
+    let wakeups = Arc::new(WakeupStore::new(path).await.unwrap());
+    let reminders = Arc::new(ReminderStore::new(path).await.unwrap());
+
+    let now = now_ts();
+    tasks
+        .create_task("u1", "report", "send report", now - 10, None)
+        .await
+        .unwrap();
+    wakeups
+        .create_task("u2", "check in", "say hi", 1)
+        .await
+        .unwrap();
+    reminders
+        .create_reminder("u3", "stretch", now - 5)
+        .await
+        .unwrap();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let scheduler = DueWorkScheduler::new(
+        Arc::new(RecordingHandler { seen: seen.clone() }),
+        Duration::from_secs(60),
+    )
+    .with_tasks(tasks.clone())
+    .with_wakeups(wakeups.clone())
+    .with_reminders(reminders.clone());
+
+    let tick = now + 61;
+    assert_eq!(scheduler.poll_once(tick).await.unwrap(), 3);
+    assert_eq!(scheduler.poll_once(tick).await.unwrap(), 0);
+
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    assert_eq!(
+        seen,
+        vec![
+            ("reminder".to_string(), "u3".to_string()),
+            ("task".to_string(), "u1".to_string()),
+            ("wakeup".to_string(), "u2".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn due_work_scheduler_caps_items_per_tick() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let reminders = Arc::new(ReminderStore::new(path).await.unwrap());
+    let now = now_ts();
+    for idx in 0..5 {
+        reminders
+            .create_reminder("u", &format!("r{idx}"), now - 1)
+            .await
+            .unwrap();
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let scheduler = DueWorkScheduler::new(
+        Arc::new(RecordingHandler { seen: seen.clone() }),
+        Duration::from_secs(60),
+    )
+    .with_reminders(reminders)
+    .with_max_per_tick(2);
+
+    assert_eq!(scheduler.poll_once(now).await.unwrap(), 2);
+    assert_eq!(scheduler.poll_once(now).await.unwrap(), 2);
+    assert_eq!(scheduler.poll_once(now).await.unwrap(), 1);
+    assert_eq!(scheduler.poll_once(now).await.unwrap(), 0);
+    assert_eq!(seen.lock().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn claimed_items_are_redelivered_after_lease_expires() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let tasks = TaskStore::new(path).await.unwrap();
+    let reminders = ReminderStore::new(path).await.unwrap();
+
+    let now = now_ts();
+    let task = tasks
+        .create_task("u1", "report", "send report", now - 10, None)
+        .await
+        .unwrap();
+    reminders
+        .create_reminder("u1", "stretch", now - 5)
+        .await
+        .unwrap();
+
+    // Claim both, then "crash" before finalizing either of them.
+    assert!(tasks.claim(task.id, now, 30).await.unwrap());
+    let claimed = reminders.claim_reminders(None, now, 30, 10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+
+    assert!(!tasks.claim(task.id, now, 30).await.unwrap());
+    assert!(tasks.list_due(now, 10).await.unwrap().is_empty());
+    assert!(reminders
+        .claim_reminders(None, now, 30, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let later = now + 31;
+    assert_eq!(tasks.list_due(later, 10).await.unwrap().len(), 1);
+    assert!(tasks.claim(task.id, later, 30).await.unwrap());
+    let redelivered = reminders
+        .claim_reminders(None, later, 30, 10)
+        .await
+        .unwrap();
+    assert_eq!(redelivered.len(), 1);
+    assert_eq!(redelivered[0].1.id, claimed[0].1.id);
+
+    reminders
+        .mark_fired(&[claimed[0].1.id], later)
+        .await
+        .unwrap();
+    assert!(reminders
+        .claim_reminders(None, later + 60, 30, 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn mock_clock_drives_due_checks() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let tasks = Arc::new(
+        TaskStore::new(path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    );
+    let reminders = ReminderStore::new(path)
+        .await
+        .unwrap()
+        .with_clock(clock.clone());
+
+    reminders
+        .create_reminder("u1", "stretch", clock.now() + 30)
+        .await
+        .unwrap();
+    tasks
+        .create_task("u1", "report", "send report", clock.now() + 60, None)
+        .await
+        .unwrap();
+
+    clock.advance(29);
+    assert!(reminders
+        .due_reminders("u1", clock.now(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+    clock.advance(1);
+    assert_eq!(
+        reminders
+            .due_reminders("u1", clock.now(), 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let scheduler = DueWorkScheduler::new(
+        Arc::new(RecordingHandler { seen: seen.clone() }),
+        Duration::from_secs(60),
+    )
+    .with_clock(clock.clone())
+    .with_tasks(tasks.clone());
+
+    assert!(tasks.list_due(clock.now(), 10).await.unwrap().is_empty());
+    scheduler.run().await.unwrap();
+    assert!(seen.lock().unwrap().is_empty());
+
+    clock.set(1_000_060);
+    assert_eq!(tasks.list_due(clock.now(), 10).await.unwrap().len(), 1);
+    scheduler.run().await.unwrap();
+    assert_eq!(
+        seen.lock().unwrap().clone(),
+        vec![("task".to_string(), "u1".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn configured_tick_picks_up_due_work_within_one_tick() {
+    let config: Config = serde_json::from_value(serde_json::json!({
+        "scheduler": {"tick_secs": 1}
+    }))
+    .unwrap();
+    let tick = config.scheduler.unwrap().tick().unwrap();
+    assert_eq!(tick, Duration::from_secs(1));
+    let floor = SchedulerConfig {
+        tick_secs: Some(0),
+        ..SchedulerConfig::default()
+    }
+    .tick()
+    .unwrap();
+    assert!(config.scheduler.unwrap().delivers_reminders());
+    let external: Config = serde_json::from_value(serde_json::json!({
+        "scheduler": {"deliver_reminders": false}
+    }))
+    .unwrap();
+    assert!(!external.scheduler.unwrap().delivers_reminders());
+    assert_eq!(floor, Duration::from_secs(MIN_SCHEDULER_TICK_SECS));
+
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let tasks = Arc::new(
+        TaskStore::new(path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    );
+    tasks
+        .create_task("u1", "report", "send report", clock.now() + 1, None)
+        .await
+        .unwrap();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut scheduler = Scheduler::new();
+    scheduler.register_job(Arc::new(
+        DueWorkScheduler::new(Arc::new(RecordingHandler { seen: seen.clone() }), tick)
+            .with_clock(clock.clone())
+            .with_tasks(tasks.clone()),
+    ));
+    scheduler.start();
+    // The first tick runs at once, before the task is due.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(seen.lock().unwrap().is_empty());
+
+    clock.advance(1);
+    let started = Instant::now();
+    while seen.lock().unwrap().is_empty() {
+        assert!(
+            started.elapsed() <= tick + Duration::from_millis(500),
+            "due task not picked up within one tick"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    scheduler.stop().await;
+    assert_eq!(
+        seen.lock().unwrap().clone(),
+        vec![("task".to_string(), "u1".to_string())]
+    );
+}
+
+fn recording_scheduler(tasks: Arc<TaskStore>) -> (DueWorkScheduler, Seen) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let scheduler = DueWorkScheduler::new(
+        Arc::new(RecordingHandler { seen: seen.clone() }),
+        Duration::from_secs(60),
+    )
+    .with_tasks(tasks);
+    (scheduler, seen)
+}
+
+#[tokio::test]
+async fn interrupted_task_is_rerun_once_after_restart() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let now = now_ts();
+
+    let task_id = {
+        let tasks = TaskStore::new(path).await.unwrap();
+        let task = tasks
+            .create_task("u1", "report", "send report", now - 10, None)
+            .await
+            .unwrap();
+        // The run starts, then the daemon dies before it finishes.
+        assert!(tasks.claim(task.id, now, 600).await.unwrap());
+        let running = tasks.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!(running.running_since, Some(now));
+        task.id
+    };
+
+    let tasks = Arc::new(TaskStore::new(path).await.unwrap());
+    let settled = tasks
+        .reconcile_interrupted(InterruptedRunPolicy::Rerun)
+        .await
+        .unwrap();
+    assert_eq!(settled.len(), 1);
+    let task = tasks.get_task(task_id).await.unwrap().unwrap();
+    assert_eq!(task.running_since, None);
+    assert_eq!(task.last_status.as_deref(), Some("interrupted"));
+
+    let (scheduler, seen) = recording_scheduler(tasks.clone());
+    let tick = now_ts();
+    assert_eq!(scheduler.poll_once(tick).await.unwrap(), 1);
+    assert_eq!(scheduler.poll_once(tick).await.unwrap(), 0);
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    let task = tasks.get_task(task_id).await.unwrap().unwrap();
+    assert!(!task.enabled);
+    assert_eq!(task.last_status.as_deref(), Some("completed"));
+    assert!(tasks
+        .reconcile_interrupted(InterruptedRunPolicy::Rerun)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn interrupted_task_can_be_marked_failed_after_restart() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let now = now_ts();
+
+    let (one_shot_id, recurring_id) = {
+        let tasks = TaskStore::new(path).await.unwrap();
+        let one_shot = tasks
+            .create_task("u1", "report", "send report", now - 10, None)
+            .await
+            .unwrap();
+        let recurring = tasks
+            .create_task("u2", "inbox", "check inbox", now - 10, Some(30))
+            .await
+            .unwrap();
+        assert!(tasks.claim(one_shot.id, now, 600).await.unwrap());
+        assert!(tasks.claim(recurring.id, now, 600).await.unwrap());
+        (one_shot.id, recurring.id)
+    };
+
+    let tasks = Arc::new(TaskStore::new(path).await.unwrap());
+    let settled = tasks
+        .reconcile_interrupted(InterruptedRunPolicy::Fail)
+        .await
+        .unwrap();
+    assert_eq!(settled.len(), 2);
+
+    let one_shot = tasks.get_task(one_shot_id).await.unwrap().unwrap();
+    assert!(!one_shot.enabled);
+    assert_eq!(one_shot.running_since, None);
+    assert_eq!(one_shot.last_status.as_deref(), Some("failed"));
+    let recurring = tasks.get_task(recurring_id).await.unwrap().unwrap();
+    assert!(recurring.enabled);
+    assert_eq!(recurring.last_status.as_deref(), Some("failed"));
+    assert_eq!(recurring.last_run_at, Some(now));
+    assert!(recurring.next_run_at >= now + 30 * 60);
+
+    let (scheduler, seen) = recording_scheduler(tasks);
+    assert_eq!(scheduler.poll_once(now_ts()).await.unwrap(), 0);
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn snooze_presets_come_from_config_and_reschedule_from_now() {
+    let config: Config = serde_json::from_value(serde_json::json!({
+        "openai": {"api_key": "key", "model": null, "base_url": null},
+        "tools": {"reminders": {"snooze_presets": [300, 7200]}}
+    }))
+    .unwrap();
+    let value = serde_json::to_value(&config).unwrap();
+    assert_eq!(resolve_snooze_presets(&value), vec![300, 7200]);
+    assert_eq!(
+        resolve_snooze_presets(&serde_json::json!({"tools": {}})),
+        DEFAULT_SNOOZE_PRESETS.to_vec()
+    );
+
+    let db = NamedTempFile::new().unwrap();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let reminders = ReminderStore::new(db.path().to_str().unwrap())
+        .await
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_snooze_presets(resolve_snooze_presets(&value));
+    assert_eq!(reminders.snooze_presets(), &[300, 7200]);
+
+    let item = reminders
+        .create_reminder("u1", "call back", clock.now() + 10)
+        .await
+        .unwrap();
+    clock.advance(10);
+    assert!(reminders.snooze_by("u1", item.id, 1).await.unwrap());
+    assert!(reminders
+        .due_reminders("u1", clock.now(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    clock.advance(7199);
+    assert!(reminders
+        .due_reminders("u1", clock.now(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+    clock.advance(1);
+    assert_eq!(
+        reminders
+            .due_reminders("u1", clock.now(), 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    assert!(reminders.snooze_by("u1", item.id, 2).await.is_err());
+    assert!(!reminders.snooze_by("u2", item.id, 0).await.unwrap());
+}
+
+#[tokio::test]
+async fn near_identical_reminders_merge_when_dedupe_is_on() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let config = serde_json::json!({"tools": {"reminders": {"dedupe_window_secs": 120}}});
+    assert_eq!(resolve_dedupe_window(&config), Some(120));
+    assert_eq!(resolve_dedupe_window(&serde_json::json!({})), None);
+
+    let reminders = ReminderStore::new(path)
+        .await
+        .unwrap()
+        .with_dedupe_window(resolve_dedupe_window(&config));
+    let first = reminders
+        .create_reminder("u1", "Call mom", 1_900_000_000)
+        .await
+        .unwrap();
+    let second = reminders
+        .create_reminder("u1", "call mom ", 1_900_000_060)
+        .await
+        .unwrap();
+    assert_eq!(second.id, first.id);
+    assert_eq!(second.due_at, 1_900_000_000);
+    assert_eq!(
+        reminders
+            .list_reminders("u1", ReminderStatus::All, 0)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Outside the window, for another user, or once completed, it's a new reminder.
+    reminders
+        .create_reminder("u1", "Call mom", 1_900_000_600)
+        .await
+        .unwrap();
+    reminders
+        .create_reminder("u2", "Call mom", 1_900_000_000)
+        .await
+        .unwrap();
+    reminders.complete_reminder("u1", first.id).await.unwrap();
+    let reopened = reminders
+        .create_reminder("u1", "Call mom", 1_900_000_000)
+        .await
+        .unwrap();
+    assert_ne!(reopened.id, first.id);
+
+    let without_dedupe = ReminderStore::new(path).await.unwrap();
+    without_dedupe
+        .create_reminder("u1", "Call mom", 1_900_000_600)
+        .await
+        .unwrap();
+    assert_eq!(
+        reminders
+            .list_reminders("u1", ReminderStatus::All, 0)
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+}
+
+struct FailingHandler;
+
+#[async_trait]
+impl DueWorkHandler for FailingHandler {
+    async fn handle(&self, _work: DueWork) -> Result<()> {
+        Err(ButterflyBotError::Runtime("boom".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn failed_items_are_not_finalized_and_retry_after_the_lease() {
+    let db = NamedTempFile::new().unwrap();
+    let path = db.path().to_str().unwrap();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let tasks = Arc::new(
+        TaskStore::new(path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    );
+    let reminders = Arc::new(
+        ReminderStore::new(path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    );
+    tasks
+        .create_task("u1", "report", "send report", clock.now() - 1, None)
+        .await
+        .unwrap();
+    reminders
+        .create_reminder("u1", "stretch", clock.now() - 1)
+        .await
+        .unwrap();
+
+    let failing = DueWorkScheduler::new(Arc::new(FailingHandler), Duration::from_secs(60))
+        .with_clock(clock.clone())
+        .with_lease_secs(30)
+        .with_tasks(tasks.clone())
+        .with_reminders(reminders.clone());
+    assert_eq!(failing.poll_once(clock.now()).await.unwrap(), 2);
+    // Still leased, so nothing is due until the lease runs out.
+    assert_eq!(failing.poll_once(clock.now()).await.unwrap(), 0);
+
+    clock.advance(31);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let scheduler = DueWorkScheduler::new(
+        Arc::new(RecordingHandler { seen: seen.clone() }),
+        Duration::from_secs(60),
+    )
+    .with_clock(clock.clone())
+    .with_lease_secs(30)
+    .with_tasks(tasks.clone())
+    .with_reminders(reminders.clone());
+    assert_eq!(scheduler.poll_once(clock.now()).await.unwrap(), 2);
+    assert_eq!(scheduler.poll_once(clock.now() + 3600).await.unwrap(), 0);
+    assert_eq!(seen.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn schedule_validation_uses_the_store_clock() {
+    let db = NamedTempFile::new().unwrap();
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let tasks = TaskStore::new(db.path().to_str().unwrap())
+        .await
+        .unwrap()
+        .with_clock(clock.clone());
+    let ten_years = 10 * 366 * 24 * 60 * 60;
+
+    assert!(tasks
+        .validate_schedule(clock.now() + ten_years, Some(60))
+        .is_ok());
+    assert!(matches!(
+        tasks.validate_schedule(clock.now() + ten_years + 1, None),
+        Err(ButterflyBotError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        tasks.validate_schedule(0, None),
+        Err(ButterflyBotError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        tasks.validate_schedule(clock.now(), Some(0)),
+        Err(ButterflyBotError::InvalidInput(_))
+    ));
+}
@@ -0,0 +1,31 @@
+use butterfly_bot::humanize::humanize_relative;
+
+const NOW: i64 = 1_700_000_000;
+
+#[test]
+fn humanizes_past_and_future_offsets() {
+    for (offset, expected) in [
+        (0, "just now"),
+        (-9, "just now"),
+        (9, "just now"),
+        (-30, "30 seconds ago"),
+        (45, "in 45 seconds"),
+        (-60, "1 minute ago"),
+        (61, "in 1 minute"),
+        (-150, "2 minutes ago"),
+        (59 * 60, "in 59 minutes"),
+        (-3_600, "1 hour ago"),
+        (2 * 3_600 + 59, "in 2 hours"),
+        (-23 * 3_600, "23 hours ago"),
+        (-86_400, "yesterday"),
+        (86_400 + 3_600, "tomorrow"),
+        (-3 * 86_400, "3 days ago"),
+        (10 * 86_400, "in 10 days"),
+    ] {
+        assert_eq!(
+            humanize_relative(NOW + offset, NOW),
+            expected,
+            "offset {offset}"
+        );
+    }
+}
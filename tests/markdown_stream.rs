@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::sync::oneshot;
+
+use butterfly_bot::markdown_stream::{stream_until_cancelled, MarkdownChunkBuffer};
+
+#[test]
+fn markdown_chunk_buffer_emits_complete_lines() {
+    let mut buffer = MarkdownChunkBuffer::new();
+    assert_eq!(buffer.push("Hello wor"), "");
+    assert_eq!(buffer.push("ld\nsecond"), "Hello world\n");
+    assert_eq!(
+        buffer.push(" line\n| a | b |\n|--"),
+        "second line\n| a | b |\n"
+    );
+    assert_eq!(buffer.push("-|---|\n"), "|---|---|\n");
+    assert_eq!(buffer.finish(), "");
+    assert_eq!(
+        buffer.text(),
+        "Hello world\nsecond line\n| a | b |\n|---|---|\n"
+    );
+}
+
+#[test]
+fn markdown_chunk_buffer_holds_fences_split_across_chunks() {
+    let mut buffer = MarkdownChunkBuffer::new();
+    assert_eq!(buffer.push("Intro\n``"), "Intro\n");
+    assert_eq!(buffer.push("`rust\nfn main() {\n"), "");
+    assert_eq!(buffer.push("}\n`"), "");
+    assert_eq!(buffer.push("``\nafter"), "```rust\nfn main() {\n}\n```\n");
+    assert_eq!(buffer.finish(), "after");
+    assert_eq!(
+        buffer.into_text(),
+        "Intro\n```rust\nfn main() {\n}\n```\nafter"
+    );
+}
+
+#[test]
+fn markdown_chunk_buffer_flushes_unclosed_fence_on_finish() {
+    let mut buffer = MarkdownChunkBuffer::new();
+    assert_eq!(buffer.push("~~~\ncode\nmore"), "");
+    assert_eq!(buffer.finish(), "~~~\ncode\nmore");
+    assert_eq!(buffer.push("next\n"), "next\n");
+}
+
+#[tokio::test]
+async fn cancelled_stream_keeps_partial_reply_and_next_turn_runs() {
+    let slow_reply = || {
+        futures::stream::iter(["Working on it\nhalf a li", "ne, dropped\n"])
+            .map(Ok::<_, std::io::Error>)
+            .chain(
+                futures::stream::once(tokio::time::sleep(Duration::from_secs(30)))
+                    .map(|()| Ok("ne that never arrives\n")),
+            )
+    };
+
+    let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
+    let mut cancel_tx = Some(cancel_tx);
+    let mut printed = String::new();
+    let reply = tokio::time::timeout(
+        Duration::from_secs(5),
+        stream_until_cancelled(
+            slow_reply(),
+            async {
+                let _ = cancel_rx.await;
+            },
+            |ready| {
+                printed.push_str(ready);
+                // Ctrl-C lands once the first line is on screen.
+                if let Some(tx) = cancel_tx.take() {
+                    let _ = tx.send(());
+                }
+                Ok(())
+            },
+        ),
+    )
+    .await
+    .expect("cancel should stop the stream")
+    .unwrap();
+    assert!(reply.cancelled);
+    assert_eq!(reply.text, "Working on it\nhalf a li");
+    assert_eq!(printed, reply.text);
+
+    let mut printed = String::new();
+    let reply = stream_until_cancelled(
+        futures::stream::iter(["Next ", "answer\n"]).map(Ok::<_, std::io::Error>),
+        std::future::pending(),
+        |ready| {
+            printed.push_str(ready);
+            Ok(())
+        },
+    )
+    .await
+    .unwrap();
+    assert!(!reply.cancelled);
+    assert_eq!(reply.text, "Next answer\n");
+    assert_eq!(printed, "Next answer\n");
+}
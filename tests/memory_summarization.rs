@@ -1,16 +1,20 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use serde_json::json;
 use tempfile::tempdir;
 
+use butterfly_bot::config::{SummaryFormat, SummaryStyle};
 use butterfly_bot::error::Result;
 use butterfly_bot::interfaces::providers::{
     ChatEvent, ImageInput, LlmProvider, LlmResponse, MemoryProvider,
 };
 use butterfly_bot::providers::sqlite::{SqliteMemoryProvider, SqliteMemoryProviderConfig};
 
-struct SummarizerMock;
+#[derive(Default)]
+struct SummarizerMock {
+    prompts: Mutex<Vec<String>>,
+}
 
 #[async_trait]
 impl LlmProvider for SummarizerMock {
@@ -32,6 +36,7 @@ impl LlmProvider for SummarizerMock {
         Ok(LlmResponse {
             text: "ok".to_string(),
             tool_calls: Vec::new(),
+            usage: None,
         })
     }
 
@@ -42,24 +47,20 @@ impl LlmProvider for SummarizerMock {
     ) -> futures::stream::BoxStream<'static, Result<ChatEvent>> {
         use async_stream::try_stream;
         Box::pin(try_stream! {
-            yield ChatEvent {
-                event_type: "content".to_string(),
-                delta: Some("ok".to_string()),
-                name: None,
-                arguments_delta: None,
-                finish_reason: None,
-                error: None,
+            yield ChatEvent::Content {
+                delta: "ok".to_string(),
             };
         })
     }
 
     async fn parse_structured_output(
         &self,
-        _prompt: &str,
+        prompt: &str,
         _system_prompt: &str,
         _json_schema: serde_json::Value,
         _tools: Option<Vec<serde_json::Value>>,
     ) -> Result<serde_json::Value> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
         Ok(json!({
             "summary": "user likes ButterFly Bot",
             "tags": ["butterfly", "preference"],
@@ -96,7 +97,7 @@ impl LlmProvider for SummarizerMock {
 async fn summarization_inserts_memory() {
     let dir = tempdir().unwrap();
     let db_path = dir.path().join("mem.db");
-    let summarizer = Arc::new(SummarizerMock);
+    let summarizer = Arc::new(SummarizerMock::default());
     let mut config = SqliteMemoryProviderConfig::new(db_path.to_str().unwrap());
     config.summarizer = Some(summarizer);
     config.summary_threshold = Some(999);
@@ -116,3 +117,30 @@ async fn summarization_inserts_memory() {
     let results = provider.search("u1", "ButterFly Bot", 5).await.unwrap();
     assert!(!results.is_empty());
 }
+
+#[tokio::test]
+async fn summary_style_reaches_the_summarizer_prompt() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("mem.db");
+    let summarizer = Arc::new(SummarizerMock::default());
+    let mut config = SqliteMemoryProviderConfig::new(db_path.to_str().unwrap());
+    config.summarizer = Some(summarizer.clone());
+    config.summary_threshold = Some(999);
+    config.summary_style = SummaryStyle {
+        format: SummaryFormat::Bullets,
+        max_words: Some(40),
+    };
+    let provider = SqliteMemoryProvider::new(config).await.unwrap();
+
+    provider
+        .append_message("u1", "user", "I like ButterFly Bot")
+        .await
+        .unwrap();
+    provider.summarize_now("u1").await.unwrap();
+
+    let prompts = summarizer.prompts.lock().unwrap().clone();
+    assert_eq!(prompts.len(), 1);
+    assert!(prompts[0].contains("bullet points"));
+    assert!(prompts[0].contains("Keep it under 40 words."));
+    assert!(prompts[0].contains("I like ButterFly Bot"));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use butterfly_bot::config::Config;
+use butterfly_bot::error::Result;
+use butterfly_bot::onboarding::{self, Prompter};
+use butterfly_bot::vault;
+use tempfile::tempdir;
+
+struct ScriptedPrompter {
+    answers: VecDeque<String>,
+    asked: Vec<String>,
+}
+
+impl ScriptedPrompter {
+    fn new(answers: &[&str]) -> Self {
+        Self {
+            answers: answers.iter().map(|answer| answer.to_string()).collect(),
+            asked: Vec::new(),
+        }
+    }
+}
+
+impl Prompter for ScriptedPrompter {
+    fn ask(&mut self, prompt: &str) -> Result<String> {
+        self.asked.push(prompt.to_string());
+        Ok(self.answers.pop_front().unwrap_or_default())
+    }
+}
+
+#[test]
+fn onboarding_remote_branch_stores_config_and_key() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+    let lancedb_path = dir.path().join("lancedb").to_string_lossy().to_string();
+
+    let mut prompter = ScriptedPrompter::new(&[
+        "2",
+        "https://llm.example.com/v1",
+        "example-model",
+        "sk-remote",
+        "",
+        "",
+        &lancedb_path,
+        "",
+        "30",
+    ]);
+    let config = onboarding::run_onboarding(&mut prompter, &db_path).unwrap();
+
+    let openai = config.openai.as_ref().unwrap();
+    assert_eq!(
+        openai.base_url.as_deref(),
+        Some("https://llm.example.com/v1")
+    );
+    assert_eq!(openai.model.as_deref(), Some("example-model"));
+    assert_eq!(openai.api_key, None);
+    let memory = config.memory.as_ref().unwrap();
+    assert_eq!(
+        memory.embedding_model.as_deref(),
+        Some(onboarding::REMOTE_EMBEDDING_MODEL)
+    );
+    assert_eq!(memory.sqlite_path.as_deref(), Some(db_path.as_str()));
+    assert_eq!(memory.lancedb_path.as_deref(), Some(lancedb_path.as_str()));
+    assert_eq!(memory.retention_days, Some(30));
+    assert_eq!(memory.rerank_model, None);
+
+    assert_eq!(
+        vault::get_secret("openai_api_key").unwrap().as_deref(),
+        Some("sk-remote")
+    );
+    let stored = Config::from_store(&db_path).unwrap();
+    assert_eq!(
+        stored.openai.unwrap().base_url.as_deref(),
+        Some("https://llm.example.com/v1")
+    );
+    assert!(prompter.asked[3].contains("API key"));
+}
+
+#[test]
+fn onboarding_defaults_to_ollama() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+
+    let mut prompter = ScriptedPrompter::new(&[]);
+    let config = onboarding::run_onboarding(&mut prompter, &db_path).unwrap();
+
+    let openai = config.openai.unwrap();
+    assert_eq!(
+        openai.base_url.as_deref(),
+        Some(onboarding::OLLAMA_BASE_URL)
+    );
+    assert_eq!(openai.model.as_deref(), Some(onboarding::OLLAMA_MODEL));
+    assert!(prompter
+        .asked
+        .iter()
+        .all(|prompt| !prompt.contains("API key")));
+}
+
+#[test]
+fn onboarding_adds_agents_and_skips_duplicates() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+    let lancedb_path = dir.path().join("lancedb").to_string_lossy().to_string();
+
+    let mut prompter = ScriptedPrompter::new(&[
+        "1",
+        "",
+        &lancedb_path,
+        "",
+        "",
+        "scout",
+        "research",
+        "Cite sources.",
+        "search_internet, todo",
+        "scout",
+        "",
+        "",
+        "",
+    ]);
+    let config = onboarding::run_onboarding(&mut prompter, &db_path).unwrap();
+
+    assert_eq!(config.agents().len(), 1);
+    let scout = &config.agents()[0];
+    assert_eq!(scout.specialization.as_deref(), Some("research"));
+    assert_eq!(scout.instructions.as_deref(), Some("Cite sources."));
+    assert_eq!(scout.tools, vec!["search_internet", "todo"]);
+    let stored = Config::from_store(&db_path).unwrap();
+    assert_eq!(stored.agents(), config.agents());
+}
+
+#[test]
+fn onboarding_model_defaults_follow_env_overrides() {
+    common::install_mock_keyring();
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("bot.db").to_string_lossy().to_string();
+    // Only the models the other tests don't assert on, so they can run in parallel.
+    std::env::set_var(
+        onboarding::OLLAMA_EMBEDDING_MODEL_DEFAULT.env,
+        "nomic-embed-text",
+    );
+    std::env::set_var(onboarding::OLLAMA_RERANK_MODEL_DEFAULT.env, "  ");
+
+    let mut prompter = ScriptedPrompter::new(&[]);
+    let config = onboarding::run_onboarding(&mut prompter, &db_path).unwrap();
+
+    let memory = config.memory.unwrap();
+    assert_eq!(memory.embedding_model.as_deref(), Some("nomic-embed-text"));
+    // A blank override keeps the built-in model.
+    assert_eq!(
+        memory.rerank_model.as_deref(),
+        Some(onboarding::OLLAMA_RERANK_MODEL)
+    );
+}
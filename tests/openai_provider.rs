@@ -1,14 +1,17 @@
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
 use futures::StreamExt;
 use httpmock::Method::POST;
 use httpmock::MockServer;
 use serde_json::json;
 
 use butterfly_bot::client::ButterflyBot;
-use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::config::{Config, EmbeddingLimit, EmbeddingOverflow, JsonMode, OpenAiConfig};
 use butterfly_bot::error::ButterflyBotError;
 use butterfly_bot::interfaces::providers::{ImageData, ImageInput, LlmProvider};
 use butterfly_bot::providers::openai::OpenAiProvider;
-use butterfly_bot::services::query::{OutputFormat, ProcessOptions, ProcessResult, UserInput};
+use butterfly_bot::providers::rate_limit::parse_retry_after;
+use butterfly_bot::services::query::{ProcessOptions, ProcessResult, UserInput};
 
 #[tokio::test]
 async fn openai_provider_via_httpmock() {
@@ -40,9 +43,9 @@ async fn openai_provider_via_httpmock() {
 
     let mut stream = provider.chat_stream(vec![json!({"role":"user","content":"hi"})], None);
     let first = stream.next().await.unwrap().unwrap();
-    assert_eq!(first.event_type, "content");
+    assert_eq!(first.event_type(), "content");
     let last = stream.next().await.unwrap().unwrap();
-    assert_eq!(last.event_type, "message_end");
+    assert_eq!(last.event_type(), "message_end");
 
     chat_mock.assert_hits(2);
 }
@@ -93,6 +96,7 @@ async fn openai_provider_tools_images_structured_audio() {
         .unwrap();
     assert_eq!(response.tool_calls.len(), 1);
     assert_eq!(response.tool_calls[0].name, "tool1");
+    assert_eq!(response.tool_calls[0].id, "call_1");
 
     tool_mock.assert_hits(1);
 
@@ -650,25 +654,25 @@ async fn openai_provider_variants_and_agent_process() {
             api_key: Some("key".to_string()),
             model: Some("gpt-4o-mini".to_string()),
             base_url: Some(agent_server.base_url()),
+            headers: None,
+            json_mode: None,
         }),
         skill_file: None,
         heartbeat_file: None,
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
     let agent = ButterflyBot::from_config(config).await.unwrap();
     let result = agent
         .process(
             "user",
             UserInput::Text("hi".to_string()),
-            ProcessOptions {
-                prompt: None,
-                images: vec![],
-                output_format: OutputFormat::Text,
-                image_detail: "auto".to_string(),
-                json_schema: None,
-            },
+            ProcessOptions::default(),
         )
         .await
         .unwrap();
@@ -737,3 +741,249 @@ async fn openai_provider_error_paths() {
     assert!(matches!(err, ButterflyBotError::Serialization(_)));
     bad_mock.assert_hits(1);
 }
+
+#[tokio::test]
+async fn openai_provider_waits_out_retry_after() {
+    let server = MockServer::start_async().await;
+    let limited = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(429)
+                .header("retry-after", "1")
+                .json_body(json!({"error": {"message": "slow down", "type": "requests"}}));
+        })
+        .await;
+
+    // Once the first attempt is refused, swap in a working endpoint.
+    let swap = async {
+        while limited.hits_async().await == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        limited.delete_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/chat/completions");
+                then.status(200).json_body(json!({
+                    "id": "chatcmpl-1",
+                    "object": "chat.completion",
+                    "created": 1,
+                    "model": "gpt-4o-mini",
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "hello"},
+                        "finish_reason": "stop"
+                    }]
+                }));
+            })
+            .await
+    };
+
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("gpt-4o-mini".to_string()),
+        Some(server.base_url()),
+    );
+    let started = Instant::now();
+    let (text, ok_mock) = tokio::join!(provider.generate_text("hi", "", None), swap);
+    assert_eq!(text.unwrap(), "hello");
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    ok_mock.assert_hits_async(1).await;
+
+    let always_limited = MockServer::start_async().await;
+    always_limited
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions");
+            then.status(429).header("retry-after", "0");
+        })
+        .await;
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("gpt-4o-mini".to_string()),
+        Some(always_limited.base_url()),
+    );
+    let err = provider.generate_text("hi", "", None).await.unwrap_err();
+    assert!(matches!(err, ButterflyBotError::RateLimited(_)));
+}
+
+#[test]
+fn retry_after_accepts_seconds_and_http_dates() {
+    let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+    assert_eq!(
+        parse_retry_after("120", now),
+        Some(Duration::from_secs(120))
+    );
+    // 784_111_777 is Sun, 06 Nov 1994 08:49:37 GMT.
+    assert_eq!(
+        parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+        parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon", now), None);
+}
+
+fn embedding_response(vectors: &[[f32; 2]]) -> serde_json::Value {
+    let data: Vec<_> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, vector)| json!({"object": "embedding", "index": index, "embedding": vector}))
+        .collect();
+    json!({
+        "object": "list",
+        "data": data,
+        "model": "text-embedding-3-small",
+        "usage": {"prompt_tokens": 1, "total_tokens": 1}
+    })
+}
+
+#[tokio::test]
+async fn openai_provider_truncates_long_embedding_inputs() {
+    let server = MockServer::start_async().await;
+    let mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/embeddings")
+                .json_body_partial(r#"{"input": ["abcdefgh", "xy"]}"#);
+            then.status(200)
+                .json_body(embedding_response(&[[1.0, 0.0], [0.0, 1.0]]));
+        })
+        .await;
+
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("text-embedding-3-small".to_string()),
+        Some(server.base_url()),
+    )
+    .with_embedding_limit(EmbeddingLimit {
+        max_tokens: 2,
+        overflow: EmbeddingOverflow::Truncate,
+    });
+    let vectors = provider
+        .embed(vec!["abcdefghijkl".to_string(), "xy".to_string()], None)
+        .await
+        .unwrap();
+    assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    mock.assert_hits_async(1).await;
+
+    let strict = OpenAiProvider::new(
+        "key".to_string(),
+        Some("text-embedding-3-small".to_string()),
+        Some(server.base_url()),
+    )
+    .with_embedding_limit(EmbeddingLimit {
+        max_tokens: 2,
+        overflow: EmbeddingOverflow::Error,
+    });
+    let err = strict
+        .embed(vec!["abcdefghijkl".to_string()], None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("over the 2 token limit"));
+    mock.assert_hits_async(1).await;
+}
+
+#[tokio::test]
+async fn openai_provider_averages_chunked_embedding_inputs() {
+    let server = MockServer::start_async().await;
+    let mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/embeddings")
+                .json_body_partial(r#"{"input": ["abcdefgh", "ijkl", "xy"]}"#);
+            then.status(200)
+                .json_body(embedding_response(&[[1.0, 0.0], [0.0, 1.0], [5.0, 5.0]]));
+        })
+        .await;
+
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("text-embedding-3-small".to_string()),
+        Some(server.base_url()),
+    )
+    .with_embedding_limit(EmbeddingLimit {
+        max_tokens: 2,
+        overflow: EmbeddingOverflow::ChunkAverage,
+    });
+    let vectors = provider
+        .embed(vec!["abcdefghijkl".to_string(), "xy".to_string()], None)
+        .await
+        .unwrap();
+    mock.assert_hits_async(1).await;
+    assert_eq!(vectors.len(), 2);
+    // The 8-character chunk counts twice as much as the 4-character one.
+    assert!((vectors[0][0] - 2.0 / 3.0).abs() < 1e-6);
+    assert!((vectors[0][1] - 1.0 / 3.0).abs() < 1e-6);
+    assert_eq!(vectors[1], vec![5.0, 5.0]);
+}
+
+#[tokio::test]
+async fn openai_provider_json_modes_pick_the_response_format() {
+    let object_server = MockServer::start_async().await;
+    let object_mock = object_server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .json_body_partial(r#"{"response_format": {"type": "json_object"}}"#)
+                .body_contains("matches this JSON schema");
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-json",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "{\"ok\":true}"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("gpt-4o-mini".to_string()),
+        Some(object_server.base_url()),
+    )
+    .with_json_mode(JsonMode::JsonObject);
+    let structured = provider
+        .parse_structured_output("hi", "be brief", json!({"type": "object"}), None)
+        .await
+        .unwrap();
+    assert_eq!(structured, json!({"ok": true}));
+    object_mock.assert_hits_async(1).await;
+
+    // Endpoints without JSON mode get no response_format and may fence the reply.
+    let prompt_server = MockServer::start_async().await;
+    let prompt_mock = prompt_server
+        .mock_async(|when, then| {
+            when.method(POST).path("/chat/completions").matches(|req| {
+                let body = req.body.as_deref().unwrap_or_default();
+                !String::from_utf8_lossy(body).contains("response_format")
+            });
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-prompt",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "```json\n{\"ok\":true}\n```"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+    let provider = OpenAiProvider::new(
+        "key".to_string(),
+        Some("gpt-4o-mini".to_string()),
+        Some(prompt_server.base_url()),
+    )
+    .with_json_mode(JsonMode::Prompt);
+    let structured = provider
+        .parse_structured_output("hi", "", json!({"type": "object"}), None)
+        .await
+        .unwrap();
+    assert_eq!(structured, json!({"ok": true}));
+    prompt_mock.assert_hits_async(1).await;
+}
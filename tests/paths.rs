@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use butterfly_bot::costs::default_usage_db_path;
+use butterfly_bot::paths::{data_dir, default_db_path, default_lancedb_path, ensure_parent_dir};
+use butterfly_bot::planning::default_plan_db_path;
+use butterfly_bot::reminders::default_reminder_db_path;
+use butterfly_bot::tasks::default_task_db_path;
+use butterfly_bot::todo::default_todo_db_path;
+use butterfly_bot::wakeup::default_wakeup_db_path;
+
+#[test]
+fn data_dir_env_relocates_default_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("bot-data");
+    std::env::set_var("BUTTERFLY_BOT_DATA_DIR", &root);
+
+    assert_eq!(data_dir(), root);
+    let db = default_db_path();
+    assert_eq!(Path::new(&db), root.join("butterfly-bot.db"));
+    for path in [
+        default_reminder_db_path(),
+        default_task_db_path(),
+        default_todo_db_path(),
+        default_plan_db_path(),
+        default_wakeup_db_path(),
+        default_usage_db_path(),
+    ] {
+        assert_eq!(path, db);
+    }
+    assert_eq!(Path::new(&default_lancedb_path()), root.join("lancedb"));
+
+    ensure_parent_dir(&db).unwrap();
+    assert!(root.is_dir());
+    ensure_parent_dir(&db).unwrap();
+
+    std::env::remove_var("BUTTERFLY_BOT_DATA_DIR");
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::json;
+use tempfile::NamedTempFile;
+
+use butterfly_bot::brain::manager::BrainManager;
+use butterfly_bot::domains::agent::AIAgent;
+use butterfly_bot::planning::{parse_steps, PlanStore};
+use butterfly_bot::services::agent::AgentService;
+use butterfly_bot::services::planner::PlanGenerator;
+
+use common::QueueLlmProvider;
+
+async fn store() -> (NamedTempFile, PlanStore) {
+    let db = NamedTempFile::new().unwrap();
+    let store = PlanStore::new(db.path().to_str().unwrap()).await.unwrap();
+    (db, store)
+}
+
+fn indexes(steps: &[butterfly_bot::planning::PlanStep]) -> Vec<usize> {
+    steps.iter().map(|step| step.index).collect()
+}
+
+#[tokio::test]
+async fn ready_steps_follow_linear_chain() {
+    let (_db, store) = store().await;
+    let steps = json!([
+        {"title": "design"},
+        {"title": "build", "depends_on": [0]},
+        {"title": "ship", "depends_on": [1]}
+    ]);
+    let plan = store
+        .create_plan("u", "release", "ship it", Some(&steps), None)
+        .await
+        .unwrap();
+    assert_eq!(indexes(&store.ready_steps(plan.id).await.unwrap()), vec![0]);
+
+    let steps = json!([
+        {"title": "design", "status": "done"},
+        {"title": "build", "depends_on": [0]},
+        {"title": "ship", "depends_on": [1]}
+    ]);
+    store
+        .update_plan(plan.id, None, None, Some(&steps), None)
+        .await
+        .unwrap();
+    assert_eq!(indexes(&store.ready_steps(plan.id).await.unwrap()), vec![1]);
+}
+
+#[tokio::test]
+async fn ready_steps_wait_for_both_sides_of_diamond() {
+    let (_db, store) = store().await;
+    let steps = json!([
+        {"title": "spec", "status": "done"},
+        {"title": "backend", "depends_on": [0], "status": "done"},
+        {"title": "frontend", "depends_on": [0]},
+        {"title": "launch", "depends_on": [1, 2]}
+    ]);
+    let plan = store
+        .create_plan("u", "app", "launch app", Some(&steps), None)
+        .await
+        .unwrap();
+    assert_eq!(indexes(&store.ready_steps(plan.id).await.unwrap()), vec![2]);
+
+    let steps = json!([
+        {"title": "spec", "status": "done"},
+        {"title": "backend", "depends_on": [0], "status": "done"},
+        {"title": "frontend", "depends_on": [0], "status": "done"},
+        {"title": "launch", "depends_on": [1, 2]}
+    ]);
+    store
+        .update_plan(plan.id, None, None, Some(&steps), None)
+        .await
+        .unwrap();
+    assert_eq!(indexes(&store.ready_steps(plan.id).await.unwrap()), vec![3]);
+}
+
+#[tokio::test]
+async fn ready_steps_rejects_cycles() {
+    let (_db, store) = store().await;
+    let steps = json!([
+        "standalone",
+        {"title": "a", "depends_on": [2]},
+        {"title": "b", "depends_on": [1]}
+    ]);
+    let plan = store
+        .create_plan("u", "loop", "never ends", Some(&steps), None)
+        .await
+        .unwrap();
+    let err = store.ready_steps(plan.id).await.unwrap_err();
+    assert!(err.to_string().contains("cycle"));
+}
+
+#[tokio::test]
+async fn generate_plan_persists_structured_draft() {
+    let (_db, store) = store().await;
+    let store = Arc::new(store);
+    let mut llm = QueueLlmProvider::new(Vec::new());
+    llm.structured = json!({
+        "title": "Launch newsletter",
+        "steps": [
+            {"title": "Pick a platform", "depends_on": []},
+            {"title": "Write first issue", "depends_on": []},
+            {"title": "Send to subscribers", "depends_on": [0, 1]}
+        ]
+    });
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = Arc::new(AgentService::new(Arc::new(llm), agent, None, brain, None));
+    let generator = PlanGenerator::new(service, store.clone());
+
+    let plan = generator
+        .generate_plan("u", "start a newsletter")
+        .await
+        .unwrap();
+    assert_eq!(plan.title, "Launch newsletter");
+    assert_eq!(plan.goal, "start a newsletter");
+    assert_eq!(plan.status, "draft");
+
+    let stored = store.get_plan(plan.id).await.unwrap();
+    let steps = parse_steps(stored.steps.as_ref().unwrap()).unwrap();
+    let titles: Vec<&str> = steps.iter().map(|step| step.title.as_str()).collect();
+    assert_eq!(
+        titles,
+        vec![
+            "Pick a platform",
+            "Write first issue",
+            "Send to subscribers"
+        ]
+    );
+    assert_eq!(steps[2].depends_on, vec![0, 1]);
+    assert_eq!(
+        indexes(&store.ready_steps(plan.id).await.unwrap()),
+        vec![0, 1]
+    );
+
+    assert!(generator.generate_plan("u", "   ").await.is_err());
+}
@@ -5,9 +5,10 @@ use std::sync::Arc;
 use serde_json::json;
 
 use butterfly_bot::error::ButterflyBotError;
-use butterfly_bot::interfaces::plugins::PluginManager;
+use butterfly_bot::interfaces::plugins::{PluginManager, Tool};
 use butterfly_bot::plugins::manager::DefaultPluginManager;
-use butterfly_bot::plugins::registry::ToolRegistry;
+use butterfly_bot::plugins::registry::{validate_tool_params, ToolRegistry};
+use butterfly_bot::tool_audit::{hash_params, ToolAuditStore};
 
 use common::{
     ConditionalTool, ConfigurablePlugin, DefaultConfigureTool, DummyPlugin, DummyTool, FailingTool,
@@ -57,7 +58,7 @@ async fn tool_registry_and_plugin_manager() {
     });
     assert!(registry.register_tool(conditional).await);
     let err = registry
-        .configure_all_tools(json!({"fail": true}))
+        .configure_all_tools(json!({"tools": {"conditional": {"fail": true}}}))
         .await
         .unwrap_err();
     assert!(matches!(err, ButterflyBotError::Runtime(_)));
@@ -78,9 +79,13 @@ async fn tool_registry_audit_log() {
         }))
         .await
         .unwrap();
-    registry.audit_tool_call("tool", "success").await.unwrap();
+    registry
+        .audit_tool_call("u1", "tool", "success")
+        .await
+        .unwrap();
 
     let content = std::fs::read_to_string(path).unwrap();
+    assert!(content.contains("\"user_id\":\"u1\""));
     assert!(content.contains("\"tool\":\"tool\""));
     assert!(content.contains("\"status\":\"success\""));
 }
@@ -142,3 +147,302 @@ async fn plugin_manager_auto_loads() {
     loaded.sort();
     assert_eq!(loaded, vec!["auto2".to_string()]);
 }
+
+#[tokio::test]
+async fn tool_schemas_list_enabled_tools() {
+    let registry = ToolRegistry::new();
+    registry
+        .configure_all_tools(json!({"tools": {"gamma": {"enabled": false}}}))
+        .await
+        .unwrap();
+    for name in ["alpha", "beta", "gamma"] {
+        assert!(registry.register_tool(Arc::new(DummyTool::new(name))).await);
+        assert!(registry.assign_tool_to_agent("agent", name).await);
+    }
+
+    let schemas = registry.tool_schemas(None).await;
+    let names: Vec<&str> = schemas.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["alpha", "beta"]);
+    for schema in &schemas {
+        assert_eq!(schema.description, "dummy");
+        assert_eq!(schema.parameters, json!({"type":"object","properties":{}}));
+    }
+
+    assert!(registry
+        .get_agent_tools("agent")
+        .await
+        .iter()
+        .all(|tool| tool.name() != "gamma"));
+
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("delta")))
+            .await
+    );
+    let scoped = registry.tool_schemas(Some("agent")).await;
+    let names: Vec<&str> = scoped.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["alpha", "beta"]);
+    assert!(registry.tool_schemas(Some("other")).await.is_empty());
+}
+
+struct TwoFieldTool {
+    first: std::sync::Mutex<i64>,
+    second: std::sync::Mutex<i64>,
+}
+
+#[async_trait::async_trait]
+impl Tool for TwoFieldTool {
+    fn name(&self) -> &str {
+        "two_field"
+    }
+
+    fn description(&self) -> &str {
+        "two field"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type":"object","properties":{}})
+    }
+
+    fn configure(&self, config: &serde_json::Value) -> butterfly_bot::error::Result<()> {
+        let version = config["tools"]["two_field"]["version"]
+            .as_i64()
+            .unwrap_or(0);
+        *self.first.lock().unwrap() = version;
+        *self.second.lock().unwrap() = version;
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::error::Result<serde_json::Value> {
+        let first = *self.first.lock().unwrap();
+        tokio::task::yield_now().await;
+        let second = *self.second.lock().unwrap();
+        Ok(json!({"first": first, "second": second}))
+    }
+}
+
+#[tokio::test]
+async fn reconfigure_waits_for_in_flight_tool_calls() {
+    let registry = Arc::new(ToolRegistry::new());
+    let tool: Arc<dyn Tool> = Arc::new(TwoFieldTool {
+        first: std::sync::Mutex::new(0),
+        second: std::sync::Mutex::new(0),
+    });
+    assert!(registry.register_tool(tool.clone()).await);
+
+    let mut handles = Vec::new();
+    for version in 1..=20 {
+        let runner = registry.clone();
+        let tool = tool.clone();
+        handles.push(tokio::spawn(async move {
+            runner.execute_tool("u", tool.as_ref(), json!({})).await
+        }));
+        let registry = registry.clone();
+        handles.push(tokio::spawn(async move {
+            registry
+                .configure_all_tools(json!({"tools": {"two_field": {"version": version}}}))
+                .await
+                .map(|_| json!(null))
+        }));
+    }
+
+    for handle in handles {
+        let value = handle.await.unwrap().unwrap();
+        if !value.is_null() {
+            assert_eq!(value["first"], value["second"]);
+        }
+    }
+}
+
+struct BrokenTool;
+
+#[async_trait::async_trait]
+impl Tool for BrokenTool {
+    fn name(&self) -> &str {
+        "broken"
+    }
+
+    fn description(&self) -> &str {
+        "always fails"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type":"object","properties":{}})
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        Err(ButterflyBotError::Runtime("boom".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn executing_a_tool_writes_one_audit_row() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("audit.db");
+    let store = Arc::new(
+        ToolAuditStore::new(db_path.to_str().unwrap())
+            .await
+            .unwrap(),
+    );
+    let registry = ToolRegistry::new();
+    registry.set_audit_store(Some(store.clone())).await;
+
+    // The audit row names the caller, not whoever the arguments claim.
+    let params = json!({"user_id": "someone-else", "api_key": "secret-value"});
+    registry
+        .execute_tool("u", &DummyTool::new("tool"), params.clone())
+        .await
+        .unwrap();
+
+    let entries = store.list("u", 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].tool, "tool");
+    assert_eq!(entries[0].status, "success");
+    assert_eq!(entries[0].params_hash, hash_params(&params));
+    assert!(!entries[0].params_hash.contains("secret-value"));
+
+    assert!(registry
+        .execute_tool("u", &BrokenTool, json!({}))
+        .await
+        .is_err());
+    let entries = store.list("u", 10).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].tool, "broken");
+    assert_eq!(entries[0].status, "error");
+    assert!(store.list("someone-else", 10).await.unwrap().is_empty());
+}
+
+struct BadSchemaTool;
+
+#[async_trait::async_trait]
+impl Tool for BadSchemaTool {
+    fn name(&self) -> &str {
+        "bad_schema"
+    }
+
+    fn description(&self) -> &str {
+        "declares a schema that doesn't compile"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type":"object","properties":{"q":{"type":"not-a-type"}}})
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        Ok(json!(null))
+    }
+}
+
+#[test]
+fn uncompilable_tool_schema_is_an_error() {
+    let err = validate_tool_params(&BadSchemaTool, &json!({"q": 1})).unwrap_err();
+    assert!(
+        matches!(err, ButterflyBotError::Config(ref message) if message.contains("bad_schema"))
+    );
+
+    let errors = validate_tool_params(&DummyTool::new("tool"), &json!({})).unwrap();
+    assert!(errors.is_empty());
+}
+
+struct RecordingConfigTool {
+    seen: std::sync::Mutex<Option<serde_json::Value>>,
+}
+
+#[async_trait::async_trait]
+impl Tool for RecordingConfigTool {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    fn description(&self) -> &str {
+        "recorder"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type":"object","properties":{}})
+    }
+
+    fn config_sections(&self) -> Vec<&'static str> {
+        vec!["memory"]
+    }
+
+    fn configure(&self, config: &serde_json::Value) -> butterfly_bot::error::Result<()> {
+        *self.seen.lock().unwrap() = Some(config.clone());
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::error::Result<serde_json::Value> {
+        Ok(json!({}))
+    }
+}
+
+#[tokio::test]
+async fn tools_are_configured_with_only_their_config_slice() {
+    let registry = ToolRegistry::new();
+    let tool = Arc::new(RecordingConfigTool {
+        seen: std::sync::Mutex::new(None),
+    });
+    assert!(registry.register_tool(tool.clone()).await);
+
+    let config = json!({
+        "openai": {"api_key": "sk-secret"},
+        "memory": {"sqlite_path": "./data/bot.db"},
+        "tools": {
+            "settings": {"max_tool_iterations": 3},
+            "recorder": {"mode": "fast"},
+            "github": {"token": "ghp-secret"}
+        }
+    });
+    registry.configure_all_tools(config.clone()).await.unwrap();
+    assert_eq!(
+        tool.seen.lock().unwrap().clone().unwrap(),
+        json!({
+            "memory": {"sqlite_path": "./data/bot.db"},
+            "tools": {
+                "settings": {"max_tool_iterations": 3},
+                "recorder": {"mode": "fast"}
+            }
+        })
+    );
+
+    let mut shared = config.clone();
+    shared["tools"]["settings"]["isolate_tool_config"] = json!(false);
+    registry.configure_all_tools(shared.clone()).await.unwrap();
+    assert_eq!(tool.seen.lock().unwrap().clone().unwrap(), shared);
+}
+
+#[tokio::test]
+async fn tools_registered_after_configure_get_only_their_slice() {
+    let registry = ToolRegistry::new();
+    registry
+        .configure_all_tools(json!({
+            "openai": {"api_key": "sk-secret"},
+            "tools": {
+                "recorder": {"mode": "fast"},
+                "github": {"token": "ghp-secret"}
+            }
+        }))
+        .await
+        .unwrap();
+
+    let tool = Arc::new(RecordingConfigTool {
+        seen: std::sync::Mutex::new(None),
+    });
+    assert!(registry.register_tool(tool.clone()).await);
+    assert_eq!(
+        tool.seen.lock().unwrap().clone().unwrap(),
+        json!({"tools": {"recorder": {"mode": "fast"}}})
+    );
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::config_store;
+use butterfly_bot::paths::default_db_path;
+use butterfly_bot::vault;
+
+fn config_with_model(model: &str) -> Config {
+    Config {
+        openai: Some(OpenAiConfig {
+            api_key: None,
+            model: Some(model.to_string()),
+            base_url: None,
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    }
+}
+
+fn model_of(config: Config) -> Option<String> {
+    config.openai.and_then(|openai| openai.model)
+}
+
+#[test]
+fn profiles_resolve_separate_paths_services_and_config() {
+    common::install_mock_keyring();
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("BUTTERFLY_BOT_DATA_DIR", dir.path());
+
+    std::env::set_var(vault::PROFILE_ENV, "work");
+    let work_db = default_db_path();
+    let work_service = vault::service_name();
+    config_store::save_config(&work_db, &config_with_model("work-model")).unwrap();
+
+    std::env::set_var(vault::PROFILE_ENV, "personal");
+    let personal_db = default_db_path();
+    let personal_service = vault::service_name();
+    assert!(Config::from_store(&personal_db).is_err());
+    config_store::save_config(&personal_db, &config_with_model("personal-model")).unwrap();
+    // A config kept in the vault only overrides the profile it was stored for.
+    vault::set_secret(
+        "app_config_json",
+        &serde_json::to_string(&config_with_model("vault-model")).unwrap(),
+    )
+    .unwrap();
+
+    assert_ne!(work_db, personal_db);
+    assert!(work_db.ends_with("profiles/work/butterfly-bot.db"));
+    assert_eq!(work_service, "butterfly-bot-work");
+    assert_eq!(personal_service, "butterfly-bot-personal");
+
+    assert_eq!(
+        model_of(Config::from_store(&personal_db).unwrap()).as_deref(),
+        Some("vault-model")
+    );
+    std::env::set_var(vault::PROFILE_ENV, "work");
+    assert_eq!(
+        model_of(Config::from_store(&work_db).unwrap()).as_deref(),
+        Some("work-model")
+    );
+
+    // Names that could escape the data dir are rejected, and never used.
+    assert!(vault::validate_profile_name("../work").is_err());
+    std::env::set_var(vault::PROFILE_ENV, "../work");
+    assert_eq!(vault::active_profile(), None);
+    assert_eq!(vault::service_name(), "butterfly-bot");
+
+    std::env::remove_var(vault::PROFILE_ENV);
+    std::env::remove_var("BUTTERFLY_BOT_DATA_DIR");
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::collections::HashMap;
+
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use serde_json::json;
+
+use butterfly_bot::config::OpenAiConfig;
+use butterfly_bot::error::ButterflyBotError;
+use butterfly_bot::interfaces::providers::LlmProvider;
+use butterfly_bot::providers::openai::OpenAiProvider;
+use butterfly_bot::vault;
+
+#[tokio::test]
+async fn configured_headers_are_sent_with_interpolated_secrets() {
+    common::install_mock_keyring();
+    vault::set_secret("provider_org_secret", "org-7f3a").unwrap();
+
+    let server = MockServer::start_async().await;
+    let mock = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/chat/completions")
+                .header("x-org", "org-7f3a")
+                .header("x-team", "agents");
+            then.status(200).json_body(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "hello"},
+                    "finish_reason": "stop"
+                }]
+            }));
+        })
+        .await;
+
+    let openai = OpenAiConfig {
+        api_key: Some("key".to_string()),
+        model: Some("gpt-4o-mini".to_string()),
+        base_url: Some(server.base_url()),
+        headers: Some(HashMap::from([
+            ("x-org".to_string(), "${provider_org_secret}".to_string()),
+            ("x-team".to_string(), "agents".to_string()),
+        ])),
+        json_mode: None,
+    };
+    let headers = openai.resolved_headers().unwrap();
+    let provider = OpenAiProvider::new("key".to_string(), openai.model, openai.base_url)
+        .with_headers(&headers)
+        .unwrap();
+    let text = provider.generate_text("hi", "", None).await.unwrap();
+    assert_eq!(text, "hello");
+    mock.assert_hits_async(1).await;
+
+    let missing = OpenAiConfig {
+        api_key: None,
+        model: None,
+        base_url: None,
+        headers: Some(HashMap::from([(
+            "x-org".to_string(),
+            "${provider_missing_secret}".to_string(),
+        )])),
+        json_mode: None,
+    };
+    let err = missing.resolved_headers().unwrap_err();
+    assert!(
+        matches!(err, ButterflyBotError::Config(ref msg) if msg.contains("provider_missing_secret"))
+    );
+}
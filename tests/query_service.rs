@@ -1,5 +1,6 @@
 mod common;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use futures::StreamExt;
@@ -9,11 +10,14 @@ use serde_json::json;
 
 use butterfly_bot::brain::manager::BrainManager;
 use butterfly_bot::client::ButterflyBot;
-use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::config::{Config, HistoryWindow, OpenAiConfig};
 use butterfly_bot::domains::agent::AIAgent;
 use butterfly_bot::error::ButterflyBotError;
-use butterfly_bot::interfaces::providers::{ImageData, ImageInput};
+use butterfly_bot::interfaces::providers::{
+    ChatEvent, ImageData, ImageInput, LlmResponse, MemoryProvider, ToolCall,
+};
 use butterfly_bot::providers::memory::InMemoryMemoryProvider;
+use butterfly_bot::providers::sqlite::{SqliteMemoryProvider, SqliteMemoryProviderConfig};
 use butterfly_bot::services::agent::AgentService;
 use butterfly_bot::services::query::{
     OutputFormat, ProcessOptions, ProcessResult, QueryService, UserInput,
@@ -29,6 +33,7 @@ async fn query_service_and_client() {
         name: "agent".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
     let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
     let memory = Arc::new(InMemoryMemoryProvider::new());
@@ -44,10 +49,8 @@ async fn query_service_and_client() {
 
     let options = ProcessOptions {
         prompt: Some("extra".to_string()),
-        images: vec![],
-        output_format: OutputFormat::Text,
-        image_detail: "auto".to_string(),
         json_schema: Some(json!({"type":"object"})),
+        ..Default::default()
     };
     let result = query
         .process(
@@ -66,13 +69,11 @@ async fn query_service_and_client() {
     }
 
     let options = ProcessOptions {
-        prompt: None,
         images: vec![ImageInput {
             data: ImageData::Bytes(vec![1, 2, 3]),
         }],
-        output_format: OutputFormat::Text,
         image_detail: "low".to_string(),
-        json_schema: None,
+        ..Default::default()
     };
     let result = query
         .process("user", UserInput::Text("img".to_string()), options)
@@ -84,14 +85,11 @@ async fn query_service_and_client() {
     }
 
     let options = ProcessOptions {
-        prompt: None,
-        images: vec![],
         output_format: OutputFormat::Audio {
             voice: "alloy".to_string(),
             format: "mp3".to_string(),
         },
-        image_detail: "auto".to_string(),
-        json_schema: None,
+        ..Default::default()
     };
     let result = query
         .process("user", UserInput::Text("hi".to_string()), options)
@@ -112,6 +110,7 @@ async fn query_service_and_client() {
         name: "agent".to_string(),
         instructions: "inst".to_string(),
         specialization: "spec".to_string(),
+        memory_namespace: None,
     };
     let service = Arc::new(AgentService::new(llm, agent, None, brain, None));
     let query = QueryService::new(service, None, None);
@@ -124,13 +123,7 @@ async fn query_service_and_client() {
         .unwrap();
     assert_eq!(text, "mock text");
 
-    let options = ProcessOptions {
-        prompt: None,
-        images: Vec::new(),
-        output_format: OutputFormat::Text,
-        image_detail: "auto".to_string(),
-        json_schema: None,
-    };
+    let options = ProcessOptions::default();
     let result = query
         .process("user", UserInput::Text("hello".to_string()), options)
         .await
@@ -145,12 +138,18 @@ async fn query_service_and_client() {
             api_key: Some("key".to_string()),
             model: None,
             base_url: None,
+            headers: None,
+            json_mode: None,
         }),
         skill_file: None,
         heartbeat_file: None,
         memory: None,
         tools: None,
         brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
     };
     let agent = ButterflyBot::from_config(config).await.unwrap();
     let tool = Arc::new(DummyTool::new("tool"));
@@ -202,3 +201,693 @@ async fn query_service_and_client() {
     agent.delete_user_history("user").await.unwrap();
     let _ = agent.get_user_history("user", 5).await.unwrap();
 }
+
+fn namespaced_query_service(
+    memory: Arc<dyn MemoryProvider>,
+    namespace: Option<&str>,
+) -> QueryService {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: namespace.map(str::to_string),
+    };
+    let service = Arc::new(AgentService::new(llm, agent, None, brain, None));
+    QueryService::new(service, Some(memory), None)
+}
+
+#[tokio::test]
+async fn memory_namespaces_partition_user_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("mem.db");
+    let memory: Arc<dyn MemoryProvider> = Arc::new(
+        SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(db_path.to_str().unwrap()))
+            .await
+            .unwrap(),
+    );
+
+    let coding = namespaced_query_service(memory.clone(), Some("coding"));
+    let therapy = namespaced_query_service(memory.clone(), Some("therapy"));
+    let shared = namespaced_query_service(memory.clone(), None);
+
+    coding
+        .process_text("u1", "I refactored the parser module", None)
+        .await
+        .unwrap();
+
+    let found = coding.search_memory("u1", "parser", 5).await.unwrap();
+    assert!(found.iter().any(|item| item.contains("parser")));
+
+    let other = therapy.search_memory("u1", "parser", 5).await.unwrap();
+    assert!(other.is_empty());
+    assert!(therapy.get_user_history("u1", 10).await.unwrap().is_empty());
+
+    let global = shared.search_memory("u1", "parser", 5).await.unwrap();
+    assert!(global.is_empty());
+}
+
+#[tokio::test]
+async fn process_without_persist_leaves_memory_untouched() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm, agent, None, brain, None));
+    let memory = Arc::new(InMemoryMemoryProvider::new());
+    let query = QueryService::new(service, Some(memory.clone()), None);
+    let options = |persist| ProcessOptions {
+        persist,
+        ..Default::default()
+    };
+
+    let result = query
+        .process(
+            "user",
+            UserInput::Text("secret".to_string()),
+            options(false),
+        )
+        .await
+        .unwrap();
+    match result {
+        ProcessResult::Text(value) => assert_eq!(value, "mock text"),
+        other => panic!("unexpected result: {other:?}"),
+    }
+    assert!(memory.get_history("user", 10).await.unwrap().is_empty());
+
+    let events: Vec<_> = query
+        .process_stream(
+            "user",
+            UserInput::Text("secret".to_string()),
+            options(false),
+        )
+        .collect()
+        .await;
+    assert!(events.iter().all(|event| event.is_ok()));
+    assert!(memory.get_history("user", 10).await.unwrap().is_empty());
+
+    query
+        .process("user", UserInput::Text("hello".to_string()), options(true))
+        .await
+        .unwrap();
+    let history = memory.get_history("user", 10).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|line| !line.contains("secret")));
+}
+
+#[tokio::test]
+async fn regenerate_keeps_original_reply_as_branch_zero() {
+    let reply = |text: &str| LlmResponse {
+        text: text.to_string(),
+        tool_calls: Vec::new(),
+        usage: None,
+    };
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        reply("first answer"),
+        reply("second answer"),
+    ]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    // With a tool assigned, replies come from the queued responses.
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let memory = Arc::new(
+        SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(
+            dir.path().join("mem.db").to_str().unwrap(),
+        ))
+        .await
+        .unwrap(),
+    );
+    let query = QueryService::new(service, Some(memory.clone()), None);
+
+    let text = query
+        .process_text("u1", "name a color", None)
+        .await
+        .unwrap();
+    assert_eq!(text, "first answer");
+    let turn = memory.get_messages("u1", None, 0).await.unwrap();
+    assert_eq!(turn.len(), 2);
+    let user_message = &turn[0];
+    assert_eq!(user_message.role, "user");
+
+    let branch = query.regenerate("u1", user_message.id).await.unwrap();
+    assert_eq!(branch.branch, 1);
+    assert_eq!(branch.content, "second answer");
+    assert!(llm
+        .prompts
+        .lock()
+        .await
+        .last()
+        .unwrap()
+        .contains("name a color"));
+
+    let branches = query.get_branches("u1", user_message.id).await.unwrap();
+    let tagged: Vec<(u32, &str)> = branches
+        .iter()
+        .map(|branch| (branch.branch, branch.content.as_str()))
+        .collect();
+    assert_eq!(tagged, vec![(0, "first answer"), (1, "second answer")]);
+    assert_eq!(memory.get_messages("u1", None, 0).await.unwrap(), turn);
+
+    let reply_id = turn[1].id;
+    let err = query.regenerate("u1", reply_id).await.unwrap_err();
+    assert!(matches!(err, ButterflyBotError::NotFound(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn regenerate_uses_the_history_window_before_the_message() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let memory = Arc::new(
+        SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(
+            dir.path().join("mem.db").to_str().unwrap(),
+        ))
+        .await
+        .unwrap(),
+    );
+    for word in ["alpha", "bravo", "charlie", "question", "later"] {
+        memory.append_message("u1", "user", word).await.unwrap();
+    }
+    let question = memory
+        .get_messages("u1", None, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|message| message.content == "question")
+        .unwrap();
+    let query = QueryService::new(service, Some(memory), None).with_history_window(HistoryWindow {
+        messages: Some(1),
+        max_tokens: None,
+    });
+
+    query.regenerate("u1", question.id).await.unwrap();
+    let prompt = llm.prompts.lock().await.last().cloned().unwrap();
+    assert!(prompt.contains("charlie"));
+    assert!(!prompt.contains("bravo") && !prompt.contains("later"));
+}
+
+#[tokio::test]
+async fn edit_and_resubmit_discards_the_tail() {
+    let reply = |text: &str| LlmResponse {
+        text: text.to_string(),
+        tool_calls: Vec::new(),
+        usage: None,
+    };
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        reply("hi there"),
+        reply("paris"),
+        reply("rome"),
+    ]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm, agent, None, brain, None));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let memory = Arc::new(
+        SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(
+            dir.path().join("mem.db").to_str().unwrap(),
+        ))
+        .await
+        .unwrap(),
+    );
+    let query = QueryService::new(service, Some(memory.clone()), None);
+
+    query.process_text("u1", "hello", None).await.unwrap();
+    query
+        .process_text("u1", "capital of france", None)
+        .await
+        .unwrap();
+    let before = memory.get_messages("u1", None, 0).await.unwrap();
+    assert_eq!(before.len(), 4);
+    let edited = before[2].id;
+
+    let err = query
+        .edit_and_resubmit("u1", edited, "   ")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ButterflyBotError::InvalidInput(_)));
+    assert_eq!(memory.get_messages("u1", None, 0).await.unwrap(), before);
+
+    let response = query
+        .edit_and_resubmit("u1", edited, "capital of italy")
+        .await
+        .unwrap();
+    assert_eq!(response, "rome");
+
+    let history: Vec<(String, String)> = memory
+        .get_messages("u1", None, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| (message.role, message.content))
+        .collect();
+    let expected = [
+        ("user", "hello"),
+        ("assistant", "hi there"),
+        ("user", "capital of italy"),
+        ("assistant", "rome"),
+    ];
+    assert_eq!(
+        history,
+        expected
+            .iter()
+            .map(|(role, content)| (role.to_string(), content.to_string()))
+            .collect::<Vec<_>>()
+    );
+    assert!(memory.search("u1", "france", 5).await.unwrap().is_empty());
+    let err = query
+        .edit_and_resubmit("u1", edited, "again")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ButterflyBotError::NotFound(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn history_window_limits_messages_sent_to_provider() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let memory = Arc::new(InMemoryMemoryProvider::new());
+    for word in ["alpha", "bravo", "charlie", "delta", "echo"] {
+        memory.append_message("u1", "user", word).await.unwrap();
+    }
+    let query = QueryService::new(service, Some(memory), None).with_history_window(HistoryWindow {
+        messages: Some(2),
+        max_tokens: None,
+    });
+
+    query.process_text("u1", "what now", None).await.unwrap();
+    let prompt = llm.prompts.lock().await.last().cloned().unwrap();
+    assert!(prompt.contains("delta") && prompt.contains("echo"));
+    assert!(!prompt.contains("charlie") && !prompt.contains("alpha"));
+
+    let options = ProcessOptions {
+        persist: false,
+        history_window: Some(HistoryWindow {
+            messages: Some(0),
+            max_tokens: None,
+        }),
+        ..Default::default()
+    };
+    query
+        .process("u1", UserInput::Text("and then".to_string()), options)
+        .await
+        .unwrap();
+    let prompt = llm.prompts.lock().await.last().cloned().unwrap();
+    assert!(!prompt.contains("echo") && !prompt.contains("what now"));
+}
+
+/// Returns `limit` memories, best first, as a reranking provider would.
+struct RankedMemory {
+    requested: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl MemoryProvider for RankedMemory {
+    async fn append_message(
+        &self,
+        _user_id: &str,
+        _role: &str,
+        _content: &str,
+    ) -> butterfly_bot::Result<()> {
+        Ok(())
+    }
+
+    async fn get_history(
+        &self,
+        _user_id: &str,
+        _limit: usize,
+    ) -> butterfly_bot::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn clear_history(&self, _user_id: &str) -> butterfly_bot::Result<()> {
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        _user_id: &str,
+        _query: &str,
+        limit: usize,
+    ) -> butterfly_bot::Result<Vec<String>> {
+        self.requested.store(limit, Ordering::SeqCst);
+        Ok((0..limit)
+            .map(|rank| format!("ranked memory #{rank}"))
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn context_top_k_injects_only_the_best_of_the_searched_memories() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let memory = Arc::new(RankedMemory {
+        requested: AtomicUsize::new(0),
+    });
+    let query = QueryService::new(service, Some(memory.clone()), None).with_memory_limits(10, 2);
+
+    query
+        .process_text("u1", "what did I say about my garden?", None)
+        .await
+        .unwrap();
+    assert_eq!(memory.requested.load(Ordering::SeqCst), 10);
+    let prompt = llm.prompts.lock().await.last().cloned().unwrap();
+    assert!(prompt.contains("ranked memory #0") && prompt.contains("ranked memory #1"));
+    assert!(!prompt.contains("ranked memory #2"));
+}
+
+#[tokio::test]
+async fn pinned_context_reaches_every_system_prompt() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("tool")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "tool")
+            .await
+    );
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let memory = Arc::new(
+        SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(db.path().to_str().unwrap()))
+            .await
+            .unwrap(),
+    );
+    memory
+        .append_message("u1", "user", "I like jazz")
+        .await
+        .unwrap();
+    let query = QueryService::new(service, Some(memory), None).with_history_window(HistoryWindow {
+        messages: Some(0),
+        max_tokens: None,
+    });
+    query.enable_pinned_context().await;
+
+    let pin = query
+        .pin_context("u1", "Allergic to penicillin")
+        .await
+        .unwrap();
+    assert_eq!(query.pinned_context("u1").await.unwrap(), vec![pin.clone()]);
+    assert!(query.pinned_context("u2").await.unwrap().is_empty());
+
+    for text in ["hello", "what should I take for a cold?"] {
+        query.process_text("u1", text, None).await.unwrap();
+        let system = llm.system_prompts.lock().await.last().cloned().unwrap();
+        assert!(system.starts_with("PINNED CONTEXT"));
+        assert!(system.contains("- Allergic to penicillin"));
+        // Ordinary memory only arrives through retrieval, which is windowed out here.
+        assert!(!system.contains("jazz"));
+        let prompt = llm.prompts.lock().await.last().cloned().unwrap();
+        assert!(!prompt.contains("jazz"));
+    }
+
+    query.process_text("u2", "hello", None).await.unwrap();
+    let system = llm.system_prompts.lock().await.last().cloned().unwrap();
+    assert!(!system.contains("penicillin"));
+
+    assert!(!query.unpin_context("u2", pin.id).await.unwrap());
+    assert!(query.unpin_context("u1", pin.id).await.unwrap());
+    query.process_text("u1", "hello again", None).await.unwrap();
+    let system = llm.system_prompts.lock().await.last().cloned().unwrap();
+    assert!(!system.contains("penicillin"));
+}
+
+struct CountingTool {
+    runs: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl butterfly_bot::interfaces::plugins::Tool for CountingTool {
+    fn name(&self) -> &str {
+        "search"
+    }
+
+    fn description(&self) -> &str {
+        "search"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {"query": {"type": "string"}}})
+    }
+
+    async fn execute(
+        &self,
+        _params: serde_json::Value,
+    ) -> butterfly_bot::Result<serde_json::Value> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        Ok(json!({"ok": true}))
+    }
+}
+
+#[tokio::test]
+async fn dry_run_returns_planned_tool_calls_without_executing() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![LlmResponse {
+        text: String::new(),
+        tool_calls: vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "search".to_string(),
+            arguments: json!({"query": "rust"}),
+        }],
+        usage: None,
+    }]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let runs = Arc::new(AtomicUsize::new(0));
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(CountingTool { runs: runs.clone() }))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "search")
+            .await
+    );
+    let memory = Arc::new(InMemoryMemoryProvider::new());
+    let query = QueryService::new(service, Some(memory.clone()), None);
+
+    let options = ProcessOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let result = query
+        .process("u1", UserInput::Text("find rust".to_string()), options)
+        .await
+        .unwrap();
+
+    let ProcessResult::ToolCalls(calls) = result else {
+        panic!("expected planned tool calls, got {result:?}");
+    };
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "search");
+    assert_eq!(calls[0].arguments, json!({"query": "rust"}));
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+    assert_eq!(llm.prompts.lock().await.len(), 1);
+    assert!(memory.get_history("u1", 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn blank_messages_are_rejected_unless_an_image_is_attached() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm.clone(), agent, None, brain, None));
+    let memory = Arc::new(InMemoryMemoryProvider::new());
+    let query = QueryService::new(service, Some(memory.clone()), None);
+    let options = |images| ProcessOptions {
+        images,
+        ..Default::default()
+    };
+
+    let err = query
+        .process("u1", UserInput::Text(" \n\t".to_string()), options(vec![]))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ButterflyBotError::InvalidInput(_)));
+
+    let mut stream = query.process_text_stream("u1", "   ", None);
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(err, ButterflyBotError::InvalidInput(_)));
+    drop(stream);
+
+    let err = query.process_text("u1", "\n", None).await.unwrap_err();
+    assert!(matches!(err, ButterflyBotError::InvalidInput(_)));
+
+    assert!(llm.prompts.lock().await.is_empty());
+    assert!(memory.get_history("u1", 10).await.unwrap().is_empty());
+
+    let image = ImageInput {
+        data: ImageData::Bytes(vec![1, 2, 3]),
+    };
+    let result = query
+        .process("u1", UserInput::Text(String::new()), options(vec![image]))
+        .await
+        .unwrap();
+    match result {
+        ProcessResult::Text(value) => assert_eq!(value, "image response"),
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn process_stream_yields_content_then_message_end() {
+    let llm = Arc::new(QueueLlmProvider::new(vec![]));
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let service = Arc::new(AgentService::new(llm, agent, None, brain, None));
+    let memory = Arc::new(InMemoryMemoryProvider::new());
+    let query = QueryService::new(service, Some(memory.clone()), None);
+    let options = |images| ProcessOptions {
+        images,
+        ..Default::default()
+    };
+
+    let events: Vec<ChatEvent> = query
+        .process_stream("u1", UserInput::Text("hello".to_string()), options(vec![]))
+        .map(|event| event.unwrap())
+        .collect()
+        .await;
+    assert_eq!(
+        events,
+        vec![
+            ChatEvent::Content {
+                delta: "mock text".to_string()
+            },
+            ChatEvent::MessageEnd {
+                finish_reason: Some("stop".to_string()),
+                usage: None,
+            },
+        ]
+    );
+    assert_eq!(memory.get_history("u1", 10).await.unwrap().len(), 2);
+
+    let image = ImageInput {
+        data: ImageData::Bytes(vec![1, 2, 3]),
+    };
+    let mut stream = query.process_stream(
+        "u1",
+        UserInput::Text("look".to_string()),
+        options(vec![image]),
+    );
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(err, ButterflyBotError::InvalidInput(_)));
+}
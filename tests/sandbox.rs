@@ -0,0 +1,90 @@
+use serde_json::json;
+
+use butterfly_bot::tools::sandbox::Sandbox;
+
+#[tokio::test]
+async fn sandbox_allows_paths_inside_the_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let sandbox = Sandbox::new(dir.path()).unwrap();
+    let root = dir.path().canonicalize().unwrap();
+
+    sandbox.write("notes/today.md", "hello").await.unwrap();
+    assert_eq!(
+        sandbox.read_to_string("notes/today.md").await.unwrap(),
+        "hello"
+    );
+    assert_eq!(
+        sandbox.resolve("notes/./today.md").unwrap(),
+        root.join("notes/today.md")
+    );
+    assert_eq!(
+        sandbox.resolve("notes/../notes/today.md").unwrap(),
+        root.join("notes/today.md")
+    );
+    assert_eq!(
+        sandbox.resolve(root.join("new/file.txt")).unwrap(),
+        root.join("new/file.txt")
+    );
+}
+
+#[tokio::test]
+async fn sandbox_rejects_escapes_from_the_root() {
+    let outer = tempfile::tempdir().unwrap();
+    let root = outer.path().join("root");
+    std::fs::create_dir(&root).unwrap();
+    std::fs::write(outer.path().join("secret.txt"), "nope").unwrap();
+    let sandbox = Sandbox::new(&root).unwrap();
+
+    for path in [
+        "../secret.txt",
+        "missing/../../secret.txt",
+        "a/b/../../../x",
+    ] {
+        let err = sandbox.resolve(path).unwrap_err();
+        assert!(
+            err.to_string().contains("outside the sandbox root"),
+            "{path}: {err}"
+        );
+    }
+    assert!(sandbox.resolve("/etc/passwd").is_err());
+    assert!(sandbox.read_to_string("../secret.txt").await.is_err());
+    assert!(sandbox.write("../escaped.txt", "x").await.is_err());
+    assert!(!outer.path().join("escaped.txt").exists());
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(outer.path(), root.join("link")).unwrap();
+        assert!(sandbox.resolve("link/secret.txt").is_err());
+    }
+}
+
+#[test]
+fn sandbox_root_comes_from_tool_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = json!({"tools": {"settings": {"sandbox_root": dir.path()}}});
+    let sandbox = Sandbox::from_config(&config).unwrap().unwrap();
+    assert_eq!(sandbox.root(), dir.path().canonicalize().unwrap());
+
+    assert!(Sandbox::from_config(&json!({"tools": {}}))
+        .unwrap()
+        .is_none());
+    let missing = json!({"tools": {"settings": {"sandbox_root": dir.path().join("nope")}}});
+    assert!(Sandbox::from_config(&missing).is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sandbox_refuses_to_write_through_a_dangling_symlink() {
+    let outer = tempfile::tempdir().unwrap();
+    let root = outer.path().join("root");
+    std::fs::create_dir(&root).unwrap();
+    let target = outer.path().join("escaped.txt");
+    std::os::unix::fs::symlink(&target, root.join("notes.md")).unwrap();
+    let sandbox = Sandbox::new(&root).unwrap();
+
+    let err = sandbox.resolve("notes.md").unwrap_err();
+    assert!(err.to_string().contains("dangling symlink"), "{err}");
+    assert!(sandbox.resolve("notes.md/inner.txt").is_err());
+    assert!(sandbox.write("notes.md", "x").await.is_err());
+    assert!(!target.exists());
+}
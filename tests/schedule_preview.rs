@@ -0,0 +1,43 @@
+use butterfly_bot::scheduler::{preview_occurrences, ScheduleRule};
+
+// Tuesday 2023-11-14 22:13:20 UTC.
+const FROM: i64 = 1_700_000_000;
+const WEEK: i64 = 7 * 24 * 60 * 60;
+
+#[test]
+fn weekly_rule_previews_the_next_three_weeks() {
+    let rule = ScheduleRule::parse("weekly").unwrap();
+    assert_eq!(
+        preview_occurrences(&rule, FROM, 3),
+        vec![FROM + WEEK, FROM + 2 * WEEK, FROM + 3 * WEEK]
+    );
+}
+
+#[test]
+fn cron_expression_previews_the_next_three_matches() {
+    // Mondays at 09:30 UTC, starting 2023-11-20.
+    let rule = ScheduleRule::parse("30 9 * * 1").unwrap();
+    let first = 1_700_472_600;
+    assert_eq!(
+        preview_occurrences(&rule, FROM, 3),
+        vec![first, first + WEEK, first + 2 * WEEK]
+    );
+
+    // Every six hours on the 15th: the same day, strictly after `from`.
+    let rule = ScheduleRule::parse("0 */6 15 * *").unwrap();
+    let midnight = 1_700_006_400;
+    assert_eq!(
+        preview_occurrences(&rule, FROM, 3),
+        vec![midnight, midnight + 6 * 3600, midnight + 12 * 3600]
+    );
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    assert!(ScheduleRule::parse("fortnightly").is_err());
+    assert!(ScheduleRule::parse("61 * * * *").is_err());
+    assert!(ScheduleRule::parse("0 0 * * 1/0").is_err());
+
+    let never = ScheduleRule::parse("0 0 30 2 *").unwrap();
+    assert!(preview_occurrences(&never, FROM, 3).is_empty());
+}
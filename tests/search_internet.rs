@@ -0,0 +1,111 @@
+use serde_json::json;
+
+use butterfly_bot::tools::search_internet::{Citation, SearchResult};
+
+#[test]
+fn openai_annotations_map_to_citations() {
+    let response = json!({
+        "choices": [{
+            "message": {
+                "role": "assistant",
+                "content": "Rust 1.80 shipped LazyLock.",
+                "annotations": [
+                    {
+                        "type": "url_citation",
+                        "url_citation": {
+                            "url": "https://blog.rust-lang.org/1.80",
+                            "title": "Announcing Rust 1.80",
+                            "start_index": 0,
+                            "end_index": 27
+                        }
+                    },
+                    {
+                        "type": "url_citation",
+                        "url_citation": {"url": "https://blog.rust-lang.org/1.80", "title": "dup"}
+                    }
+                ]
+            }
+        }]
+    });
+
+    let result = SearchResult::from_openai(&response);
+    assert_eq!(result.answer, "Rust 1.80 shipped LazyLock.");
+    assert_eq!(
+        result.citations,
+        vec![Citation {
+            title: "Announcing Rust 1.80".to_string(),
+            url: "https://blog.rust-lang.org/1.80".to_string(),
+            snippet: "Rust 1.80 shipped LazyLock.".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn perplexity_search_results_and_bare_citations_map_to_citations() {
+    let response = json!({
+        "choices": [{"message": {"content": "It rained [1].\n\nSources:\n[1] https://weather.example"}}],
+        "citations": ["https://weather.example"],
+        "search_results": [{
+            "title": "Weather today",
+            "url": "https://weather.example",
+            "snippet": "Rain all day"
+        }]
+    });
+    let result = SearchResult::from_perplexity(&response);
+    assert_eq!(result.answer, "It rained [1].");
+    assert_eq!(
+        result.citations,
+        vec![Citation {
+            title: "Weather today".to_string(),
+            url: "https://weather.example".to_string(),
+            snippet: "Rain all day".to_string(),
+        }]
+    );
+
+    let bare = json!({
+        "choices": [{"message": {"content": "It rained [1]."}}],
+        "citations": ["https://a.example", "https://b.example"]
+    });
+    let result = SearchResult::from_perplexity(&bare);
+    let urls: Vec<_> = result.citations.iter().map(|c| c.url.as_str()).collect();
+    assert_eq!(urls, vec!["https://a.example", "https://b.example"]);
+    assert!(result.citations.iter().all(|c| c.title.is_empty()));
+}
+
+#[test]
+fn grok_output_annotations_map_to_citations() {
+    let response = json!({
+        "output": [
+            {"type": "web_search_call", "status": "completed"},
+            {
+                "type": "message",
+                "content": [{
+                    "type": "output_text",
+                    "text": "Launch moved to Friday.",
+                    "annotations": [
+                        {
+                            "type": "url_citation",
+                            "url": "https://x.com/nasa/status/1",
+                            "title": "NASA",
+                            "start_index": 0,
+                            "end_index": 23
+                        },
+                        {"type": "file_citation", "url": "ignored"}
+                    ]
+                }]
+            }
+        ]
+    });
+
+    let result = SearchResult::from_grok(&response);
+    assert_eq!(result.answer, "Launch moved to Friday.");
+    assert_eq!(result.citations.len(), 1);
+    assert_eq!(result.citations[0].url, "https://x.com/nasa/status/1");
+    assert_eq!(result.citations[0].title, "NASA");
+    assert_eq!(result.citations[0].snippet, "Launch moved to Friday.");
+
+    assert_eq!(
+        result.render(),
+        "Launch moved to Friday.\n\n**Sources:**\n[1] NASA - https://x.com/nasa/status/1"
+    );
+}
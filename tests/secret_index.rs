@@ -0,0 +1,25 @@
+mod common;
+
+use butterfly_bot::vault;
+
+#[test]
+fn secrets_stored_before_the_index_are_listed_and_kept() {
+    common::install_mock_keyring();
+
+    // Written the way older builds did, without touching the name index.
+    keyring::Entry::new(&vault::service_name(), "openai_api_key")
+        .unwrap()
+        .set_password("sk-legacy")
+        .unwrap();
+    assert_eq!(vault::list_secret_names().unwrap(), vec!["openai_api_key"]);
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| std::thread::spawn(move || vault::set_secret(&format!("extra_{i}"), "v")))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap().unwrap();
+    }
+    let names = vault::list_secret_names().unwrap();
+    assert_eq!(names.len(), 9, "{names:?}");
+    assert!(names.contains(&"openai_api_key".to_string()));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::io::Write;
+
+use butterfly_bot::vault;
+use tempfile::NamedTempFile;
+
+#[test]
+fn secrets_file_import_stores_each_secret() {
+    common::install_mock_keyring();
+
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        r#"{{"openai_api_key": "sk-ci", "github_pat": "ghp-ci"}}"#
+    )
+    .unwrap();
+    let mut names = vault::import_secrets_file(file.path().to_str().unwrap()).unwrap();
+    names.sort();
+    assert_eq!(names, vec!["github_pat", "openai_api_key"]);
+
+    assert_eq!(
+        vault::get_secret("openai_api_key").unwrap().as_deref(),
+        Some("sk-ci")
+    );
+    assert_eq!(
+        vault::get_secret("github_pat").unwrap().as_deref(),
+        Some("ghp-ci")
+    );
+    assert_eq!(
+        vault::list_secret_names().unwrap(),
+        vec!["github_pat", "openai_api_key"]
+    );
+
+    let mut invalid = NamedTempFile::new().unwrap();
+    write!(invalid, r#"{{"brave_api_key": "ok", "count": 3}}"#).unwrap();
+    assert!(vault::import_secrets_file(invalid.path().to_str().unwrap()).is_err());
+    assert_eq!(vault::get_secret("brave_api_key").unwrap(), None);
+
+    let mut not_json = NamedTempFile::new().unwrap();
+    write!(not_json, "openai_api_key=sk").unwrap();
+    assert!(vault::import_secrets_file(not_json.path().to_str().unwrap()).is_err());
+}
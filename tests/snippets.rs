@@ -0,0 +1,36 @@
+use butterfly_bot::services::snippets::{build_snippet, DEFAULT_SNIPPET_CHARS};
+
+#[test]
+fn snippet_centers_on_query_term_within_bound() {
+    let text = format!(
+        "{} the Parser crashed on nested lists {}",
+        "lorem ipsum ".repeat(40),
+        "dolor sit amet ".repeat(40)
+    );
+    let snippet = build_snippet(&text, "parser", 60);
+
+    assert_eq!(snippet.text, text);
+    assert!(snippet.snippet.chars().count() <= 60);
+    assert_eq!(snippet.matches.len(), 1);
+    let hit = snippet.matches[0];
+    let matched: String = snippet
+        .snippet
+        .chars()
+        .skip(hit.start)
+        .take(hit.end - hit.start)
+        .collect();
+    assert_eq!(matched, "Parser");
+    let centre = (hit.start + hit.end) / 2;
+    assert!((centre as i64 - 30).abs() <= 1, "centre was {centre}");
+    assert!(snippet.highlighted().contains("<mark>Parser</mark>"));
+}
+
+#[test]
+fn snippet_without_match_uses_opening_passage() {
+    let snippet = build_snippet("short note about lunch", "parser", DEFAULT_SNIPPET_CHARS);
+    assert_eq!(snippet.snippet, "short note about lunch");
+    assert!(snippet.matches.is_empty());
+
+    let snippet = build_snippet("abcdefghij", "zzz", 4);
+    assert_eq!(snippet.snippet, "abcd");
+}
@@ -1,6 +1,15 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use httpmock::prelude::*;
 use tempfile::tempdir;
 
-use butterfly_bot::interfaces::providers::MemoryProvider;
+use butterfly_bot::config::{VectorIndexConfig, VectorIndexKind};
+use butterfly_bot::error::{ButterflyBotError, Result};
+use butterfly_bot::interfaces::providers::{
+    ChatEvent, ImageInput, LlmProvider, LlmResponse, MemoryProvider, SearchModels,
+};
 use butterfly_bot::providers::sqlite::{SqliteMemoryProvider, SqliteMemoryProviderConfig};
 
 #[tokio::test]
@@ -44,3 +53,498 @@ async fn sqlite_memory_search_uses_fts() {
     let results = provider.search("u2", "memory", 5).await.unwrap();
     assert!(results.iter().any(|item| item.contains("memory")));
 }
+
+/// Embeds everything to all ones, unless `keywords` is set: then each axis
+/// is 1.0 when its keyword appears in the text. Records the requested models.
+struct FixedDimEmbedder {
+    dim: usize,
+    keywords: Vec<&'static str>,
+    models: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl LlmProvider for FixedDimEmbedder {
+    async fn generate_text(
+        &self,
+        _prompt: &str,
+        _system_prompt: &str,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<String> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    async fn generate_with_tools(
+        &self,
+        _prompt: &str,
+        _system_prompt: &str,
+        _tools: Vec<serde_json::Value>,
+    ) -> Result<LlmResponse> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    fn chat_stream(
+        &self,
+        _messages: Vec<serde_json::Value>,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> BoxStream<'static, Result<ChatEvent>> {
+        Box::pin(futures::stream::empty())
+    }
+
+    async fn parse_structured_output(
+        &self,
+        _prompt: &str,
+        _system_prompt: &str,
+        _json_schema: serde_json::Value,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<serde_json::Value> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    async fn tts(&self, _text: &str, _voice: &str, _response_format: &str) -> Result<Vec<u8>> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    async fn transcribe_audio(&self, _audio_bytes: Vec<u8>, _input_format: &str) -> Result<String> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    async fn generate_text_with_images(
+        &self,
+        _prompt: &str,
+        _images: Vec<ImageInput>,
+        _system_prompt: &str,
+        _detail: &str,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<String> {
+        Err(ButterflyBotError::Runtime("unused".to_string()))
+    }
+
+    async fn embed(&self, inputs: Vec<String>, model: Option<&str>) -> Result<Vec<Vec<f32>>> {
+        self.models
+            .lock()
+            .unwrap()
+            .push(model.unwrap_or("default").to_string());
+        if self.keywords.is_empty() {
+            return Ok(inputs.iter().map(|_| vec![1.0; self.dim]).collect());
+        }
+        Ok(inputs
+            .iter()
+            .map(|input| {
+                let input = input.to_lowercase();
+                self.keywords
+                    .iter()
+                    .map(|keyword| if input.contains(keyword) { 1.0 } else { 0.0 })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+async fn vector_provider(dir: &std::path::Path, dim: usize, model: &str) -> SqliteMemoryProvider {
+    let mut config = SqliteMemoryProviderConfig::new(dir.join("mem.db").to_str().unwrap());
+    config.lancedb_path = Some(dir.join("lancedb").to_str().unwrap().to_string());
+    config.embedder = Some(Arc::new(FixedDimEmbedder {
+        dim,
+        keywords: Vec::new(),
+        models: Arc::default(),
+    }));
+    config.embedding_model = Some(model.to_string());
+    SqliteMemoryProvider::new(config).await.unwrap()
+}
+
+#[tokio::test]
+async fn sqlite_memory_reindex_after_embedding_model_change() {
+    let dir = tempdir().unwrap();
+    let query = "where did we travel last summer";
+
+    let provider = vector_provider(dir.path(), 2, "small-model").await;
+    provider
+        .append_message("u3", "user", "Hiking trip through the Alps")
+        .await
+        .unwrap();
+    let results = provider.search("u3", query, 5).await.unwrap();
+    assert!(results.iter().any(|item| item.contains("Alps")));
+    drop(provider);
+
+    let provider = vector_provider(dir.path(), 3, "large-model").await;
+    let stale = provider.search("u3", query, 5).await;
+    assert!(!matches!(stale, Ok(ref items) if items.iter().any(|item| item.contains("Alps"))));
+
+    let report = provider.reindex_memory("u3", None).await.unwrap();
+    assert_eq!(report.total, 1);
+    assert_eq!(report.reindexed, 1);
+    assert!(report.last_id.is_some());
+
+    let results = provider.search("u3", query, 5).await.unwrap();
+    assert!(results.iter().any(|item| item.contains("Alps")));
+
+    let resumed = provider.reindex_memory("u3", report.last_id).await.unwrap();
+    assert_eq!(resumed.reindexed, 0);
+    assert_eq!(resumed.last_id, report.last_id);
+
+    let again = provider.reindex_memory("u3", None).await.unwrap();
+    assert_eq!(again.reindexed, 1);
+    let results = provider.search("u3", query, 5).await.unwrap();
+    assert_eq!(
+        results.iter().filter(|item| item.contains("Alps")).count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn sqlite_memory_search_drops_results_below_min_score() {
+    let dir = tempdir().unwrap();
+    let mut config = SqliteMemoryProviderConfig::new(dir.path().join("mem.db").to_str().unwrap());
+    config.lancedb_path = Some(dir.path().join("lancedb").to_str().unwrap().to_string());
+    config.embedder = Some(Arc::new(FixedDimEmbedder {
+        dim: 2,
+        keywords: vec!["alps", "budget"],
+        models: Arc::default(),
+    }));
+    config.min_score = Some(0.8);
+    let provider = SqliteMemoryProvider::new(config).await.unwrap();
+
+    for text in [
+        "Hiking trip through the Alps",
+        "Quarterly budget review",
+        "Groceries for the week",
+    ] {
+        provider.append_message("u4", "user", text).await.unwrap();
+    }
+
+    let query = "what did we do in the alps";
+    let results = provider.search("u4", query, 5).await.unwrap();
+    assert_eq!(results.len(), 1, "{results:?}");
+    assert!(results[0].contains("Alps"));
+
+    let relaxed = provider
+        .search_with_min_score("u4", query, 5, Some(0.0))
+        .await
+        .unwrap();
+    assert_eq!(relaxed.len(), 3);
+}
+
+#[tokio::test]
+async fn sqlite_memory_full_text_hits_are_scored_by_bm25() {
+    let dir = tempdir().unwrap();
+    let config = SqliteMemoryProviderConfig::new(dir.path().join("mem.db").to_str().unwrap());
+    let provider = SqliteMemoryProvider::new(config).await.unwrap();
+    for text in [
+        "Quarterly budget review",
+        "Hiking trip through the Alps",
+        "Groceries for the week",
+    ] {
+        provider.append_message("u5", "user", text).await.unwrap();
+    }
+
+    let hits = provider
+        .search_with_min_score("u5", "budget", 5, Some(0.0))
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].contains("budget"));
+
+    // A one-word hit in a tiny corpus is a weak match, not a perfect one.
+    assert!(provider
+        .search_with_min_score("u5", "budget", 5, Some(0.95))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn sqlite_memory_builds_configured_vector_index_and_keeps_top_k() {
+    let dir = tempdir().unwrap();
+    let indexed_config = |index: VectorIndexConfig| {
+        let mut config =
+            SqliteMemoryProviderConfig::new(dir.path().join("mem.db").to_str().unwrap());
+        config.lancedb_path = Some(dir.path().join("lancedb").to_str().unwrap().to_string());
+        config.embedder = Some(Arc::new(FixedDimEmbedder {
+            dim: 4,
+            keywords: vec!["alps", "budget", "groceries", "piano"],
+            models: Arc::default(),
+        }));
+        config.min_score = Some(0.8);
+        config.vector_index = Some(index);
+        config
+    };
+
+    for invalid in [
+        VectorIndexConfig {
+            partitions: Some(0),
+            ..Default::default()
+        },
+        VectorIndexConfig {
+            partitions: Some(2),
+            nprobes: Some(3),
+            ..Default::default()
+        },
+        VectorIndexConfig {
+            sub_vectors: Some(2),
+            ..Default::default()
+        },
+    ] {
+        let err = SqliteMemoryProvider::new(indexed_config(invalid))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, ButterflyBotError::Config(_)), "{err:?}");
+    }
+
+    let index = VectorIndexConfig {
+        kind: VectorIndexKind::IvfFlat,
+        partitions: Some(2),
+        nprobes: Some(2),
+        sub_vectors: None,
+    };
+    let provider = SqliteMemoryProvider::new(indexed_config(index))
+        .await
+        .unwrap();
+    for text in [
+        "Hiking trip through the Alps",
+        "Quarterly budget review",
+        "Skiing in the Alps in March",
+        "Groceries for the week",
+        "Piano lesson on Tuesday",
+    ] {
+        provider.append_message("u5", "user", text).await.unwrap();
+    }
+    assert!(provider.build_vector_index().await.unwrap());
+
+    let results = provider
+        .search("u5", "what about the alps", 2)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2, "{results:?}");
+    assert!(results.iter().all(|item| item.contains("Alps")));
+    drop(provider);
+
+    // Reopening picks the index back up without rebuilding it.
+    let provider = SqliteMemoryProvider::new(indexed_config(index))
+        .await
+        .unwrap();
+    let results = provider
+        .search("u5", "when is my next piano practice", 1)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1, "{results:?}");
+    assert!(results[0].contains("Piano"));
+}
+
+#[tokio::test]
+async fn sqlite_memory_search_uses_override_models_for_one_query() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/api/tags");
+            then.status(200).json_body(serde_json::json!({
+                "models": [{"name": "embed-b:latest"}, {"name": "rerank-b:latest"}]
+            }));
+        })
+        .await;
+
+    let dir = tempdir().unwrap();
+    let models = Arc::new(Mutex::new(Vec::new()));
+    let mut config = SqliteMemoryProviderConfig::new(dir.path().join("mem.db").to_str().unwrap());
+    config.lancedb_path = Some(dir.path().join("lancedb").to_str().unwrap().to_string());
+    config.embedder = Some(Arc::new(FixedDimEmbedder {
+        dim: 2,
+        keywords: Vec::new(),
+        models: models.clone(),
+    }));
+    config.embedding_model = Some("embed-a".to_string());
+    config.ollama_tags_url = Some(server.url("/api/tags"));
+    let provider = SqliteMemoryProvider::new(config).await.unwrap();
+    provider
+        .append_message("u5", "user", "Hiking trip through the Alps")
+        .await
+        .unwrap();
+
+    let query = "where did we travel last summer";
+    let last_model = || models.lock().unwrap().last().cloned();
+    provider.search("u5", query, 5).await.unwrap();
+    assert_eq!(last_model().as_deref(), Some("embed-a"));
+
+    let overrides = SearchModels {
+        embedding_model: Some("embed-b".to_string()),
+        rerank_model: None,
+    };
+    let results = provider
+        .search_with_models("u5", query, 5, None, &overrides)
+        .await
+        .unwrap();
+    assert!(results.iter().any(|item| item.contains("Alps")));
+    assert_eq!(last_model().as_deref(), Some("embed-b"));
+
+    // The override only lasts for that query.
+    provider
+        .search("u5", "which mountains did we hike that summer", 5)
+        .await
+        .unwrap();
+    assert_eq!(last_model().as_deref(), Some("embed-a"));
+
+    let calls = models.lock().unwrap().len();
+    let missing = SearchModels {
+        embedding_model: Some("not-pulled".to_string()),
+        rerank_model: None,
+    };
+    assert!(provider
+        .search_with_models("u5", query, 5, None, &missing)
+        .await
+        .is_err());
+    // No reranker factory is configured, so a rerank override is refused too.
+    let rerank = SearchModels {
+        embedding_model: None,
+        rerank_model: Some("rerank-b".to_string()),
+    };
+    assert!(provider
+        .search_with_models("u5", query, 5, None, &rerank)
+        .await
+        .is_err());
+    assert_eq!(models.lock().unwrap().len(), calls);
+}
+
+#[tokio::test]
+async fn sqlite_memory_archives_idle_chats_and_restores_them() {
+    use diesel::connection::SimpleConnection;
+    use diesel::prelude::*;
+    use diesel::sqlite::SqliteConnection;
+
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("mem.db");
+    let db_path = db_path.to_str().unwrap();
+    let provider = SqliteMemoryProvider::new(SqliteMemoryProviderConfig::new(db_path))
+        .await
+        .unwrap();
+    provider
+        .append_message("idle", "user", "old")
+        .await
+        .unwrap();
+    provider
+        .append_message("busy", "user", "new")
+        .await
+        .unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let ttl = 7 * 24 * 60 * 60;
+    let mut conn = SqliteConnection::establish(db_path).unwrap();
+    conn.batch_execute(&format!(
+        "UPDATE messages SET timestamp = {} WHERE user_id = 'idle'",
+        now - ttl - 60
+    ))
+    .unwrap();
+
+    let archived = provider.archive_idle_chats(now - ttl).await.unwrap();
+    assert_eq!(archived, vec!["idle".to_string()]);
+    assert!(provider
+        .archive_idle_chats(now - ttl)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let listed: Vec<String> = provider
+        .list_chats(false)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|chat| chat.user_id)
+        .collect();
+    assert_eq!(listed, vec!["busy".to_string()]);
+    let all = provider.list_chats(true).await.unwrap();
+    let idle = all.iter().find(|chat| chat.user_id == "idle").unwrap();
+    assert!(idle.archived_at.is_some());
+    assert_eq!(idle.message_count, 1);
+    assert_eq!(provider.get_history("idle", 10).await.unwrap().len(), 1);
+
+    assert!(provider.unarchive_chat("idle").await.unwrap());
+    assert!(!provider.unarchive_chat("idle").await.unwrap());
+    assert_eq!(provider.list_chats(false).await.unwrap().len(), 2);
+
+    assert!(provider.archive_chat("busy").await.unwrap());
+    assert!(!provider.archive_chat("busy").await.unwrap());
+    assert!(!provider.archive_chat("nobody").await.unwrap());
+    provider
+        .append_message("busy", "user", "back")
+        .await
+        .unwrap();
+    let busy = provider.list_chats(false).await.unwrap();
+    assert!(busy.iter().any(|chat| chat.user_id == "busy"));
+}
+
+#[tokio::test]
+async fn sqlite_memory_truncate_keeps_a_quoted_user_id_to_its_own_vectors() {
+    let dir = tempdir().unwrap();
+    let mut config = SqliteMemoryProviderConfig::new(dir.path().join("mem.db").to_str().unwrap());
+    config.lancedb_path = Some(dir.path().join("lancedb").to_str().unwrap().to_string());
+    config.embedder = Some(Arc::new(FixedDimEmbedder {
+        dim: 1,
+        keywords: vec!["alp"],
+        models: Arc::default(),
+    }));
+    let provider = SqliteMemoryProvider::new(config).await.unwrap();
+
+    let intruder = "x' OR user_id != '";
+    provider
+        .append_message(intruder, "user", "Alpine lodge booking")
+        .await
+        .unwrap();
+    provider
+        .append_message("victim", "user", "Alpine hike on Sunday")
+        .await
+        .unwrap();
+    let vector_hits = |user_id: &'static str| {
+        let provider = &provider;
+        async move {
+            provider
+                .search_with_min_score(user_id, "alp trip", 5, Some(0.9))
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(vector_hits("victim").await.len(), 1);
+    assert_eq!(vector_hits(intruder).await.len(), 1);
+
+    let first = provider.get_messages(intruder, None, 10).await.unwrap()[0].id;
+    provider.truncate_from(intruder, first).await.unwrap();
+
+    assert!(vector_hits(intruder).await.is_empty());
+    let kept = vector_hits("victim").await;
+    assert_eq!(kept.len(), 1);
+    assert!(kept[0].contains("Alpine hike"));
+}
+
+#[tokio::test]
+async fn sqlite_memory_reindex_leaves_other_users_vectors_alone() {
+    let dir = tempdir().unwrap();
+    let query = "where did we travel last summer";
+    let vector_hits = |provider: &SqliteMemoryProvider, user_id: &'static str| {
+        let provider = provider.clone();
+        async move {
+            provider
+                .search_with_min_score(user_id, query, 5, Some(0.9))
+                .await
+                .unwrap()
+        }
+    };
+
+    let provider = vector_provider(dir.path(), 2, "small-model").await;
+    for user_id in ["u5", "u6"] {
+        provider
+            .append_message(user_id, "user", "Hiking trip through the Alps")
+            .await
+            .unwrap();
+    }
+    drop(provider);
+
+    let provider = vector_provider(dir.path(), 3, "large-model").await;
+    provider.reindex_memory("u5", None).await.unwrap();
+    assert_eq!(vector_hits(&provider, "u5").await.len(), 1);
+    drop(provider);
+
+    let provider = vector_provider(dir.path(), 2, "small-model").await;
+    assert_eq!(vector_hits(&provider, "u6").await.len(), 1);
+    assert!(vector_hits(&provider, "u5").await.is_empty());
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use butterfly_bot::interfaces::plugins::Tool;
+use butterfly_bot::reminders::ReminderStore;
+use butterfly_bot::services::links::{LinkService, LinkedId};
+use butterfly_bot::todo::{TodoRecurrence, TodoStatus, TodoStore};
+use butterfly_bot::tools::reminders::RemindersTool;
+use butterfly_bot::tools::todo::TodoTool;
+use serde_json::json;
+use tempfile::tempdir;
+
+const WEEK: i64 = 7 * 24 * 60 * 60;
+
+#[tokio::test]
+async fn completing_weekly_todo_opens_next_occurrence() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("todo.db");
+    let store = TodoStore::new(db_path.to_str().unwrap()).await.unwrap();
+
+    let due_at = 1_900_000_000;
+    let item = store
+        .create_scheduled_item(
+            "u1",
+            "take out trash",
+            None,
+            Some(due_at),
+            Some(TodoRecurrence::parse("weekly").unwrap()),
+        )
+        .await
+        .unwrap();
+
+    let completed = store.set_completed(item.id, true).await.unwrap();
+    assert!(completed.completed_at.is_some());
+
+    let open = store.list_items("u1", TodoStatus::Open, 10).await.unwrap();
+    assert_eq!(open.len(), 1);
+    assert_ne!(open[0].id, item.id);
+    assert_eq!(open[0].title, "take out trash");
+    assert_eq!(open[0].due_at, Some(due_at + WEEK));
+    assert_eq!(open[0].recurrence, Some(TodoRecurrence::Weekly));
+
+    // Completing an already-completed todo does not spawn another occurrence.
+    store.set_completed(item.id, true).await.unwrap();
+    assert_eq!(
+        store
+            .list_items("u1", TodoStatus::All, 10)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn a_recurring_todo_opens_its_next_occurrence_only_once() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("todo.db");
+    let path = db_path.to_str().unwrap();
+    let store = Arc::new(TodoStore::new(path).await.unwrap());
+    let other = Arc::new(TodoStore::new(path).await.unwrap());
+
+    let item = store
+        .create_scheduled_item(
+            "u1",
+            "water plants",
+            None,
+            Some(1_900_000_000),
+            Some(TodoRecurrence::Weekly),
+        )
+        .await
+        .unwrap();
+    let (first, second) = tokio::join!(
+        {
+            let store = store.clone();
+            tokio::spawn(async move { store.set_completed(item.id, true).await })
+        },
+        {
+            let other = other.clone();
+            tokio::spawn(async move { other.set_completed(item.id, true).await })
+        },
+    );
+    first.unwrap().unwrap();
+    second.unwrap().unwrap();
+
+    // Reopening and completing again doesn't add a second occurrence either.
+    store.set_completed(item.id, false).await.unwrap();
+    store.set_completed(item.id, true).await.unwrap();
+    store.complete_all("u1").await.unwrap();
+
+    let items = store.list_items("u1", TodoStatus::All, 10).await.unwrap();
+    let spawned: Vec<_> = items
+        .iter()
+        .filter(|todo| todo.spawned_from == Some(item.id))
+        .collect();
+    assert_eq!(spawned.len(), 1);
+    // complete_all finished that occurrence, which opened the one after it.
+    assert_eq!(items.len(), 3);
+    assert_eq!(
+        items
+            .iter()
+            .filter(|todo| todo.completed_at.is_none())
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn non_recurring_completion_is_unchanged() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("todo.db");
+    let store = TodoStore::new(db_path.to_str().unwrap()).await.unwrap();
+
+    let item = store.create_item("u1", "call mom", None).await.unwrap();
+    assert_eq!(item.recurrence, None);
+    store.set_completed(item.id, true).await.unwrap();
+    assert!(store
+        .list_items("u1", TodoStatus::Open, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(
+        TodoRecurrence::parse("every 3 days").unwrap(),
+        TodoRecurrence::EveryDays(3)
+    );
+    assert_eq!(
+        TodoRecurrence::EveryDays(3).interval_secs(),
+        3 * 24 * 60 * 60
+    );
+    assert!(TodoRecurrence::parse("every 0 days").is_err());
+    assert!(TodoRecurrence::parse("hourly").is_err());
+}
+
+#[tokio::test]
+async fn completing_linked_todo_completes_its_reminder() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("items.db");
+    let db_path = db_path.to_str().unwrap();
+    let todos = Arc::new(TodoStore::new(db_path).await.unwrap());
+    let reminders = Arc::new(ReminderStore::new(db_path).await.unwrap());
+    let links = LinkService::new(reminders.clone(), todos.clone());
+
+    let todo = todos.create_item("u1", "pay rent", None).await.unwrap();
+    let reminder = reminders
+        .create_reminder("u1", "pay rent", 1_900_000_000)
+        .await
+        .unwrap();
+    links.link("u1", todo.id, reminder.id).await.unwrap();
+    assert_eq!(
+        todos
+            .get_item(todo.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .linked_reminder_id,
+        Some(reminder.id)
+    );
+
+    let completion = links
+        .complete_linked("u1", LinkedId::Todo(todo.id))
+        .await
+        .unwrap();
+    assert_eq!(completion.todo_id, Some(todo.id));
+    assert_eq!(completion.reminder_id, Some(reminder.id));
+    let reminder = reminders
+        .get_reminder("u1", reminder.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(reminder.completed_at.is_some());
+    assert_eq!(reminder.linked_todo_id, Some(todo.id));
+}
+
+#[tokio::test]
+async fn tools_and_bulk_completion_complete_linked_items() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("items.db");
+    let db_path = db_path.to_str().unwrap();
+    let todos = Arc::new(TodoStore::new(db_path).await.unwrap());
+    let reminders = Arc::new(ReminderStore::new(db_path).await.unwrap());
+    let links = LinkService::new(reminders.clone(), todos.clone());
+    let config = json!({"tools": {
+        "todo": {"sqlite_path": db_path},
+        "reminders": {"sqlite_path": db_path},
+    }});
+
+    let mut pairs = Vec::new();
+    for title in ["pay rent", "call mum", "water plants", "book flights"] {
+        let todo = todos.create_item("u1", title, None).await.unwrap();
+        let reminder = reminders
+            .create_reminder("u1", title, 1_900_000_000)
+            .await
+            .unwrap();
+        links.link("u1", todo.id, reminder.id).await.unwrap();
+        pairs.push((todo.id, reminder.id));
+    }
+    let reminder_done = |id: i32| {
+        let reminders = reminders.clone();
+        async move {
+            reminders
+                .get_reminder("u1", id)
+                .await
+                .unwrap()
+                .unwrap()
+                .completed_at
+                .is_some()
+        }
+    };
+    let todo_done = |id: i32| {
+        let todos = todos.clone();
+        async move {
+            todos
+                .get_item(id)
+                .await
+                .unwrap()
+                .unwrap()
+                .completed_at
+                .is_some()
+        }
+    };
+
+    let todo_tool = TodoTool::new().with_reminder_path(db_path);
+    todo_tool.configure(&config).unwrap();
+    let result = todo_tool
+        .execute(json!({"action": "complete", "user_id": "u1", "id": pairs[0].0}))
+        .await
+        .unwrap();
+    assert!(result["item"]["completed_at"].is_i64());
+    assert!(reminder_done(pairs[0].1).await);
+
+    let reminders_tool = RemindersTool::new().with_todo_path(db_path);
+    reminders_tool.configure(&config).unwrap();
+    let result = reminders_tool
+        .execute(json!({"action": "complete", "user_id": "u1", "id": pairs[1].1}))
+        .await
+        .unwrap();
+    assert_eq!(result["completed"], true);
+    assert!(todo_done(pairs[1].0).await);
+    let result = reminders_tool
+        .execute(json!({"action": "complete", "user_id": "u1", "id": 9999}))
+        .await
+        .unwrap();
+    assert_eq!(result["completed"], false);
+
+    links.complete_reminders(&[pairs[2].1]).await.unwrap();
+    assert!(todo_done(pairs[2].0).await);
+
+    assert_eq!(links.complete_all_todos("u1").await.unwrap(), 1);
+    assert!(reminder_done(pairs[3].1).await);
+}
+
+#[tokio::test]
+async fn dangling_links_are_rejected_or_cleared() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("items.db");
+    let db_path = db_path.to_str().unwrap();
+    let todos = Arc::new(TodoStore::new(db_path).await.unwrap());
+    let reminders = Arc::new(ReminderStore::new(db_path).await.unwrap());
+    let links = LinkService::new(reminders.clone(), todos.clone());
+
+    let todo = todos
+        .create_item("u1", "renew passport", None)
+        .await
+        .unwrap();
+    assert!(links.link("u1", todo.id, 999).await.is_err());
+    assert!(links.link("u2", todo.id, 999).await.is_err());
+
+    let reminder = reminders
+        .create_reminder("u1", "renew passport", 1_900_000_000)
+        .await
+        .unwrap();
+    links.link("u1", todo.id, reminder.id).await.unwrap();
+    assert!(todos.delete_item(todo.id).await.unwrap());
+
+    let completion = links
+        .complete_linked("u1", LinkedId::Reminder(reminder.id))
+        .await
+        .unwrap();
+    assert_eq!(completion.todo_id, None);
+    assert_eq!(completion.reminder_id, Some(reminder.id));
+    let reminder = reminders
+        .get_reminder("u1", reminder.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reminder.linked_todo_id, None);
+    assert!(links
+        .complete_linked("u1", LinkedId::Todo(todo.id))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn complete_all_then_clear_completed_keeps_open_positions_contiguous() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("todo.db");
+    let store = TodoStore::new(db_path.to_str().unwrap()).await.unwrap();
+
+    let mut ids = Vec::new();
+    for title in ["milk", "eggs", "bread", "jam"] {
+        ids.push(store.create_item("u1", title, None).await.unwrap().id);
+    }
+    store.create_item("u2", "other", None).await.unwrap();
+    store.set_completed(ids[0], true).await.unwrap();
+    store.set_completed(ids[2], true).await.unwrap();
+
+    assert_eq!(store.clear_completed("u1").await.unwrap(), 2);
+    let open = store.list_items("u1", TodoStatus::All, 10).await.unwrap();
+    let remaining: Vec<(&str, i32)> = open
+        .iter()
+        .map(|item| (item.title.as_str(), item.position))
+        .collect();
+    assert_eq!(remaining, vec![("eggs", 1), ("jam", 2)]);
+
+    // New items still land at the end.
+    let late = store.create_item("u1", "butter", None).await.unwrap();
+    assert_eq!(late.position, 3);
+
+    assert_eq!(store.complete_all("u1").await.unwrap(), 3);
+    assert_eq!(store.complete_all("u1").await.unwrap(), 0);
+    assert_eq!(store.count_open("u1").await.unwrap(), 0);
+    assert_eq!(store.count_open("u2").await.unwrap(), 1);
+
+    assert_eq!(store.clear_completed("u1").await.unwrap(), 3);
+    assert!(store
+        .list_items("u1", TodoStatus::All, 10)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        store
+            .list_items("u2", TodoStatus::All, 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn concurrent_reorders_leave_a_valid_permutation() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("todo.db");
+    let path = db_path.to_str().unwrap();
+    let store = Arc::new(TodoStore::new(path).await.unwrap());
+    // A second store has its own pool, like another process would.
+    let other = Arc::new(TodoStore::new(path).await.unwrap());
+
+    let mut ids = Vec::new();
+    for title in ["a", "b", "c", "d", "e", "f"] {
+        ids.push(store.create_item("u1", title, None).await.unwrap().id);
+    }
+    let foreign = store.create_item("u2", "x", None).await.unwrap().id;
+
+    let reversed: Vec<i32> = ids.iter().rev().copied().collect();
+    let rotated: Vec<i32> = ids[3..].iter().chain(&ids[..3]).copied().collect();
+    for _ in 0..10 {
+        let (first, second) = tokio::join!(
+            {
+                let store = store.clone();
+                let order = reversed.clone();
+                tokio::spawn(async move { store.reorder("u1", &order).await })
+            },
+            {
+                let other = other.clone();
+                let order = rotated.clone();
+                tokio::spawn(async move { other.reorder("u1", &order).await })
+            },
+        );
+        first.unwrap().unwrap();
+        second.unwrap().unwrap();
+
+        let items = store.list_items("u1", TodoStatus::All, 10).await.unwrap();
+        let positions: Vec<i32> = items.iter().map(|item| item.position).collect();
+        assert_eq!(positions, vec![1, 2, 3, 4, 5, 6]);
+        let order: Vec<i32> = items.iter().map(|item| item.id).collect();
+        assert!(order == reversed || order == rotated, "{order:?}");
+    }
+
+    // The order must name every one of the user's items exactly once.
+    for bad in [
+        ids[..5].to_vec(),
+        [ids.clone(), vec![ids[0]]].concat(),
+        [ids[1..].to_vec(), vec![ids[1]]].concat(),
+        [ids.clone(), vec![foreign]].concat(),
+    ] {
+        assert!(store.reorder("u1", &bad).await.is_err(), "{bad:?}");
+    }
+    let items = store.list_items("u1", TodoStatus::All, 10).await.unwrap();
+    let positions: Vec<i32> = items.iter().map(|item| item.position).collect();
+    assert_eq!(positions, vec![1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use butterfly_bot::brain::manager::BrainManager;
+use butterfly_bot::domains::agent::AIAgent;
+use butterfly_bot::interfaces::providers::{LlmResponse, ToolCall};
+use butterfly_bot::services::agent::AgentService;
+
+use common::{DummyTool, QueueLlmProvider};
+
+type Fields = HashMap<String, String>;
+
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<HashMap<u64, (String, Fields)>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S> Layer<S> for SpanRecorder
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id.into_u64(), (attrs.metadata().name().to_string(), fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+        if let Some((_, fields)) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+}
+
+#[tokio::test]
+async fn tool_execution_emits_span_with_name_and_status() {
+    let recorder = SpanRecorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let llm = Arc::new(QueueLlmProvider::new(vec![
+        LlmResponse {
+            text: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "dummy".to_string(),
+                arguments: json!({}),
+            }],
+            usage: None,
+        },
+        LlmResponse {
+            text: "done".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+        },
+    ]));
+    let agent = AIAgent {
+        name: "agent".to_string(),
+        instructions: "inst".to_string(),
+        specialization: "spec".to_string(),
+        memory_namespace: None,
+    };
+    let brain = Arc::new(BrainManager::new(json!({})));
+    let service = AgentService::new(llm, agent, None, brain, None);
+    let registry = service.tool_registry.clone();
+    assert!(
+        registry
+            .register_tool(Arc::new(DummyTool::new("dummy")))
+            .await
+    );
+    assert!(
+        registry
+            .assign_tool_to_agent(service.agent_name(), "dummy")
+            .await
+    );
+
+    let response = service
+        .generate_response("u1", "query", "", None)
+        .await
+        .unwrap();
+    assert_eq!(response, "done");
+
+    let spans = recorder.spans.lock().unwrap();
+    let (_, fields) = spans
+        .values()
+        .find(|(name, _)| name == "tool.execute")
+        .expect("tool.execute span");
+    assert_eq!(fields.get("tool").map(String::as_str), Some("dummy"));
+    assert_eq!(fields.get("call_id").map(String::as_str), Some("call_1"));
+    assert_eq!(fields.get("status").map(String::as_str), Some("success"));
+    assert!(fields.contains_key("duration_ms"));
+}
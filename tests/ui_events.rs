@@ -0,0 +1,57 @@
+use butterfly_bot::services::agent::UiEvent;
+use butterfly_bot::services::ui_events::UiEventHub;
+use serde_json::json;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+fn event(user_id: &str, seq: usize) -> UiEvent {
+    UiEvent {
+        event_type: "tool".to_string(),
+        user_id: user_id.to_string(),
+        tool: format!("step-{seq}"),
+        status: "success".to_string(),
+        payload: json!({ "seq": seq }),
+        timestamp: 0,
+        request_id: None,
+    }
+}
+
+#[tokio::test]
+async fn slow_subscriber_lags_without_starving_fast_one() {
+    let hub = UiEventHub::new(4);
+    let mut fast = hub.subscribe("u1");
+    let mut slow = hub.subscribe("u1");
+    let mut other_user = hub.subscribe("u2");
+
+    for seq in 0..10 {
+        hub.publish(event("u1", seq));
+        let received = fast.recv().await.unwrap();
+        assert_eq!(received.tool, format!("step-{seq}"));
+    }
+
+    // The slow reader only gets the newest `capacity` events, after a marker
+    // saying how many it missed.
+    match slow.recv().await {
+        Err(RecvError::Lagged(skipped)) => assert_eq!(skipped, 6),
+        other => panic!("expected a lag, got {other:?}"),
+    }
+    for seq in 6..10 {
+        assert_eq!(slow.recv().await.unwrap().tool, format!("step-{seq}"));
+    }
+
+    assert!(matches!(other_user.try_recv(), Err(TryRecvError::Empty)));
+}
+
+#[tokio::test]
+async fn forwarded_events_reach_only_their_user() {
+    let (tx, _rx) = tokio::sync::broadcast::channel(16);
+    let hub = UiEventHub::new(8);
+    let mut u1 = hub.subscribe("u1");
+    let mut u2 = hub.subscribe("u2");
+    hub.forward_from(tx.subscribe());
+
+    tx.send(event("u2", 0)).unwrap();
+    tx.send(event("u1", 1)).unwrap();
+
+    assert_eq!(u1.recv().await.unwrap().tool, "step-1");
+    assert_eq!(u2.recv().await.unwrap().tool, "step-0");
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use butterfly_bot::vault;
+
+#[test]
+fn profiles_store_secrets_independently() {
+    common::install_mock_keyring();
+
+    assert_eq!(vault::service_for_profile(None), "butterfly-bot");
+    assert_eq!(vault::service_for_profile(Some("")), "butterfly-bot");
+    assert_eq!(
+        vault::service_for_profile(Some("work")),
+        "butterfly-bot-work"
+    );
+
+    std::env::set_var(vault::PROFILE_ENV, "work");
+    vault::set_secret("openai_api_key", "work-key").unwrap();
+    std::env::set_var(vault::PROFILE_ENV, "personal");
+    assert_eq!(vault::get_secret("openai_api_key").unwrap(), None);
+    vault::set_secret("openai_api_key", "personal-key").unwrap();
+
+    std::env::set_var(vault::PROFILE_ENV, "work");
+    assert_eq!(
+        vault::get_secret("openai_api_key").unwrap().as_deref(),
+        Some("work-key")
+    );
+    std::env::set_var(vault::PROFILE_ENV, "personal");
+    assert_eq!(
+        vault::get_secret("openai_api_key").unwrap().as_deref(),
+        Some("personal-key")
+    );
+
+    std::env::remove_var(vault::PROFILE_ENV);
+    assert_eq!(vault::get_secret("openai_api_key").unwrap(), None);
+}
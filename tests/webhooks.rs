@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use httpmock::Method::POST;
+use httpmock::MockServer;
+use serde_json::json;
+use tempfile::NamedTempFile;
+use tokio::sync::{broadcast, RwLock};
+use tower::ServiceExt;
+
+use butterfly_bot::captures::CaptureStore;
+use butterfly_bot::client::ButterflyBot;
+use butterfly_bot::config::{Config, OpenAiConfig};
+use butterfly_bot::daemon::{build_router, AppState, DaemonWorkHandler};
+use butterfly_bot::planning::PlanStore;
+use butterfly_bot::reminders::{ReminderItem, ReminderStore};
+use butterfly_bot::scheduler::due_work::{DueWork, DueWorkHandler};
+use butterfly_bot::services::agent::UiEvent;
+use butterfly_bot::services::ui_events::UiEventHub;
+use butterfly_bot::tasks::{ScheduledTask, TaskStore};
+use butterfly_bot::todo::TodoStore;
+use butterfly_bot::webhooks::{WebhookNotifier, WebhookPayload};
+
+#[tokio::test]
+async fn webhook_posts_fired_item_payload() {
+    let server = MockServer::start_async().await;
+    let hook = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/hook").json_body(json!({
+                "kind": "reminder",
+                "id": 7,
+                "title": "stretch",
+                "user_id": "u1",
+                "fired_at": 1000
+            }));
+            then.status(200);
+        })
+        .await;
+
+    let config = json!({"tools": {"settings": {"webhook_url": server.url("/hook")}}});
+    let notifier = WebhookNotifier::from_config(&config).unwrap();
+    notifier
+        .send(&WebhookPayload {
+            kind: "reminder".to_string(),
+            id: 7,
+            title: "stretch".to_string(),
+            user_id: "u1".to_string(),
+            fired_at: 1000,
+        })
+        .await
+        .unwrap();
+    hook.assert_hits(1);
+
+    assert!(WebhookNotifier::from_config(&json!({"tools": {}})).is_none());
+}
+
+#[tokio::test]
+async fn failing_webhook_is_retried_then_reported() {
+    let server = MockServer::start_async().await;
+    let hook = server
+        .mock_async(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(500);
+        })
+        .await;
+    let notifier = Arc::new(
+        WebhookNotifier::new(server.url("/hook"))
+            .with_retries(1)
+            .with_timeout(Duration::from_secs(2)),
+    );
+    let payload = WebhookPayload {
+        kind: "task".to_string(),
+        id: 1,
+        title: "t".to_string(),
+        user_id: "u".to_string(),
+        fired_at: 0,
+    };
+    assert!(notifier.send(&payload).await.is_err());
+    hook.assert_hits(2);
+}
+
+async fn offline_agent() -> ButterflyBot {
+    ButterflyBot::from_config(Config {
+        openai: Some(OpenAiConfig {
+            api_key: Some("key".to_string()),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            headers: None,
+            json_mode: None,
+        }),
+        skill_file: None,
+        heartbeat_file: None,
+        memory: None,
+        tools: None,
+        brains: None,
+        costs: None,
+        agents: None,
+        scheduler: None,
+        daemon: None,
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn reminder_stream_relays_reminders_fired_by_the_scheduler() {
+    let db = NamedTempFile::new().unwrap();
+    let db_path = db.path().to_str().unwrap().to_string();
+    let agent = offline_agent().await;
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let state = AppState {
+        agent: Arc::new(RwLock::new(Arc::new(agent))),
+        reminder_store: Arc::new(ReminderStore::new(&db_path).await.unwrap()),
+        task_store: Arc::new(TaskStore::new(&db_path).await.unwrap()),
+        todo_store: Arc::new(TodoStore::new(&db_path).await.unwrap()),
+        plan_store: Arc::new(PlanStore::new(&db_path).await.unwrap()),
+        capture_store: Arc::new(CaptureStore::new(&db_path).await.unwrap()),
+        token: "token".to_string(),
+        ui_event_tx: ui_event_tx.clone(),
+        db_path,
+        webhook: None,
+        sse_keep_alive: None,
+        cors: None,
+        body_limits: None,
+    };
+    let app = build_router(state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/reminder_stream?user_id=u")
+                .header("authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let event = |user_id: &str, event_type: &str, title: &str| UiEvent {
+        event_type: event_type.to_string(),
+        user_id: user_id.to_string(),
+        tool: "reminders".to_string(),
+        status: "fired".to_string(),
+        payload: json!({"id": 7, "title": title, "due_at": 100}),
+        timestamp: 100,
+        request_id: None,
+    };
+    ui_event_tx
+        .send(event("other", "reminder", "not yours"))
+        .unwrap();
+    ui_event_tx
+        .send(event("u", "tasks", "not a reminder"))
+        .unwrap();
+    ui_event_tx.send(event("u", "reminder", "stretch")).unwrap();
+
+    let mut body = response.into_body();
+    let frame = tokio::time::timeout(Duration::from_secs(2), body.frame())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let chunk = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+    assert!(chunk.starts_with("data: "));
+    assert!(chunk.contains("stretch"));
+    assert!(chunk.contains("\"id\":7"));
+}
+
+#[tokio::test]
+async fn undelivered_reminders_fail_so_their_lease_retries() {
+    let (ui_event_tx, _) = broadcast::channel(16);
+    let hub = UiEventHub::new(16);
+    hub.forward_from(ui_event_tx.subscribe());
+    let handler = DaemonWorkHandler::new(
+        Arc::new(RwLock::new(Arc::new(offline_agent().await))),
+        ui_event_tx,
+        hub.clone(),
+    );
+    let work = DueWork::Reminder {
+        user_id: "u".to_string(),
+        item: ReminderItem {
+            id: 3,
+            title: "stretch".to_string(),
+            due_at: 100,
+            created_at: 50,
+            completed_at: None,
+            fired_at: None,
+            linked_todo_id: None,
+        },
+    };
+
+    assert!(handler.handle(work.clone()).await.is_err());
+
+    let mut subscriber = hub.subscribe("u");
+    handler.handle(work).await.unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(2), subscriber.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.event_type, "reminder");
+    assert_eq!(event.payload["id"], 3);
+}
+
+#[tokio::test]
+async fn failed_task_runs_are_handed_back_to_the_scheduler() {
+    let (ui_event_tx, mut events) = broadcast::channel(16);
+    let handler = DaemonWorkHandler::new(
+        Arc::new(RwLock::new(Arc::new(offline_agent().await))),
+        ui_event_tx,
+        UiEventHub::new(16),
+    );
+    let task = ScheduledTask {
+        id: 4,
+        user_id: "u".to_string(),
+        name: "digest".to_string(),
+        prompt: "summarize".to_string(),
+        run_at: 100,
+        interval_minutes: Some(60),
+        enabled: true,
+        created_at: 50,
+        updated_at: 50,
+        last_run_at: None,
+        next_run_at: 100,
+        running_since: Some(100),
+        last_status: None,
+    };
+
+    assert!(handler.handle(DueWork::Task(task)).await.is_err());
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.status, "error");
+    assert_eq!(event.payload["task_id"], 4);
+}